@@ -41,11 +41,11 @@ pub fn main() {
         let buffer = BufferResource::new_host_visible_with_data(logical_device.clone(), &data);
         pipeline.set_storage_buffer(0, 0, &buffer);
 
-        let mut command_buffer = CommandBuffer::new(queue);
-        command_buffer.begin();
-        command_buffer.bind_compute_pipeline(&pipeline);
-        command_buffer.dispatch_compute(data.len() as _, 1, 1);
-        command_buffer.submit().wait();
+        CommandBuffer::record(queue, |recorder| {
+            recorder.bind_compute_pipeline(&pipeline);
+            recorder.dispatch_compute(data.len() as _, 1, 1);
+        })
+        .wait();
         Some(buffer.copy_data::<i32>())
     } else {
         None
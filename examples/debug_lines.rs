@@ -0,0 +1,175 @@
+use ash::ext::debug_utils;
+use ash::vk::{
+    AttachmentLoadOp, AttachmentStoreOp, CullModeFlags, DynamicState, Format, ImageLayout,
+    ImageUsageFlags, MemoryPropertyFlags, PhysicalDeviceFeatures, PipelineBindPoint,
+    PrimitiveTopology, QueueFlags,
+};
+use shaderc::ShaderKind;
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::clear_value::Clear;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::framebuffer::Framebuffer;
+use vk_utils::graphics_pipeline::{GraphicsPipeline, GraphicsPipelineState};
+use vk_utils::image2d_resource::Image2DResource;
+use vk_utils::image_resource::ImageResource;
+use vk_utils::queue::CommandQueue;
+use vk_utils::renderpass::RenderPassBuilder;
+use vk_utils::shader_compiler::ShaderCompiler;
+use vk_utils::validation_capture::ValidationCapture;
+use vk_utils::vulkan::Vulkan;
+
+const SIZE: u32 = 64;
+const CELLS: u32 = 8;
+
+const TRIANGLE_VERT: &str = r"
+#version 450
+const vec2 corners[3] = vec2[](vec2(0.0, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5));
+void main() {
+    gl_Position = vec4(corners[gl_VertexIndex], 0.0, 1.0);
+}
+";
+
+// Two vertices per line, the vertical lines first. Lines go through pixel centers so every line
+// width covers the same center column or row.
+const GRID_VERT: &str = r"
+#version 450
+void main() {
+    uint line = gl_VertexIndex / 2;
+    float end = float(gl_VertexIndex % 2) * 2.0 - 1.0;
+    float offset = (float(line % 8) * 8.0 + 4.5) / 32.0 - 1.0;
+    gl_Position = vec4(line < 8 ? vec2(offset, end) : vec2(end, offset), 0.0, 1.0);
+}
+";
+
+const COLOR_FRAG: &str = r"
+#version 450
+layout(location = 0) out vec4 color;
+void main() {
+    color = vec4(1.0, 0.0, 0.0, 1.0);
+}
+";
+
+const LINE_FRAG: &str = r"
+#version 450
+layout(location = 0) out vec4 color;
+void main() {
+    color = vec4(1.0);
+}
+";
+
+fn compile(src: &str, kind: ShaderKind) -> Vec<u32> {
+    let result = ShaderCompiler::compile_string(src, kind, "debug_lines", "main");
+    assert!(!result.failed(), "{}", result.error_string());
+    result.spirv().to_vec()
+}
+
+// A wireframe grid drawn over a triangle with a line list, dynamic line width and non-indexed
+// draws, rendered offscreen and checked on the CPU.
+pub fn main() {
+    let mut vulkan = Vulkan::new(
+        "Debug lines",
+        &["VK_LAYER_KHRONOS_validation"],
+        &[debug_utils::NAME.to_str().unwrap()],
+    );
+    let capture = ValidationCapture::new();
+    vulkan.set_validation_capture(&capture);
+
+    let gpu = &vulkan.devices_with_queue_support(QueueFlags::GRAPHICS)[0];
+    // Wide lines are optional, without them the grid is drawn one pixel wide.
+    let wide_lines = gpu.features().wide_lines != 0;
+    let features = PhysicalDeviceFeatures::default().wide_lines(wide_lines);
+    let device = Rc::new(gpu.device_context_builder(&[], |info| info.enabled_features(&features)));
+    let queue = Rc::new(CommandQueue::new(device.clone(), QueueFlags::GRAPHICS));
+    let line_width = if wide_lines {
+        device.gpu().limits().line_width_range[1].min(2.0)
+    } else {
+        1.0
+    };
+
+    let mut target = Image2DResource::new(
+        device.clone(),
+        SIZE,
+        SIZE,
+        Format::R8G8B8A8_UNORM,
+        ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        1,
+    );
+    let render_pass = RenderPassBuilder::new()
+        .with_color_attachment(
+            Format::R8G8B8A8_UNORM,
+            AttachmentLoadOp::CLEAR,
+            AttachmentStoreOp::STORE,
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )
+        .build(device.clone());
+    let framebuffer = Framebuffer::new(device.clone(), &render_pass, &[&target], SIZE, SIZE);
+
+    let state = GraphicsPipelineState::new()
+        .with_viewport(SIZE, SIZE)
+        .with_cull_mode(CullModeFlags::NONE);
+    let triangle = GraphicsPipeline::new_from_shaders(
+        device.clone(),
+        &compile(TRIANGLE_VERT, ShaderKind::Vertex),
+        &compile(COLOR_FRAG, ShaderKind::Fragment),
+        &state,
+        &render_pass,
+        &[],
+    );
+    let grid = GraphicsPipeline::new_from_shaders(
+        device.clone(),
+        &compile(GRID_VERT, ShaderKind::Vertex),
+        &compile(LINE_FRAG, ShaderKind::Fragment),
+        &state
+            .clone()
+            .with_topology(PrimitiveTopology::LINE_LIST)
+            .with_dynamic_state(DynamicState::LINE_WIDTH),
+        &render_pass,
+        &[],
+    );
+
+    let mut readback =
+        BufferResource::new_host_visible_storage(device.clone(), (SIZE * SIZE * 4) as usize);
+    CommandBuffer::record(queue.clone(), |recorder| {
+        {
+            let mut pass = recorder
+                .begin_render_pass(
+                    &render_pass,
+                    framebuffer.handle(),
+                    SIZE,
+                    SIZE,
+                    &[Clear::Color(0.0, 0.0, 0.0, 1.0)],
+                )
+                .expect("Clear values don't match the attachments");
+            pass.bind_pipeline(PipelineBindPoint::GRAPHICS, triangle.handle());
+            pass.draw_vertices(3, 0, 1, 0);
+            pass.bind_pipeline(PipelineBindPoint::GRAPHICS, grid.handle());
+            pass.set_line_width(line_width);
+            pass.draw_vertices(CELLS * 4, 0, 1, 0);
+        }
+        // The render pass left the target in its final layout.
+        target.set_layout(ImageLayout::TRANSFER_SRC_OPTIMAL);
+        recorder.copy_image_to_buffer(&target, &mut readback);
+    })
+    .wait();
+
+    let pixels = readback.copy_data::<[u8; 4]>();
+    let pixel = |x: u32, y: u32| pixels[(y * SIZE + x) as usize];
+    // Grid lines run through the centers of column and row 4, 12, 20 and so on.
+    assert_eq!(pixel(4, 1), [255; 4], "Vertical grid line missing");
+    assert_eq!(pixel(1, 12), [255; 4], "Horizontal grid line missing");
+    assert_eq!(
+        pixel(36, 32),
+        [255; 4],
+        "Grid is not drawn over the triangle"
+    );
+    assert_eq!(pixel(32, 32), [255, 0, 0, 255], "Triangle missing");
+    assert_eq!(pixel(1, 1), [0, 0, 0, 255], "Background is not clear");
+
+    capture.assert_no_errors();
+    println!(
+        "Drew a {}x{} grid with {} pixel wide lines over a triangle",
+        CELLS, CELLS, line_width
+    );
+}
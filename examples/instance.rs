@@ -1,5 +1,12 @@
 use vk_utils::vulkan::Vulkan;
 
 pub fn main() {
-    Vulkan::new("My Application", &[], &[]);
+    if !Vulkan::is_available() {
+        println!("No Vulkan driver found");
+        return;
+    }
+
+    if let Err(error) = Vulkan::try_new("My Application", &[], &[]) {
+        println!("{}", error);
+    }
 }
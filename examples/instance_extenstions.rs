@@ -1,7 +1,8 @@
 use vk_utils::vulkan::Vulkan;
 
 pub fn main() {
-    let instance_extensions = Vulkan::available_instance_extensions();
+    let instance_extensions =
+        Vulkan::available_instance_extensions().expect("Extension enumeration failed");
     for extension in instance_extensions {
         println!("{}", extension);
     }
@@ -1,7 +1,7 @@
 use vk_utils::vulkan::Vulkan;
 
 pub fn main() {
-    let instance_layers = Vulkan::available_instance_layers();
+    let instance_layers = Vulkan::available_instance_layers().expect("Layer enumeration failed");
     for layer in instance_layers {
         println!("{}", layer);
     }
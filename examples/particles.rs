@@ -0,0 +1,262 @@
+use std::time::Instant;
+
+use ash::vk::{
+    AccessFlags, BufferUsageFlags, Format, ImageLayout, ImageUsageFlags, MemoryPropertyFlags,
+    PipelineStageFlags,
+};
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::image2d_resource::Image2DResource;
+use vk_utils::image_resource::ImageResource;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::swapchain_util::SwapchainConfig;
+use vk_utils::window_renderer::WindowRenderer;
+use winit::application::ApplicationHandler;
+use winit::event::WindowEvent;
+use winit::event_loop::{ActiveEventLoop, EventLoop};
+use winit::window::{Window, WindowId};
+
+const PARTICLE_COUNT: usize = 16384;
+const LOCAL_SIZE: u32 = 64;
+
+// Every particle is a vec4, position in xy and velocity in zw, in a [-1, 1] box.
+const UPDATE_SRC: &str = r"
+#version 450
+layout(local_size_x = 64) in;
+layout(set = 0, binding = 0) buffer Particles { vec4 particles[]; };
+layout(push_constant) uniform Constants { float dt; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    if (i >= particles.length()) {
+        return;
+    }
+    vec4 p = particles[i];
+    p.w -= 0.5 * dt;
+    p.xy += p.zw * dt;
+    if (abs(p.x) > 1.0) { p.x = sign(p.x); p.z = -p.z; }
+    if (abs(p.y) > 1.0) { p.y = sign(p.y); p.w = -p.w * 0.9; }
+    particles[i] = p;
+}
+";
+
+// Pulls every particle from the buffer and plots it, scale is an orthographic projection that
+// keeps the box square whatever the aspect ratio of the window.
+const SPLAT_SRC: &str = r"
+#version 450
+layout(local_size_x = 64) in;
+layout(set = 0, binding = 0) readonly buffer Particles { vec4 particles[]; };
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D target;
+layout(push_constant) uniform Constants { vec2 scale; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    if (i >= particles.length()) {
+        return;
+    }
+    vec4 p = particles[i];
+    vec2 size = vec2(imageSize(target));
+    vec2 pixel = (p.xy * scale * vec2(1.0, -1.0) * 0.5 + 0.5) * size;
+    float speed = clamp(length(p.zw), 0.0, 1.0);
+    imageStore(target, ivec2(pixel), vec4(1.0, speed, 1.0 - speed, 1.0));
+}
+";
+
+// Orthographic scale mapping the [-1, 1] box onto the largest centered square of the target.
+fn orthographic_scale(width: u32, height: u32) -> [f32; 2] {
+    let aspect = width as f32 / height.max(1) as f32;
+    if aspect > 1.0 {
+        [1.0 / aspect, 1.0]
+    } else {
+        [1.0, aspect]
+    }
+}
+
+fn initial_particles() -> Vec<[f32; 4]> {
+    // A small LCG keeps the example free of extra dependencies.
+    let mut state = 0x2545_f491u32;
+    let mut random = move || {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (state >> 8) as f32 / (1 << 24) as f32
+    };
+    (0..PARTICLE_COUNT)
+        .map(|_| {
+            let angle = random() * std::f32::consts::TAU;
+            let speed = 0.2 + random() * 0.6;
+            [0.0, 0.5, angle.cos() * speed, angle.sin() * speed]
+        })
+        .collect()
+}
+
+struct Simulation {
+    particles: BufferResource,
+    update: ComputePipeline,
+    splat: ComputePipeline,
+    // Sized to the swapchain, recreated after a resize.
+    target: Option<Image2DResource>,
+    last_frame: Instant,
+}
+
+impl Simulation {
+    fn new(renderer: &WindowRenderer) -> Self {
+        let device = renderer.device();
+        let particles = BufferResource::new_device_local_with_data(
+            device.clone(),
+            renderer.queue(),
+            BufferUsageFlags::STORAGE_BUFFER,
+            &initial_particles(),
+        );
+        let mut update =
+            ComputePipeline::new_from_source_string(device.clone(), 1, UPDATE_SRC, "main", None)
+                .expect("Update shader compilation failed");
+        update.set_storage_buffer(0, 0, &particles);
+        let mut splat = ComputePipeline::new_from_source_string(device, 1, SPLAT_SRC, "main", None)
+            .expect("Splat shader compilation failed");
+        splat.set_storage_buffer(0, 0, &particles);
+        Self {
+            particles,
+            update,
+            splat,
+            target: None,
+            last_frame: Instant::now(),
+        }
+    }
+
+    fn ensure_target(&mut self, renderer: &WindowRenderer, width: u32, height: u32) {
+        if let Some(target) = &self.target {
+            if target.width() == width && target.height() == height {
+                return;
+            }
+        }
+        // The splat pipeline's descriptor set may still be used by a frame in flight.
+        renderer.device().wait();
+        let target = Image2DResource::new(
+            renderer.device(),
+            width,
+            height,
+            Format::R8G8B8A8_UNORM,
+            ImageUsageFlags::STORAGE
+                | ImageUsageFlags::TRANSFER_SRC
+                | ImageUsageFlags::TRANSFER_DST,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            1,
+        );
+        self.splat
+            .set_storage_image_with_layout(0, 1, &target, ImageLayout::GENERAL);
+        self.target = Some(target);
+    }
+
+    fn frame(&mut self, renderer: &mut WindowRenderer) {
+        let Some((width, height)) = renderer
+            .swapchain()
+            .map(|swapchain| (swapchain.physical_width(), swapchain.physical_height()))
+        else {
+            return;
+        };
+        self.ensure_target(renderer, width, height);
+        let Some(mut frame) = renderer.begin_frame() else {
+            return;
+        };
+        let now = Instant::now();
+        let dt = (now - self.last_frame).as_secs_f32().min(1.0 / 30.0);
+        self.last_frame = now;
+
+        let groups = (PARTICLE_COUNT as u32).div_ceil(LOCAL_SIZE);
+        let target = self.target.as_mut().unwrap();
+        let recorder = &mut frame.command_buffer;
+        // The previous frame may still be splatting from the particles and reading the target.
+        recorder.full_pipeline_barrier();
+
+        recorder.bind_compute_pipeline(&self.update);
+        recorder.push_compute_constants(&self.update, 0, &dt);
+        recorder.dispatch_compute(groups, 1, 1);
+        recorder.buffer_resource_barrier(
+            &self.particles,
+            PipelineStageFlags::COMPUTE_SHADER,
+            PipelineStageFlags::COMPUTE_SHADER,
+            AccessFlags::SHADER_WRITE,
+            AccessFlags::SHADER_READ,
+        );
+
+        recorder.image_resource_transition(target, ImageLayout::TRANSFER_DST_OPTIMAL);
+        recorder.clear_image(target, 0.02, 0.02, 0.05, 1.0);
+        recorder.memory_barrier(
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::COMPUTE_SHADER,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::SHADER_WRITE,
+        );
+        recorder.image_resource_transition(target, ImageLayout::GENERAL);
+        recorder.bind_compute_pipeline(&self.splat);
+        recorder.push_compute_constants(&self.splat, 0, &orthographic_scale(width, height));
+        recorder.dispatch_compute(groups, 1, 1);
+
+        recorder.memory_barrier(
+            PipelineStageFlags::COMPUTE_SHADER,
+            PipelineStageFlags::TRANSFER,
+            AccessFlags::SHADER_WRITE,
+            AccessFlags::TRANSFER_READ,
+        );
+        recorder.image_resource_transition(target, ImageLayout::TRANSFER_SRC_OPTIMAL);
+        recorder.blit_to_swapchain(target, renderer.swapchain_mut().unwrap(), frame.image_index);
+        renderer.end_frame_with_wait_stage(frame, PipelineStageFlags::TRANSFER);
+    }
+}
+
+// Simulates particles in a compute pass and plots them into the window every frame, run with
+// --features winit. The splat pass stands in for an instanced draw until graphics pipelines take
+// shader stages, the particle buffer is then handed over with compute_write_to_vertex_read_barrier.
+#[derive(Default)]
+struct App {
+    // Declared before the renderer so its resources are released while the device still exists.
+    simulation: Option<Simulation>,
+    // Declared before the window so it is dropped first, the surface refers to the window.
+    renderer: Option<WindowRenderer>,
+    window: Option<Window>,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+        let window = event_loop
+            .create_window(Window::default_attributes().with_title("vk_utils particles"))
+            .expect("Window creation failed");
+        let renderer = WindowRenderer::new(&window, SwapchainConfig::new(0, 0))
+            .unwrap_or_else(|error| panic!("{}", error));
+        self.simulation = Some(Simulation::new(&renderer));
+        self.renderer = Some(renderer);
+        self.window = Some(window);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        let (Some(renderer), Some(simulation), Some(window)) = (
+            self.renderer.as_mut(),
+            self.simulation.as_mut(),
+            self.window.as_ref(),
+        ) else {
+            return;
+        };
+        match event {
+            WindowEvent::CloseRequested => {
+                renderer.device().wait();
+                self.simulation = None;
+                self.renderer = None;
+                event_loop.exit();
+            }
+            WindowEvent::Resized(size) => renderer.resize(size.width, size.height),
+            WindowEvent::RedrawRequested => {
+                simulation.frame(renderer);
+                window.request_redraw();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn main() {
+    let event_loop = EventLoop::new().expect("Event loop creation failed");
+    event_loop
+        .run_app(&mut App::default())
+        .expect("Event loop failed");
+}
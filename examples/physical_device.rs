@@ -16,7 +16,22 @@ pub fn main() {
         panic!("No physical devices found");
     } else {
         for device in devices {
-            println!("{}", device.name())
+            println!("{}", device.name());
+            for heap in device.memory_report() {
+                println!(
+                    "\theap {}: {} MiB{}{}",
+                    heap.index,
+                    heap.size >> 20,
+                    if heap.device_local {
+                        " device local"
+                    } else {
+                        ""
+                    },
+                    heap.budget
+                        .map(|budget| format!(", {} MiB budget", budget >> 20))
+                        .unwrap_or_default()
+                );
+            }
         }
     }
 }
@@ -0,0 +1,115 @@
+use ash::vk::{
+    AccessFlags, DescriptorSetLayoutBinding, DescriptorType, Format, PipelineStageFlags,
+    QueueFlags, ShaderStageFlags,
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::ping_pong::PingPong;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::queue::CommandQueue;
+use vk_utils::readback::read_image_region;
+use vk_utils::vulkan::Vulkan;
+
+const SIZE: u32 = 64;
+
+// 3-tap box blur along the direction given by the push constant.
+const BLUR_SRC: &str = r"
+#version 450
+layout(local_size_x = 8, local_size_y = 8) in;
+layout(set = 0, binding = 0, r32f) uniform readonly image2D source;
+layout(set = 0, binding = 1, r32f) uniform writeonly image2D target;
+layout(push_constant) uniform Constants { ivec2 direction; };
+
+void main() {
+    ivec2 p = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(source);
+    if (p.x >= size.x || p.y >= size.y) {
+        return;
+    }
+    float sum = imageLoad(source, p).r;
+    sum += imageLoad(source, clamp(p - direction, ivec2(0), size - 1)).r;
+    sum += imageLoad(source, clamp(p + direction, ivec2(0), size - 1)).r;
+    imageStore(target, p, vec4(sum / 3.0));
+}
+";
+
+pub fn main() {
+    let vulkan = Vulkan::new("PingPong blur", &[], &[]);
+    let device =
+        Rc::new(vulkan.devices_with_queue_support(QueueFlags::COMPUTE)[0].device_context(&[]));
+    let queue = Rc::new(CommandQueue::new(device.clone(), QueueFlags::COMPUTE));
+
+    let bindings = (0..2)
+        .map(|binding| {
+            DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(ShaderStageFlags::COMPUTE)
+        })
+        .collect();
+    let pipeline = ComputePipeline::new_from_source_string(
+        device.clone(),
+        1,
+        BLUR_SRC,
+        "main",
+        Some(HashMap::from([(0, bindings)])),
+    )
+    .expect("Blur shader compilation failed");
+
+    // A single bright texel in the middle of a black image.
+    let mut texels = vec![0f32; (SIZE * SIZE) as usize];
+    texels[(SIZE / 2 * SIZE + SIZE / 2) as usize] = 1.0;
+    let staging = BufferResource::new_staging(device.clone(), std::mem::size_of_val(&texels[..]))
+        .with_data(&texels);
+
+    let mut ping_pong = PingPong::new(device.clone(), SIZE, SIZE, Format::R32_SFLOAT);
+    let descriptors = ping_pong.descriptors(&pipeline, 0, 0, 1);
+
+    let (x, y, z) = device
+        .gpu()
+        .dispatch_size_for_image(ping_pong.source(), 8, 8);
+    CommandBuffer::record(queue.clone(), |recorder| {
+        ping_pong.prepare(recorder);
+        recorder.copy_buffer_to_image(&staging, ping_pong.source_mut());
+        recorder.image_resource_barrier(
+            ping_pong.source(),
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::COMPUTE_SHADER,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::SHADER_READ,
+        );
+
+        for direction in [[1i32, 0], [0, 1], [1, 0]] {
+            recorder.bind_compute_pipeline(&pipeline);
+            ping_pong.bind(recorder, &pipeline, &descriptors);
+            recorder.push_compute_constants(&pipeline, 0, &direction);
+            recorder.dispatch_compute(x, y, z);
+            ping_pong.flip(recorder);
+        }
+    })
+    .wait();
+
+    let bytes = read_image_region(queue, ping_pong.source_mut(), 0, 0, SIZE, SIZE);
+    let result: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|texel| f32::from_ne_bytes(texel.try_into().unwrap()))
+        .collect();
+
+    // Blurring twice along x and once along y spreads the texel as [1 2 3 2 1] / 9 times [1 1 1] / 3.
+    let center = result[(SIZE / 2 * SIZE + SIZE / 2) as usize];
+    let total: f32 = result.iter().sum();
+    println!("center: {}, total: {}", center, total);
+    assert!(
+        (center - 1.0 / 9.0).abs() < 1e-5,
+        "Unexpected center value {}",
+        center
+    );
+    assert!(
+        (total - 1.0).abs() < 1e-4,
+        "Blur did not preserve energy, total {}",
+        total
+    );
+}
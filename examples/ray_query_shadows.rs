@@ -0,0 +1,342 @@
+use ash::ext::debug_utils;
+use ash::khr::{acceleration_structure, ray_query};
+use ash::vk::{
+    AccelerationStructureBuildGeometryInfoKHR, AccelerationStructureBuildRangeInfoKHR,
+    AccelerationStructureBuildSizesInfoKHR, AccelerationStructureBuildTypeKHR,
+    AccelerationStructureGeometryDataKHR, AccelerationStructureGeometryInstancesDataKHR,
+    AccelerationStructureGeometryKHR, AccelerationStructureGeometryTrianglesDataKHR,
+    AccelerationStructureInstanceKHR, AccelerationStructureReferenceKHR,
+    AccelerationStructureTypeKHR, AccessFlags, AttachmentLoadOp, AttachmentStoreOp,
+    BufferUsageFlags, BuildAccelerationStructureFlagsKHR, BuildAccelerationStructureModeKHR,
+    CullModeFlags, DeviceOrHostAddressConstKHR, DeviceOrHostAddressKHR, Format, GeometryFlagsKHR,
+    GeometryInstanceFlagsKHR, GeometryTypeKHR, ImageLayout, ImageUsageFlags, IndexType,
+    MemoryPropertyFlags, Packed24_8, PipelineBindPoint, PipelineStageFlags, QueueFlags,
+    ShaderStageFlags, TransformMatrixKHR,
+};
+use shaderc::ShaderKind;
+use std::rc::Rc;
+use vk_utils::acceleration_structure::AccelerationStructure;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::clear_value::Clear;
+use vk_utils::command_buffer::{CommandBuffer, Recorder};
+use vk_utils::device_context::DeviceContext;
+use vk_utils::framebuffer::Framebuffer;
+use vk_utils::graphics_pipeline::{GraphicsPipeline, GraphicsPipelineState};
+use vk_utils::image2d_resource::Image2DResource;
+use vk_utils::image_resource::ImageResource;
+use vk_utils::queue::CommandQueue;
+use vk_utils::renderpass::RenderPassBuilder;
+use vk_utils::shader_compiler::{ShaderCompiler, ShaderReflection};
+use vk_utils::shared_binding::SharedBinding;
+use vk_utils::validation_capture::ValidationCapture;
+use vk_utils::vulkan::Vulkan;
+
+const SIZE: u32 = 64;
+
+const FULLSCREEN_VERT: &str = r"
+#version 460
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+";
+
+// Looks straight down on a floor spanning -2..2 in x and z. Every pixel traces a ray from the
+// floor to a point light above it and is black when the blocker is in the way.
+const SHADOW_FRAG: &str = r"
+#version 460
+#extension GL_EXT_ray_query : require
+layout(set = 0, binding = 0) uniform accelerationStructureEXT scene;
+layout(location = 0) out vec4 color;
+void main() {
+    vec2 uv = gl_FragCoord.xy / 64.0;
+    vec3 origin = vec3(uv.x * 4.0 - 2.0, 0.0, uv.y * 4.0 - 2.0);
+    vec3 to_light = vec3(0.0, 10.0, 0.0) - origin;
+
+    rayQueryEXT query;
+    rayQueryInitializeEXT(query, scene, gl_RayFlagsTerminateOnFirstHitEXT, 0xff, origin, 0.001,
+        normalize(to_light), length(to_light));
+    while (rayQueryProceedEXT(query)) {}
+    bool shadowed =
+        rayQueryGetIntersectionTypeEXT(query, true) != gl_RayQueryCommittedIntersectionNoneEXT;
+    color = vec4(vec3(shadowed ? 0.0 : 1.0), 1.0);
+}
+";
+
+fn compile(src: &str, kind: ShaderKind) -> Vec<u32> {
+    let result = ShaderCompiler::compile_string(src, kind, "ray_query_shadows", "main");
+    assert!(!result.failed(), "{}", result.error_string());
+    result.spirv().to_vec()
+}
+
+fn build_input(device: Rc<DeviceContext>, bytes: &[u8]) -> BufferResource {
+    let mut buffer = BufferResource::new(
+        device,
+        bytes.len(),
+        MemoryPropertyFlags::HOST_VISIBLE,
+        BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+            | BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+    );
+    buffer.upload(bytes);
+    buffer
+}
+
+// The scratch buffer is retained by the recorder until the build has completed.
+fn build(
+    recorder: &mut Recorder,
+    device: &Rc<DeviceContext>,
+    ty: AccelerationStructureTypeKHR,
+    geometry: AccelerationStructureGeometryKHR,
+    primitive_count: u32,
+) -> AccelerationStructure {
+    let loader =
+        acceleration_structure::Device::new(device.gpu().vulkan().vk_instance(), device.handle());
+    let geometries = [geometry];
+    let mut info = AccelerationStructureBuildGeometryInfoKHR::default()
+        .ty(ty)
+        .flags(BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+        .mode(BuildAccelerationStructureModeKHR::BUILD)
+        .geometries(&geometries);
+    let mut sizes = AccelerationStructureBuildSizesInfoKHR::default();
+    unsafe {
+        loader.get_acceleration_structure_build_sizes(
+            AccelerationStructureBuildTypeKHR::DEVICE,
+            &info,
+            &[primitive_count],
+            &mut sizes,
+        )
+    };
+
+    let acceleration_structure =
+        AccelerationStructure::new(device.clone(), ty, sizes.acceleration_structure_size);
+    let scratch = BufferResource::new(
+        device.clone(),
+        sizes.build_scratch_size as usize,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+    );
+    info = info
+        .dst_acceleration_structure(acceleration_structure.handle())
+        .scratch_data(DeviceOrHostAddressKHR {
+            device_address: scratch.device_address(),
+        });
+    let ranges =
+        [AccelerationStructureBuildRangeInfoKHR::default().primitive_count(primitive_count)];
+    recorder.record_handle(|handle| {
+        unsafe { loader.cmd_build_acceleration_structures(handle, &[info], &[&ranges]) };
+        handle
+    });
+    recorder.retain(scratch);
+    acceleration_structure
+}
+
+// Renders the hard shadow of a square blocker floating over a floor into an offscreen target.
+// The blocker is traced with rayQueryEXT from the fragment shader, through a TLAS bound to a
+// graphics pipeline. Skips devices without ray queries.
+pub fn main() {
+    let mut vulkan = Vulkan::new(
+        "Ray query shadows",
+        &["VK_LAYER_KHRONOS_validation"],
+        &[debug_utils::NAME.to_str().unwrap()],
+    );
+    let capture = ValidationCapture::new();
+    vulkan.set_validation_capture(&capture);
+
+    let extensions = [
+        acceleration_structure::NAME.to_str().unwrap(),
+        ray_query::NAME.to_str().unwrap(),
+    ];
+    let Some(gpu) = vulkan
+        .devices_with_queue_support(QueueFlags::GRAPHICS)
+        .into_iter()
+        .find(|gpu| gpu.has_all_extensions(&extensions))
+    else {
+        println!("No device supports ray queries, skipping");
+        return;
+    };
+    let device = Rc::new(gpu.ray_query_device_context(&[]));
+    let queue = Rc::new(CommandQueue::new(device.clone(), QueueFlags::GRAPHICS));
+
+    // A 1x1 square at height 1, the light at height 10 casts a slightly larger shadow.
+    let vertices: [[f32; 3]; 4] = [
+        [-0.5, 1.0, -0.5],
+        [0.5, 1.0, -0.5],
+        [0.5, 1.0, 0.5],
+        [-0.5, 1.0, 0.5],
+    ];
+    let indices: [u32; 6] = [0, 1, 2, 0, 2, 3];
+    let vertex_buffer = build_input(device.clone(), unsafe {
+        std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            std::mem::size_of_val(&vertices),
+        )
+    });
+    let index_buffer = build_input(device.clone(), unsafe {
+        std::slice::from_raw_parts(
+            indices.as_ptr() as *const u8,
+            std::mem::size_of_val(&indices),
+        )
+    });
+
+    let triangles = AccelerationStructureGeometryTrianglesDataKHR::default()
+        .vertex_format(Format::R32G32B32_SFLOAT)
+        .vertex_data(DeviceOrHostAddressConstKHR {
+            device_address: vertex_buffer.device_address(),
+        })
+        .vertex_stride(std::mem::size_of::<[f32; 3]>() as _)
+        .max_vertex(vertices.len() as u32 - 1)
+        .index_type(IndexType::UINT32)
+        .index_data(DeviceOrHostAddressConstKHR {
+            device_address: index_buffer.device_address(),
+        });
+    let mut blas = None;
+    CommandBuffer::record(queue.clone(), |recorder| {
+        blas = Some(build(
+            recorder,
+            &device,
+            AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            AccelerationStructureGeometryKHR::default()
+                .geometry_type(GeometryTypeKHR::TRIANGLES)
+                .geometry(AccelerationStructureGeometryDataKHR { triangles })
+                .flags(GeometryFlagsKHR::OPAQUE),
+            indices.len() as u32 / 3,
+        ));
+    })
+    .wait();
+    let blas = blas.unwrap();
+
+    let instance = AccelerationStructureInstanceKHR {
+        transform: TransformMatrixKHR {
+            matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        },
+        instance_custom_index_and_mask: Packed24_8::new(0, 0xff),
+        instance_shader_binding_table_record_offset_and_flags: Packed24_8::new(
+            0,
+            GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8,
+        ),
+        acceleration_structure_reference: AccelerationStructureReferenceKHR {
+            device_handle: blas.device_address(),
+        },
+    };
+    // The instance layout is fixed by the spec, 64 bytes without padding.
+    let instance_bytes: [u8; 64] = unsafe { std::mem::transmute(instance) };
+    let instance_buffer = build_input(device.clone(), &instance_bytes);
+    let instances = AccelerationStructureGeometryInstancesDataKHR::default().data(
+        DeviceOrHostAddressConstKHR {
+            device_address: instance_buffer.device_address(),
+        },
+    );
+    let mut tlas = None;
+    CommandBuffer::record(queue.clone(), |recorder| {
+        tlas = Some(build(
+            recorder,
+            &device,
+            AccelerationStructureTypeKHR::TOP_LEVEL,
+            AccelerationStructureGeometryKHR::default()
+                .geometry_type(GeometryTypeKHR::INSTANCES)
+                .geometry(AccelerationStructureGeometryDataKHR { instances }),
+            1,
+        ));
+        // Makes the build visible to the fragment shaders of the render pass below.
+        recorder.memory_barrier(
+            PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
+            PipelineStageFlags::FRAGMENT_SHADER,
+            AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR,
+            AccessFlags::ACCELERATION_STRUCTURE_READ_KHR,
+        );
+    })
+    .wait();
+    let tlas = tlas.unwrap();
+
+    let mut target = Image2DResource::new(
+        device.clone(),
+        SIZE,
+        SIZE,
+        Format::R8G8B8A8_UNORM,
+        ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        1,
+    );
+    let render_pass = RenderPassBuilder::new()
+        .with_color_attachment(
+            Format::R8G8B8A8_UNORM,
+            AttachmentLoadOp::CLEAR,
+            AttachmentStoreOp::STORE,
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )
+        .build(device.clone());
+    let framebuffer = Framebuffer::new(device.clone(), &render_pass, &[&target], SIZE, SIZE);
+
+    // The TLAS binding is reflected from the fragment shader with FRAGMENT stage flags.
+    let fragment_spirv = compile(SHADOW_FRAG, ShaderKind::Fragment);
+    let bindings = ShaderReflection::from_spirv(&fragment_spirv)
+        .expect("Fragment shader reflection failed")
+        .descriptor_set_bindings(ShaderStageFlags::FRAGMENT)
+        .remove(&0)
+        .expect("The fragment shader declares set 0");
+    let scene = SharedBinding::new(device.clone(), &bindings);
+    scene.set_acceleration_structure(0, &tlas);
+
+    let pipeline = GraphicsPipeline::new_from_shaders(
+        device.clone(),
+        &compile(FULLSCREEN_VERT, ShaderKind::Vertex),
+        &fragment_spirv,
+        &GraphicsPipelineState::new()
+            .with_viewport(SIZE, SIZE)
+            .with_cull_mode(CullModeFlags::NONE),
+        &render_pass,
+        &[scene.layout()],
+    );
+
+    let mut readback =
+        BufferResource::new_host_visible_storage(device.clone(), (SIZE * SIZE * 4) as usize);
+    CommandBuffer::record(queue.clone(), |recorder| {
+        {
+            let mut pass = recorder
+                .begin_render_pass(
+                    &render_pass,
+                    framebuffer.handle(),
+                    SIZE,
+                    SIZE,
+                    &[Clear::Color(1.0, 0.0, 1.0, 1.0)],
+                )
+                .expect("Clear values don't match the attachments");
+            pass.bind_pipeline(PipelineBindPoint::GRAPHICS, pipeline.handle());
+            pass.bind_descriptor_sets_at(
+                pipeline.layout(),
+                PipelineBindPoint::GRAPHICS,
+                0,
+                &[scene.descriptor_set()],
+            );
+            pass.draw_vertices(3, 0, 1, 0);
+        }
+        // The render pass left the target in its final layout.
+        target.set_layout(ImageLayout::TRANSFER_SRC_OPTIMAL);
+        recorder.copy_image_to_buffer(&target, &mut readback);
+    })
+    .wait();
+
+    // The shadow covers about -0.56..0.56 in x and z, pixels 23 to 40.
+    let pixels = readback.copy_data::<[u8; 4]>();
+    let pixel = |x: u32, y: u32| pixels[(y * SIZE + x) as usize];
+    assert_eq!(
+        pixel(32, 32),
+        [0, 0, 0, 255],
+        "Floor below the blocker is lit"
+    );
+    assert_eq!(pixel(24, 39), [0, 0, 0, 255], "Shadow is too small");
+    assert_eq!(
+        pixel(4, 4),
+        [255; 4],
+        "Floor away from the blocker is shadowed"
+    );
+    assert_eq!(pixel(20, 32), [255; 4], "Shadow is too large");
+    let shadowed = pixels.iter().filter(|pixel| pixel[0] == 0).count();
+
+    capture.assert_no_errors();
+    println!(
+        "Traced {} pixels from a fragment shader, {} in shadow",
+        pixels.len(),
+        shadowed
+    );
+}
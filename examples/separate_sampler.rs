@@ -0,0 +1,103 @@
+use ash::ext::debug_utils;
+use ash::vk::{Filter, Format, ImageLayout, ImageUsageFlags, MemoryPropertyFlags, QueueFlags};
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::image2d_resource::Image2DResource;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::queue::CommandQueue;
+use vk_utils::sampler_resource::SamplerDescriptor;
+use vk_utils::validation_capture::ValidationCapture;
+use vk_utils::vulkan::Vulkan;
+
+const SIZE: u32 = 4;
+
+// A texture2D and a sampler bound separately and combined in the shader. Compute shaders have no
+// implicit derivatives, so the level is passed explicitly.
+const SRC: &str = r"
+#version 450
+layout(local_size_x = 4, local_size_y = 4) in;
+layout(set = 0, binding = 0) uniform texture2D tex;
+layout(set = 0, binding = 1) uniform sampler samp;
+layout(set = 0, binding = 2) buffer Result { vec4 texels[]; };
+void main() {
+    uvec2 p = gl_GlobalInvocationID.xy;
+    vec2 uv = (vec2(p) + 0.5) / vec2(textureSize(sampler2D(tex, samp), 0));
+    texels[p.y * 4 + p.x] = textureLod(sampler2D(tex, samp), uv, 0.0);
+}
+";
+
+pub fn main() {
+    let mut vulkan = Vulkan::new(
+        "Separate sampler",
+        &["VK_LAYER_KHRONOS_validation"],
+        &[debug_utils::NAME.to_str().unwrap()],
+    );
+    let capture = ValidationCapture::new();
+    vulkan.set_validation_capture(&capture);
+
+    let device =
+        Rc::new(vulkan.devices_with_queue_support(QueueFlags::COMPUTE)[0].device_context(&[]));
+    let queue = Rc::new(CommandQueue::new(device.clone(), QueueFlags::COMPUTE));
+
+    // Red increases along x, green along y.
+    let pixels: Vec<[u8; 4]> = (0..SIZE * SIZE)
+        .map(|i| [(i % SIZE * 64) as u8, (i / SIZE * 64) as u8, 0, 255])
+        .collect();
+    let staging = BufferResource::new_staging(device.clone(), pixels.len() * 4).with_data(&pixels);
+    let mut image = Image2DResource::new(
+        device.clone(),
+        SIZE,
+        SIZE,
+        Format::R8G8B8A8_UNORM,
+        ImageUsageFlags::SAMPLED | ImageUsageFlags::TRANSFER_DST,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        1,
+    );
+    let result = BufferResource::new_host_visible_storage(
+        device.clone(),
+        (SIZE * SIZE) as usize * std::mem::size_of::<[f32; 4]>(),
+    );
+
+    let sampler = device.get_sampler(&SamplerDescriptor {
+        mag_filter: Filter::NEAREST,
+        min_filter: Filter::NEAREST,
+        ..Default::default()
+    });
+    let mut pipeline =
+        ComputePipeline::new_from_source_string(device.clone(), 1, SRC, "main", None)
+            .expect("Compute pipeline creation failed");
+    pipeline.set_sampled_image_with_layout(0, 0, &image, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    pipeline.set_sampler(0, 1, sampler);
+    pipeline.set_storage_buffer(0, 2, &result);
+
+    CommandBuffer::record(queue.clone(), |recorder| {
+        recorder.image_resource_transition(&mut image, ImageLayout::TRANSFER_DST_OPTIMAL);
+        recorder.copy_buffer_to_image(&staging, &mut image);
+        recorder.image_resource_transition(&mut image, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        recorder.bind_compute_pipeline(&pipeline);
+        recorder.dispatch_compute(1, 1, 1);
+    })
+    .wait();
+
+    // Sampling texel centers with nearest filtering returns every texel unchanged.
+    let texels = result.copy_data::<[f32; 4]>();
+    for (texel, pixel) in texels.iter().zip(&pixels) {
+        let expected = pixel.map(|channel| channel as f32 / 255.0);
+        assert!(
+            texel
+                .iter()
+                .zip(expected)
+                .all(|(value, expected)| (value - expected).abs() < 1e-3),
+            "Sampled {:?}, expected {:?}",
+            texel,
+            expected
+        );
+    }
+
+    capture.assert_no_errors();
+    println!(
+        "Sampled {} texels through sampler2D(tex, samp)",
+        texels.len()
+    );
+}
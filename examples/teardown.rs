@@ -0,0 +1,100 @@
+use ash::ext::debug_utils;
+use ash::vk::{
+    DescriptorSetLayoutBinding, DescriptorType, Format, ImageLayout, ImageUsageFlags,
+    MemoryPropertyFlags, QueueFlags, ShaderStageFlags,
+};
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::image2d_resource::Image2DResource;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::queue::CommandQueue;
+use vk_utils::renderpass::RenderPass;
+use vk_utils::sampler_resource::{SamplerDescriptor, SamplerResource};
+use vk_utils::shared_binding::SharedBinding;
+use vk_utils::validation_capture::ValidationCapture;
+use vk_utils::vulkan::Vulkan;
+
+// Creates one of every resource type, drops them all and then the device. The validation layer
+// reports anything still alive when the device is destroyed, which fails the capture.
+pub fn main() {
+    let mut vulkan = Vulkan::new(
+        "Teardown",
+        &["VK_LAYER_KHRONOS_validation"],
+        &[debug_utils::NAME.to_str().unwrap()],
+    );
+    let capture = ValidationCapture::new();
+    if !vulkan.set_validation_capture(&capture) {
+        println!("VK_EXT_debug_utils is not available, leaks can't be detected");
+        return;
+    }
+
+    {
+        let device =
+            Rc::new(vulkan.devices_with_queue_support(QueueFlags::COMPUTE)[0].device_context(&[]));
+        let queue = Rc::new(CommandQueue::new(device.clone(), QueueFlags::COMPUTE));
+
+        let buffer = BufferResource::new_host_visible_with_data(device.clone(), &[0u32; 64]);
+        let mut image = Image2DResource::new(
+            device.clone(),
+            64,
+            64,
+            Format::R8G8B8A8_UNORM,
+            ImageUsageFlags::STORAGE | ImageUsageFlags::TRANSFER_SRC,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            1,
+        );
+        let _cached_sampler = device.get_sampler(&SamplerDescriptor::default());
+        let _sampler = SamplerResource::new(device.clone(), &SamplerDescriptor::default());
+        let _render_pass = RenderPass::new_with_single_output(
+            device.clone(),
+            Format::R8G8B8A8_UNORM,
+            ImageLayout::UNDEFINED,
+            ImageLayout::GENERAL,
+        );
+
+        let shared = Rc::new(SharedBinding::new(
+            device.clone(),
+            &[DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(ShaderStageFlags::COMPUTE)],
+        ));
+        shared.set_storage_buffer(0, &buffer);
+
+        let src = r"
+        #version 450
+        layout(set = 0, binding = 0) buffer Data {
+            uint x[];
+        } data;
+        layout(set = 1, binding = 0, rgba8) uniform image2D target;
+        void main() {
+            data.x[gl_GlobalInvocationID.x] += 1;
+            imageStore(target, ivec2(gl_GlobalInvocationID.xy), vec4(1.0));
+        }
+        ";
+        let mut pipeline = ComputePipeline::new_with_shared_sets(
+            device.clone(),
+            1,
+            src,
+            "main",
+            None,
+            &[(0, shared.clone())],
+        )
+        .expect("Compute pipeline creation failed");
+        pipeline.set_storage_image_with_layout(1, 0, &image, ImageLayout::GENERAL);
+
+        CommandBuffer::record(queue.clone(), |recorder| {
+            recorder.image_resource_transition(&mut image, ImageLayout::GENERAL);
+            recorder.bind_compute_pipeline(&pipeline);
+            recorder.dispatch_compute(64, 1, 1);
+        })
+        .wait();
+    }
+
+    // The device was destroyed at the end of the scope, every leak has been reported by now.
+    capture.assert_no_leaks();
+    capture.assert_no_errors();
+    println!("No validation errors or leaked objects");
+}
@@ -0,0 +1,62 @@
+use vk_utils::swapchain_util::SwapchainConfig;
+use vk_utils::window_renderer::WindowRenderer;
+use winit::application::ApplicationHandler;
+use winit::event::WindowEvent;
+use winit::event_loop::{ActiveEventLoop, EventLoop};
+use winit::window::{Window, WindowId};
+
+// Clears the window to a color, run with --features winit. Resize and minimize to check the
+// swapchain is recreated and zero sized windows are skipped.
+#[derive(Default)]
+struct App {
+    // Declared before the window so it is dropped first, the surface refers to the window.
+    renderer: Option<WindowRenderer>,
+    window: Option<Window>,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+        let window = event_loop
+            .create_window(Window::default_attributes().with_title("vk_utils window"))
+            .expect("Window creation failed");
+        let config = SwapchainConfig::new(0, 0).with_clear_color([0.1, 0.3, 0.6, 1.0]);
+        self.renderer =
+            Some(WindowRenderer::new(&window, config).unwrap_or_else(|error| panic!("{}", error)));
+        self.window = Some(window);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        let (Some(renderer), Some(window)) = (self.renderer.as_mut(), self.window.as_ref()) else {
+            return;
+        };
+        match event {
+            WindowEvent::CloseRequested => {
+                self.renderer = None;
+                event_loop.exit();
+            }
+            WindowEvent::Resized(size) => renderer.resize(size.width, size.height),
+            WindowEvent::RedrawRequested => {
+                if let Some(mut frame) = renderer.begin_frame() {
+                    let swapchain = renderer.swapchain().unwrap();
+                    frame
+                        .command_buffer
+                        .begin_swapchain_render_pass(swapchain, frame.image_index);
+                    frame.command_buffer.end_render_pass();
+                    renderer.end_frame(frame);
+                }
+                window.request_redraw();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn main() {
+    let event_loop = EventLoop::new().expect("Event loop creation failed");
+    event_loop
+        .run_app(&mut App::default())
+        .expect("Event loop failed");
+}
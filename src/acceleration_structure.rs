@@ -0,0 +1,90 @@
+use std::rc::Rc;
+
+use ash::khr::acceleration_structure;
+use ash::vk::{
+    AccelerationStructureCreateInfoKHR, AccelerationStructureDeviceAddressInfoKHR,
+    AccelerationStructureKHR, AccelerationStructureTypeKHR, BufferUsageFlags, DeviceAddress,
+    DeviceSize, MemoryPropertyFlags,
+};
+
+use crate::{buffer_resource::BufferResource, device_context::DeviceContext};
+
+pub struct AccelerationStructure {
+    device: Rc<DeviceContext>,
+    loader: acceleration_structure::Device,
+    handle: AccelerationStructureKHR,
+    ty: AccelerationStructureTypeKHR,
+    buffer: BufferResource,
+}
+
+impl AccelerationStructure {
+    pub fn new(
+        device: Rc<DeviceContext>,
+        ty: AccelerationStructureTypeKHR,
+        size: DeviceSize,
+    ) -> Self {
+        let loader = acceleration_structure::Device::new(
+            device.gpu().vulkan().vk_instance(),
+            device.handle(),
+        );
+        let buffer = BufferResource::new(
+            device.clone(),
+            size as _,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        );
+        let info = AccelerationStructureCreateInfoKHR::default()
+            .buffer(buffer.buffer)
+            .size(size)
+            .ty(ty);
+        let handle = unsafe {
+            loader
+                .create_acceleration_structure(&info, device.allocation_callbacks())
+                .expect("Acceleration structure creation failed")
+        };
+
+        Self {
+            device,
+            loader,
+            handle,
+            ty,
+            buffer,
+        }
+    }
+
+    pub fn handle(&self) -> AccelerationStructureKHR {
+        self.handle
+    }
+
+    pub fn ty(&self) -> AccelerationStructureTypeKHR {
+        self.ty
+    }
+
+    pub fn size(&self) -> DeviceSize {
+        self.buffer.content_size()
+    }
+
+    pub fn buffer(&self) -> &BufferResource {
+        &self.buffer
+    }
+
+    pub fn device_address(&self) -> DeviceAddress {
+        let info = AccelerationStructureDeviceAddressInfoKHR::default()
+            .acceleration_structure(self.handle);
+        unsafe { self.loader.get_acceleration_structure_device_address(&info) }
+    }
+
+    pub fn loader(&self) -> &acceleration_structure::Device {
+        &self.loader
+    }
+}
+
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader
+                .destroy_acceleration_structure(self.handle, self.device.allocation_callbacks())
+        }
+    }
+}
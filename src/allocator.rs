@@ -0,0 +1,77 @@
+use std::ffi::c_void;
+
+use ash::vk::{AllocationCallbacks, SystemAllocationScope};
+
+// Host memory used by the driver for Vulkan objects, bridged to vk::AllocationCallbacks.
+// Returning null from allocate or reallocate reports an out of host memory error to the driver.
+pub trait HostAllocator {
+    fn allocate(&self, size: usize, alignment: usize, scope: SystemAllocationScope) -> *mut c_void;
+
+    fn reallocate(
+        &self,
+        original: *mut c_void,
+        size: usize,
+        alignment: usize,
+        scope: SystemAllocationScope,
+    ) -> *mut c_void;
+
+    fn free(&self, memory: *mut c_void);
+}
+
+// Notified of every device memory allocation the crate makes, the tag names the resource kind.
+pub trait DeviceAllocationListener {
+    fn allocated(&self, size: u64, memory_type: u32, tag: &str);
+
+    fn freed(&self, size: u64, memory_type: u32, tag: &str);
+}
+
+unsafe extern "system" fn allocate(
+    user_data: *mut c_void,
+    size: usize,
+    alignment: usize,
+    scope: SystemAllocationScope,
+) -> *mut c_void {
+    let allocator = &*(user_data as *const Box<dyn HostAllocator>);
+    allocator.allocate(size, alignment, scope)
+}
+
+unsafe extern "system" fn reallocate(
+    user_data: *mut c_void,
+    original: *mut c_void,
+    size: usize,
+    alignment: usize,
+    scope: SystemAllocationScope,
+) -> *mut c_void {
+    let allocator = &*(user_data as *const Box<dyn HostAllocator>);
+    allocator.reallocate(original, size, alignment, scope)
+}
+
+unsafe extern "system" fn free(user_data: *mut c_void, memory: *mut c_void) {
+    let allocator = &*(user_data as *const Box<dyn HostAllocator>);
+    allocator.free(memory)
+}
+
+// Owns the allocator, the callbacks point into the boxed allocator so they stay valid when this moves.
+pub(crate) struct HostCallbacks {
+    _allocator: Box<Box<dyn HostAllocator>>,
+    callbacks: AllocationCallbacks<'static>,
+}
+
+impl HostCallbacks {
+    pub(crate) fn new(allocator: Box<dyn HostAllocator>) -> Self {
+        let allocator = Box::new(allocator);
+        let callbacks = AllocationCallbacks::default()
+            .user_data(&*allocator as *const Box<dyn HostAllocator> as *mut c_void)
+            .pfn_allocation(Some(allocate))
+            .pfn_reallocation(Some(reallocate))
+            .pfn_free(Some(free));
+        Self {
+            _allocator: allocator,
+            callbacks,
+        }
+    }
+
+    pub(crate) fn callbacks(&self) -> &AllocationCallbacks<'static> {
+        &self.callbacks
+    }
+}
@@ -1,20 +1,171 @@
-use std::{mem::size_of, rc::Rc};
+use std::{cell::Cell, ffi::c_void, mem::size_of, rc::Rc};
 
+use crate::command_buffer::CommandBuffer;
 use crate::device_context::DeviceContext;
+use crate::format_info::{block_count, block_size_bytes};
+use crate::image_upload::DEFAULT_STAGING_BUDGET;
 use crate::memory::memory_type_index;
+use crate::queue::CommandQueue;
+use crate::readback::PendingRead;
+use crate::wait_handle::WaitHandle;
 
 use ash::vk::{
-    Buffer, BufferCreateInfo, BufferDeviceAddressInfo, BufferUsageFlags, DeviceAddress,
-    DeviceMemory, MappedMemoryRange, MemoryAllocateFlags, MemoryAllocateFlagsInfo,
-    MemoryAllocateInfo, MemoryMapFlags, MemoryPropertyFlags, PhysicalDeviceMemoryProperties2,
-    SharingMode,
+    AccessFlags, Buffer, BufferCopy, BufferCreateInfo, BufferDeviceAddressInfo, BufferUsageFlags,
+    DeviceAddress, DeviceMemory, Format, MappedMemoryRange, MemoryAllocateFlags,
+    MemoryAllocateFlagsInfo, MemoryAllocateInfo, MemoryMapFlags, MemoryPropertyFlags,
+    PipelineStageFlags, SharingMode,
 };
+
+// Element types that are copied into and out of buffer memory byte for byte. With the bytemuck
+// feature this requires Pod, so padding bytes and invalid bit patterns are ruled out at compile
+// time. Without it any type is accepted and the caller has to uphold the same contract.
+#[cfg(feature = "bytemuck")]
+pub trait BufferData: bytemuck::Pod {}
+
+#[cfg(feature = "bytemuck")]
+impl<T: bytemuck::Pod> BufferData for T {}
+
+#[cfg(not(feature = "bytemuck"))]
+pub trait BufferData {}
+
+#[cfg(not(feature = "bytemuck"))]
+impl<T> BufferData for T {}
+
+// Zero sized element types are rejected, the count would divide by zero.
+fn element_count<T>(content_size: u64) -> usize {
+    assert!(
+        size_of::<T>() > 0,
+        "Buffers can't be viewed as zero sized elements"
+    );
+    content_size as usize / size_of::<T>()
+}
+
+fn as_bytes<T: BufferData>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+// Staging buffers an asynchronous upload cycles through, a slot is refilled once the copy out of
+// it has completed.
+const STAGING_RING_SLOTS: usize = 3;
+
+// Byte offset and contents of every chunk_size piece of the data.
+fn staging_chunks(bytes: &[u8], chunk_size: usize) -> impl Iterator<Item = (u64, &[u8])> {
+    bytes
+        .chunks(chunk_size)
+        .enumerate()
+        .map(move |(index, chunk)| ((index * chunk_size) as u64, chunk))
+}
+
 pub struct BufferResource {
     device: Rc<DeviceContext>,
     pub buffer: Buffer,
     memory: DeviceMemory,
+    memory_type: u32,
+    memory_flags: MemoryPropertyFlags,
+    usage: BufferUsageFlags,
     size: u64,
     content_size: u64,
+    // Vulkan memory can only be mapped once, guards share a single mapping that is released when
+    // the last of them is dropped.
+    mapping: Cell<*mut c_void>,
+    map_count: Cell<u32>,
+}
+
+pub struct PersistentlyMappedBuffer<T> {
+    buffer: BufferResource,
+    ptr: *mut T,
+    len: usize,
+}
+
+impl<T: BufferData> PersistentlyMappedBuffer<T> {
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+
+    pub fn flush(&self) {
+        let ranges = [MappedMemoryRange::default()
+            .memory(self.buffer.memory)
+            .size(ash::vk::WHOLE_SIZE)];
+        unsafe {
+            self.buffer
+                .device
+                .handle()
+                .flush_mapped_memory_ranges(&ranges)
+                .expect("Memory flush failed");
+        }
+    }
+
+    pub fn buffer(&self) -> &BufferResource {
+        &self.buffer
+    }
+}
+
+impl<T> Drop for PersistentlyMappedBuffer<T> {
+    fn drop(&mut self) {
+        unsafe { self.buffer.device.handle().unmap_memory(self.buffer.memory) }
+    }
+}
+
+// The buffer's contents while mapped, unmapped again when the last guard is dropped. Any number
+// of them can be held at once.
+pub struct MappedSlice<'a, T> {
+    buffer: &'a BufferResource,
+    ptr: *const T,
+    len: usize,
+}
+
+impl<T> std::ops::Deref for MappedSlice<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for MappedSlice<'_, T> {
+    fn drop(&mut self) {
+        self.buffer.unmap_whole()
+    }
+}
+
+// Writable counterpart of MappedSlice, flushes non-coherent memory before unmapping.
+pub struct MappedSliceMut<'a, T> {
+    buffer: &'a mut BufferResource,
+    ptr: *mut T,
+    len: usize,
+}
+
+impl<T> std::ops::Deref for MappedSliceMut<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> std::ops::DerefMut for MappedSliceMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for MappedSliceMut<'_, T> {
+    fn drop(&mut self) {
+        let buffer = &self.buffer;
+        if !buffer.is_host_coherent() {
+            let ranges = [buffer.whole_mapped_range()];
+            if let Err(error) =
+                unsafe { buffer.device.handle().flush_mapped_memory_ranges(&ranges) }
+            {
+                println!("Flushing mapped buffer memory failed: {:?}", error);
+            }
+        }
+        buffer.unmap_whole()
+    }
 }
 
 impl BufferResource {
@@ -31,13 +182,10 @@ impl BufferResource {
         }
     }
 
-    pub fn upload<T>(&mut self, data: &[T]) {
+    pub fn upload<T: BufferData>(&mut self, data: &[T]) {
+        self.debug_assert_fits::<T>(0, data);
         unsafe {
-            let ptr = self
-                .device
-                .handle()
-                .map_memory(self.memory, 0, self.size, MemoryMapFlags::default())
-                .expect("Memory map failed on buffer");
+            let ptr = self.map_whole();
 
             let size = data.len();
 
@@ -51,17 +199,14 @@ impl BufferResource {
                 .handle()
                 .flush_mapped_memory_ranges(&ranges)
                 .expect("Memory flush failed");
-            self.device.handle().unmap_memory(self.memory);
         }
+        self.unmap_whole();
     }
 
-    pub fn upload_at<T>(&mut self, offset: u64, data: &[T]) {
+    pub fn upload_at<T: BufferData>(&mut self, offset: u64, data: &[T]) {
+        self.debug_assert_fits::<T>(offset, data);
         unsafe {
-            let ptr = self
-                .device
-                .handle()
-                .map_memory(self.memory, 0, self.size, MemoryMapFlags::default())
-                .expect("Memory map failed on buffer");
+            let ptr = self.map_whole();
 
             let size = data.len();
 
@@ -75,11 +220,16 @@ impl BufferResource {
                 .handle()
                 .flush_mapped_memory_ranges(&ranges)
                 .expect("Memory flush failed");
-            self.device.handle().unmap_memory(self.memory);
         }
+        self.unmap_whole();
     }
 
-    pub fn copy_aligned_to<T>(&mut self, data: &[T], element_size: Option<usize>, stride: usize) {
+    pub fn copy_aligned_to<T: BufferData>(
+        &mut self,
+        data: &[T],
+        element_size: Option<usize>,
+        stride: usize,
+    ) {
         unsafe {
             let element_size = if let Some(element_size) = element_size {
                 element_size
@@ -116,47 +266,167 @@ impl BufferResource {
         }
     }
 
-    pub fn copy_data<T: Copy>(&self) -> Vec<T> {
-        unsafe {
-            let ptr = self
-                .device
-                .handle()
-                .map_memory(self.memory, 0, self.size, MemoryMapFlags::default())
-                .expect("Memory map failed on buffer") as *mut T;
+    pub fn copy_data<T: BufferData + Copy>(&self) -> Vec<T> {
+        self.map_read::<T>().to_vec()
+    }
 
-            let mut output = Vec::new();
-            let count = (self.content_size as usize / std::mem::size_of::<T>()) as isize;
-            for i in 0..count {
-                output.push(*ptr.offset(i) as T);
-            }
+    // Maps, copies the contents out and unmaps again, see map_read.
+    pub fn download<T: BufferData + Copy>(&self) -> Vec<T> {
+        self.map_read::<T>().to_vec()
+    }
 
-            self.device.handle().unmap_memory(self.memory);
+    // Copies the buffer into a host visible one without waiting, the buffer must stay alive and
+    // unmodified until the returned handle is ready.
+    pub fn read_async<T: BufferData + Copy>(&self, queue: Rc<CommandQueue>) -> PendingRead<T> {
+        assert!(
+            self.usage.contains(BufferUsageFlags::TRANSFER_SRC),
+            "Buffers read back asynchronously need TRANSFER_SRC usage"
+        );
+        let mut readback = Self::new(
+            self.device.clone(),
+            self.content_size as usize,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+            BufferUsageFlags::TRANSFER_DST,
+        );
+
+        let mut command_buffer = CommandBuffer::new(queue);
+        command_buffer.begin();
+        command_buffer.copy_buffer(self, &mut readback);
+        command_buffer.buffer_resource_barrier(
+            &readback,
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::HOST,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::HOST_READ,
+        );
+        PendingRead::new(command_buffer.submit(), readback)
+    }
 
-            output
+    // Non-coherent memory is invalidated first, so device writes made available to the host show up.
+    pub fn map_read<T: BufferData>(&self) -> MappedSlice<'_, T> {
+        self.debug_assert_element_size::<T>();
+        let ptr = self.map_whole();
+        if !self.is_host_coherent() {
+            unsafe {
+                self.device
+                    .handle()
+                    .invalidate_mapped_memory_ranges(&[self.whole_mapped_range()])
+                    .expect("Memory invalidate failed");
+            }
+        }
+        MappedSlice {
+            buffer: self,
+            ptr: ptr as *const T,
+            len: element_count::<T>(self.content_size),
         }
     }
 
-    pub fn read<T>(&self) -> &[T] {
-        unsafe {
-            let ptr = self
-                .device
-                .handle()
-                .map_memory(self.memory, 0, self.size, MemoryMapFlags::default())
-                .expect("Memory map failed on buffer") as *const T;
+    pub fn map_write<T: BufferData>(&mut self) -> MappedSliceMut<'_, T> {
+        self.debug_assert_element_size::<T>();
+        let ptr = self.map_whole();
+        let len = element_count::<T>(self.content_size);
+        MappedSliceMut {
+            buffer: self,
+            ptr: ptr as *mut T,
+            len,
+        }
+    }
 
-            std::slice::from_raw_parts(ptr, self.content_size as usize / size_of::<T>())
+    // Maps on first use, later calls reuse the mapping. Every call is paired with unmap_whole.
+    fn map_whole(&self) -> *mut c_void {
+        assert!(
+            self.memory_flags
+                .contains(MemoryPropertyFlags::HOST_VISIBLE),
+            "Only host visible buffers can be mapped"
+        );
+        if self.map_count.get() == 0 {
+            let ptr = unsafe {
+                self.device
+                    .handle()
+                    .map_memory(
+                        self.memory,
+                        0,
+                        ash::vk::WHOLE_SIZE,
+                        MemoryMapFlags::default(),
+                    )
+                    .expect("Memory map failed on buffer")
+            };
+            self.mapping.set(ptr);
+        }
+        self.map_count.set(self.map_count.get() + 1);
+        self.mapping.get()
+    }
+
+    fn unmap_whole(&self) {
+        debug_assert!(self.map_count.get() > 0, "Buffer memory is not mapped");
+        self.map_count.set(self.map_count.get() - 1);
+        if self.map_count.get() == 0 {
+            unsafe { self.device.handle().unmap_memory(self.memory) }
         }
     }
 
+    fn is_host_coherent(&self) -> bool {
+        self.memory_flags
+            .contains(MemoryPropertyFlags::HOST_COHERENT)
+    }
+
+    // WHOLE_SIZE from offset 0 satisfies the nonCoherentAtomSize alignment rules.
+    fn whole_mapped_range(&self) -> MappedMemoryRange<'static> {
+        MappedMemoryRange::default()
+            .memory(self.memory)
+            .size(ash::vk::WHOLE_SIZE)
+    }
+
+    // The mapping is never released, so the memory stays mapped until the buffer is dropped.
+    #[deprecated(note = "use map_read, which unmaps when the guard is dropped")]
+    pub fn read<T: BufferData>(&self) -> &[T] {
+        self.debug_assert_element_size::<T>();
+        let ptr = self.map_whole() as *const T;
+        unsafe { std::slice::from_raw_parts(ptr, element_count::<T>(self.content_size)) }
+    }
+
     pub fn for_each<T, F>(&self, f: F)
     where
+        T: BufferData,
         F: Fn(&T),
     {
-        self.read().iter().for_each(f);
-        self.flush_all();
-        unsafe {
-            self.device.handle().unmap_memory(self.memory);
-        }
+        self.map_read().iter().for_each(f);
+    }
+
+    // Reading back as a type whose size doesn't divide the contents silently drops the tail.
+    fn debug_assert_element_size<T>(&self) {
+        debug_assert!(
+            size_of::<T>() != 0 && self.content_size.is_multiple_of(size_of::<T>() as u64),
+            "Buffer content size {} is not a multiple of the {} byte element type",
+            self.content_size,
+            size_of::<T>()
+        );
+    }
+
+    fn debug_assert_fits<T>(&self, offset: u64, data: &[T]) {
+        debug_assert!(
+            offset + std::mem::size_of_val(data) as u64 <= self.content_size,
+            "Writing {} bytes at offset {} overflows the {} byte buffer",
+            std::mem::size_of_val(data),
+            offset,
+            self.content_size
+        );
+    }
+
+    // Host visible memory is cleared so shaders never see what a previous allocation left behind.
+    // Device local memory can't be written from here and is left as is.
+    unsafe fn zero_memory(device: &DeviceContext, memory: DeviceMemory, size: u64) {
+        let ptr = device
+            .handle()
+            .map_memory(memory, 0, size, MemoryMapFlags::default())
+            .expect("Memory map failed on buffer");
+        std::ptr::write_bytes(ptr as *mut u8, 0, size as usize);
+        let ranges = [MappedMemoryRange::default().memory(memory).size(size)];
+        device
+            .handle()
+            .flush_mapped_memory_ranges(&ranges)
+            .expect("Memory flush failed");
+        device.handle().unmap_memory(memory);
     }
 }
 
@@ -175,15 +445,14 @@ impl BufferResource {
                 .usage(usage);
 
             let buffer = device
-                .create_buffer(&buffer_info, None)
+                .create_buffer(&buffer_info, device_context.allocation_callbacks())
                 .expect("Buffer creation failed");
             let memory_requirements = device.get_buffer_memory_requirements(buffer);
-            let mut properties = PhysicalDeviceMemoryProperties2::default();
-            device_context.gpu().memory_properties(&mut properties);
+            let memory_properties = device_context.gpu().memory_properties2();
 
             let type_index = memory_type_index(
                 memory_requirements.memory_type_bits,
-                &properties.memory_properties,
+                &memory_properties,
                 property_flags,
             );
 
@@ -197,19 +466,35 @@ impl BufferResource {
                     .memory_type_index(type_index)
                     .allocation_size(memory_requirements.size);
                 let memory = device
-                    .allocate_memory(&allocation_info, None)
+                    .allocate_memory(&allocation_info, device_context.allocation_callbacks())
                     .expect("Memory allocation failed");
+                device_context.notify_allocated(
+                    memory_requirements.size,
+                    type_index,
+                    "BufferResource",
+                );
 
                 device
                     .bind_buffer_memory(buffer, memory, 0)
                     .expect("Buffer memory bind failed");
 
+                let memory_flags =
+                    memory_properties.memory_types[type_index as usize].property_flags;
+                if memory_flags.contains(MemoryPropertyFlags::HOST_VISIBLE) {
+                    Self::zero_memory(&device_context, memory, memory_requirements.size);
+                }
+
                 Self {
                     device: device_context.clone(),
                     buffer,
                     memory,
+                    memory_type: type_index,
+                    memory_flags,
+                    usage,
                     size: memory_requirements.size,
                     content_size: size as _,
+                    mapping: Cell::new(std::ptr::null_mut()),
+                    map_count: Cell::new(0),
                 }
             } else {
                 panic!()
@@ -228,15 +513,214 @@ impl BufferResource {
         )
     }
 
-    pub fn new_host_visible_with_data<T: Sized>(device: Rc<DeviceContext>, data: &[T]) -> Self {
+    pub fn new_vertex_buffer(device: Rc<DeviceContext>, size: usize) -> Self {
+        Self::new(
+            device,
+            size,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            BufferUsageFlags::VERTEX_BUFFER | BufferUsageFlags::TRANSFER_DST,
+        )
+    }
+
+    pub fn new_index_buffer(device: Rc<DeviceContext>, size: usize) -> Self {
+        Self::new(
+            device,
+            size,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            BufferUsageFlags::INDEX_BUFFER | BufferUsageFlags::TRANSFER_DST,
+        )
+    }
+
+    pub fn new_staging(device: Rc<DeviceContext>, size: usize) -> Self {
+        Self::new(
+            device,
+            size,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+            BufferUsageFlags::TRANSFER_SRC,
+        )
+    }
+
+    pub fn new_uniform(device: Rc<DeviceContext>, size: usize) -> Self {
+        Self::new(
+            device,
+            size,
+            MemoryPropertyFlags::HOST_VISIBLE,
+            BufferUsageFlags::UNIFORM_BUFFER | BufferUsageFlags::TRANSFER_DST,
+        )
+    }
+
+    pub fn new_host_visible_with_data<T: BufferData>(
+        device: Rc<DeviceContext>,
+        data: &[T],
+    ) -> Self {
         Self::new_host_visible_storage(device, std::mem::size_of_val(data)).with_data(data)
     }
 
-    pub fn with_data<T>(mut self, data: &[T]) -> Self {
+    // Blocks until the data has arrived. Data larger than DEFAULT_STAGING_BUDGET goes through one
+    // reused staging buffer, a chunk at a time.
+    pub fn new_device_local_with_data<T: BufferData>(
+        device: Rc<DeviceContext>,
+        queue: Rc<CommandQueue>,
+        usage: BufferUsageFlags,
+        data: &[T],
+    ) -> Self {
+        let bytes = as_bytes(data);
+        let mut buffer = Self::new(
+            device.clone(),
+            bytes.len(),
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            usage | BufferUsageFlags::TRANSFER_DST,
+        );
+        let mut staging = Self::new_staging(device, bytes.len().min(DEFAULT_STAGING_BUDGET));
+        for (offset, chunk) in staging_chunks(bytes, DEFAULT_STAGING_BUDGET) {
+            staging.upload(chunk);
+            let mut command_buffer = CommandBuffer::new(queue.clone());
+            command_buffer.begin();
+            let region = BufferCopy::default()
+                .dst_offset(offset)
+                .size(chunk.len() as u64);
+            command_buffer.copy_buffer_regions(&staging, &mut buffer, &[region]);
+            command_buffer.submit().wait();
+        }
+
+        buffer
+    }
+
+    // Doesn't wait, the buffer can't be used before the handle is ready. Stages through at most
+    // DEFAULT_STAGING_BUDGET bytes, see new_device_local_with_data_async_within.
+    #[track_caller]
+    pub fn new_device_local_with_data_async<T: BufferData>(
+        device: Rc<DeviceContext>,
+        queue: Rc<CommandQueue>,
+        usage: BufferUsageFlags,
+        data: &[T],
+    ) -> (Self, WaitHandle) {
+        Self::new_device_local_with_data_async_within(
+            device,
+            queue,
+            usage,
+            data,
+            DEFAULT_STAGING_BUDGET,
+        )
+    }
+
+    // The budget is split over a ring of staging buffers, one submission per chunk. Only data
+    // larger than the budget waits, for the copy out of a slot to complete before refilling it.
+    #[track_caller]
+    pub fn new_device_local_with_data_async_within<T: BufferData>(
+        device: Rc<DeviceContext>,
+        queue: Rc<CommandQueue>,
+        usage: BufferUsageFlags,
+        data: &[T],
+        staging_budget: usize,
+    ) -> (Self, WaitHandle) {
+        let bytes = as_bytes(data);
+        let mut buffer = Self::new(
+            device.clone(),
+            bytes.len(),
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            usage | BufferUsageFlags::TRANSFER_DST,
+        );
+        let slot_size = (staging_budget / STAGING_RING_SLOTS).clamp(1, bytes.len().max(1));
+        let mut slots: Vec<(Self, Option<WaitHandle>)> = Vec::with_capacity(STAGING_RING_SLOTS);
+        let mut chunks = staging_chunks(bytes, slot_size).peekable();
+        let mut index = 0;
+        loop {
+            let slot = index % STAGING_RING_SLOTS;
+            index += 1;
+            let mut command_buffer = CommandBuffer::new(queue.clone());
+            command_buffer.begin();
+            if let Some((offset, chunk)) = chunks.next() {
+                if slot == slots.len() {
+                    slots.push((Self::new_staging(device.clone(), slot_size), None));
+                }
+                let (staging, pending) = &mut slots[slot];
+                if let Some(pending) = pending.take() {
+                    pending.wait();
+                }
+                staging.upload(chunk);
+                let region = BufferCopy::default()
+                    .dst_offset(offset)
+                    .size(chunk.len() as u64);
+                command_buffer.copy_buffer_regions(staging, &mut buffer, &[region]);
+            }
+            if chunks.peek().is_none() {
+                // The last submission's fence covers the earlier ones, the ring is freed with it.
+                for (staging, pending) in slots {
+                    command_buffer.retain(staging);
+                    if let Some(pending) = pending {
+                        command_buffer.retain(pending);
+                    }
+                }
+                return (buffer, command_buffer.submit());
+            }
+            slots[slot].1 = Some(command_buffer.submit());
+        }
+    }
+
+    pub fn new_for_compressed_upload(
+        device: Rc<DeviceContext>,
+        width: u32,
+        height: u32,
+        format: Format,
+        mip_levels: u32,
+    ) -> Self {
+        let block_size = block_size_bytes(format).expect("Not a block compressed format") as usize;
+        let size = (0..mip_levels).fold(0usize, |offset, level| {
+            let (blocks_x, blocks_y) =
+                block_count(format, (width >> level).max(1), (height >> level).max(1));
+            // Every level has to start on a block boundary for BufferImageCopy::buffer_offset.
+            offset.next_multiple_of(block_size) + blocks_x as usize * blocks_y as usize * block_size
+        });
+        Self::new(
+            device,
+            size,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+            BufferUsageFlags::TRANSFER_SRC,
+        )
+    }
+
+    pub fn new_index_buffer_u16(
+        device: Rc<DeviceContext>,
+        queue: Rc<CommandQueue>,
+        indices: &[u16],
+    ) -> Self {
+        Self::new_device_local_with_data(device, queue, BufferUsageFlags::INDEX_BUFFER, indices)
+    }
+
+    pub fn with_data<T: BufferData>(mut self, data: &[T]) -> Self {
         self.upload(data);
         self
     }
 
+    pub fn into_persistent_map<T: BufferData>(self) -> PersistentlyMappedBuffer<T> {
+        self.debug_assert_element_size::<T>();
+        // Never released, PersistentlyMappedBuffer unmaps when it's dropped.
+        let ptr = self.map_whole() as *mut T;
+        let len = element_count::<T>(self.content_size);
+        PersistentlyMappedBuffer {
+            buffer: self,
+            ptr,
+            len,
+        }
+    }
+
+    pub fn memory_flags(&self) -> MemoryPropertyFlags {
+        self.memory_flags
+    }
+
+    pub fn usage(&self) -> BufferUsageFlags {
+        self.usage
+    }
+
+    pub fn valid_for_vertex_binding(&self) -> bool {
+        self.usage.contains(BufferUsageFlags::VERTEX_BUFFER)
+    }
+
+    pub fn valid_for_index_binding(&self) -> bool {
+        self.usage.contains(BufferUsageFlags::INDEX_BUFFER)
+    }
+
     pub fn size(&self) -> u64 {
         self.size
     }
@@ -257,7 +741,35 @@ impl BufferResource {
 
 impl Drop for BufferResource {
     fn drop(&mut self) {
-        unsafe { self.device.handle().free_memory(self.memory, None) }
-        unsafe { self.device.handle().destroy_buffer(self.buffer, None) }
+        unsafe {
+            self.device
+                .handle()
+                .free_memory(self.memory, self.device.allocation_callbacks())
+        }
+        self.device
+            .notify_freed(self.size, self.memory_type, "BufferResource");
+        unsafe {
+            self.device
+                .handle()
+                .destroy_buffer(self.buffer, self.device.allocation_callbacks())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_count_divides_by_the_element_size() {
+        assert_eq!(element_count::<u32>(64), 16);
+        assert_eq!(element_count::<[f32; 4]>(64), 4);
+        assert_eq!(element_count::<u8>(0), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "zero sized")]
+    fn zero_sized_elements_are_rejected() {
+        element_count::<()>(64);
     }
 }
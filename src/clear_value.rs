@@ -0,0 +1,100 @@
+use ash::vk::{ClearColorValue, ClearDepthStencilValue, ClearValue, Format};
+
+use crate::format_info::{is_depth_format, is_integer_format, is_stencil_format};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clear {
+    Color(f32, f32, f32, f32),
+    // For UINT and SINT color attachments, signed values are passed as their bit pattern.
+    ColorU32([u32; 4]),
+    DepthStencil(f32, u32),
+}
+
+impl Clear {
+    pub fn matches_format(&self, format: Format) -> bool {
+        let depth_stencil = is_depth_format(format) || is_stencil_format(format);
+        match self {
+            Self::Color(..) => !depth_stencil && !is_integer_format(format),
+            Self::ColorU32(_) => is_integer_format(format),
+            Self::DepthStencil(..) => depth_stencil,
+        }
+    }
+}
+
+impl From<Clear> for ClearValue {
+    fn from(clear: Clear) -> Self {
+        match clear {
+            Clear::Color(r, g, b, a) => ClearValue {
+                color: ClearColorValue {
+                    float32: [r, g, b, a],
+                },
+            },
+            Clear::ColorU32(values) => ClearValue {
+                color: ClearColorValue { uint32: values },
+            },
+            Clear::DepthStencil(depth, stencil) => ClearValue {
+                depth_stencil: ClearDepthStencilValue { depth, stencil },
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClearError {
+    CountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    FormatMismatch {
+        attachment: usize,
+        format: Format,
+        clear: Clear,
+    },
+}
+
+impl std::fmt::Display for ClearError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CountMismatch { expected, actual } => write!(
+                f,
+                "Render pass has {} attachments but {} clear values were given",
+                expected, actual
+            ),
+            Self::FormatMismatch {
+                attachment,
+                format,
+                clear,
+            } => write!(
+                f,
+                "Clear value {:?} can't be used for attachment {} with format {:?}",
+                clear, attachment, format
+            ),
+        }
+    }
+}
+
+pub fn clear_values(clears: &[Clear], formats: &[Format]) -> Result<Vec<ClearValue>, ClearError> {
+    if clears.len() != formats.len() {
+        return Err(ClearError::CountMismatch {
+            expected: formats.len(),
+            actual: clears.len(),
+        });
+    }
+
+    clears
+        .iter()
+        .zip(formats)
+        .enumerate()
+        .map(|(attachment, (clear, format))| {
+            if clear.matches_format(*format) {
+                Ok((*clear).into())
+            } else {
+                Err(ClearError::FormatMismatch {
+                    attachment,
+                    format: *format,
+                    clear: *clear,
+                })
+            }
+        })
+        .collect()
+}
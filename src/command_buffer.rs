@@ -1,25 +1,230 @@
+use std::any::Any;
+use std::collections::HashSet;
 use std::rc::Rc;
 
 use ash::vk::{
-    AccessFlags, Buffer, BufferImageCopy, BufferMemoryBarrier, ClearColorValue, ClearValue,
-    CommandBufferAllocateInfo, CommandBufferBeginInfo, CommandBufferUsageFlags, DependencyFlags,
-    DescriptorSet, Extent2D, Extent3D, FenceCreateInfo, Filter, Framebuffer, ImageAspectFlags,
-    ImageBlit, ImageLayout, ImageMemoryBarrier, ImageSubresourceLayers, ImageSubresourceRange,
-    Offset3D, PipelineBindPoint, PipelineLayout, PipelineStageFlags, Rect2D, RenderPassBeginInfo,
-    ShaderStageFlags, SubmitInfo, SubpassContents,
+    AccessFlags, AttachmentLoadOp, Buffer, BufferCopy, BufferImageCopy, BufferMemoryBarrier,
+    BufferUsageFlags, ClearColorValue, CommandBufferAllocateInfo, CommandBufferBeginInfo,
+    CommandBufferResetFlags, CommandBufferUsageFlags, CopyAccelerationStructureInfoKHR,
+    CopyAccelerationStructureModeKHR, DependencyFlags, DescriptorSet, DescriptorSetLayout,
+    DescriptorType, Extent2D, Extent3D, Fence, FenceCreateInfo, Filter, Framebuffer,
+    ImageAspectFlags, ImageBlit, ImageLayout, ImageMemoryBarrier, ImageSubresourceLayers,
+    ImageSubresourceRange, ImageUsageFlags, IndexType, MemoryBarrier, Offset3D,
+    PhysicalDeviceLimits, PipelineBindPoint, PipelineLayout, PipelineStageFlags, PushConstantRange,
+    QueryPool, QueryType, Rect2D, RenderPassBeginInfo, Semaphore, ShaderStageFlags, SubmitInfo,
+    SubpassContents, Viewport,
 };
 
+use crate::acceleration_structure::AccelerationStructure;
 use crate::buffer_resource::BufferResource;
+use crate::clear_value::{clear_values, Clear, ClearError};
 use crate::device_context::DeviceContext;
+use crate::format_info::{aspect_mask, block_extent};
+use crate::graphics_pipeline::{validate_line_width, validate_viewport};
+use crate::image2d_resource::Image2DResource;
 use crate::image_resource::ImageResource;
-use crate::pipeline_descriptor::ComputePipeline;
+use crate::indices::SetIndex;
+use crate::mesh::Mesh;
+use crate::pipeline_descriptor::{validate_push_constant_range, ComputePipeline};
 use crate::queue::CommandQueue;
+use crate::swapchain::Swapchain;
 use crate::wait_handle::WaitHandle;
 
-pub struct CommandBuffer {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DispatchError {
+    ExceedsMaxGroupCount {
+        axis: usize,
+        requested: u32,
+        max: u32,
+    },
+    ExceedsMaxWorkGroupSize {
+        axis: usize,
+        requested: u32,
+        max: u32,
+    },
+    ExceedsMaxInvocations {
+        requested: u64,
+        max: u32,
+    },
+}
+
+impl std::fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ExceedsMaxGroupCount {
+                axis,
+                requested,
+                max,
+            } => write!(
+                f,
+                "Dispatch of {} workgroups on axis {} exceeds the limit of {}",
+                requested, axis, max
+            ),
+            Self::ExceedsMaxWorkGroupSize {
+                axis,
+                requested,
+                max,
+            } => write!(
+                f,
+                "Workgroup size {} on axis {} exceeds the limit of {}",
+                requested, axis, max
+            ),
+            Self::ExceedsMaxInvocations { requested, max } => write!(
+                f,
+                "Workgroup of {} invocations exceeds the limit of {}",
+                requested, max
+            ),
+        }
+    }
+}
+
+// The invocation count is computed in u64, the product of three u32 sizes can overflow u32.
+fn validate_dispatch(
+    limits: &PhysicalDeviceLimits,
+    groups: [u32; 3],
+    size: [u32; 3],
+) -> Result<(), DispatchError> {
+    for axis in 0..3 {
+        if groups[axis] > limits.max_compute_work_group_count[axis] {
+            return Err(DispatchError::ExceedsMaxGroupCount {
+                axis,
+                requested: groups[axis],
+                max: limits.max_compute_work_group_count[axis],
+            });
+        }
+        if size[axis] > limits.max_compute_work_group_size[axis] {
+            return Err(DispatchError::ExceedsMaxWorkGroupSize {
+                axis,
+                requested: size[axis],
+                max: limits.max_compute_work_group_size[axis],
+            });
+        }
+    }
+    let invocations = size.iter().map(|&size| size as u64).product::<u64>();
+    if invocations > limits.max_compute_work_group_invocations as u64 {
+        return Err(DispatchError::ExceedsMaxInvocations {
+            requested: invocations,
+            max: limits.max_compute_work_group_invocations,
+        });
+    }
+    Ok(())
+}
+
+// Catches missing transitions and usage flags at record time instead of at submit, debug builds only.
+#[track_caller]
+fn validate_transfer_image(
+    image: &impl ImageResource,
+    command: &str,
+    layout: ImageLayout,
+    usage: ImageUsageFlags,
+) {
+    if cfg!(debug_assertions) {
+        assert!(
+            image.layout() == layout || image.layout() == ImageLayout::GENERAL,
+            "{} on image {} requires layout {:?} or GENERAL but it is in {:?}, transition it first with image_resource_transition",
+            command,
+            image.debug_name(),
+            layout,
+            image.layout()
+        );
+        assert!(
+            image.usage().contains(usage),
+            "{} on image {} requires {:?} usage, the image was created with {:?}",
+            command,
+            image.debug_name(),
+            usage,
+            image.usage()
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandBufferState {
+    NotBegun,
+    Recording,
+    Ended,
+    Submitted,
+}
+
+// A dispatch reading or writing a buffer a previous dispatch wrote as a storage buffer, without a
+// barrier on the buffer in between. Only buffers written to the pipeline's own sets are tracked,
+// readonly storage buffers don't count as writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferHazard {
+    pub buffer: Buffer,
+    pub descriptor_type: DescriptorType,
+    // SHADER_READ and / or SHADER_WRITE, how the later dispatch accesses the buffer.
+    pub access: AccessFlags,
+}
+
+// Everything that records commands. CommandBuffer derefs to it, CommandBuffer::record hands it
+// to a closure so begin, end and submit can't be called out of order.
+pub struct Recorder {
     device: Rc<DeviceContext>,
     queue: Rc<CommandQueue>,
-    handle: Vec<ash::vk::CommandBuffer>,
+    handle: ash::vk::CommandBuffer,
+    state: CommandBufferState,
+    in_render_pass: bool,
+    retained: Vec<Box<dyn Any>>,
+    // Record-time hazard tracking, debug builds only.
+    bound_buffers: Vec<(Buffer, DescriptorType, AccessFlags)>,
+    unsynchronized_writes: HashSet<Buffer>,
+    buffer_hazards: Vec<BufferHazard>,
+    // Layout and set bound at every compute set index, with the push constant ranges of the
+    // pipeline layout they were bound with. Lets bind_compute_pipeline skip compatible sets.
+    bound_compute_sets: Vec<(DescriptorSetLayout, DescriptorSet)>,
+    bound_compute_push_constants: Vec<(u32, u32, u32)>,
+    descriptor_set_bind_count: usize,
+}
+
+fn push_constant_key(ranges: &[PushConstantRange]) -> Vec<(u32, u32, u32)> {
+    ranges
+        .iter()
+        .map(|range| (range.stage_flags.as_raw(), range.offset, range.size))
+        .collect()
+}
+
+pub struct CommandBuffer {
+    recorder: Recorder,
+}
+
+impl std::ops::Deref for CommandBuffer {
+    type Target = Recorder;
+
+    fn deref(&self) -> &Recorder {
+        &self.recorder
+    }
+}
+
+impl std::ops::DerefMut for CommandBuffer {
+    fn deref_mut(&mut self) -> &mut Recorder {
+        &mut self.recorder
+    }
+}
+
+// Ends the render pass when dropped, records into the pass through Deref.
+pub struct RenderPassScope<'a> {
+    recorder: &'a mut Recorder,
+}
+
+impl std::ops::Deref for RenderPassScope<'_> {
+    type Target = Recorder;
+
+    fn deref(&self) -> &Recorder {
+        self.recorder
+    }
+}
+
+impl std::ops::DerefMut for RenderPassScope<'_> {
+    fn deref_mut(&mut self) -> &mut Recorder {
+        self.recorder
+    }
+}
+
+impl Drop for RenderPassScope<'_> {
+    fn drop(&mut self) {
+        self.recorder.record_end_render_pass()
+    }
 }
 
 impl CommandBuffer {
@@ -29,132 +234,546 @@ impl CommandBuffer {
             .command_buffer_count(1)
             .command_pool(queue.pool());
         let handle = unsafe { device.handle().allocate_command_buffers(&info) };
+        Self::from_handle(queue, handle.expect("Command buffer allocation failed")[0])
+    }
+
+    pub(crate) fn from_handle(queue: Rc<CommandQueue>, handle: ash::vk::CommandBuffer) -> Self {
         Self {
-            device,
-            queue,
-            handle: handle.expect("Command buffer allocation failed"),
+            recorder: Recorder {
+                device: queue.device(),
+                queue,
+                handle,
+                state: CommandBufferState::NotBegun,
+                in_render_pass: false,
+                retained: Vec::new(),
+                bound_buffers: Vec::new(),
+                unsynchronized_writes: HashSet::new(),
+                buffer_hazards: Vec::new(),
+                bound_compute_sets: Vec::new(),
+                bound_compute_push_constants: Vec::new(),
+                descriptor_set_bind_count: 0,
+            },
         }
     }
 
-    pub(crate) fn queue(&self) -> Rc<CommandQueue> {
-        self.queue.clone()
+    // One time submit of everything f records.
+    #[track_caller]
+    pub fn record<F: FnOnce(&mut Recorder)>(queue: Rc<CommandQueue>, f: F) -> WaitHandle {
+        let mut command_buffer = Self::new(queue);
+        command_buffer.begin();
+        f(&mut command_buffer);
+        command_buffer.submit()
     }
 
     pub fn begin(&mut self) {
-        let begin_info =
-            CommandBufferBeginInfo::default().flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        self.begin_with_flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+    }
+
+    pub fn begin_reusable(&mut self) {
+        self.begin_with_flags(CommandBufferUsageFlags::empty())
+    }
+
+    pub fn begin_simultaneous(&mut self) {
+        self.begin_with_flags(CommandBufferUsageFlags::SIMULTANEOUS_USE)
+    }
+
+    // Ended buffers may be begun again, which implicitly resets them. That needs a queue created
+    // with CommandQueue::new_resettable.
+    #[track_caller]
+    pub fn begin_with_flags(&mut self, flags: CommandBufferUsageFlags) {
+        debug_assert!(
+            matches!(
+                self.state,
+                CommandBufferState::NotBegun | CommandBufferState::Ended
+            ),
+            "begin called on a command buffer in state {:?}",
+            self.state
+        );
+        debug_assert!(
+            self.state == CommandBufferState::NotBegun || self.queue.is_resettable(),
+            "Recording a command buffer again requires a queue from CommandQueue::new_resettable"
+        );
+        let begin_info = CommandBufferBeginInfo::default().flags(flags);
         unsafe {
             let success = self
                 .device
                 .handle()
-                .begin_command_buffer(self.handle(), &begin_info);
+                .begin_command_buffer(self.handle, &begin_info);
 
             match success {
                 Ok(_) => (),
                 Err(_) => panic!(),
             }
         }
+        self.state = CommandBufferState::Recording;
+        self.bound_buffers.clear();
+        self.unsynchronized_writes.clear();
+        self.buffer_hazards.clear();
+        self.bound_compute_sets.clear();
+        self.descriptor_set_bind_count = 0;
     }
 
-    pub fn record_handle<F>(&mut self, f: F)
-    where
-        F: FnOnce(ash::vk::CommandBuffer) -> ash::vk::CommandBuffer,
-    {
-        self.handle[0] = f(self.handle());
+    // The buffer must not be pending, its resources go back to the pool.
+    #[track_caller]
+    pub fn reset(&mut self) {
+        assert!(
+            self.queue.is_resettable(),
+            "reset requires a queue from CommandQueue::new_resettable"
+        );
+        unsafe {
+            self.device
+                .handle()
+                .reset_command_buffer(self.handle, CommandBufferResetFlags::RELEASE_RESOURCES)
+                .expect("Command buffer reset failed");
+        }
+        self.state = CommandBufferState::NotBegun;
+        self.in_render_pass = false;
+        self.retained.clear();
+        self.bound_buffers.clear();
+        self.unsynchronized_writes.clear();
+        self.buffer_hazards.clear();
     }
 
+    #[track_caller]
     pub fn end(&mut self) {
+        debug_assert!(
+            !self.in_render_pass,
+            "end called while a render pass is still active"
+        );
         unsafe {
-            let success = self.device.handle().end_command_buffer(self.handle());
+            let success = self
+                .device
+                .handle()
+                .end_command_buffer(self.recording_handle());
 
             match success {
                 Ok(_) => (),
                 Err(_) => panic!(),
             }
         }
+        self.state = CommandBufferState::Ended;
     }
 
-    pub fn submit(self) -> WaitHandle {
+    // Ends the command buffer first when it is still recording.
+    #[track_caller]
+    pub fn submit(mut self) -> WaitHandle {
+        let location = std::panic::Location::caller();
+        debug_assert!(
+            matches!(
+                self.state,
+                CommandBufferState::Recording | CommandBufferState::Ended
+            ),
+            "submit called on a command buffer in state {:?}",
+            self.state
+        );
+        assert!(
+            !self.device.is_shut_down(),
+            "submit called after DeviceContext::shutdown"
+        );
+        if self.state == CommandBufferState::Recording {
+            self.end();
+        }
         unsafe {
             let info = FenceCreateInfo::default();
             let fence = self
                 .device
                 .handle()
-                .create_fence(&info, None)
+                .create_fence(&info, self.device.allocation_callbacks())
                 .expect("Fence creation failed");
 
+            let submit_info =
+                SubmitInfo::default().command_buffers(std::slice::from_ref(&self.handle));
             self.device
-                .handle()
-                .end_command_buffer(self.handle())
-                .expect("Command Buffer end failed");
-            let submit_info = SubmitInfo::default().command_buffers(&self.handle);
-            self.device
-                .handle()
-                .queue_submit(self.queue.handle(), &[submit_info], fence)
+                .observe(self.device.handle().queue_submit(
+                    self.queue.handle(),
+                    &[submit_info],
+                    fence,
+                ))
                 .expect("Queue submit failed");
+            self.state = CommandBufferState::Submitted;
+
+            WaitHandle::new(self, fence, location)
+        }
+    }
+
+    // Ends the buffer for a submission made elsewhere, e.g. by SubmissionBatch.
+    #[track_caller]
+    pub(crate) fn prepare_submit(&mut self) {
+        debug_assert!(
+            matches!(
+                self.state,
+                CommandBufferState::Recording | CommandBufferState::Ended
+            ),
+            "submit called on a command buffer in state {:?}",
+            self.state
+        );
+        if self.state == CommandBufferState::Recording {
+            self.end();
+        }
+        self.state = CommandBufferState::Submitted;
+    }
 
-            WaitHandle::new(self, fence)
+    // Submits an already ended command buffer without consuming it, so it can be submitted again.
+    // Buffers begun with begin_simultaneous may be pending more than once at a time.
+    #[track_caller]
+    pub fn submit_reusable(
+        &self,
+        wait_semaphores: &[Semaphore],
+        wait_stages: &[PipelineStageFlags],
+        signal_semaphores: &[Semaphore],
+        fence: Fence,
+    ) {
+        debug_assert_eq!(
+            self.state,
+            CommandBufferState::Ended,
+            "submit_reusable requires an ended command buffer"
+        );
+        let submit_info = SubmitInfo::default()
+            .wait_semaphores(wait_semaphores)
+            .wait_dst_stage_mask(wait_stages)
+            .signal_semaphores(signal_semaphores)
+            .command_buffers(std::slice::from_ref(&self.handle));
+        unsafe {
+            self.device
+                .observe(self.device.handle().queue_submit(
+                    self.queue.handle(),
+                    &[submit_info],
+                    fence,
+                ))
+                .expect("Queue submit failed");
         }
     }
+}
+
+impl CommandBuffer {
+    // The imperative counterparts of the scoped render pass functions on Recorder.
+    pub fn begin_render_pass(
+        &mut self,
+        render_pass: &crate::renderpass::RenderPass,
+        framebuffer: &Framebuffer,
+        width: u32,
+        height: u32,
+        clears: &[Clear],
+    ) -> Result<(), ClearError> {
+        self.recorder
+            .record_begin_render_pass(render_pass, framebuffer, width, height, clears)
+    }
+
+    pub fn begin_swapchain_render_pass(&mut self, swapchain: &Swapchain, frame_index: u32) {
+        self.recorder
+            .record_begin_swapchain_render_pass(swapchain, frame_index)
+    }
+
+    pub fn end_render_pass(&mut self) {
+        self.recorder.record_end_render_pass()
+    }
+}
+
+impl Recorder {
+    pub(crate) fn queue(&self) -> Rc<CommandQueue> {
+        self.queue.clone()
+    }
+
+    pub fn state(&self) -> CommandBufferState {
+        self.state
+    }
+
+    // For commands without a wrapper, f has to return the handle it was given.
+    #[track_caller]
+    pub fn record_handle<F>(&mut self, f: F)
+    where
+        F: FnOnce(ash::vk::CommandBuffer) -> ash::vk::CommandBuffer,
+    {
+        let handle = f(self.recording_handle());
+        debug_assert_eq!(
+            handle, self.handle,
+            "record_handle must return the command buffer handle it was given"
+        );
+    }
+
+    // Keeps a resource alive until the submission of this command buffer has completed.
+    pub fn retain<T: 'static>(&mut self, resource: T) {
+        self.retained.push(Box::new(resource))
+    }
+
+    pub(crate) fn take_retained(&mut self) -> Vec<Box<dyn Any>> {
+        std::mem::take(&mut self.retained)
+    }
 
     pub fn bind_pipeline(&mut self, bind_point: PipelineBindPoint, pipeline: &ash::vk::Pipeline) {
         unsafe {
             self.device
                 .handle()
-                .cmd_bind_pipeline(self.handle(), bind_point, *pipeline);
+                .cmd_bind_pipeline(self.recording_handle(), bind_point, *pipeline);
         }
     }
 
     pub fn bind_compute_pipeline(&mut self, pipeline: &ComputePipeline) {
+        if cfg!(debug_assertions) {
+            self.bound_buffers = pipeline.bound_buffers().collect();
+        }
         unsafe {
             self.device.handle().cmd_bind_pipeline(
-                self.handle(),
+                self.recording_handle(),
                 PipelineBindPoint::COMPUTE,
                 *pipeline.handle(),
             );
+        }
 
+        // Binding a pipeline doesn't disturb bound sets. Sets bound with the same layout as every
+        // set before them, and the same push constant ranges, are compatible and stay bound, so
+        // only the sets from the first difference on are bound again.
+        let push_constants = push_constant_key(pipeline.push_constant_ranges());
+        let sets: Vec<(DescriptorSetLayout, DescriptorSet)> = pipeline
+            .descriptor_set_layouts()
+            .iter()
+            .copied()
+            .zip(pipeline.descriptor_sets().iter().copied())
+            .collect();
+        let first = if push_constants == self.bound_compute_push_constants {
+            sets.iter()
+                .zip(&self.bound_compute_sets)
+                .take_while(|(set, bound)| set == bound)
+                .count()
+        } else {
+            0
+        };
+        // Skipped set numbers have no set, the runs of sets between them are bound one by one.
+        let mut index = first;
+        for run in pipeline.descriptor_sets()[first..].split(|set| *set == DescriptorSet::null()) {
+            if !run.is_empty() {
+                self.cmd_bind_descriptor_sets(
+                    *pipeline.layout(),
+                    PipelineBindPoint::COMPUTE,
+                    index as u32,
+                    run,
+                );
+            }
+            index += run.len() + 1;
+        }
+        self.bound_compute_sets = sets;
+        self.bound_compute_push_constants = push_constants;
+    }
+
+    // How many descriptor sets were bound since begin, bind_compute_pipeline doesn't count the
+    // sets it could skip.
+    pub fn descriptor_set_bind_count(&self) -> usize {
+        self.descriptor_set_bind_count
+    }
+
+    fn cmd_bind_descriptor_sets(
+        &mut self,
+        layout: PipelineLayout,
+        bind_point: PipelineBindPoint,
+        first_set: u32,
+        sets: &[DescriptorSet],
+    ) {
+        unsafe {
             self.device.handle().cmd_bind_descriptor_sets(
-                self.handle(),
-                PipelineBindPoint::COMPUTE,
-                *pipeline.layout(),
-                0,
-                pipeline.descriptor_sets(),
+                self.recording_handle(),
+                bind_point,
+                layout,
+                first_set,
+                sets,
                 &[],
             )
         }
+        self.descriptor_set_bind_count += sets.len();
     }
 
     pub fn dispatch_compute(&mut self, width: u32, height: u32, depth: u32) {
+        if cfg!(debug_assertions) {
+            self.track_buffer_hazards();
+        }
         unsafe {
             self.device
                 .handle()
-                .cmd_dispatch(self.handle(), width, height, depth)
+                .cmd_dispatch(self.recording_handle(), width, height, depth)
+        }
+    }
+
+    // Write after write and read after write are hazards, reads after reads need no barrier.
+    // Storage buffers count as written unless the shader declares them readonly.
+    fn track_buffer_hazards(&mut self) {
+        for (buffer, descriptor_type, access) in &self.bound_buffers {
+            if self.unsynchronized_writes.contains(buffer) {
+                println!(
+                    "Dispatch accesses buffer {:?} as {:?} after a storage write without a barrier, see compute_write_to_uniform_read_barrier",
+                    buffer, descriptor_type
+                );
+                self.buffer_hazards.push(BufferHazard {
+                    buffer: *buffer,
+                    descriptor_type: *descriptor_type,
+                    access: *access,
+                });
+            }
+        }
+        for (buffer, descriptor_type, access) in &self.bound_buffers {
+            if *descriptor_type == DescriptorType::STORAGE_BUFFER
+                && access.contains(AccessFlags::SHADER_WRITE)
+            {
+                self.unsynchronized_writes.insert(*buffer);
+            }
+        }
+    }
+
+    // Sets bound with bind_compute_descriptor_set are unknown to the hazard tracking, crate code
+    // binding its own sets passes their buffers here, after binding the pipeline.
+    pub(crate) fn track_bound_buffers(
+        &mut self,
+        buffers: Vec<(Buffer, DescriptorType, AccessFlags)>,
+    ) {
+        if cfg!(debug_assertions) {
+            self.bound_buffers = buffers;
         }
     }
 
+    // Hazards found while recording so far, always empty in release builds.
+    pub fn buffer_hazards(&self) -> &[BufferHazard] {
+        &self.buffer_hazards
+    }
+
+    pub fn dispatch_compute_validated(
+        &mut self,
+        pipeline: &ComputePipeline,
+        groups_x: u32,
+        groups_y: u32,
+        groups_z: u32,
+    ) -> Result<(), DispatchError> {
+        let (x, y, z) = pipeline.workgroup_size();
+        validate_dispatch(
+            &self.device.gpu().limits(),
+            [groups_x, groups_y, groups_z],
+            [x, y, z],
+        )?;
+        self.dispatch_compute(groups_x, groups_y, groups_z);
+        Ok(())
+    }
+
+    #[deprecated(note = "use bind_descriptor_sets_at, which takes the first set index")]
     pub fn bind_descriptor_sets(
         &mut self,
         layout: &PipelineLayout,
         bind_point: PipelineBindPoint,
         sets: &[DescriptorSet],
     ) {
+        self.bind_descriptor_sets_at(layout, bind_point, 0, sets)
+    }
+
+    pub fn bind_descriptor_sets_at(
+        &mut self,
+        layout: &PipelineLayout,
+        bind_point: PipelineBindPoint,
+        first_set: impl Into<SetIndex>,
+        sets: &[DescriptorSet],
+    ) {
+        // The layouts of the sets aren't known here, nothing bound for compute can be reused.
+        if bind_point == PipelineBindPoint::COMPUTE {
+            self.bound_compute_sets.clear();
+        }
+        self.cmd_bind_descriptor_sets(*layout, bind_point, first_set.into().0, sets)
+    }
+
+    // Binds a single set on top of the ones bound by bind_compute_pipeline.
+    pub fn bind_compute_descriptor_set(
+        &mut self,
+        pipeline: &ComputePipeline,
+        set: impl Into<SetIndex>,
+        descriptor_set: DescriptorSet,
+    ) {
+        let set = set.into().0;
+        self.cmd_bind_descriptor_sets(
+            *pipeline.layout(),
+            PipelineBindPoint::COMPUTE,
+            set,
+            &[descriptor_set],
+        );
+        // Sets below stay bound when they were bound with the pipeline's layouts, higher ones may
+        // have been disturbed.
+        let index = set as usize;
+        let layouts = pipeline.descriptor_set_layouts();
+        let compatible = push_constant_key(pipeline.push_constant_ranges())
+            == self.bound_compute_push_constants
+            && self.bound_compute_sets.len() >= index
+            && self.bound_compute_sets[..index]
+                .iter()
+                .zip(layouts)
+                .all(|((bound, _), layout)| bound == layout);
+        if compatible && index < layouts.len() {
+            self.bound_compute_sets.truncate(index);
+            self.bound_compute_sets
+                .push((layouts[index], descriptor_set));
+        } else {
+            self.bound_compute_sets.clear();
+        }
+    }
+
+    pub fn bind_vertex_buffer(&mut self, first_binding: u32, buffers: &[&BufferResource]) {
+        debug_assert!(
+            buffers
+                .iter()
+                .all(|buffer| buffer.valid_for_vertex_binding()),
+            "Vertex buffers must be created with VERTEX_BUFFER usage"
+        );
+        let handles: Vec<_> = buffers.iter().map(|buffer| buffer.buffer).collect();
+        let offsets = vec![0; buffers.len()];
         unsafe {
-            self.device.handle().cmd_bind_descriptor_sets(
-                self.handle(),
-                bind_point,
-                *layout,
+            self.device.handle().cmd_bind_vertex_buffers(
+                self.recording_handle(),
+                first_binding,
+                &handles,
+                &offsets,
+            )
+        }
+    }
+
+    pub fn bind_index_buffer(&mut self, buffer: &BufferResource, index_type: IndexType) {
+        debug_assert!(
+            buffer.valid_for_index_binding(),
+            "Index buffers must be created with INDEX_BUFFER usage"
+        );
+        debug_assert!(
+            index_type == IndexType::UINT16 || index_type == IndexType::UINT32,
+            "Unsupported index type {:?}",
+            index_type
+        );
+        unsafe {
+            self.device.handle().cmd_bind_index_buffer(
+                self.recording_handle(),
+                buffer.buffer,
                 0,
-                sets,
-                &[],
+                index_type,
             )
         }
     }
 
-    pub fn bind_vertex_buffer(&mut self, first_binding: u32, buffers: &[Buffer]) {
+    pub fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        instance_count: u32,
+        first_instance: u32,
+    ) {
         unsafe {
-            self.device
-                .handle()
-                .cmd_bind_vertex_buffers(self.handle(), first_binding, buffers, &[])
+            self.device.handle().cmd_draw_indexed(
+                self.recording_handle(),
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset,
+                first_instance,
+            )
+        }
+    }
+
+    pub fn draw_mesh(&mut self, mesh: &Mesh) {
+        self.bind_vertex_buffer(0, &[mesh.vertex_buffer()]);
+        if let Some(index_buffer) = mesh.index_buffer() {
+            self.bind_index_buffer(index_buffer, IndexType::UINT32);
+            self.draw_indexed(mesh.index_count(), 0, 0, 1, 0);
+        } else {
+            self.draw_vertices(mesh.vertex_count(), 0, 1, 0);
         }
     }
 
@@ -167,7 +786,7 @@ impl CommandBuffer {
     ) {
         unsafe {
             self.device.handle().cmd_draw(
-                self.handle(),
+                self.recording_handle(),
                 vertex_count,
                 instance_count,
                 first_vertex,
@@ -177,11 +796,18 @@ impl CommandBuffer {
     }
 
     pub(crate) fn handle(&self) -> ash::vk::CommandBuffer {
-        self.handle[0]
+        self.handle
     }
 
-    pub(crate) fn device(&self) -> Rc<DeviceContext> {
-        self.device.clone()
+    // The handle for recording commands, catches recording outside of begin / end.
+    #[track_caller]
+    fn recording_handle(&self) -> ash::vk::CommandBuffer {
+        debug_assert_eq!(
+            self.state,
+            CommandBufferState::Recording,
+            "Commands can only be recorded between begin and end"
+        );
+        self.handle
     }
 
     pub fn buffer_resource_barrier(
@@ -192,6 +818,7 @@ impl CommandBuffer {
         source: AccessFlags,
         destination: AccessFlags,
     ) {
+        self.unsynchronized_writes.remove(&buffer.buffer);
         let barrier = BufferMemoryBarrier::default()
             .buffer(buffer.buffer)
             .size(buffer.size())
@@ -200,7 +827,7 @@ impl CommandBuffer {
 
         unsafe {
             self.device.handle().cmd_pipeline_barrier(
-                self.handle(),
+                self.recording_handle(),
                 producer,
                 consumer,
                 DependencyFlags::BY_REGION,
@@ -211,48 +838,216 @@ impl CommandBuffer {
         }
     }
 
-    pub fn image_resource_transition(
+    // For a buffer written as a storage buffer by a compute pass and read as a uniform buffer after.
+    pub fn compute_write_to_uniform_read_barrier(&mut self, buffer: &BufferResource) {
+        self.buffer_resource_barrier(
+            buffer,
+            PipelineStageFlags::COMPUTE_SHADER,
+            PipelineStageFlags::COMPUTE_SHADER
+                | PipelineStageFlags::VERTEX_SHADER
+                | PipelineStageFlags::FRAGMENT_SHADER,
+            AccessFlags::SHADER_WRITE,
+            AccessFlags::UNIFORM_READ,
+        )
+    }
+
+    // For a buffer written by a compute pass and then read as vertex attributes or pulled from in
+    // the vertex shader, e.g. particles simulated on the GPU.
+    pub fn compute_write_to_vertex_read_barrier(&mut self, buffer: &BufferResource) {
+        self.buffer_resource_barrier(
+            buffer,
+            PipelineStageFlags::COMPUTE_SHADER,
+            PipelineStageFlags::VERTEX_INPUT | PipelineStageFlags::VERTEX_SHADER,
+            AccessFlags::SHADER_WRITE,
+            AccessFlags::VERTEX_ATTRIBUTE_READ | AccessFlags::SHADER_READ,
+        )
+    }
+
+    // Everything recorded or submitted before finishes before anything after starts. An escape hatch
+    // while getting something to work, the narrower barriers are cheaper.
+    pub fn full_pipeline_barrier(&mut self) {
+        self.memory_barrier(
+            PipelineStageFlags::ALL_COMMANDS,
+            PipelineStageFlags::ALL_COMMANDS,
+            AccessFlags::MEMORY_WRITE,
+            AccessFlags::MEMORY_READ | AccessFlags::MEMORY_WRITE,
+        )
+    }
+
+    // Global memory dependency covering every resource, e.g. between dependent compute dispatches.
+    pub fn memory_barrier(
         &mut self,
-        image: &mut impl ImageResource,
-        layout: ImageLayout,
+        producer: PipelineStageFlags,
+        consumer: PipelineStageFlags,
+        source: AccessFlags,
+        destination: AccessFlags,
+    ) {
+        self.unsynchronized_writes.clear();
+        let barrier = MemoryBarrier::default()
+            .src_access_mask(source)
+            .dst_access_mask(destination);
+
+        unsafe {
+            self.device.handle().cmd_pipeline_barrier(
+                self.recording_handle(),
+                producer,
+                consumer,
+                DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    // Memory dependency on an image that stays in the same layout, e.g. GENERAL storage images.
+    pub fn image_resource_barrier(
+        &mut self,
+        image: &impl ImageResource,
+        producer: PipelineStageFlags,
+        consumer: PipelineStageFlags,
+        source: AccessFlags,
+        destination: AccessFlags,
     ) {
         let barrier = ImageMemoryBarrier::default()
             .old_layout(image.layout())
-            .new_layout(layout)
+            .new_layout(image.layout())
+            .src_access_mask(source)
+            .dst_access_mask(destination)
             .image(image.handle())
             .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
             .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
             .subresource_range(
                 ImageSubresourceRange::default()
-                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .aspect_mask(aspect_mask(image.format()))
                     .layer_count(1)
-                    .level_count(1),
+                    .level_count(image.mip_levels()),
             );
 
         unsafe {
             self.device.handle().cmd_pipeline_barrier(
-                self.handle(),
+                self.recording_handle(),
+                producer,
+                consumer,
+                DependencyFlags::BY_REGION,
+                &[],
+                &[],
+                &[barrier],
+            );
+        }
+    }
+
+    pub fn image_resource_transition(
+        &mut self,
+        image: &mut impl ImageResource,
+        layout: ImageLayout,
+    ) {
+        let aspect = aspect_mask(image.format());
+        self.image_resource_aspect_transition(image, aspect, layout)
+    }
+
+    // Transitioning only the depth or the stencil aspect of a combined depth/stencil image
+    // needs the Vulkan 1.2 separate_depth_stencil_layouts feature.
+    pub fn image_resource_aspect_transition(
+        &mut self,
+        image: &mut impl ImageResource,
+        aspect: ImageAspectFlags,
+        layout: ImageLayout,
+    ) {
+        let full_aspect = aspect_mask(image.format());
+        assert!(
+            full_aspect.contains(aspect),
+            "Image with format {:?} has no {:?} aspect",
+            image.format(),
+            aspect
+        );
+        if aspect != full_aspect {
+            assert!(
+                self.device
+                    .enabled_vulkan12_features()
+                    .separate_depth_stencil_layouts
+                    != 0,
+                "Separate depth and stencil transitions need the separate_depth_stencil_layouts feature, enable it through Gpu::device_context_builder"
+            );
+        }
+
+        // Depth and stencil may be in different layouts, each needs its own barrier then.
+        let aspects = if aspect == ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL
+            && image.aspect_layout(ImageAspectFlags::DEPTH)
+                != image.aspect_layout(ImageAspectFlags::STENCIL)
+        {
+            vec![ImageAspectFlags::DEPTH, ImageAspectFlags::STENCIL]
+        } else {
+            vec![aspect]
+        };
+        let barriers: Vec<ImageMemoryBarrier> = aspects
+            .iter()
+            .map(|aspect| {
+                ImageMemoryBarrier::default()
+                    .old_layout(image.aspect_layout(*aspect))
+                    .new_layout(layout)
+                    .image(image.handle())
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(
+                        ImageSubresourceRange::default()
+                            .aspect_mask(*aspect)
+                            .layer_count(1)
+                            // The tracked layout covers every mip level.
+                            .level_count(image.mip_levels()),
+                    )
+            })
+            .collect();
+
+        unsafe {
+            self.device.handle().cmd_pipeline_barrier(
+                self.recording_handle(),
                 PipelineStageFlags::ALL_COMMANDS,
                 PipelineStageFlags::ALL_COMMANDS,
                 DependencyFlags::BY_REGION,
                 &[],
                 &[],
-                &[barrier],
+                &barriers,
             );
         }
 
-        image.set_layout(layout);
+        image.set_aspect_layout(aspect, layout);
     }
 
+    #[track_caller]
     pub fn blit(&mut self, src: &impl ImageResource, dst: &mut impl ImageResource) {
+        validate_transfer_image(
+            src,
+            "blit",
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageUsageFlags::TRANSFER_SRC,
+        );
+        validate_transfer_image(
+            dst,
+            "blit",
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageUsageFlags::TRANSFER_DST,
+        );
         let regions = [ImageBlit::default()
             .dst_subresource(
                 ImageSubresourceLayers::default()
                     .aspect_mask(ImageAspectFlags::COLOR)
                     .layer_count(1),
             )
-            .dst_offsets([Offset3D::default(), Offset3D::default().z(1)])
-            .src_offsets([Offset3D::default(), Offset3D::default().z(1)])
+            .dst_offsets([
+                Offset3D::default(),
+                Offset3D::default()
+                    .x(dst.width() as _)
+                    .y(dst.height() as _)
+                    .z(1),
+            ])
+            .src_offsets([
+                Offset3D::default(),
+                Offset3D::default()
+                    .x(src.width() as _)
+                    .y(src.height() as _)
+                    .z(1),
+            ])
             .src_subresource(
                 ImageSubresourceLayers::default()
                     .aspect_mask(ImageAspectFlags::COLOR)
@@ -260,7 +1055,7 @@ impl CommandBuffer {
             )];
         unsafe {
             self.device.handle().cmd_blit_image(
-                self.handle(),
+                self.recording_handle(),
                 src.handle(),
                 src.layout(),
                 dst.handle(),
@@ -271,6 +1066,237 @@ impl CommandBuffer {
         }
     }
 
+    // Fills every level below 0 by repeatedly blitting a level into the next, halving the extent
+    // each step. All levels end up in SHADER_READ_ONLY_OPTIMAL. The format has to support linear
+    // filtered blits.
+    #[track_caller]
+    pub fn generate_mipmaps(&mut self, image: &mut Image2DResource) {
+        if cfg!(debug_assertions) {
+            let usage = ImageUsageFlags::TRANSFER_SRC | ImageUsageFlags::TRANSFER_DST;
+            assert!(
+                image.usage().contains(usage),
+                "generate_mipmaps on image {} requires {:?} usage, the image was created with {:?}",
+                image.debug_name(),
+                usage,
+                image.usage()
+            );
+        }
+        self.image_resource_transition(image, ImageLayout::TRANSFER_DST_OPTIMAL);
+
+        let level_barrier = |level: u32,
+                             old_layout: ImageLayout,
+                             new_layout: ImageLayout,
+                             source: AccessFlags,
+                             destination: AccessFlags| {
+            ImageMemoryBarrier::default()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(source)
+                .dst_access_mask(destination)
+                .image(image.handle())
+                .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(
+                    ImageSubresourceRange::default()
+                        .aspect_mask(ImageAspectFlags::COLOR)
+                        .base_mip_level(level)
+                        .level_count(1)
+                        .layer_count(1),
+                )
+        };
+
+        let mut width = image.width() as i32;
+        let mut height = image.height() as i32;
+        for level in 1..image.mip_levels() {
+            let source = level - 1;
+            let to_source = level_barrier(
+                source,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                AccessFlags::TRANSFER_WRITE,
+                AccessFlags::TRANSFER_READ,
+            );
+            let next_width = (width / 2).max(1);
+            let next_height = (height / 2).max(1);
+            let regions = [ImageBlit::default()
+                .src_subresource(
+                    ImageSubresourceLayers::default()
+                        .aspect_mask(ImageAspectFlags::COLOR)
+                        .mip_level(source)
+                        .layer_count(1),
+                )
+                .src_offsets([
+                    Offset3D::default(),
+                    Offset3D::default().x(width).y(height).z(1),
+                ])
+                .dst_subresource(
+                    ImageSubresourceLayers::default()
+                        .aspect_mask(ImageAspectFlags::COLOR)
+                        .mip_level(level)
+                        .layer_count(1),
+                )
+                .dst_offsets([
+                    Offset3D::default(),
+                    Offset3D::default().x(next_width).y(next_height).z(1),
+                ])];
+            // The source level is done once the blit has read it.
+            let to_shader = level_barrier(
+                source,
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                AccessFlags::TRANSFER_READ,
+                AccessFlags::SHADER_READ,
+            );
+            unsafe {
+                self.device.handle().cmd_pipeline_barrier(
+                    self.recording_handle(),
+                    PipelineStageFlags::TRANSFER,
+                    PipelineStageFlags::TRANSFER,
+                    DependencyFlags::BY_REGION,
+                    &[],
+                    &[],
+                    &[to_source],
+                );
+                self.device.handle().cmd_blit_image(
+                    self.recording_handle(),
+                    image.handle(),
+                    ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image.handle(),
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                    Filter::LINEAR,
+                );
+                self.device.handle().cmd_pipeline_barrier(
+                    self.recording_handle(),
+                    PipelineStageFlags::TRANSFER,
+                    PipelineStageFlags::ALL_COMMANDS,
+                    DependencyFlags::BY_REGION,
+                    &[],
+                    &[],
+                    &[to_shader],
+                );
+            }
+            width = next_width;
+            height = next_height;
+        }
+
+        // The last level was only written to.
+        let last = level_barrier(
+            image.mip_levels() - 1,
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::SHADER_READ,
+        );
+        unsafe {
+            self.device.handle().cmd_pipeline_barrier(
+                self.recording_handle(),
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::ALL_COMMANDS,
+                DependencyFlags::BY_REGION,
+                &[],
+                &[],
+                &[last],
+            );
+        }
+        image.set_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    }
+
+    // Needs TRANSFER_DST swapchain images, which surfaces don't have to support. Without it, draw
+    // src in the swapchain render pass instead, e.g. as a fullscreen triangle.
+    pub fn blit_to_swapchain(
+        &mut self,
+        src: &Image2DResource,
+        swapchain: &mut Swapchain,
+        frame_index: u32,
+    ) {
+        let width = swapchain.physical_width();
+        let height = swapchain.physical_height();
+        let image = swapchain.image_mut(frame_index);
+        // The blit below starts from UNDEFINED, a LOAD render pass after it must keep the result.
+        image.take_first_use();
+        assert!(
+            image.usage().contains(ImageUsageFlags::TRANSFER_DST),
+            "The surface doesn't support TRANSFER_DST swapchain images, copy in a render pass instead"
+        );
+        let range = ImageSubresourceRange::default()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .layer_count(1)
+            .level_count(1);
+
+        // The blit overwrites the whole image, so its previous contents can be discarded. A freshly
+        // acquired image may not have been used yet and still be UNDEFINED.
+        let to_transfer = ImageMemoryBarrier::default()
+            .old_layout(ImageLayout::UNDEFINED)
+            .new_layout(ImageLayout::TRANSFER_DST_OPTIMAL)
+            .src_access_mask(AccessFlags::empty())
+            .dst_access_mask(AccessFlags::TRANSFER_WRITE)
+            .image(image.handle())
+            .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(range);
+
+        let layers = ImageSubresourceLayers::default()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .layer_count(1);
+        let regions = [ImageBlit::default()
+            .src_subresource(layers)
+            .src_offsets([
+                Offset3D::default(),
+                Offset3D::default()
+                    .x(src.width() as _)
+                    .y(src.height() as _)
+                    .z(1),
+            ])
+            .dst_subresource(layers)
+            .dst_offsets([
+                Offset3D::default(),
+                Offset3D::default().x(width as _).y(height as _).z(1),
+            ])];
+
+        let to_present = ImageMemoryBarrier::default()
+            .old_layout(ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(ImageLayout::PRESENT_SRC_KHR)
+            .src_access_mask(AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(AccessFlags::MEMORY_READ)
+            .image(image.handle())
+            .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(range);
+
+        unsafe {
+            self.device.handle().cmd_pipeline_barrier(
+                self.recording_handle(),
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::TRANSFER,
+                DependencyFlags::BY_REGION,
+                &[],
+                &[],
+                &[to_transfer],
+            );
+            self.device.handle().cmd_blit_image(
+                self.recording_handle(),
+                src.handle(),
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image.handle(),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+                Filter::LINEAR,
+            );
+            self.device.handle().cmd_pipeline_barrier(
+                self.recording_handle(),
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::BOTTOM_OF_PIPE,
+                DependencyFlags::BY_REGION,
+                &[],
+                &[],
+                &[to_present],
+            );
+        }
+
+        image.set_layout(ImageLayout::PRESENT_SRC_KHR);
+    }
+
     pub fn color_image_transition(
         &mut self,
         image: &ash::vk::Image,
@@ -292,7 +1318,7 @@ impl CommandBuffer {
 
         unsafe {
             self.device.handle().cmd_pipeline_barrier(
-                self.handle(),
+                self.recording_handle(),
                 PipelineStageFlags::ALL_COMMANDS,
                 PipelineStageFlags::ALL_COMMANDS,
                 DependencyFlags::BY_REGION,
@@ -303,7 +1329,14 @@ impl CommandBuffer {
         }
     }
 
+    #[track_caller]
     pub fn clear_image(&mut self, image: &mut impl ImageResource, r: f32, g: f32, b: f32, a: f32) {
+        validate_transfer_image(
+            image,
+            "clear_image",
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageUsageFlags::TRANSFER_DST,
+        );
         unsafe {
             let value = ClearColorValue {
                 float32: [r, g, b, a],
@@ -313,20 +1346,27 @@ impl CommandBuffer {
                 .level_count(1)
                 .aspect_mask(ImageAspectFlags::COLOR)];
             self.device.handle().cmd_clear_color_image(
-                self.handle(),
+                self.recording_handle(),
                 image.handle(),
-                ImageLayout::GENERAL,
+                image.layout(),
                 &value,
                 &range,
             )
         }
     }
 
+    #[track_caller]
     pub fn copy_image_to_buffer(
         &mut self,
         image: &impl ImageResource,
         buffer: &mut BufferResource,
     ) {
+        validate_transfer_image(
+            image,
+            "copy_image_to_buffer",
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageUsageFlags::TRANSFER_SRC,
+        );
         let layer_info = ImageSubresourceLayers::default()
             .layer_count(1)
             .aspect_mask(ImageAspectFlags::COLOR);
@@ -341,7 +1381,57 @@ impl CommandBuffer {
 
         unsafe {
             self.device.handle().cmd_copy_image_to_buffer(
-                self.handle(),
+                self.recording_handle(),
+                image.handle(),
+                image.layout(),
+                buffer.buffer,
+                &copy,
+            )
+        }
+    }
+
+    // Copies a rectangle of the image tightly packed to the start of the buffer.
+    #[track_caller]
+    pub fn copy_image_region_to_buffer(
+        &mut self,
+        image: &impl ImageResource,
+        buffer: &mut BufferResource,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) {
+        validate_transfer_image(
+            image,
+            "copy_image_region_to_buffer",
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageUsageFlags::TRANSFER_SRC,
+        );
+        assert!(
+            x >= 0
+                && y >= 0
+                && x as u32 + width <= image.width()
+                && y as u32 + height <= image.height(),
+            "Region {}x{} at ({}, {}) is outside of the {}x{} image",
+            width,
+            height,
+            x,
+            y,
+            image.width(),
+            image.height()
+        );
+        let copy = [BufferImageCopy::default()
+            .image_offset(Offset3D::default().x(x).y(y))
+            .image_extent(Extent3D::default().width(width).height(height).depth(1))
+            .image_subresource(
+                ImageSubresourceLayers::default()
+                    .layer_count(1)
+                    .aspect_mask(aspect_mask(image.format())),
+            )];
+
+        unsafe {
+            self.device.handle().cmd_copy_image_to_buffer(
+                self.recording_handle(),
                 image.handle(),
                 image.layout(),
                 buffer.buffer,
@@ -350,15 +1440,77 @@ impl CommandBuffer {
         }
     }
 
+    // Copies as much as fits, min(src.content_size(), dst.content_size()) bytes from the start.
+    #[track_caller]
+    pub fn copy_buffer(&mut self, src: &BufferResource, dst: &mut BufferResource) {
+        debug_assert!(
+            dst.content_size() >= src.content_size(),
+            "copy_buffer destination holds {} bytes, the source {}",
+            dst.content_size(),
+            src.content_size()
+        );
+        let size = src.content_size().min(dst.content_size());
+        self.copy_buffer_regions(src, dst, &[BufferCopy::default().size(size)])
+    }
+
+    #[track_caller]
+    pub fn copy_buffer_regions(
+        &mut self,
+        src: &BufferResource,
+        dst: &mut BufferResource,
+        regions: &[BufferCopy],
+    ) {
+        if cfg!(debug_assertions) {
+            assert!(
+                src.usage().contains(BufferUsageFlags::TRANSFER_SRC),
+                "copy_buffer source requires TRANSFER_SRC usage, the buffer was created with {:?}",
+                src.usage()
+            );
+            assert!(
+                dst.usage().contains(BufferUsageFlags::TRANSFER_DST),
+                "copy_buffer destination requires TRANSFER_DST usage, the buffer was created with {:?}",
+                dst.usage()
+            );
+            for region in regions {
+                assert!(
+                    region.src_offset + region.size <= src.size()
+                        && region.dst_offset + region.size <= dst.size(),
+                    "Copy of {} bytes from offset {} to offset {} is out of bounds",
+                    region.size,
+                    region.src_offset,
+                    region.dst_offset
+                );
+            }
+        }
+        unsafe {
+            self.device.handle().cmd_copy_buffer(
+                self.recording_handle(),
+                src.buffer,
+                dst.buffer,
+                regions,
+            )
+        }
+    }
+
+    #[track_caller]
     pub fn copy_buffer_to_image(
         &mut self,
         buffer: &BufferResource,
         image: &mut impl ImageResource,
     ) {
+        validate_transfer_image(
+            image,
+            "copy_buffer_to_image",
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageUsageFlags::TRANSFER_DST,
+        );
         let layer_info = ImageSubresourceLayers::default()
             .layer_count(1)
             .aspect_mask(ImageAspectFlags::COLOR);
+        let (block_width, block_height) = block_extent(image.format());
         let copy = [BufferImageCopy::default()
+            .buffer_row_length(image.width().next_multiple_of(block_width))
+            .buffer_image_height(image.height().next_multiple_of(block_height))
             .image_extent(
                 Extent3D::default()
                     .width(image.width())
@@ -369,7 +1521,59 @@ impl CommandBuffer {
 
         unsafe {
             self.device.handle().cmd_copy_buffer_to_image(
-                self.handle(),
+                self.recording_handle(),
+                buffer.buffer,
+                image.handle(),
+                image.layout(),
+                &copy,
+            )
+        }
+    }
+
+    // Copies tightly packed rows from the start of the buffer into a region of the image.
+    #[track_caller]
+    pub fn copy_buffer_to_image_region(
+        &mut self,
+        buffer: &BufferResource,
+        image: &mut impl ImageResource,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) {
+        validate_transfer_image(
+            image,
+            "copy_buffer_to_image_region",
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageUsageFlags::TRANSFER_DST,
+        );
+        assert!(
+            x >= 0
+                && y >= 0
+                && x as u32 + width <= image.width()
+                && y as u32 + height <= image.height(),
+            "Region {}x{} at ({}, {}) is outside of the {}x{} image",
+            width,
+            height,
+            x,
+            y,
+            image.width(),
+            image.height()
+        );
+        let copy = [BufferImageCopy::default()
+            .buffer_row_length(width)
+            .buffer_image_height(height)
+            .image_offset(Offset3D::default().x(x).y(y))
+            .image_extent(Extent3D::default().width(width).height(height).depth(1))
+            .image_subresource(
+                ImageSubresourceLayers::default()
+                    .layer_count(1)
+                    .aspect_mask(aspect_mask(image.format())),
+            )];
+
+        unsafe {
+            self.device.handle().cmd_copy_buffer_to_image(
+                self.recording_handle(),
                 buffer.buffer,
                 image.handle(),
                 image.layout(),
@@ -378,34 +1582,218 @@ impl CommandBuffer {
         }
     }
 
+    #[track_caller]
+    pub fn copy_buffer_to_image_layers(
+        &mut self,
+        buffer: &BufferResource,
+        image: &mut impl ImageResource,
+        layer_data: &[(u64, u32)],
+    ) {
+        validate_transfer_image(
+            image,
+            "copy_buffer_to_image_layers",
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageUsageFlags::TRANSFER_DST,
+        );
+        let (block_width, block_height) = block_extent(image.format());
+        let copies: Vec<BufferImageCopy> = layer_data
+            .iter()
+            .map(|(buffer_offset, array_layer)| {
+                BufferImageCopy::default()
+                    .buffer_offset(*buffer_offset)
+                    .buffer_row_length(image.width().next_multiple_of(block_width))
+                    .buffer_image_height(image.height().next_multiple_of(block_height))
+                    .image_extent(
+                        Extent3D::default()
+                            .width(image.width())
+                            .height(image.height())
+                            .depth(image.depth()),
+                    )
+                    .image_subresource(
+                        ImageSubresourceLayers::default()
+                            .base_array_layer(*array_layer)
+                            .layer_count(1)
+                            .aspect_mask(ImageAspectFlags::COLOR),
+                    )
+            })
+            .collect();
+
+        unsafe {
+            self.device.handle().cmd_copy_buffer_to_image(
+                self.recording_handle(),
+                buffer.buffer,
+                image.handle(),
+                image.layout(),
+                &copies,
+            )
+        }
+    }
+
+    pub fn copy_acceleration_structure(
+        &mut self,
+        src: &AccelerationStructure,
+        dst: &mut AccelerationStructure,
+        mode: CopyAccelerationStructureModeKHR,
+    ) {
+        let info = CopyAccelerationStructureInfoKHR::default()
+            .src(src.handle())
+            .dst(dst.handle())
+            .mode(mode);
+        unsafe {
+            src.loader()
+                .cmd_copy_acceleration_structure(self.recording_handle(), &info)
+        }
+    }
+
+    pub fn write_acceleration_structure_properties(
+        &mut self,
+        src: &AccelerationStructure,
+        query_type: QueryType,
+        pool: &QueryPool,
+        first_query: u32,
+    ) {
+        unsafe {
+            self.device.handle().cmd_reset_query_pool(
+                self.recording_handle(),
+                *pool,
+                first_query,
+                1,
+            );
+            src.loader().cmd_write_acceleration_structures_properties(
+                self.recording_handle(),
+                &[src.handle()],
+                query_type,
+                *pool,
+                first_query,
+            )
+        }
+    }
+
     pub fn begin_render_pass(
         &mut self,
         render_pass: &crate::renderpass::RenderPass,
         framebuffer: &Framebuffer,
         width: u32,
         height: u32,
-    ) {
+        clears: &[Clear],
+    ) -> Result<RenderPassScope<'_>, ClearError> {
+        self.record_begin_render_pass(render_pass, framebuffer, width, height, clears)?;
+        Ok(RenderPassScope { recorder: self })
+    }
+
+    pub fn begin_swapchain_render_pass(
+        &mut self,
+        swapchain: &Swapchain,
+        frame_index: u32,
+    ) -> RenderPassScope<'_> {
+        self.record_begin_swapchain_render_pass(swapchain, frame_index);
+        RenderPassScope { recorder: self }
+    }
+
+    #[track_caller]
+    fn record_begin_render_pass(
+        &mut self,
+        render_pass: &crate::renderpass::RenderPass,
+        framebuffer: &Framebuffer,
+        width: u32,
+        height: u32,
+        clears: &[Clear],
+    ) -> Result<(), ClearError> {
+        let clear_values = clear_values(clears, &render_pass.attachment_formats())?;
         let info = RenderPassBeginInfo::default()
             .render_pass(*render_pass.handle())
-            .clear_values(&[ClearValue {
-                color: ClearColorValue {
-                    float32: [0.0, 1.0, 0.0, 1.0],
-                },
-            }])
+            .clear_values(&clear_values)
             .render_area(Rect2D::default().extent(Extent2D::default().width(width).height(height)))
             .framebuffer(*framebuffer);
 
         unsafe {
             self.device.handle().cmd_begin_render_pass(
-                self.handle(),
+                self.recording_handle(),
                 &info,
                 SubpassContents::INLINE,
             )
         }
+        self.set_in_render_pass(true);
+        Ok(())
     }
 
-    pub fn end_render_pass(&mut self) {
-        unsafe { self.device.handle().cmd_end_render_pass(self.handle()) }
+    #[track_caller]
+    fn record_begin_swapchain_render_pass(&mut self, swapchain: &Swapchain, frame_index: u32) {
+        let image = swapchain.image(frame_index);
+        if swapchain.resolved_config().color_load_op == AttachmentLoadOp::LOAD
+            && image.take_first_use()
+        {
+            self.color_image_transition(
+                &image.handle(),
+                ImageLayout::UNDEFINED,
+                ImageLayout::PRESENT_SRC_KHR,
+            );
+        }
+        let clear_values = swapchain.clear_values();
+        let info = RenderPassBeginInfo::default()
+            .render_pass(*swapchain.render_pass())
+            .clear_values(&clear_values)
+            .render_area(
+                Rect2D::default().extent(
+                    Extent2D::default()
+                        .width(swapchain.physical_width())
+                        .height(swapchain.physical_height()),
+                ),
+            )
+            .framebuffer(swapchain.framebuffer(frame_index));
+
+        unsafe {
+            self.device.handle().cmd_begin_render_pass(
+                self.recording_handle(),
+                &info,
+                SubpassContents::INLINE,
+            )
+        }
+        self.set_in_render_pass(true);
+    }
+
+    #[track_caller]
+    fn record_end_render_pass(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .cmd_end_render_pass(self.recording_handle())
+        }
+        self.set_in_render_pass(false);
+    }
+
+    #[track_caller]
+    fn set_in_render_pass(&mut self, active: bool) {
+        debug_assert!(
+            self.in_render_pass != active,
+            "{}",
+            if active {
+                "Render pass begun while another one is still active"
+            } else {
+                "end_render_pass called without an active render pass"
+            }
+        );
+        self.in_render_pass = active;
+    }
+
+    // Needs a pipeline with dynamic LINE_WIDTH state.
+    pub fn set_line_width(&mut self, width: f32) {
+        validate_line_width(&self.device, width);
+        unsafe {
+            self.device
+                .handle()
+                .cmd_set_line_width(self.recording_handle(), width)
+        }
+    }
+
+    // Needs a pipeline with dynamic viewport state, negative heights flip Y.
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        validate_viewport(&self.device, &viewport);
+        unsafe {
+            self.device
+                .handle()
+                .cmd_set_viewport(self.recording_handle(), 0, &[viewport])
+        }
     }
 
     pub fn push_compute_constants<T: Sized + Copy>(
@@ -415,14 +1803,84 @@ impl CommandBuffer {
         constants: &T,
     ) {
         let array = [*constants];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(array.as_ptr() as *const u8, std::mem::size_of::<T>())
+        };
+        self.push_compute_constants_bytes(pipeline, offset, bytes)
+    }
+
+    pub fn push_compute_constants_bytes(
+        &mut self,
+        pipeline: &ComputePipeline,
+        offset: u32,
+        bytes: &[u8],
+    ) {
+        validate_push_constant_range(pipeline.push_constant_ranges(), offset, bytes.len() as _);
         unsafe {
             self.device.handle().cmd_push_constants(
-                self.handle(),
+                self.recording_handle(),
                 *pipeline.layout(),
                 ShaderStageFlags::COMPUTE,
                 offset,
-                std::slice::from_raw_parts(array.as_ptr() as *const u8, std::mem::size_of::<T>()),
+                bytes,
             )
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> PhysicalDeviceLimits {
+        PhysicalDeviceLimits {
+            max_compute_work_group_count: [65535; 3],
+            max_compute_work_group_size: [u32::MAX; 3],
+            max_compute_work_group_invocations: 1024,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dispatch_within_limits() {
+        assert_eq!(
+            validate_dispatch(&limits(), [65535, 1, 1], [1024, 1, 1]),
+            Ok(())
+        );
+        assert_eq!(validate_dispatch(&limits(), [1, 1, 1], [8, 8, 16]), Ok(()));
+    }
+
+    #[test]
+    fn dispatch_over_limits() {
+        assert_eq!(
+            validate_dispatch(&limits(), [1, 65536, 1], [1, 1, 1]),
+            Err(DispatchError::ExceedsMaxGroupCount {
+                axis: 1,
+                requested: 65536,
+                max: 65535
+            })
+        );
+        let mut small = limits();
+        small.max_compute_work_group_size = [1024, 1024, 64];
+        assert_eq!(
+            validate_dispatch(&small, [1, 1, 1], [1, 1, 65]),
+            Err(DispatchError::ExceedsMaxWorkGroupSize {
+                axis: 2,
+                requested: 65,
+                max: 64
+            })
+        );
+    }
+
+    #[test]
+    fn invocation_count_does_not_overflow() {
+        // 65536^2 wraps to 0 in u32 and would pass the check.
+        assert_eq!(
+            validate_dispatch(&limits(), [1, 1, 1], [65536, 65536, 1]),
+            Err(DispatchError::ExceedsMaxInvocations {
+                requested: 1 << 32,
+                max: 1024
+            })
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::ops::Index;
+use std::rc::Rc;
+
+use ash::vk::{Fence, Framebuffer, PipelineStageFlags, Semaphore, SwapchainKHR};
+
+use crate::command_buffer::CommandBuffer;
+use crate::queue::CommandQueue;
+use crate::swapchain::Swapchain;
+
+type RecordFn = Box<dyn FnMut(&mut CommandBuffer, u32, Framebuffer)>;
+
+// One pre-recorded command buffer per swapchain image, resubmitted every frame.
+// Anything the recorded commands reference has to outlive the set.
+pub struct CommandBufferSet {
+    queue: Rc<CommandQueue>,
+    command_buffers: Vec<CommandBuffer>,
+    recorder: RecordFn,
+    swapchain: SwapchainKHR,
+    generation: u64,
+}
+
+impl CommandBufferSet {
+    pub fn record_per_image<F>(swapchain: &Swapchain, record: F) -> Self
+    where
+        F: FnMut(&mut CommandBuffer, u32, Framebuffer) + 'static,
+    {
+        let mut set = Self {
+            queue: swapchain.queue(),
+            command_buffers: Vec::new(),
+            recorder: Box::new(record),
+            swapchain: SwapchainKHR::null(),
+            generation: 0,
+        };
+        set.record(swapchain);
+        set
+    }
+
+    fn record(&mut self, swapchain: &Swapchain) {
+        self.free();
+        self.command_buffers = (0..swapchain.image_count() as u32)
+            .map(|index| {
+                let mut command_buffer = CommandBuffer::new(self.queue.clone());
+                command_buffer.begin_simultaneous();
+                (self.recorder)(&mut command_buffer, index, swapchain.framebuffer(index));
+                command_buffer.end();
+                command_buffer
+            })
+            .collect();
+        self.swapchain = swapchain.handle();
+        self.generation = swapchain.generation();
+    }
+
+    pub fn is_valid_for(&self, swapchain: &Swapchain) -> bool {
+        self.swapchain == swapchain.handle() && self.generation == swapchain.generation()
+    }
+
+    // Re-records every buffer when the swapchain was recreated since the last recording.
+    pub fn update(&mut self, swapchain: &Swapchain) -> bool {
+        if self.is_valid_for(swapchain) {
+            return false;
+        }
+        self.record(swapchain);
+        true
+    }
+
+    pub fn submit(
+        &self,
+        image_index: u32,
+        wait_semaphore: Semaphore,
+        signal_semaphore: Semaphore,
+        fence: Fence,
+    ) {
+        self[image_index].submit_reusable(
+            &[wait_semaphore],
+            &[PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+            &[signal_semaphore],
+            fence,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.command_buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.command_buffers.is_empty()
+    }
+
+    fn free(&mut self) {
+        if self.command_buffers.is_empty() {
+            return;
+        }
+        let device = self.queue.device();
+        let handles: Vec<ash::vk::CommandBuffer> = self
+            .command_buffers
+            .drain(..)
+            .map(|command_buffer| command_buffer.handle())
+            .collect();
+        unsafe {
+            device
+                .handle()
+                .queue_wait_idle(self.queue.handle())
+                .expect("Queue wait failed");
+            device
+                .handle()
+                .free_command_buffers(self.queue.pool(), &handles);
+        }
+    }
+}
+
+impl Index<u32> for CommandBufferSet {
+    type Output = CommandBuffer;
+
+    fn index(&self, image_index: u32) -> &CommandBuffer {
+        &self.command_buffers[image_index as usize]
+    }
+}
+
+impl Drop for CommandBufferSet {
+    fn drop(&mut self) {
+        self.free()
+    }
+}
@@ -0,0 +1,315 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    AccessFlags, DescriptorSet, Fence, FenceCreateFlags, FenceCreateInfo, Image,
+    PipelineStageFlags, Semaphore,
+};
+
+use crate::buffer_resource::BufferData;
+use crate::command_buffer::CommandBuffer;
+use crate::device_context::DeviceContext;
+use crate::image_resource::ImageResource;
+use crate::pipeline_descriptor::ComputePipeline;
+use crate::queue::CommandQueue;
+use crate::wait_handle::WaitHandle;
+
+struct ComputePass {
+    pipeline: Rc<ComputePipeline>,
+    // Bound on top of the pipeline's own sets, e.g. SharedBinding sets.
+    descriptor_sets: Vec<(u32, DescriptorSet)>,
+    push_constants: Vec<u8>,
+    groups: (u32, u32, u32),
+}
+
+// Shared with the handles submit returns, they can still be polled after the graph is gone.
+struct GraphFence {
+    device: Rc<DeviceContext>,
+    handle: Fence,
+}
+
+impl Drop for GraphFence {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .destroy_fence(self.handle, self.device.allocation_callbacks())
+        }
+    }
+}
+
+// A fixed sequence of compute dispatches recorded once into a reusable command buffer and
+// submitted every frame. Consecutive passes are separated by a compute to compute memory barrier.
+//
+// Push constants are baked into the recording, so changing them re-records the command buffer on
+// the next submit. That costs a few microseconds for a handful of dispatches and keeps the shaders
+// unchanged. A persistently mapped uniform buffer would avoid the re-record, but needs one copy per
+// frame in flight and the shaders would have to read their constants from a uniform block.
+//
+// Descriptor sets used by the recording can't be updated while it is in use, so rewrite them only
+// after resizing referenced images and report the new images with update_image.
+//
+// Re-recording resets the command buffer, so the queue has to come from CommandQueue::new_resettable.
+pub struct ComputeGraph {
+    device: Rc<DeviceContext>,
+    command_buffer: CommandBuffer,
+    fence: Rc<GraphFence>,
+    passes: Vec<ComputePass>,
+    // Handle and extent of every tracked image, a change invalidates the recording.
+    images: Vec<(Image, u32, u32)>,
+    dirty: bool,
+    // The submission id of the recording while it may still be executing.
+    pending: Option<u64>,
+    record_count: usize,
+}
+
+impl ComputeGraph {
+    pub fn new(queue: Rc<CommandQueue>) -> Self {
+        assert!(
+            queue.is_resettable(),
+            "ComputeGraph needs a queue from CommandQueue::new_resettable"
+        );
+        let device = queue.device();
+        // Created signaled so the first submit doesn't wait on a fence that was never submitted.
+        let info = FenceCreateInfo::default().flags(FenceCreateFlags::SIGNALED);
+        let fence = unsafe {
+            device
+                .handle()
+                .create_fence(&info, device.allocation_callbacks())
+                .expect("Fence creation failed")
+        };
+        Self {
+            fence: Rc::new(GraphFence {
+                device: device.clone(),
+                handle: fence,
+            }),
+            device,
+            command_buffer: CommandBuffer::new(queue),
+            passes: Vec::new(),
+            images: Vec::new(),
+            dirty: true,
+            pending: None,
+            record_count: 0,
+        }
+    }
+
+    // Returns the pass index for update_push_constants and set_dispatch_size.
+    pub fn add_pass(
+        &mut self,
+        pipeline: Rc<ComputePipeline>,
+        groups_x: u32,
+        groups_y: u32,
+        groups_z: u32,
+    ) -> usize {
+        self.passes.push(ComputePass {
+            pipeline,
+            descriptor_sets: Vec::new(),
+            push_constants: Vec::new(),
+            groups: (groups_x, groups_y, groups_z),
+        });
+        self.dirty = true;
+        self.passes.len() - 1
+    }
+
+    pub fn bind_descriptor_set(&mut self, pass: usize, set: u32, descriptor_set: DescriptorSet) {
+        let sets = &mut self.passes[pass].descriptor_sets;
+        sets.retain(|(index, _)| *index != set);
+        sets.push((set, descriptor_set));
+        self.dirty = true;
+    }
+
+    pub fn set_dispatch_size(&mut self, pass: usize, groups_x: u32, groups_y: u32, groups_z: u32) {
+        let groups = (groups_x, groups_y, groups_z);
+        if self.passes[pass].groups != groups {
+            self.passes[pass].groups = groups;
+            self.dirty = true;
+        }
+    }
+
+    // Pushed at offset 0, only re-records when the bytes differ from the recorded ones.
+    pub fn update_push_constants<T: BufferData + Copy>(&mut self, pass: usize, constants: &T) {
+        let array = [*constants];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(array.as_ptr() as *const u8, std::mem::size_of::<T>())
+        };
+        self.update_push_constants_bytes(pass, bytes)
+    }
+
+    pub fn update_push_constants_bytes(&mut self, pass: usize, bytes: &[u8]) {
+        if self.passes[pass].push_constants != bytes {
+            self.passes[pass].push_constants = bytes.to_vec();
+            self.dirty = true;
+        }
+    }
+
+    // Returns the slot to report a resized image to with update_image.
+    pub fn track_image(&mut self, image: &impl ImageResource) -> usize {
+        self.images
+            .push((image.handle(), image.width(), image.height()));
+        self.images.len() - 1
+    }
+
+    pub fn update_image(&mut self, slot: usize, image: &impl ImageResource) {
+        let tracked = (image.handle(), image.width(), image.height());
+        if self.images[slot] != tracked {
+            self.images[slot] = tracked;
+            self.dirty = true;
+        }
+    }
+
+    // Forces a re-record on the next submit, e.g. after rewriting descriptor sets.
+    pub fn invalidate(&mut self) {
+        self.dirty = true
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    // How often the command buffer has been recorded, stays at 1 while nothing changes.
+    pub fn record_count(&self) -> usize {
+        self.record_count
+    }
+
+    // The one command buffer every submit reuses.
+    pub fn command_buffer(&self) -> ash::vk::CommandBuffer {
+        self.command_buffer.handle()
+    }
+
+    pub fn fence(&self) -> Fence {
+        self.fence.handle
+    }
+
+    pub fn has_completed(&self) -> bool {
+        unsafe {
+            self.device
+                .observe(
+                    self.device
+                        .handle()
+                        .wait_for_fences(&[self.fence.handle], true, 0),
+                )
+                .is_ok()
+        }
+    }
+
+    pub fn wait(&mut self) {
+        unsafe {
+            self.device
+                .observe(
+                    self.device
+                        .handle()
+                        .wait_for_fences(&[self.fence.handle], true, u64::MAX),
+                )
+                .expect("Wait failed");
+        }
+        if let Some(submission) = self.pending.take() {
+            self.command_buffer.queue().end_submission(submission)
+        }
+    }
+
+    fn record(&mut self) {
+        self.command_buffer.begin_reusable();
+        for (index, pass) in self.passes.iter().enumerate() {
+            if index > 0 {
+                self.command_buffer.memory_barrier(
+                    PipelineStageFlags::COMPUTE_SHADER,
+                    PipelineStageFlags::COMPUTE_SHADER,
+                    AccessFlags::SHADER_WRITE,
+                    AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE,
+                );
+            }
+            self.command_buffer.bind_compute_pipeline(&pass.pipeline);
+            for (set, descriptor_set) in &pass.descriptor_sets {
+                self.command_buffer.bind_compute_descriptor_set(
+                    &pass.pipeline,
+                    *set,
+                    *descriptor_set,
+                );
+            }
+            if !pass.push_constants.is_empty() {
+                self.command_buffer.push_compute_constants_bytes(
+                    &pass.pipeline,
+                    0,
+                    &pass.push_constants,
+                );
+            }
+            let (x, y, z) = pass.groups;
+            self.command_buffer.dispatch_compute(x, y, z);
+        }
+        self.command_buffer.end();
+        self.dirty = false;
+        self.record_count += 1;
+    }
+
+    // Waits for the previous frame's submission first, the recording is never pending twice.
+    // Every wait semaphore is waited on in the compute shader stage. The handle waits on the
+    // graph's fence, dropping it doesn't block.
+    #[track_caller]
+    pub fn submit(
+        &mut self,
+        wait_semaphores: &[Semaphore],
+        signal_semaphores: &[Semaphore],
+    ) -> WaitHandle {
+        let location = std::panic::Location::caller();
+        assert!(
+            !self.device.is_shut_down(),
+            "submit called after DeviceContext::shutdown"
+        );
+        if self.pending.is_some() {
+            self.wait();
+        }
+        if self.dirty {
+            self.record();
+        }
+        unsafe {
+            self.device
+                .handle()
+                .reset_fences(&[self.fence.handle])
+                .expect("Fence reset failed");
+        }
+        let wait_stages = vec![PipelineStageFlags::COMPUTE_SHADER; wait_semaphores.len()];
+        self.command_buffer.submit_reusable(
+            wait_semaphores,
+            &wait_stages,
+            signal_semaphores,
+            self.fence.handle,
+        );
+        let queue = self.command_buffer.queue();
+        let submission = queue.begin_submission();
+        self.pending = Some(submission);
+        WaitHandle::new_reusable(
+            queue,
+            self.fence.handle,
+            self.fence.clone(),
+            submission,
+            location,
+        )
+    }
+}
+
+impl Drop for ComputeGraph {
+    fn drop(&mut self) {
+        if let Some(submission) = self.pending.take() {
+            let result = unsafe {
+                self.device.observe(self.device.handle().wait_for_fences(
+                    &[self.fence.handle],
+                    true,
+                    u64::MAX,
+                ))
+            };
+            match result {
+                Ok(()) => self.command_buffer.queue().end_submission(submission),
+                Err(error) => println!("Waiting for compute graph failed: {:?}", error),
+            }
+        }
+        unsafe {
+            self.device.handle().free_command_buffers(
+                self.command_buffer.queue().pool(),
+                &[self.command_buffer.handle()],
+            );
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    DescriptorPool, DescriptorPoolCreateInfo, DescriptorPoolResetFlags, DescriptorPoolSize,
+    DescriptorSet, DescriptorSetAllocateInfo,
+};
+
+use crate::descriptor_set_layout::DescriptorSetLayoutHandle;
+use crate::device_context::DeviceContext;
+
+// Upper bound for the doubling, keeps a single pool from growing without limit.
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+// Pools used for one kind of allocation, the last one is allocated from until it runs out.
+struct PoolChain {
+    pools: Vec<DescriptorPool>,
+    // Reset pools waiting to be reused before a new one is created.
+    free: Vec<DescriptorPool>,
+}
+
+impl PoolChain {
+    fn new() -> Self {
+        Self {
+            pools: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+// Allocates descriptor sets from a growing list of pools. When the current pool runs out a new one
+// with twice the capacity is created, so extra sets never fail with ERROR_OUT_OF_POOL_MEMORY.
+// Transient sets are allocated per frame and released all at once by reset_frame.
+pub struct DescriptorAllocator {
+    device: Rc<DeviceContext>,
+    // Descriptors of every type a single set may need, raised to fit each layout allocated.
+    sizes_per_set: Vec<DescriptorPoolSize>,
+    sets_per_pool: u32,
+    persistent: PoolChain,
+    frames: Vec<PoolChain>,
+}
+
+impl DescriptorAllocator {
+    pub fn new(
+        device: Rc<DeviceContext>,
+        sizes_per_set: &[DescriptorPoolSize],
+        initial_sets: u32,
+    ) -> Self {
+        Self {
+            device,
+            sizes_per_set: sizes_per_set.to_vec(),
+            sets_per_pool: initial_sets.clamp(1, MAX_SETS_PER_POOL),
+            persistent: PoolChain::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    // Sets live until the allocator is dropped.
+    pub fn allocate(
+        &mut self,
+        layout: &DescriptorSetLayoutHandle,
+    ) -> Result<DescriptorSet, ash::vk::Result> {
+        let mut chain = std::mem::replace(&mut self.persistent, PoolChain::new());
+        let set = self.allocate_from(&mut chain, layout);
+        self.persistent = chain;
+        set
+    }
+
+    // Sets stay valid until reset_frame is called for the same frame index.
+    pub fn allocate_for_frame(
+        &mut self,
+        frame_index: usize,
+        layout: &DescriptorSetLayoutHandle,
+    ) -> Result<DescriptorSet, ash::vk::Result> {
+        if self.frames.len() <= frame_index {
+            self.frames.resize_with(frame_index + 1, PoolChain::new);
+        }
+        let mut chain = std::mem::replace(&mut self.frames[frame_index], PoolChain::new());
+        let set = self.allocate_from(&mut chain, layout);
+        self.frames[frame_index] = chain;
+        set
+    }
+
+    // The frame's previous submission has to be complete, its sets are freed.
+    pub fn reset_frame(&mut self, frame_index: usize) {
+        let Some(chain) = self.frames.get_mut(frame_index) else {
+            return;
+        };
+        for pool in chain.pools.drain(..) {
+            unsafe {
+                self.device
+                    .handle()
+                    .reset_descriptor_pool(pool, DescriptorPoolResetFlags::empty())
+                    .expect("Descriptor pool reset failed");
+            }
+            chain.free.push(pool);
+        }
+    }
+
+    pub fn pool_count(&self) -> usize {
+        let chains = std::iter::once(&self.persistent).chain(&self.frames);
+        chains
+            .map(|chain| chain.pools.len() + chain.free.len())
+            .sum()
+    }
+
+    // Tries the current pool, then reset ones, then creates a pool big enough for every layout
+    // allocated so far.
+    fn allocate_from(
+        &mut self,
+        chain: &mut PoolChain,
+        layout: &DescriptorSetLayoutHandle,
+    ) -> Result<DescriptorSet, ash::vk::Result> {
+        self.fit_layout(layout.pool_sizes());
+        if let Some(pool) = chain.pools.last() {
+            if let Some(set) = self.try_allocate(*pool, layout)? {
+                return Ok(set);
+            }
+        }
+        while let Some(pool) = chain.free.pop() {
+            chain.pools.push(pool);
+            if let Some(set) = self.try_allocate(pool, layout)? {
+                return Ok(set);
+            }
+        }
+
+        let pool = self.create_pool()?;
+        chain.pools.push(pool);
+        self.try_allocate(pool, layout)?
+            .ok_or(ash::vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+    }
+
+    // None when the pool is exhausted or too fragmented for the layout.
+    fn try_allocate(
+        &self,
+        pool: DescriptorPool,
+        layout: &DescriptorSetLayoutHandle,
+    ) -> Result<Option<DescriptorSet>, ash::vk::Result> {
+        let layouts = [layout.handle()];
+        let allocation_info = DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        match unsafe {
+            self.device
+                .handle()
+                .allocate_descriptor_sets(&allocation_info)
+        } {
+            Ok(sets) => Ok(Some(sets[0])),
+            Err(ash::vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+            | Err(ash::vk::Result::ERROR_FRAGMENTED_POOL) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Raises the per set sizes to what the layout needs, pools created after this fit its sets.
+    fn fit_layout(&mut self, layout_sizes: &[DescriptorPoolSize]) {
+        for required in layout_sizes {
+            match self
+                .sizes_per_set
+                .iter_mut()
+                .find(|size| size.ty == required.ty)
+            {
+                Some(size) => {
+                    size.descriptor_count = size.descriptor_count.max(required.descriptor_count)
+                }
+                None => self.sizes_per_set.push(*required),
+            }
+        }
+    }
+
+    fn create_pool(&mut self) -> Result<DescriptorPool, ash::vk::Result> {
+        let sets = self.sets_per_pool;
+        let pool_sizes: Vec<DescriptorPoolSize> = self
+            .sizes_per_set
+            .iter()
+            .filter(|size| size.descriptor_count > 0)
+            .map(|size| {
+                DescriptorPoolSize::default()
+                    .ty(size.ty)
+                    .descriptor_count(size.descriptor_count * sets)
+            })
+            .collect();
+        let pool_info = DescriptorPoolCreateInfo::default()
+            .pool_sizes(&pool_sizes)
+            .max_sets(sets);
+        let pool = unsafe {
+            self.device
+                .handle()
+                .create_descriptor_pool(&pool_info, self.device.allocation_callbacks())?
+        };
+        self.sets_per_pool = (sets * 2).min(MAX_SETS_PER_POOL);
+        Ok(pool)
+    }
+}
+
+impl Drop for DescriptorAllocator {
+    fn drop(&mut self) {
+        let chains = std::iter::once(&self.persistent).chain(&self.frames);
+        for chain in chains {
+            for pool in chain.pools.iter().chain(&chain.free) {
+                unsafe {
+                    self.device
+                        .handle()
+                        .destroy_descriptor_pool(*pool, self.device.allocation_callbacks())
+                }
+            }
+        }
+    }
+}
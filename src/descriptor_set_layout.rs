@@ -0,0 +1,100 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    DescriptorPoolSize, DescriptorSetLayout, DescriptorSetLayoutBinding,
+    DescriptorSetLayoutCreateInfo,
+};
+
+use crate::device_context::DeviceContext;
+
+// Binding, descriptor type, count and stages of every binding, sorted by binding.
+pub(crate) type SetLayoutKey = Vec<(u32, i32, u32, u32)>;
+
+// None for bindings with immutable samplers, those layouts are never shared.
+pub(crate) fn set_layout_key(bindings: &[DescriptorSetLayoutBinding]) -> Option<SetLayoutKey> {
+    if bindings
+        .iter()
+        .any(|binding| !binding.p_immutable_samplers.is_null())
+    {
+        return None;
+    }
+    let mut key: SetLayoutKey = bindings
+        .iter()
+        .map(|binding| {
+            (
+                binding.binding,
+                binding.descriptor_type.as_raw(),
+                binding.descriptor_count,
+                binding.stage_flags.as_raw(),
+            )
+        })
+        .collect();
+    key.sort_unstable();
+    Some(key)
+}
+
+// One entry per descriptor type, empty bindings are left out since pools reject zero counts.
+fn pool_sizes(bindings: &[DescriptorSetLayoutBinding]) -> Vec<DescriptorPoolSize> {
+    let mut sizes: Vec<DescriptorPoolSize> = Vec::new();
+    for binding in bindings
+        .iter()
+        .filter(|binding| binding.descriptor_count > 0)
+    {
+        match sizes
+            .iter_mut()
+            .find(|size| size.ty == binding.descriptor_type)
+        {
+            Some(size) => size.descriptor_count += binding.descriptor_count,
+            None => sizes.push(
+                DescriptorPoolSize::default()
+                    .ty(binding.descriptor_type)
+                    .descriptor_count(binding.descriptor_count),
+            ),
+        }
+    }
+    sizes
+}
+
+// A descriptor set layout shared through DeviceContext::get_or_create_descriptor_set_layout,
+// destroyed when the last pipeline or SharedBinding using it drops.
+pub struct DescriptorSetLayoutHandle {
+    device: Rc<DeviceContext>,
+    layout: DescriptorSetLayout,
+    // Descriptors of every type one set of this layout takes from a pool.
+    pool_sizes: Vec<DescriptorPoolSize>,
+}
+
+impl DescriptorSetLayoutHandle {
+    pub(crate) fn new(device: Rc<DeviceContext>, bindings: &[DescriptorSetLayoutBinding]) -> Self {
+        let info = DescriptorSetLayoutCreateInfo::default().bindings(bindings);
+        let layout = unsafe {
+            device
+                .handle()
+                .create_descriptor_set_layout(&info, device.allocation_callbacks())
+                .expect("Creating descriptorset layout failed")
+        };
+        Self {
+            device,
+            layout,
+            pool_sizes: pool_sizes(bindings),
+        }
+    }
+
+    pub fn handle(&self) -> DescriptorSetLayout {
+        self.layout
+    }
+
+    pub fn pool_sizes(&self) -> &[DescriptorPoolSize] {
+        &self.pool_sizes
+    }
+}
+
+impl Drop for DescriptorSetLayoutHandle {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .destroy_descriptor_set_layout(self.layout, self.device.allocation_callbacks())
+        }
+    }
+}
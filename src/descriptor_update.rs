@@ -0,0 +1,284 @@
+use ash::vk::{
+    AccelerationStructureKHR, Buffer, DescriptorBufferInfo, DescriptorImageInfo, DescriptorSet,
+    DescriptorType, ImageLayout, ImageView, Sampler, WriteDescriptorSet,
+    WriteDescriptorSetAccelerationStructureKHR,
+};
+
+use crate::device_context::DeviceContext;
+
+// Index into one of the batch's info arrays, only turned into a pointer by flush.
+#[derive(Clone, Copy, Debug)]
+enum Info {
+    Buffer(usize),
+    Image(usize),
+    // Acceleration structures are chained in the order their writes were added.
+    AccelerationStructure,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PendingWrite {
+    set: DescriptorSet,
+    binding: u32,
+    ty: DescriptorType,
+    info: Info,
+}
+
+// Collects descriptor writes and owns the infos they point to. WriteDescriptorSet borrows its
+// infos, so they are stored here by index and only referenced while flush builds the writes,
+// collecting any number of writes can't leave one pointing at a dropped info.
+#[derive(Default)]
+pub struct DescriptorUpdateBatch {
+    buffer_infos: Vec<DescriptorBufferInfo>,
+    image_infos: Vec<DescriptorImageInfo>,
+    acceleration_structures: Vec<AccelerationStructureKHR>,
+    writes: Vec<PendingWrite>,
+}
+
+impl DescriptorUpdateBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_buffer(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        ty: DescriptorType,
+        buffer: Buffer,
+        range: u64,
+    ) -> &mut Self {
+        self.buffer_infos
+            .push(DescriptorBufferInfo::default().buffer(buffer).range(range));
+        self.push(set, binding, ty, Info::Buffer(self.buffer_infos.len() - 1))
+    }
+
+    // The sampler is null for storage and separate sampled images.
+    pub fn write_image(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        ty: DescriptorType,
+        view: ImageView,
+        sampler: Sampler,
+        layout: ImageLayout,
+    ) -> &mut Self {
+        self.image_infos.push(
+            DescriptorImageInfo::default()
+                .image_view(view)
+                .sampler(sampler)
+                .image_layout(layout),
+        );
+        self.push(set, binding, ty, Info::Image(self.image_infos.len() - 1))
+    }
+
+    pub fn write_sampler(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        sampler: Sampler,
+    ) -> &mut Self {
+        self.write_image(
+            set,
+            binding,
+            DescriptorType::SAMPLER,
+            ImageView::null(),
+            sampler,
+            ImageLayout::UNDEFINED,
+        )
+    }
+
+    pub fn write_acceleration_structure(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        acceleration_structure: AccelerationStructureKHR,
+    ) -> &mut Self {
+        self.acceleration_structures.push(acceleration_structure);
+        self.push(
+            set,
+            binding,
+            DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            Info::AccelerationStructure,
+        )
+    }
+
+    fn push(
+        &mut self,
+        set: DescriptorSet,
+        binding: u32,
+        ty: DescriptorType,
+        info: Info,
+    ) -> &mut Self {
+        self.writes.push(PendingWrite {
+            set,
+            binding,
+            ty,
+            info,
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    // Writes everything in one update_descriptor_sets call in the order it was added, a later
+    // write to the same binding wins. The batch is empty afterwards and can be reused.
+    pub fn flush(&mut self, device: &DeviceContext) {
+        if !self.writes.is_empty() {
+            self.update(device);
+        }
+        self.writes.clear();
+        self.buffer_infos.clear();
+        self.image_infos.clear();
+        self.acceleration_structures.clear();
+    }
+
+    fn update(&self, device: &DeviceContext) {
+        self.with_writes(|writes| unsafe { device.handle().update_descriptor_sets(writes, &[]) })
+    }
+
+    // The writes point into the batch and the chained acceleration structure infos, only valid
+    // while f runs.
+    fn with_writes<R>(&self, f: impl FnOnce(&[WriteDescriptorSet]) -> R) -> R {
+        let mut acceleration_structure_infos: Vec<WriteDescriptorSetAccelerationStructureKHR> =
+            self.acceleration_structures
+                .iter()
+                .map(|handle| {
+                    WriteDescriptorSetAccelerationStructureKHR::default()
+                        .acceleration_structures(std::slice::from_ref(handle))
+                })
+                .collect();
+        let mut acceleration_structure_infos = acceleration_structure_infos.iter_mut();
+
+        let writes: Vec<WriteDescriptorSet> = self
+            .writes
+            .iter()
+            .map(|pending| {
+                let write = WriteDescriptorSet::default()
+                    .dst_set(pending.set)
+                    .dst_binding(pending.binding)
+                    .descriptor_type(pending.ty);
+                match pending.info {
+                    Info::Buffer(index) => {
+                        write.buffer_info(std::slice::from_ref(&self.buffer_infos[index]))
+                    }
+                    Info::Image(index) => {
+                        write.image_info(std::slice::from_ref(&self.image_infos[index]))
+                    }
+                    Info::AccelerationStructure => write
+                        .descriptor_count(1)
+                        .push_next(acceleration_structure_infos.next().unwrap()),
+                }
+            })
+            .collect();
+        f(&writes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::{Handle, StructureType};
+
+    #[test]
+    fn many_mixed_writes_point_at_their_own_infos() {
+        const COUNT: u64 = 200;
+        let mut batch = DescriptorUpdateBatch::new();
+        // Interleaved so the info arrays grow (and reallocate) while writes are added.
+        for i in 0..COUNT {
+            let set = DescriptorSet::from_raw(i % 3 + 1);
+            let binding = i as u32;
+            match i % 4 {
+                0 => batch.write_buffer(
+                    set,
+                    binding,
+                    DescriptorType::STORAGE_BUFFER,
+                    Buffer::from_raw(1000 + i),
+                    i * 16,
+                ),
+                1 => batch.write_image(
+                    set,
+                    binding,
+                    DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    ImageView::from_raw(2000 + i),
+                    Sampler::from_raw(3000 + i),
+                    ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ),
+                2 => batch.write_sampler(set, binding, Sampler::from_raw(3000 + i)),
+                _ => batch.write_acceleration_structure(
+                    set,
+                    binding,
+                    AccelerationStructureKHR::from_raw(4000 + i),
+                ),
+            };
+        }
+        assert_eq!(batch.len(), COUNT as usize);
+
+        batch.with_writes(|writes| {
+            assert_eq!(writes.len(), COUNT as usize);
+            for (i, write) in (0..COUNT).zip(writes) {
+                assert_eq!(write.dst_set, DescriptorSet::from_raw(i % 3 + 1));
+                assert_eq!(write.dst_binding, i as u32);
+                assert_eq!(write.descriptor_count, 1);
+                let (buffer_info, image_info) =
+                    unsafe { (write.p_buffer_info.as_ref(), write.p_image_info.as_ref()) };
+                match i % 4 {
+                    0 => {
+                        assert_eq!(write.descriptor_type, DescriptorType::STORAGE_BUFFER);
+                        let info = buffer_info.unwrap();
+                        assert_eq!(info.buffer, Buffer::from_raw(1000 + i));
+                        assert_eq!(info.range, i * 16);
+                        assert!(image_info.is_none());
+                    }
+                    1 => {
+                        assert_eq!(
+                            write.descriptor_type,
+                            DescriptorType::COMBINED_IMAGE_SAMPLER
+                        );
+                        let info = image_info.unwrap();
+                        assert_eq!(info.image_view, ImageView::from_raw(2000 + i));
+                        assert_eq!(info.sampler, Sampler::from_raw(3000 + i));
+                        assert_eq!(info.image_layout, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+                    }
+                    2 => {
+                        assert_eq!(write.descriptor_type, DescriptorType::SAMPLER);
+                        let info = image_info.unwrap();
+                        assert_eq!(info.image_view, ImageView::null());
+                        assert_eq!(info.sampler, Sampler::from_raw(3000 + i));
+                    }
+                    _ => {
+                        assert_eq!(
+                            write.descriptor_type,
+                            DescriptorType::ACCELERATION_STRUCTURE_KHR
+                        );
+                        assert!(buffer_info.is_none() && image_info.is_none());
+                        let info = unsafe {
+                            &*(write.p_next as *const WriteDescriptorSetAccelerationStructureKHR)
+                        };
+                        assert_eq!(
+                            info.s_type,
+                            StructureType::WRITE_DESCRIPTOR_SET_ACCELERATION_STRUCTURE_KHR
+                        );
+                        assert_eq!(info.acceleration_structure_count, 1);
+                        assert_eq!(
+                            unsafe { *info.p_acceleration_structures },
+                            AccelerationStructureKHR::from_raw(4000 + i)
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn an_empty_batch_has_no_writes() {
+        let batch = DescriptorUpdateBatch::new();
+        assert!(batch.is_empty());
+        batch.with_writes(|writes| assert!(writes.is_empty()));
+    }
+}
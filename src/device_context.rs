@@ -1,21 +1,211 @@
+use crate::allocator::DeviceAllocationListener;
+use crate::command_buffer::CommandBuffer;
+use crate::descriptor_set_layout::{set_layout_key, DescriptorSetLayoutHandle, SetLayoutKey};
 use crate::gpu::Gpu;
-use ash::vk::{DeviceCreateInfo, DeviceQueueCreateInfo, QueueFlags};
+use crate::indices::QueueFamilyIndex;
+use crate::kernels::Kernels;
+use crate::queue::{CommandQueue, Garbage};
+use crate::sampler_resource::{SamplerDescriptor, SamplerResource};
+use crate::shader_module_cache::{ShaderModuleCache, ShaderModuleHandle};
+use ash::vk::{
+    AllocationCallbacks, BaseInStructure, CommandBufferAllocateInfo, CommandPool,
+    DescriptorSetLayoutBinding, DeviceCreateInfo, DeviceQueueCreateInfo,
+    DeviceQueueGlobalPriorityCreateInfoKHR, PhysicalDeviceCustomBorderColorFeaturesEXT,
+    PhysicalDeviceFeatures, PhysicalDeviceFeatures2, PhysicalDeviceVulkan12Features, QueueFlags,
+    QueueGlobalPriorityKHR, StructureType, SurfaceKHR,
+};
 use ash::Device;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+// Priority of the queue created for a family, passed to Gpu::device_context_with_queue_priorities.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueuePriority {
+    pub family: u32,
+    pub priority: f32,
+    pub global_priority: Option<QueueGlobalPriorityKHR>,
+}
+
+impl QueuePriority {
+    pub fn new(family: impl Into<QueueFamilyIndex>, priority: f32) -> Self {
+        Self {
+            family: family.into().0,
+            priority,
+            global_priority: None,
+        }
+    }
+
+    // System wide priority, needs VK_KHR_global_priority. Priorities above MEDIUM usually need
+    // elevated permissions, when they are denied the device is created with the default instead.
+    pub fn with_global_priority(mut self, priority: QueueGlobalPriorityKHR) -> Self {
+        self.global_priority = Some(priority);
+        self
+    }
+}
 
 pub struct DeviceContext {
     gpu: Gpu,
     handle: Device,
+    enabled_features: EnabledFeatures,
+    extensions: Vec<String>,
+    // The priority every family's queue was created with, indexed by family.
+    queue_priorities: Vec<QueuePriority>,
+    // Weak like the modules and layouts below, a sampler lives as long as something uses it.
+    samplers: RefCell<HashMap<SamplerDescriptor, Weak<SamplerResource>>>,
+    // Weak since the queues hold on to the device, they live as long as a swapchain uses them.
+    present_queues: RefCell<HashMap<SurfaceKHR, Weak<CommandQueue>>>,
+    // Keyed by ShaderModuleCache::hash, the handles destroy their module when the last one drops.
+    shader_modules: RefCell<HashMap<u64, Weak<ShaderModuleHandle>>>,
+    // Pipelines with identical set layouts share one object, so their pipeline layouts are
+    // compatible for those sets and bound sets can stay bound when switching between them.
+    set_layouts: RefCell<HashMap<SetLayoutKey, Weak<DescriptorSetLayoutHandle>>>,
+    // Weak since the pipelines hold on to the device, every queue that ran a kernel keeps it alive.
+    kernels: RefCell<Weak<Kernels>>,
+    allocation_listener: RefCell<Option<Rc<dyn DeviceAllocationListener>>>,
+    lost: Cell<bool>,
+    shut_down: Cell<bool>,
+    // Deferred submissions of every queue, freed by shutdown.
+    garbage: RefCell<Vec<(CommandPool, Weak<Garbage>)>>,
+}
+
+#[derive(Default)]
+struct EnabledFeatures {
+    core: PhysicalDeviceFeatures,
+    vulkan12: PhysicalDeviceVulkan12Features<'static>,
+    custom_border_colors: bool,
 }
 
-unsafe impl Send for DeviceContext {}
+fn enabled_features(info: &DeviceCreateInfo) -> EnabledFeatures {
+    let mut enabled = EnabledFeatures::default();
+    unsafe {
+        if !info.p_enabled_features.is_null() {
+            enabled.core = *info.p_enabled_features;
+        }
+
+        let mut next = info.p_next as *const BaseInStructure;
+        while !next.is_null() {
+            match (*next).s_type {
+                StructureType::PHYSICAL_DEVICE_FEATURES_2 => {
+                    enabled.core = (*(next as *const PhysicalDeviceFeatures2)).features;
+                }
+                StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES => {
+                    enabled.vulkan12 = *(next as *const PhysicalDeviceVulkan12Features<'static>);
+                    enabled.vulkan12.p_next = std::ptr::null_mut();
+                }
+                StructureType::PHYSICAL_DEVICE_CUSTOM_BORDER_COLOR_FEATURES_EXT => {
+                    enabled.custom_border_colors = (*(next
+                        as *const PhysicalDeviceCustomBorderColorFeaturesEXT))
+                        .custom_border_colors
+                        != 0;
+                }
+                _ => {}
+            }
+            next = (*next).p_next;
+        }
+    }
+
+    enabled
+}
+
+// Falls back to the default global priority when the family doesn't list the requested one.
+fn resolve_queue_priorities(
+    gpu: &Gpu,
+    extensions: &[&str],
+    queue_priorities: &[QueuePriority],
+) -> Vec<QueuePriority> {
+    (0..gpu.queue_family_count())
+        .map(|family| {
+            let Some(mut requested) = queue_priorities
+                .iter()
+                .find(|requested| requested.family == family)
+                .copied()
+            else {
+                return QueuePriority::new(family, 1.0);
+            };
+            assert!(
+                (0.0..=1.0).contains(&requested.priority),
+                "Queue priority {} of family {} is outside of [0, 1]",
+                requested.priority,
+                family
+            );
+            if let Some(global_priority) = requested.global_priority {
+                assert!(
+                    extensions.iter().any(|name| {
+                        *name == ash::khr::global_priority::NAME.to_str().unwrap()
+                            || *name == ash::ext::global_priority::NAME.to_str().unwrap()
+                    }),
+                    "Global queue priorities need VK_KHR_global_priority in the device extensions"
+                );
+                let supported = gpu.queue_family_global_priorities(family);
+                if !supported.is_empty() && !supported.contains(&global_priority) {
+                    println!(
+                        "Queue family {} doesn't support global priority {:?}, using the default",
+                        family, global_priority
+                    );
+                    requested.global_priority = None;
+                }
+            }
+            requested
+        })
+        .collect()
+}
+
+fn create_device(
+    gpu: &Gpu,
+    info: DeviceCreateInfo,
+    queue_priorities: &[QueuePriority],
+) -> Result<Device, ash::vk::Result> {
+    let priorities: Vec<[f32; 1]> = queue_priorities
+        .iter()
+        .map(|queue| [queue.priority])
+        .collect();
+    let mut global_priorities: Vec<DeviceQueueGlobalPriorityCreateInfoKHR> = queue_priorities
+        .iter()
+        .map(|queue| {
+            DeviceQueueGlobalPriorityCreateInfoKHR::default()
+                .global_priority(queue.global_priority.unwrap_or_default())
+        })
+        .collect();
+    // One queue per family so present and transfer queues can be created on demand.
+    let queue_info: Vec<DeviceQueueCreateInfo> = queue_priorities
+        .iter()
+        .zip(&priorities)
+        .zip(&mut global_priorities)
+        .map(|((queue, priorities), global_priority)| {
+            let info = DeviceQueueCreateInfo::default()
+                .queue_priorities(priorities)
+                .queue_family_index(queue.family);
+            if queue.global_priority.is_some() {
+                info.push_next(global_priority)
+            } else {
+                info
+            }
+        })
+        .collect();
+    let info = info.queue_create_infos(&queue_info);
+    unsafe {
+        gpu.vulkan().vk_instance().create_device(
+            *gpu.vk_physical_device(),
+            &info,
+            gpu.vulkan().allocation_callbacks(),
+        )
+    }
+}
 
 impl DeviceContext {
     pub(crate) fn new(gpu: &Gpu, extensions: &[&str], builder: DeviceCreateInfo) -> Self {
-        let priorities: [f32; 1] = [1.];
-        if let Some(index) = gpu.family_type_index(QueueFlags::GRAPHICS) {
-            let queue_info = [DeviceQueueCreateInfo::default()
-                .queue_priorities(&priorities)
-                .queue_family_index(index)];
+        Self::new_with_queue_priorities(gpu, extensions, &[], builder)
+    }
+
+    pub(crate) fn new_with_queue_priorities(
+        gpu: &Gpu,
+        extensions: &[&str],
+        queue_priorities: &[QueuePriority],
+        builder: DeviceCreateInfo,
+    ) -> Self {
+        if gpu.family_type_index(QueueFlags::GRAPHICS).is_some() {
+            let mut queue_priorities = resolve_queue_priorities(gpu, extensions, queue_priorities);
 
             let mut extension_names_raw: Vec<*const i8> = extensions
                 .iter()
@@ -31,20 +221,40 @@ impl DeviceContext {
                 panic!("Missing extensions");
             }
 
-            let builder = builder
-                .enabled_extension_names(&extension_names_raw)
-                .queue_create_infos(&queue_info);
-
-            unsafe {
-                let device_context: Device = gpu
-                    .vulkan()
-                    .vk_instance()
-                    .create_device(*gpu.vk_physical_device(), &builder, None)
-                    .unwrap();
-                Self {
-                    gpu: gpu.clone(),
-                    handle: device_context,
+            let builder = builder.enabled_extension_names(&extension_names_raw);
+
+            // Whether a global priority is granted depends on OS policy, e.g. HIGH and REALTIME
+            // often need elevated permissions. Denied priorities fall back to the default.
+            let device_context = match create_device(gpu, builder, &queue_priorities) {
+                Ok(device) => device,
+                Err(ash::vk::Result::ERROR_NOT_PERMITTED_KHR)
+                    if queue_priorities
+                        .iter()
+                        .any(|queue| queue.global_priority.is_some()) =>
+                {
+                    println!("Global queue priority not permitted, using the default priority");
+                    for queue in &mut queue_priorities {
+                        queue.global_priority = None;
+                    }
+                    create_device(gpu, builder, &queue_priorities).expect("Device creation failed")
                 }
+                Err(error) => panic!("Device creation failed: {}", error),
+            };
+            Self {
+                gpu: gpu.clone(),
+                handle: device_context,
+                enabled_features: enabled_features(&builder),
+                extensions: extensions.iter().map(|name| name.to_string()).collect(),
+                queue_priorities,
+                samplers: RefCell::new(HashMap::new()),
+                present_queues: RefCell::new(HashMap::new()),
+                shader_modules: RefCell::new(HashMap::new()),
+                set_layouts: RefCell::new(HashMap::new()),
+                kernels: RefCell::new(Weak::new()),
+                allocation_listener: RefCell::new(None),
+                lost: Cell::new(false),
+                shut_down: Cell::new(false),
+                garbage: RefCell::new(Vec::new()),
             }
         } else {
             panic!("No queue family found");
@@ -55,16 +265,204 @@ impl DeviceContext {
         self.gpu.family_type_index(flags)
     }
 
-    pub fn queue(&self, queue_family_index: u32) -> ash::vk::Queue {
-        unsafe { self.handle.get_device_queue(queue_family_index, 0) }
+    // The priority the family's queue was created with, after any fallback.
+    pub fn queue_priority(&self, queue_family_index: impl Into<QueueFamilyIndex>) -> QueuePriority {
+        self.queue_priorities[queue_family_index.into().index()]
+    }
+
+    pub fn queue(&self, queue_family_index: impl Into<QueueFamilyIndex>) -> ash::vk::Queue {
+        unsafe { self.handle.get_device_queue(queue_family_index.into().0, 0) }
     }
 
     pub fn wait(&self) {
-        unsafe {
-            self.handle.device_wait_idle().expect("Wait failed");
+        self.try_wait().expect("Wait failed")
+    }
+
+    // Returns ERROR_DEVICE_LOST instead of panicking, which is expected when shutting down after a crash.
+    pub fn try_wait(&self) -> Result<(), ash::vk::Result> {
+        unsafe { self.observe(self.handle.device_wait_idle()) }
+    }
+
+    // Clean exit hook: waits for the device, frees the deferred submissions of all queues and
+    // marks the context unusable. Device loss is logged, not returned, so this can't fail.
+    pub fn shutdown(&self) {
+        if let Err(error) = self.try_wait() {
+            println!("Waiting for the device during shutdown failed: {:?}", error);
+        }
+        for (pool, garbage) in self.garbage.borrow_mut().drain(..) {
+            let Some(garbage) = garbage.upgrade() else {
+                continue;
+            };
+            for (command_buffer, fence, _, _) in garbage.borrow_mut().drain(..) {
+                unsafe {
+                    self.handle.free_command_buffers(pool, &[command_buffer]);
+                    self.handle
+                        .destroy_fence(fence, self.allocation_callbacks());
+                }
+            }
+        }
+        self.shut_down.set(true);
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down.get()
+    }
+
+    pub(crate) fn register_garbage(&self, pool: CommandPool, garbage: &Rc<Garbage>) {
+        let mut registered = self.garbage.borrow_mut();
+        registered.retain(|(_, garbage)| garbage.strong_count() > 0);
+        registered.push((pool, Rc::downgrade(garbage)));
+    }
+
+    // Set once any call through the crate returned ERROR_DEVICE_LOST. Nothing created from this
+    // context can be used anymore, see Swapchain::into_surface for rebuilding on a new device.
+    pub fn is_lost(&self) -> bool {
+        self.lost.get()
+    }
+
+    pub(crate) fn observe<T>(
+        &self,
+        result: Result<T, ash::vk::Result>,
+    ) -> Result<T, ash::vk::Result> {
+        if result.as_ref().err() == Some(&ash::vk::Result::ERROR_DEVICE_LOST) {
+            self.lost.set(true)
+        }
+        result
+    }
+
+    // Allocates all buffers in one call, e.g. one per recording thread.
+    pub fn allocate_command_buffers(
+        &self,
+        queue: Rc<CommandQueue>,
+        count: u32,
+    ) -> Vec<CommandBuffer> {
+        let info = CommandBufferAllocateInfo::default()
+            .command_buffer_count(count)
+            .command_pool(queue.pool());
+        let handles = unsafe {
+            self.handle
+                .allocate_command_buffers(&info)
+                .expect("Command buffer allocation failed")
+        };
+        handles
+            .into_iter()
+            .map(|handle| CommandBuffer::from_handle(queue.clone(), handle))
+            .collect()
+    }
+
+    // The host allocator installed on the instance, passed to every create and destroy call.
+    pub fn allocation_callbacks(&self) -> Option<&AllocationCallbacks<'static>> {
+        self.gpu.vulkan().allocation_callbacks()
+    }
+
+    pub fn set_allocation_listener(&self, listener: Option<Rc<dyn DeviceAllocationListener>>) {
+        *self.allocation_listener.borrow_mut() = listener;
+    }
+
+    pub(crate) fn notify_allocated(&self, size: u64, memory_type: u32, tag: &str) {
+        if let Some(listener) = self.allocation_listener.borrow().as_ref() {
+            listener.allocated(size, memory_type, tag)
         }
     }
 
+    pub(crate) fn notify_freed(&self, size: u64, memory_type: u32, tag: &str) {
+        if let Some(listener) = self.allocation_listener.borrow().as_ref() {
+            listener.freed(size, memory_type, tag)
+        }
+    }
+
+    pub fn get_sampler(self: &Rc<Self>, descriptor: &SamplerDescriptor) -> Rc<SamplerResource> {
+        let mut samplers = self.samplers.borrow_mut();
+        if let Some(sampler) = samplers.get(descriptor).and_then(Weak::upgrade) {
+            return sampler;
+        }
+
+        samplers.retain(|_, sampler| sampler.strong_count() > 0);
+        let sampler = Rc::new(SamplerResource::new(self.clone(), descriptor));
+        samplers.insert(*descriptor, Rc::downgrade(&sampler));
+        sampler
+    }
+
+    // Pipelines created from the same SPIR-V share one module for as long as any of them holds it.
+    pub fn get_or_create_shader_module(self: &Rc<Self>, spirv: &[u32]) -> Rc<ShaderModuleHandle> {
+        let hash = ShaderModuleCache::hash(spirv);
+        let mut modules = self.shader_modules.borrow_mut();
+        if let Some(module) = modules.get(&hash).and_then(Weak::upgrade) {
+            return module;
+        }
+
+        modules.retain(|_, module| module.strong_count() > 0);
+        let module = Rc::new(ShaderModuleHandle::new(self.clone(), spirv));
+        modules.insert(hash, Rc::downgrade(&module));
+        module
+    }
+
+    // Bindings are compared regardless of their order. Layouts with immutable samplers are
+    // created on every call.
+    pub fn get_or_create_descriptor_set_layout(
+        self: &Rc<Self>,
+        bindings: &[DescriptorSetLayoutBinding],
+    ) -> Rc<DescriptorSetLayoutHandle> {
+        let Some(key) = set_layout_key(bindings) else {
+            return Rc::new(DescriptorSetLayoutHandle::new(self.clone(), bindings));
+        };
+        let mut layouts = self.set_layouts.borrow_mut();
+        if let Some(layout) = layouts.get(&key).and_then(Weak::upgrade) {
+            return layout;
+        }
+
+        layouts.retain(|_, layout| layout.strong_count() > 0);
+        let layout = Rc::new(DescriptorSetLayoutHandle::new(self.clone(), bindings));
+        layouts.insert(key, Rc::downgrade(&layout));
+        layout
+    }
+
+    // Compiled on first use, shared by all queues. See CommandQueue::kernels.
+    pub(crate) fn get_or_create_kernels(self: &Rc<Self>) -> Rc<Kernels> {
+        if let Some(kernels) = self.kernels.borrow().upgrade() {
+            return kernels;
+        }
+        let kernels = Rc::new(Kernels::new(self));
+        *self.kernels.borrow_mut() = Rc::downgrade(&kernels);
+        kernels
+    }
+
+    // Prefers the graphics family when it can present.
+    pub fn present_queue(self: &Rc<Self>, surface: SurfaceKHR) -> Option<Rc<CommandQueue>> {
+        if let Some(queue) = self
+            .present_queues
+            .borrow()
+            .get(&surface)
+            .and_then(Weak::upgrade)
+        {
+            return Some(queue);
+        }
+
+        let index = *self.gpu.present_family_indices(surface).first()?;
+
+        let queue = Rc::new(CommandQueue::new_with_family_index(self.clone(), index));
+        self.present_queues
+            .borrow_mut()
+            .insert(surface, Rc::downgrade(&queue));
+        Some(queue)
+    }
+
+    pub fn enabled_features(&self) -> &PhysicalDeviceFeatures {
+        &self.enabled_features.core
+    }
+
+    pub fn enabled_vulkan12_features(&self) -> &PhysicalDeviceVulkan12Features<'static> {
+        &self.enabled_features.vulkan12
+    }
+
+    pub fn custom_border_colors_enabled(&self) -> bool {
+        self.enabled_features.custom_border_colors
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+
     pub fn handle(&self) -> &Device {
         &self.handle
     }
@@ -73,3 +471,17 @@ impl DeviceContext {
         &self.gpu
     }
 }
+
+// Every resource holds an Rc to the context and its caches only hold Weak references, so this
+// only runs after everything created from it is gone.
+impl Drop for DeviceContext {
+    fn drop(&mut self) {
+        if let Err(error) = self.try_wait() {
+            println!(
+                "Waiting for the device before destroying it failed: {:?}",
+                error
+            );
+        }
+        unsafe { self.handle.destroy_device(self.allocation_callbacks()) }
+    }
+}
@@ -0,0 +1,343 @@
+use ash::vk::{Format, ImageAspectFlags};
+
+pub fn is_depth_format(format: Format) -> bool {
+    matches!(
+        format,
+        Format::D16_UNORM
+            | Format::X8_D24_UNORM_PACK32
+            | Format::D32_SFLOAT
+            | Format::D16_UNORM_S8_UINT
+            | Format::D24_UNORM_S8_UINT
+            | Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+pub fn is_stencil_format(format: Format) -> bool {
+    matches!(
+        format,
+        Format::S8_UINT
+            | Format::D16_UNORM_S8_UINT
+            | Format::D24_UNORM_S8_UINT
+            | Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+pub fn aspect_mask(format: Format) -> ImageAspectFlags {
+    let mut aspect = ImageAspectFlags::empty();
+    if is_depth_format(format) {
+        aspect |= ImageAspectFlags::DEPTH;
+    }
+    if is_stencil_format(format) {
+        aspect |= ImageAspectFlags::STENCIL;
+    }
+    if aspect.is_empty() {
+        ImageAspectFlags::COLOR
+    } else {
+        aspect
+    }
+}
+
+// Color formats read as integers, these have to be cleared with integer values.
+pub fn is_integer_format(format: Format) -> bool {
+    matches!(
+        format,
+        Format::R8_UINT
+            | Format::R8_SINT
+            | Format::R8G8_UINT
+            | Format::R8G8_SINT
+            | Format::R8G8B8_UINT
+            | Format::R8G8B8_SINT
+            | Format::B8G8R8_UINT
+            | Format::B8G8R8_SINT
+            | Format::R8G8B8A8_UINT
+            | Format::R8G8B8A8_SINT
+            | Format::B8G8R8A8_UINT
+            | Format::B8G8R8A8_SINT
+            | Format::A8B8G8R8_UINT_PACK32
+            | Format::A8B8G8R8_SINT_PACK32
+            | Format::A2R10G10B10_UINT_PACK32
+            | Format::A2R10G10B10_SINT_PACK32
+            | Format::A2B10G10R10_UINT_PACK32
+            | Format::A2B10G10R10_SINT_PACK32
+            | Format::R16_UINT
+            | Format::R16_SINT
+            | Format::R16G16_UINT
+            | Format::R16G16_SINT
+            | Format::R16G16B16_UINT
+            | Format::R16G16B16_SINT
+            | Format::R16G16B16A16_UINT
+            | Format::R16G16B16A16_SINT
+            | Format::R32_UINT
+            | Format::R32_SINT
+            | Format::R32G32_UINT
+            | Format::R32G32_SINT
+            | Format::R32G32B32_UINT
+            | Format::R32G32B32_SINT
+            | Format::R32G32B32A32_UINT
+            | Format::R32G32B32A32_SINT
+            | Format::R64_UINT
+            | Format::R64_SINT
+            | Format::R64G64_UINT
+            | Format::R64G64_SINT
+            | Format::R64G64B64_UINT
+            | Format::R64G64B64_SINT
+            | Format::R64G64B64A64_UINT
+            | Format::R64G64B64A64_SINT
+    )
+}
+
+pub fn is_signed_integer_format(format: Format) -> bool {
+    matches!(
+        format,
+        Format::R8_SINT
+            | Format::R8G8_SINT
+            | Format::R8G8B8_SINT
+            | Format::B8G8R8_SINT
+            | Format::R8G8B8A8_SINT
+            | Format::B8G8R8A8_SINT
+            | Format::A8B8G8R8_SINT_PACK32
+            | Format::A2R10G10B10_SINT_PACK32
+            | Format::A2B10G10R10_SINT_PACK32
+            | Format::R16_SINT
+            | Format::R16G16_SINT
+            | Format::R16G16B16_SINT
+            | Format::R16G16B16A16_SINT
+            | Format::R32_SINT
+            | Format::R32G32_SINT
+            | Format::R32G32B32_SINT
+            | Format::R32G32B32A32_SINT
+            | Format::R64_SINT
+            | Format::R64G64_SINT
+            | Format::R64G64B64_SINT
+            | Format::R64G64B64A64_SINT
+    )
+}
+
+pub fn is_compressed_format(format: Format) -> bool {
+    block_size_bytes(format).is_some()
+}
+
+// Texel extent of one compression block, (1, 1) for uncompressed formats.
+pub fn block_extent(format: Format) -> (u32, u32) {
+    match format {
+        Format::ASTC_5X4_UNORM_BLOCK | Format::ASTC_5X4_SRGB_BLOCK => (5, 4),
+        Format::ASTC_5X5_UNORM_BLOCK | Format::ASTC_5X5_SRGB_BLOCK => (5, 5),
+        Format::ASTC_6X5_UNORM_BLOCK | Format::ASTC_6X5_SRGB_BLOCK => (6, 5),
+        Format::ASTC_6X6_UNORM_BLOCK | Format::ASTC_6X6_SRGB_BLOCK => (6, 6),
+        Format::ASTC_8X5_UNORM_BLOCK | Format::ASTC_8X5_SRGB_BLOCK => (8, 5),
+        Format::ASTC_8X6_UNORM_BLOCK | Format::ASTC_8X6_SRGB_BLOCK => (8, 6),
+        Format::ASTC_8X8_UNORM_BLOCK | Format::ASTC_8X8_SRGB_BLOCK => (8, 8),
+        Format::ASTC_10X5_UNORM_BLOCK | Format::ASTC_10X5_SRGB_BLOCK => (10, 5),
+        Format::ASTC_10X6_UNORM_BLOCK | Format::ASTC_10X6_SRGB_BLOCK => (10, 6),
+        Format::ASTC_10X8_UNORM_BLOCK | Format::ASTC_10X8_SRGB_BLOCK => (10, 8),
+        Format::ASTC_10X10_UNORM_BLOCK | Format::ASTC_10X10_SRGB_BLOCK => (10, 10),
+        Format::ASTC_12X10_UNORM_BLOCK | Format::ASTC_12X10_SRGB_BLOCK => (12, 10),
+        Format::ASTC_12X12_UNORM_BLOCK | Format::ASTC_12X12_SRGB_BLOCK => (12, 12),
+        _ if is_compressed_format(format) => (4, 4),
+        _ => (1, 1),
+    }
+}
+
+pub fn block_size_bytes(format: Format) -> Option<u32> {
+    match format {
+        Format::BC1_RGB_UNORM_BLOCK
+        | Format::BC1_RGB_SRGB_BLOCK
+        | Format::BC1_RGBA_UNORM_BLOCK
+        | Format::BC1_RGBA_SRGB_BLOCK
+        | Format::BC4_UNORM_BLOCK
+        | Format::BC4_SNORM_BLOCK
+        | Format::ETC2_R8G8B8_UNORM_BLOCK
+        | Format::ETC2_R8G8B8_SRGB_BLOCK
+        | Format::ETC2_R8G8B8A1_UNORM_BLOCK
+        | Format::ETC2_R8G8B8A1_SRGB_BLOCK
+        | Format::EAC_R11_UNORM_BLOCK
+        | Format::EAC_R11_SNORM_BLOCK => Some(8),
+        Format::BC2_UNORM_BLOCK
+        | Format::BC2_SRGB_BLOCK
+        | Format::BC3_UNORM_BLOCK
+        | Format::BC3_SRGB_BLOCK
+        | Format::BC5_UNORM_BLOCK
+        | Format::BC5_SNORM_BLOCK
+        | Format::BC6H_UFLOAT_BLOCK
+        | Format::BC6H_SFLOAT_BLOCK
+        | Format::BC7_UNORM_BLOCK
+        | Format::BC7_SRGB_BLOCK
+        | Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | Format::EAC_R11G11_UNORM_BLOCK
+        | Format::EAC_R11G11_SNORM_BLOCK => Some(16),
+        _ if format.as_raw() >= Format::ASTC_4X4_UNORM_BLOCK.as_raw()
+            && format.as_raw() <= Format::ASTC_12X12_SRGB_BLOCK.as_raw() =>
+        {
+            Some(16)
+        }
+        _ => None,
+    }
+}
+
+// Size of a single texel for uncompressed color and depth formats.
+pub fn texel_size_bytes(format: Format) -> Option<u32> {
+    match format {
+        Format::R8_UNORM
+        | Format::R8_SNORM
+        | Format::R8_UINT
+        | Format::R8_SINT
+        | Format::R8_SRGB
+        | Format::S8_UINT => Some(1),
+        Format::R8G8_UNORM
+        | Format::R8G8_SNORM
+        | Format::R8G8_UINT
+        | Format::R8G8_SINT
+        | Format::R8G8_SRGB
+        | Format::R16_UNORM
+        | Format::R16_SNORM
+        | Format::R16_UINT
+        | Format::R16_SINT
+        | Format::R16_SFLOAT
+        | Format::D16_UNORM => Some(2),
+        Format::R8G8B8A8_UNORM
+        | Format::R8G8B8A8_SNORM
+        | Format::R8G8B8A8_UINT
+        | Format::R8G8B8A8_SINT
+        | Format::R8G8B8A8_SRGB
+        | Format::B8G8R8A8_UNORM
+        | Format::B8G8R8A8_SRGB
+        | Format::A2B10G10R10_UNORM_PACK32
+        | Format::A2R10G10B10_UNORM_PACK32
+        | Format::B10G11R11_UFLOAT_PACK32
+        | Format::R16G16_UNORM
+        | Format::R16G16_SNORM
+        | Format::R16G16_UINT
+        | Format::R16G16_SINT
+        | Format::R16G16_SFLOAT
+        | Format::R32_UINT
+        | Format::R32_SINT
+        | Format::R32_SFLOAT
+        | Format::X8_D24_UNORM_PACK32
+        | Format::D32_SFLOAT => Some(4),
+        Format::R16G16B16A16_UNORM
+        | Format::R16G16B16A16_SNORM
+        | Format::R16G16B16A16_UINT
+        | Format::R16G16B16A16_SINT
+        | Format::R16G16B16A16_SFLOAT
+        | Format::R32G32_UINT
+        | Format::R32G32_SINT
+        | Format::R32G32_SFLOAT => Some(8),
+        Format::R32G32B32_UINT | Format::R32G32B32_SINT | Format::R32G32B32_SFLOAT => Some(12),
+        Format::R32G32B32A32_UINT | Format::R32G32B32A32_SINT | Format::R32G32B32A32_SFLOAT => {
+            Some(16)
+        }
+        _ => None,
+    }
+}
+
+// Number of blocks needed to cover a width x height region.
+pub fn block_count(format: Format, width: u32, height: u32) -> (u32, u32) {
+    let (block_width, block_height) = block_extent(format);
+    (width.div_ceil(block_width), height.div_ceil(block_height))
+}
+
+// Bytes one vertex attribute of this format occupies, packed formats like A2B10G10R10 are a
+// single 32-bit word. Covers the formats commonly used for vertex data.
+pub fn attribute_size_bytes(format: Format) -> Option<u32> {
+    match format {
+        Format::R8G8B8_UNORM
+        | Format::R8G8B8_SNORM
+        | Format::R8G8B8_UINT
+        | Format::R8G8B8_SINT
+        | Format::B8G8R8_UNORM
+        | Format::B8G8R8_SNORM => Some(3),
+        Format::B8G8R8A8_SNORM
+        | Format::B8G8R8A8_UINT
+        | Format::B8G8R8A8_SINT
+        | Format::A8B8G8R8_UNORM_PACK32
+        | Format::A8B8G8R8_SNORM_PACK32
+        | Format::A8B8G8R8_UINT_PACK32
+        | Format::A8B8G8R8_SINT_PACK32
+        | Format::A2B10G10R10_SNORM_PACK32
+        | Format::A2B10G10R10_UINT_PACK32
+        | Format::A2B10G10R10_SINT_PACK32
+        | Format::A2R10G10B10_SNORM_PACK32
+        | Format::A2R10G10B10_UINT_PACK32
+        | Format::A2R10G10B10_SINT_PACK32
+        | Format::E5B9G9R9_UFLOAT_PACK32 => Some(4),
+        Format::R16G16B16_UNORM
+        | Format::R16G16B16_SNORM
+        | Format::R16G16B16_UINT
+        | Format::R16G16B16_SINT
+        | Format::R16G16B16_SFLOAT => Some(6),
+        Format::R64_UINT | Format::R64_SINT | Format::R64_SFLOAT => Some(8),
+        Format::R64G64_UINT | Format::R64G64_SINT | Format::R64G64_SFLOAT => Some(16),
+        Format::D16_UNORM | Format::X8_D24_UNORM_PACK32 | Format::D32_SFLOAT | Format::S8_UINT => {
+            None
+        }
+        _ => texel_size_bytes(format),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_sizes() {
+        let table = [
+            // Plain formats, the same size as a texel.
+            (Format::R32_SFLOAT, Some(4)),
+            (Format::R32G32_SFLOAT, Some(8)),
+            (Format::R32G32B32_SFLOAT, Some(12)),
+            (Format::R32G32B32A32_SFLOAT, Some(16)),
+            (Format::R32G32B32A32_UINT, Some(16)),
+            (Format::R8G8B8A8_UNORM, Some(4)),
+            (Format::R8G8_SNORM, Some(2)),
+            (Format::R16G16_SNORM, Some(4)),
+            (Format::R16G16_SFLOAT, Some(4)),
+            (Format::R16G16B16A16_SFLOAT, Some(8)),
+            // Three component 8 and 16 bit formats aren't padded.
+            (Format::R8G8B8_UNORM, Some(3)),
+            (Format::B8G8R8_SNORM, Some(3)),
+            (Format::R16G16B16_SFLOAT, Some(6)),
+            (Format::R16G16B16_SINT, Some(6)),
+            // Packed formats are a single 32-bit word.
+            (Format::A2B10G10R10_UNORM_PACK32, Some(4)),
+            (Format::A2B10G10R10_SNORM_PACK32, Some(4)),
+            (Format::A2R10G10B10_SINT_PACK32, Some(4)),
+            (Format::A8B8G8R8_SNORM_PACK32, Some(4)),
+            (Format::B10G11R11_UFLOAT_PACK32, Some(4)),
+            (Format::E5B9G9R9_UFLOAT_PACK32, Some(4)),
+            // Double precision.
+            (Format::R64_SFLOAT, Some(8)),
+            (Format::R64G64_UINT, Some(16)),
+            // Depth and stencil formats are never vertex attributes.
+            (Format::D16_UNORM, None),
+            (Format::D32_SFLOAT, None),
+            (Format::X8_D24_UNORM_PACK32, None),
+            (Format::S8_UINT, None),
+            (Format::UNDEFINED, None),
+            (Format::BC1_RGB_UNORM_BLOCK, None),
+        ];
+        for (format, size) in table {
+            assert_eq!(attribute_size_bytes(format), size, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn attribute_sizes_match_texel_sizes_where_both_are_known() {
+        for format in [
+            Format::R8_UNORM,
+            Format::R16_SFLOAT,
+            Format::R8G8B8A8_SRGB,
+            Format::A2B10G10R10_UNORM_PACK32,
+            Format::R16G16B16A16_UNORM,
+            Format::R32G32_UINT,
+            Format::R32G32B32A32_SINT,
+        ] {
+            assert_eq!(
+                attribute_size_bytes(format),
+                texel_size_bytes(format),
+                "{:?}",
+                format
+            );
+        }
+    }
+}
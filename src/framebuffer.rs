@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use ash::vk::{FramebufferCreateInfo, ImageView};
+
+use crate::{device_context::DeviceContext, image_resource::ImageResource, renderpass::RenderPass};
+
+pub struct Framebuffer {
+    device: Rc<DeviceContext>,
+    handle: ash::vk::Framebuffer,
+    width: u32,
+    height: u32,
+}
+
+impl Framebuffer {
+    pub fn new(
+        device: Rc<DeviceContext>,
+        render_pass: &RenderPass,
+        attachments: &[&dyn ImageResource],
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let formats = render_pass.attachment_formats();
+        assert_eq!(
+            attachments.len(),
+            formats.len(),
+            "Framebuffer has {} attachments but the render pass expects {}",
+            attachments.len(),
+            formats.len()
+        );
+        for (index, (attachment, format)) in attachments.iter().zip(formats.iter()).enumerate() {
+            assert_eq!(
+                attachment.format(),
+                *format,
+                "Framebuffer attachment {} has format {:?} but the render pass expects {:?}",
+                index,
+                attachment.format(),
+                format
+            );
+        }
+
+        let views: Vec<ImageView> = attachments
+            .iter()
+            .map(|attachment| attachment.view())
+            .collect();
+        let info = FramebufferCreateInfo::default()
+            .render_pass(*render_pass.handle())
+            .attachments(&views)
+            .width(width)
+            .height(height)
+            .layers(1);
+        let handle = unsafe {
+            device
+                .handle()
+                .create_framebuffer(&info, device.allocation_callbacks())
+                .expect("Framebuffer creation failed")
+        };
+
+        Self {
+            device,
+            handle,
+            width,
+            height,
+        }
+    }
+
+    pub fn handle(&self) -> &ash::vk::Framebuffer {
+        &self.handle
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .destroy_framebuffer(self.handle, self.device.allocation_callbacks())
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    AttachmentLoadOp, AttachmentStoreOp, Format, ImageLayout, ImageUsageFlags, MemoryPropertyFlags,
+};
+
+use crate::{
+    device_context::DeviceContext,
+    framebuffer::Framebuffer,
+    image2d_resource::Image2DResource,
+    image_resource::ImageResource,
+    renderpass::{RenderPass, RenderPassBuilder},
+};
+
+pub struct GBuffer {
+    device: Rc<DeviceContext>,
+    formats: Vec<Format>,
+    depth_format: Format,
+    targets: Vec<Image2DResource>,
+    depth: Image2DResource,
+    render_pass: RenderPass,
+    framebuffer: Framebuffer,
+}
+
+impl GBuffer {
+    pub fn new(
+        device: Rc<DeviceContext>,
+        width: u32,
+        height: u32,
+        formats: &[Format],
+        depth_format: Format,
+    ) -> Self {
+        let mut builder = RenderPassBuilder::new();
+        for format in formats {
+            builder = builder.with_color_attachment(
+                *format,
+                AttachmentLoadOp::CLEAR,
+                AttachmentStoreOp::STORE,
+                ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        }
+        let render_pass = builder
+            .with_depth_attachment(
+                depth_format,
+                AttachmentLoadOp::CLEAR,
+                AttachmentStoreOp::STORE,
+                ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            )
+            .build(device.clone());
+
+        let (targets, depth, framebuffer) =
+            Self::create_targets(&device, &render_pass, formats, depth_format, width, height);
+
+        Self {
+            device,
+            formats: formats.to_vec(),
+            depth_format,
+            targets,
+            depth,
+            render_pass,
+            framebuffer,
+        }
+    }
+
+    fn create_targets(
+        device: &Rc<DeviceContext>,
+        render_pass: &RenderPass,
+        formats: &[Format],
+        depth_format: Format,
+        width: u32,
+        height: u32,
+    ) -> (Vec<Image2DResource>, Image2DResource, Framebuffer) {
+        let targets: Vec<Image2DResource> = formats
+            .iter()
+            .map(|format| {
+                Image2DResource::new(
+                    device.clone(),
+                    width,
+                    height,
+                    *format,
+                    ImageUsageFlags::COLOR_ATTACHMENT
+                        | ImageUsageFlags::SAMPLED
+                        | ImageUsageFlags::TRANSFER_SRC,
+                    MemoryPropertyFlags::DEVICE_LOCAL,
+                    1,
+                )
+            })
+            .collect();
+        let depth = Image2DResource::new(
+            device.clone(),
+            width,
+            height,
+            depth_format,
+            ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | ImageUsageFlags::SAMPLED,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            1,
+        );
+
+        let mut attachments: Vec<&dyn ImageResource> = targets
+            .iter()
+            .map(|target| target as &dyn ImageResource)
+            .collect();
+        attachments.push(&depth);
+        let framebuffer =
+            Framebuffer::new(device.clone(), render_pass, &attachments, width, height);
+
+        (targets, depth, framebuffer)
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (targets, depth, framebuffer) = Self::create_targets(
+            &self.device,
+            &self.render_pass,
+            &self.formats,
+            self.depth_format,
+            width,
+            height,
+        );
+        self.framebuffer = framebuffer;
+        self.targets = targets;
+        self.depth = depth;
+    }
+
+    pub fn width(&self) -> u32 {
+        self.framebuffer.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.framebuffer.height()
+    }
+
+    pub fn targets(&self) -> &[Image2DResource] {
+        &self.targets
+    }
+
+    pub fn target(&self, index: usize) -> &Image2DResource {
+        &self.targets[index]
+    }
+
+    pub fn target_mut(&mut self, index: usize) -> &mut Image2DResource {
+        &mut self.targets[index]
+    }
+
+    pub fn depth(&self) -> &Image2DResource {
+        &self.depth
+    }
+
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+}
@@ -1,19 +1,54 @@
+use ash::ext::memory_budget;
+use ash::khr::global_priority;
+use ash::khr::{
+    acceleration_structure, deferred_host_operations, present_id, present_wait, ray_query, surface,
+};
 use ash::vk::{
-    DeviceCreateInfo, ExtensionProperties, PhysicalDevice, PhysicalDeviceFeatures,
-    PhysicalDeviceLimits, PhysicalDeviceMemoryProperties2, PhysicalDeviceProperties,
-    PhysicalDeviceProperties2, PhysicalDeviceType, QueueFamilyProperties, QueueFlags,
+    DeviceCreateInfo, DeviceGroupDeviceCreateInfo, ExtensionProperties, Format, FormatFeatureFlags,
+    FormatProperties, MemoryHeapFlags, MemoryPropertyFlags, PhysicalDevice,
+    PhysicalDeviceAccelerationStructureFeaturesKHR, PhysicalDeviceBufferDeviceAddressFeatures,
+    PhysicalDeviceFeatures, PhysicalDeviceFeatures2, PhysicalDeviceIDProperties,
+    PhysicalDeviceLimits, PhysicalDeviceMemoryBudgetPropertiesEXT, PhysicalDeviceMemoryProperties,
+    PhysicalDeviceMemoryProperties2, PhysicalDevicePresentIdFeaturesKHR,
+    PhysicalDevicePresentWaitFeaturesKHR, PhysicalDeviceProperties, PhysicalDeviceProperties2,
+    PhysicalDeviceRayQueryFeaturesKHR, PhysicalDeviceType, QueueFamilyGlobalPriorityPropertiesKHR,
+    QueueFamilyProperties, QueueFamilyProperties2, QueueFlags, QueueGlobalPriorityKHR, SurfaceKHR,
 };
 
-use crate::device_context::DeviceContext;
+use crate::device_context::{DeviceContext, QueuePriority};
+use crate::image_resource::ImageResource;
+use crate::indices::QueueFamilyIndex;
 use crate::vulkan::Vulkan;
 use std::ffi::CStr;
 
+/// A set of physical devices that can be driven as one logical device (SLI / NVLink / CrossFire).
+/// Device groups are core since Vulkan 1.1; older instances need `VK_KHR_device_group_creation`
+/// and devices need `VK_KHR_device_group`. Allocations that span the group must use device masks
+/// (`MemoryAllocateFlagsInfo`) and bind per device, e.g. through `DeviceGroupBindSparseInfo`.
+#[derive(Clone)]
+pub struct PhysicalDeviceGroup {
+    pub devices: Vec<Gpu>,
+    pub subset_allocation: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeapInfo {
+    pub index: u32,
+    pub size: u64,
+    pub device_local: bool,
+    // What the process can allocate from the heap right now, needs VK_EXT_memory_budget.
+    pub budget: Option<u64>,
+}
+
 #[derive(Clone)]
 pub struct Gpu {
     vulkan: Vulkan,
     physical_device: PhysicalDevice,
     features: PhysicalDeviceFeatures,
     properties: PhysicalDeviceProperties,
+    uuid: [u8; 16],
+    #[cfg(windows)]
+    luid: Option<[u8; 8]>,
     queue_family_properties: Vec<QueueFamilyProperties>,
 }
 
@@ -46,6 +81,21 @@ impl Gpu {
                 }
             }
 
+            // Stable across reboots and driver updates, unlike the enumeration order.
+            let mut id_properties = PhysicalDeviceIDProperties::default();
+            let mut properties2 =
+                PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+            vulkan
+                .vk_instance()
+                .get_physical_device_properties2(*physical_device, &mut properties2);
+            let uuid = id_properties.device_uuid;
+            #[cfg(windows)]
+            let luid = if id_properties.device_luid_valid != 0 {
+                Some(id_properties.device_luid)
+            } else {
+                None
+            };
+
             let mut memory_properties = PhysicalDeviceMemoryProperties2::default();
             vulkan
                 .vk_instance()
@@ -54,6 +104,9 @@ impl Gpu {
                 vulkan: vulkan.clone(),
                 features,
                 properties,
+                uuid,
+                #[cfg(windows)]
+                luid,
                 physical_device: *physical_device,
                 queue_family_properties: vulkan
                     .vk_instance()
@@ -70,13 +123,84 @@ impl Gpu {
     where
         F: FnOnce(DeviceCreateInfo<'b>) -> DeviceCreateInfo<'b>,
     {
-        DeviceContext::new(
+        self.device_context_with_queue_priorities(extensions, &[], builder_function)
+    }
+
+    // Families without an entry get a single queue at priority 1.0. Global priorities need
+    // VK_KHR_global_priority in the extensions, see QueuePriority::with_global_priority.
+    pub fn device_context_with_queue_priorities<'b, F>(
+        &self,
+        extensions: &[&str],
+        queue_priorities: &[QueuePriority],
+        builder_function: F,
+    ) -> DeviceContext
+    where
+        F: FnOnce(DeviceCreateInfo<'b>) -> DeviceCreateInfo<'b>,
+    {
+        DeviceContext::new_with_queue_priorities(
             self,
             extensions,
+            queue_priorities,
             builder_function(DeviceCreateInfo::default()),
         )
     }
 
+    pub fn device_group_context(
+        &self,
+        group: &PhysicalDeviceGroup,
+        extensions: &[&str],
+    ) -> DeviceContext {
+        debug_assert!(
+            group
+                .devices
+                .iter()
+                .any(|gpu| gpu.physical_device == self.physical_device),
+            "Gpu is not part of the device group"
+        );
+        let physical_devices: Vec<PhysicalDevice> = group
+            .devices
+            .iter()
+            .map(|gpu| gpu.physical_device)
+            .collect();
+        let mut group_info =
+            DeviceGroupDeviceCreateInfo::default().physical_devices(&physical_devices);
+        DeviceContext::new(
+            self,
+            extensions,
+            DeviceCreateInfo::default().push_next(&mut group_info),
+        )
+    }
+
+    // Enables acceleration structures and ray queries so any shader stage can trace with rayQueryEXT.
+    pub fn ray_query_device_context(&self, extensions: &[&str]) -> DeviceContext {
+        let mut extensions = extensions.to_vec();
+        for name in [
+            acceleration_structure::NAME,
+            ray_query::NAME,
+            deferred_host_operations::NAME,
+        ] {
+            let name = name.to_str().unwrap();
+            if !extensions.contains(&name) {
+                extensions.push(name);
+            }
+        }
+
+        let mut buffer_device_address =
+            PhysicalDeviceBufferDeviceAddressFeatures::default().buffer_device_address(true);
+        let mut acceleration_structure_features =
+            PhysicalDeviceAccelerationStructureFeaturesKHR::default().acceleration_structure(true);
+        let mut ray_query_features = PhysicalDeviceRayQueryFeaturesKHR::default().ray_query(true);
+        let mut features = PhysicalDeviceFeatures2::default()
+            .push_next(&mut buffer_device_address)
+            .push_next(&mut acceleration_structure_features)
+            .push_next(&mut ray_query_features);
+        DeviceContext::new(
+            self,
+            &extensions,
+            DeviceCreateInfo::default().push_next(&mut features),
+        )
+    }
+
     pub fn has_extension(&self, extension: &str) -> bool {
         self.device_extensions().iter().any(|ext| unsafe {
             CStr::from_ptr(ext.extension_name.as_ptr())
@@ -112,6 +236,39 @@ impl Gpu {
         None
     }
 
+    /// A family that only does transfers, usually a DMA engine that runs alongside the graphics
+    /// queue. Desktop AMD and NVIDIA GPUs almost always expose one.
+    pub fn dedicated_transfer_family_index(&self) -> Option<u32> {
+        self.queue_family_properties
+            .iter()
+            .position(|queue_info| {
+                queue_info.queue_flags.contains(QueueFlags::TRANSFER)
+                    && !queue_info
+                        .queue_flags
+                        .intersects(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+            })
+            .map(|index| index as u32)
+    }
+
+    /// Families that can present to `surface`, the ones that also support graphics come first.
+    pub fn present_family_indices(&self, surface: SurfaceKHR) -> Vec<u32> {
+        let surface_loader =
+            surface::Instance::new(self.vulkan.library(), self.vulkan.vk_instance());
+        let mut indices: Vec<u32> = (0..self.queue_family_count())
+            .filter(|index| unsafe {
+                surface_loader
+                    .get_physical_device_surface_support(self.physical_device, *index, surface)
+                    .unwrap_or(false)
+            })
+            .collect();
+        indices.sort_by_key(|index| {
+            !self.queue_family_properties[*index as usize]
+                .queue_flags
+                .contains(QueueFlags::GRAPHICS)
+        });
+        indices
+    }
+
     pub fn vk_physical_device(&self) -> &PhysicalDevice {
         &self.physical_device
     }
@@ -128,6 +285,21 @@ impl Gpu {
         self.properties.device_id
     }
 
+    // Persistent identifier, e.g. for remembering the selected gpu in a config file.
+    pub fn uuid(&self) -> [u8; 16] {
+        self.uuid
+    }
+
+    // Matches the DXGI adapter LUID, None when the driver doesn't report a valid one.
+    #[cfg(windows)]
+    pub fn luid(&self) -> Option<[u8; 8]> {
+        self.luid
+    }
+
+    pub fn api_version(&self) -> u32 {
+        self.properties.api_version
+    }
+
     pub fn driver_version(&self) -> u32 {
         self.properties.driver_version
     }
@@ -140,6 +312,33 @@ impl Gpu {
         self.properties.device_type == PhysicalDeviceType::VIRTUAL_GPU
     }
 
+    /// True when `VK_KHR_present_id` and `VK_KHR_present_wait` and their features are available.
+    /// Both extensions and features still have to be enabled through `device_context_builder`.
+    pub fn present_wait_supported(&self) -> bool {
+        if !self.has_all_extensions(&[
+            present_id::NAME.to_str().unwrap(),
+            present_wait::NAME.to_str().unwrap(),
+        ]) {
+            return false;
+        }
+
+        let mut present_id_features = PhysicalDevicePresentIdFeaturesKHR::default();
+        let mut present_wait_features = PhysicalDevicePresentWaitFeaturesKHR::default();
+        let mut features = PhysicalDeviceFeatures2::default()
+            .push_next(&mut present_id_features)
+            .push_next(&mut present_wait_features);
+        unsafe {
+            self.vulkan
+                .vk_instance()
+                .get_physical_device_features2(self.physical_device, &mut features);
+        }
+        present_id_features.present_id != 0 && present_wait_features.present_wait != 0
+    }
+
+    pub fn features(&self) -> &PhysicalDeviceFeatures {
+        &self.features
+    }
+
     pub fn limits(&self) -> PhysicalDeviceLimits {
         self.properties.limits
     }
@@ -148,8 +347,37 @@ impl Gpu {
         self.queue_family_properties.len() as u32
     }
 
-    pub fn queue_count(&self, queue_family_index: u32) -> u32 {
-        self.queue_family_properties[queue_family_index as usize].queue_count
+    pub fn queue_count(&self, queue_family_index: impl Into<QueueFamilyIndex>) -> u32 {
+        self.queue_family_properties[queue_family_index.into().index()].queue_count
+    }
+
+    // Global priorities a queue of the family may be created with, empty when the device doesn't
+    // expose VK_KHR_global_priority.
+    pub fn queue_family_global_priorities(
+        &self,
+        queue_family_index: impl Into<QueueFamilyIndex>,
+    ) -> Vec<QueueGlobalPriorityKHR> {
+        if !self.has_extension(global_priority::NAME.to_str().unwrap()) {
+            return Vec::new();
+        }
+        let instance = self.vulkan.vk_instance();
+        unsafe {
+            let count =
+                instance.get_physical_device_queue_family_properties2_len(self.physical_device);
+            let mut priorities = vec![QueueFamilyGlobalPriorityPropertiesKHR::default(); count];
+            let mut properties: Vec<QueueFamilyProperties2> = priorities
+                .iter_mut()
+                .map(|priorities| QueueFamilyProperties2::default().push_next(priorities))
+                .collect();
+            instance.get_physical_device_queue_family_properties2(
+                self.physical_device,
+                &mut properties,
+            );
+            drop(properties);
+            priorities[queue_family_index.into().index()]
+                .priorities_as_slice()
+                .to_vec()
+        }
     }
 
     pub fn device_extensions(&self) -> Vec<ExtensionProperties> {
@@ -204,6 +432,64 @@ impl Gpu {
         false
     }
 
+    pub fn format_properties(&self, format: Format) -> FormatProperties {
+        unsafe {
+            self.vulkan
+                .vk_instance()
+                .get_physical_device_format_properties(self.physical_device, format)
+        }
+    }
+
+    pub fn supported_depth_format(&self, need_stencil: bool) -> Option<Format> {
+        let candidates: &[Format] = if need_stencil {
+            &[Format::D32_SFLOAT_S8_UINT, Format::D24_UNORM_S8_UINT]
+        } else {
+            &[
+                Format::D32_SFLOAT,
+                Format::D32_SFLOAT_S8_UINT,
+                Format::D24_UNORM_S8_UINT,
+            ]
+        };
+        candidates.iter().copied().find(|format| {
+            self.format_properties(*format)
+                .optimal_tiling_features
+                .contains(FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+    }
+
+    pub fn dispatch_size_for_image(
+        &self,
+        image: &impl ImageResource,
+        local_x: u32,
+        local_y: u32,
+    ) -> (u32, u32, u32) {
+        let size = (
+            image.width().div_ceil(local_x),
+            image.height().div_ceil(local_y),
+            1,
+        );
+        let max = self.properties.limits.max_compute_work_group_count;
+        debug_assert!(
+            size.0 <= max[0] && size.1 <= max[1],
+            "Dispatch of {:?} workgroups exceeds the device limit of {:?}",
+            size,
+            max
+        );
+        size
+    }
+
+    pub fn dispatch_size_for_elements(&self, count: usize, local_size: u32) -> u32 {
+        let size = count.div_ceil(local_size as usize) as u32;
+        let max = self.properties.limits.max_compute_work_group_count[0];
+        debug_assert!(
+            size <= max,
+            "Dispatch of {} workgroups exceeds the device limit of {}",
+            size,
+            max
+        );
+        size
+    }
+
     pub fn vulkan(&self) -> &Vulkan {
         &self.vulkan
     }
@@ -215,4 +501,61 @@ impl Gpu {
                 .get_physical_device_memory_properties2(*self.vk_physical_device(), properties)
         };
     }
+
+    pub fn memory_properties2(&self) -> PhysicalDeviceMemoryProperties {
+        let mut properties = PhysicalDeviceMemoryProperties2::default();
+        self.memory_properties(&mut properties);
+        properties.memory_properties
+    }
+
+    pub fn device_local_memory_bytes(&self) -> u64 {
+        let properties = self.memory_properties2();
+        properties.memory_heaps[..properties.memory_heap_count as usize]
+            .iter()
+            .filter(|heap| heap.flags.contains(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum()
+    }
+
+    // Heaps with at least one host visible memory type, on integrated GPUs this includes the
+    // device local heaps.
+    pub fn host_visible_memory_bytes(&self) -> u64 {
+        let properties = self.memory_properties2();
+        (0..properties.memory_heap_count)
+            .filter(|heap| {
+                properties.memory_types[..properties.memory_type_count as usize]
+                    .iter()
+                    .any(|memory_type| {
+                        memory_type.heap_index == *heap
+                            && memory_type
+                                .property_flags
+                                .contains(MemoryPropertyFlags::HOST_VISIBLE)
+                    })
+            })
+            .map(|heap| properties.memory_heaps[heap as usize].size)
+            .sum()
+    }
+
+    // Budgets are only filled in when the device exposes VK_EXT_memory_budget.
+    pub fn memory_report(&self) -> Vec<HeapInfo> {
+        let mut budget_properties = PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let has_budget = self.has_extension(memory_budget::NAME.to_str().unwrap());
+        let mut properties = PhysicalDeviceMemoryProperties2::default();
+        if has_budget {
+            properties = properties.push_next(&mut budget_properties);
+        }
+        self.memory_properties(&mut properties);
+        let memory_properties = properties.memory_properties;
+
+        memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| HeapInfo {
+                index: index as u32,
+                size: heap.size,
+                device_local: heap.flags.contains(MemoryHeapFlags::DEVICE_LOCAL),
+                budget: has_budget.then(|| budget_properties.heap_budget[index]),
+            })
+            .collect()
+    }
 }
@@ -1,15 +1,26 @@
-use std::{ffi::CString, rc::Rc};
+use std::{collections::BTreeMap, ffi::CString, rc::Rc};
 
 use ash::vk::{
-    Bool32, CullModeFlags, FrontFace, GraphicsPipelineCreateInfo, Pipeline, PipelineCache,
-    PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo,
-    PipelineDepthStencilStateCreateInfo, PipelineDynamicStateCreateInfo, PipelineLayout,
+    Bool32, ColorComponentFlags, CompareOp, CullModeFlags, DescriptorSetLayout, DynamicState,
+    Format, FrontFace, GraphicsPipelineCreateInfo, LogicOp, Pipeline, PipelineCache,
+    PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo, PipelineCreateFlags,
+    PipelineDepthStencilStateCreateInfo, PipelineDynamicStateCreateInfo,
+    PipelineInputAssemblyStateCreateInfo, PipelineLayout, PipelineLayoutCreateInfo,
     PipelineMultisampleStateCreateInfo, PipelineRasterizationStateCreateInfo,
     PipelineShaderStageCreateInfo, PipelineTessellationStateCreateInfo,
-    PipelineViewportStateCreateInfo, PolygonMode, ShaderModule, ShaderStageFlags, Viewport,
+    PipelineVertexInputStateCreateInfo, PipelineViewportStateCreateInfo, PolygonMode,
+    PrimitiveTopology, PushConstantRange, Rect2D, SampleCountFlags, SampleMask, ShaderModule,
+    ShaderStageFlags, VertexInputAttributeDescription, VertexInputBindingDescription,
+    VertexInputRate, Viewport,
 };
 
-use crate::device_context::DeviceContext;
+use crate::{
+    device_context::DeviceContext,
+    renderpass::RenderPass,
+    shader_compiler::ShaderReflection,
+    shader_module_cache::ShaderModuleHandle,
+    vertex_layout::{validate_vertex_formats, validate_vertex_layout, VertexLayout},
+};
 
 #[derive(Clone)]
 pub struct DepthState {
@@ -23,15 +34,33 @@ impl Default for DepthState {
         Self {
             depth_test_enable: 0,
             depth_write_enable: 0,
-            depth_compare_op: 0,
+            // Raw CompareOp, nearer fragments pass.
+            depth_compare_op: CompareOp::LESS.as_raw() as u32,
         }
     }
 }
 
 #[derive(Clone)]
 pub struct MultiSampleState {
-    pub sample_shading_enable: u32,
-    pub rasterization_samples: u32,
+    pub rasterization_samples: SampleCountFlags,
+    pub sample_shading_enable: bool,
+    // Fraction of the samples shaded individually, 1.0 runs the fragment shader per sample.
+    pub min_sample_shading: f32,
+    // One bit per sample in words of 32 samples, empty covers all samples.
+    pub sample_mask: Vec<SampleMask>,
+    pub alpha_to_coverage_enable: bool,
+}
+
+impl Default for MultiSampleState {
+    fn default() -> Self {
+        Self {
+            rasterization_samples: SampleCountFlags::TYPE_1,
+            sample_shading_enable: false,
+            min_sample_shading: 1.0,
+            sample_mask: Vec::new(),
+            alpha_to_coverage_enable: false,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -40,6 +69,10 @@ pub struct RasterizerState {
     pub polygon_mode: PolygonMode,
     pub cull_mode: CullModeFlags,
     pub front_face: FrontFace,
+    pub depth_bias_enable: Bool32,
+    pub depth_bias_constant_factor: f32,
+    pub depth_bias_slope_factor: f32,
+    pub line_width: f32,
 }
 
 impl Default for RasterizerState {
@@ -49,17 +82,32 @@ impl Default for RasterizerState {
             polygon_mode: PolygonMode::FILL,
             cull_mode: CullModeFlags::BACK,
             front_face: FrontFace::COUNTER_CLOCKWISE,
+            depth_bias_enable: 0,
+            depth_bias_constant_factor: 0.0,
+            depth_bias_slope_factor: 0.0,
+            line_width: 1.0,
         }
     }
 }
 
 #[derive(Default, Clone)]
 pub struct GraphicsPipelineState {
-    blend_state: Option<PipelineColorBlendAttachmentState>,
+    render_pass: ash::vk::RenderPass,
+    subpass: u32,
+    blend_states: Vec<PipelineColorBlendAttachmentState>,
+    logic_op: Option<LogicOp>,
+    topology: Option<PrimitiveTopology>,
+    primitive_restart: bool,
     depth_stencil_state: Option<DepthState>,
     multisample_state: Option<MultiSampleState>,
     rasterization_state: Option<RasterizerState>,
+    dynamic_states: Vec<DynamicState>,
     viewports: Vec<Viewport>,
+    scissors: Vec<Rect2D>,
+    vertex_bindings: Vec<VertexInputBindingDescription>,
+    vertex_attributes: Vec<VertexInputAttributeDescription>,
+    vertex_shader_inputs: Option<BTreeMap<u32, Format>>,
+    flags: PipelineCreateFlags,
 }
 
 impl GraphicsPipelineState {
@@ -67,14 +115,138 @@ impl GraphicsPipelineState {
         Self::default()
     }
 
+    pub fn with_flags(mut self, flags: PipelineCreateFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
     pub fn with_viewport(mut self, width: u32, height: u32) -> Self {
         let vp = Viewport::default()
             .width(width as f32)
-            .height(height as f32);
+            .height(height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
         self.viewports = [vp].to_vec();
         self
     }
 
+    // A negative height flips Y, y is then the bottom edge of the viewport.
+    pub fn with_viewport_rect(
+        mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        min_depth: f32,
+        max_depth: f32,
+    ) -> Self {
+        let vp = Viewport {
+            x,
+            y,
+            width,
+            height,
+            min_depth,
+            max_depth,
+        };
+        self.viewports = [vp].to_vec();
+        self
+    }
+
+    pub fn with_viewport_depth(mut self, min_depth: f32, max_depth: f32) -> Self {
+        for viewport in &mut self.viewports {
+            viewport.min_depth = min_depth;
+            viewport.max_depth = max_depth;
+        }
+        self
+    }
+
+    // Without an explicit scissor every viewport gets one covering it entirely.
+    pub fn with_scissor(mut self, x: i32, y: i32, width: u32, height: u32) -> Self {
+        self.scissors = [Rect2D {
+            offset: ash::vk::Offset2D { x, y },
+            extent: ash::vk::Extent2D { width, height },
+        }]
+        .to_vec();
+        self
+    }
+
+    // Y points up like in OpenGL and D3D content, without touching the shaders.
+    pub fn with_flipped_viewport(self, width: u32, height: u32) -> Self {
+        self.with_viewport_rect(0.0, height as f32, width as f32, -(height as f32), 0.0, 1.0)
+    }
+
+    // Locations continue after the attributes of previously added bindings.
+    pub fn with_vertex_layout<V: VertexLayout>(self, binding: u32, rate: VertexInputRate) -> Self {
+        let first_location = self.vertex_attributes.len() as u32;
+        self.with_vertex_input(
+            VertexInputBindingDescription::default()
+                .binding(binding)
+                .stride(V::stride())
+                .input_rate(rate),
+            &V::attribute_descriptions(binding, first_location),
+        )
+    }
+
+    pub fn with_vertex_input(
+        mut self,
+        binding: VertexInputBindingDescription,
+        attributes: &[VertexInputAttributeDescription],
+    ) -> Self {
+        self.vertex_bindings.push(binding);
+        self.vertex_attributes.extend_from_slice(attributes);
+        self
+    }
+
+    // The vertex attributes are checked against these inputs when the pipeline is created.
+    pub fn with_vertex_shader_reflection(mut self, reflection: &ShaderReflection) -> Self {
+        self.vertex_shader_inputs = Some(reflection.vertex_inputs());
+        self
+    }
+
+    pub fn with_render_pass(mut self, render_pass: &RenderPass, subpass: u32) -> Self {
+        self.render_pass = *render_pass.handle();
+        self.subpass = subpass;
+        if self.blend_states.len() < render_pass.color_attachment_count() {
+            self.blend_states.resize(
+                render_pass.color_attachment_count(),
+                Self::default_blend_state(),
+            );
+        }
+        self
+    }
+
+    pub fn with_blend_state(
+        mut self,
+        attachment: usize,
+        state: PipelineColorBlendAttachmentState,
+    ) -> Self {
+        if self.blend_states.len() <= attachment {
+            self.blend_states
+                .resize(attachment + 1, Self::default_blend_state());
+        }
+        self.blend_states[attachment] = state;
+        self
+    }
+
+    pub fn with_color_write_mask(mut self, attachment: usize, mask: ColorComponentFlags) -> Self {
+        if self.blend_states.len() <= attachment {
+            self.blend_states
+                .resize(attachment + 1, Self::default_blend_state());
+        }
+        self.blend_states[attachment].color_write_mask = mask;
+        self
+    }
+
+    // Requires the logic_op device feature, blending is ignored while a logic op is active.
+    pub fn with_logic_op(mut self, op: LogicOp) -> Self {
+        self.logic_op = Some(op);
+        self
+    }
+
+    fn default_blend_state() -> PipelineColorBlendAttachmentState {
+        PipelineColorBlendAttachmentState::default().color_write_mask(ColorComponentFlags::RGBA)
+    }
+
     pub fn with_polygon_mode(mut self, mode: PolygonMode) -> Self {
         if self.rasterization_state.is_none() {
             self.rasterization_state = Some(RasterizerState::default())
@@ -84,6 +256,86 @@ impl GraphicsPipelineState {
         self
     }
 
+    // Point sizes come from gl_PointSize in the vertex shader, sizes above 1 need the large_points feature.
+    pub fn with_topology(mut self, topology: PrimitiveTopology) -> Self {
+        self.topology = Some(topology);
+        self.validate_primitive_restart();
+        self
+    }
+
+    // Restart indices are 0xFFFF for UINT16 and 0xFFFFFFFF for UINT32 index buffers.
+    pub fn with_primitive_restart_enable(mut self, enable: bool) -> Self {
+        self.primitive_restart = enable;
+        if self.topology.is_some() {
+            self.validate_primitive_restart();
+        }
+        self
+    }
+
+    fn validate_primitive_restart(&self) {
+        let topology = self.topology.unwrap_or(PrimitiveTopology::TRIANGLE_LIST);
+        assert!(
+            !self.primitive_restart
+                || matches!(
+                    topology,
+                    PrimitiveTopology::LINE_STRIP
+                        | PrimitiveTopology::TRIANGLE_STRIP
+                        | PrimitiveTopology::TRIANGLE_FAN
+                        | PrimitiveTopology::LINE_STRIP_WITH_ADJACENCY
+                        | PrimitiveTopology::TRIANGLE_STRIP_WITH_ADJACENCY
+                ),
+            "Primitive restart requires a strip or fan topology, got {:?}",
+            topology
+        );
+    }
+
+    pub fn with_line_width(mut self, width: f32) -> Self {
+        if self.rasterization_state.is_none() {
+            self.rasterization_state = Some(RasterizerState::default())
+        }
+
+        self.rasterization_state.as_mut().unwrap().line_width = width;
+        self
+    }
+
+    // The state is then set while recording, e.g. CommandBuffer::set_line_width for LINE_WIDTH.
+    pub fn with_dynamic_state(mut self, state: DynamicState) -> Self {
+        if !self.dynamic_states.contains(&state) {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    pub fn with_cull_mode(mut self, mode: CullModeFlags) -> Self {
+        if self.rasterization_state.is_none() {
+            self.rasterization_state = Some(RasterizerState::default())
+        }
+
+        self.rasterization_state.as_mut().unwrap().cull_mode = mode;
+        self
+    }
+
+    pub fn with_front_face(mut self, face: FrontFace) -> Self {
+        if self.rasterization_state.is_none() {
+            self.rasterization_state = Some(RasterizerState::default())
+        }
+
+        self.rasterization_state.as_mut().unwrap().front_face = face;
+        self
+    }
+
+    pub fn with_depth_bias(mut self, constant: f32, slope: f32) -> Self {
+        if self.rasterization_state.is_none() {
+            self.rasterization_state = Some(RasterizerState::default())
+        }
+
+        let rasterization_state = self.rasterization_state.as_mut().unwrap();
+        rasterization_state.depth_bias_enable = 1;
+        rasterization_state.depth_bias_constant_factor = constant;
+        rasterization_state.depth_bias_slope_factor = slope;
+        self
+    }
+
     pub fn with_depth_testing(mut self) -> Self {
         if self.depth_stencil_state.is_none() {
             self.depth_stencil_state = Some(DepthState::default())
@@ -104,6 +356,43 @@ impl GraphicsPipelineState {
         self
     }
 
+    pub fn with_multisample_state(mut self, state: MultiSampleState) -> Self {
+        self.multisample_state = Some(state);
+        self
+    }
+
+    // Has to match the sample count of the render pass attachments.
+    pub fn with_rasterization_samples(mut self, samples: SampleCountFlags) -> Self {
+        self.multisample_state
+            .get_or_insert_with(MultiSampleState::default)
+            .rasterization_samples = samples;
+        self
+    }
+
+    // Requires the sample_rate_shading device feature.
+    pub fn with_sample_shading(mut self, min_sample_shading: f32) -> Self {
+        let state = self
+            .multisample_state
+            .get_or_insert_with(MultiSampleState::default);
+        state.sample_shading_enable = true;
+        state.min_sample_shading = min_sample_shading;
+        self
+    }
+
+    pub fn with_sample_mask(mut self, mask: &[SampleMask]) -> Self {
+        self.multisample_state
+            .get_or_insert_with(MultiSampleState::default)
+            .sample_mask = mask.to_vec();
+        self
+    }
+
+    pub fn with_alpha_to_coverage(mut self, enable: bool) -> Self {
+        self.multisample_state
+            .get_or_insert_with(MultiSampleState::default)
+            .alpha_to_coverage_enable = enable;
+        self
+    }
+
     // pub fn with_vertex_shader(mut self, name: &str, module: &ShaderModule) -> Self {
     //     self.shader_stage_state.push(
     //         PipelineShaderStageCreateInfo::default()
@@ -157,30 +446,327 @@ impl GraphicsPipelineState {
     // }
 }
 
+pub(crate) fn validate_line_width(device: &DeviceContext, width: f32) {
+    if width == 1.0 {
+        return;
+    }
+
+    assert!(
+        device.enabled_features().wide_lines != 0,
+        "Line width {} requires the wide_lines device feature",
+        width
+    );
+    let range = device.gpu().limits().line_width_range;
+    assert!(
+        width >= range[0] && width <= range[1],
+        "Line width {} is outside the supported range {:?}",
+        width,
+        range
+    );
+}
+
+pub(crate) fn validate_multisample_state(device: &DeviceContext, state: &MultiSampleState) {
+    assert!(
+        !state.sample_shading_enable || device.enabled_features().sample_rate_shading != 0,
+        "Sample shading requires the sample_rate_shading device feature"
+    );
+    assert!(
+        (0.0..=1.0).contains(&state.min_sample_shading),
+        "Min sample shading {} is outside of [0, 1]",
+        state.min_sample_shading
+    );
+    let words = (state.rasterization_samples.as_raw() as usize).div_ceil(32);
+    assert!(
+        state.sample_mask.is_empty() || state.sample_mask.len() == words,
+        "Sample mask for {:?} needs {} words, got {}",
+        state.rasterization_samples,
+        words,
+        state.sample_mask.len()
+    );
+}
+
+// Depth outside of [0, 1] needs VK_EXT_depth_range_unrestricted.
+pub(crate) fn validate_viewport(device: &DeviceContext, viewport: &Viewport) {
+    let unrestricted =
+        device.has_extension(ash::ext::depth_range_unrestricted::NAME.to_str().unwrap());
+    assert!(
+        unrestricted
+            || [viewport.min_depth, viewport.max_depth]
+                .iter()
+                .all(|depth| (0.0..=1.0).contains(depth)),
+        "Viewport depth range {}..{} is outside of [0, 1], enable VK_EXT_depth_range_unrestricted",
+        viewport.min_depth,
+        viewport.max_depth
+    );
+}
+
+// Scissor covering the viewport, flipped viewports extend upwards from y.
+fn viewport_scissor(viewport: &Viewport) -> Rect2D {
+    let top = viewport.y.min(viewport.y + viewport.height);
+    Rect2D {
+        offset: ash::vk::Offset2D {
+            x: viewport.x as i32,
+            y: top as i32,
+        },
+        extent: ash::vk::Extent2D {
+            width: viewport.width as u32,
+            height: viewport.height.abs() as u32,
+        },
+    }
+}
+
+fn blend_states_equal(
+    a: &PipelineColorBlendAttachmentState,
+    b: &PipelineColorBlendAttachmentState,
+) -> bool {
+    a.blend_enable == b.blend_enable
+        && a.src_color_blend_factor == b.src_color_blend_factor
+        && a.dst_color_blend_factor == b.dst_color_blend_factor
+        && a.color_blend_op == b.color_blend_op
+        && a.src_alpha_blend_factor == b.src_alpha_blend_factor
+        && a.dst_alpha_blend_factor == b.dst_alpha_blend_factor
+        && a.alpha_blend_op == b.alpha_blend_op
+        && a.color_write_mask == b.color_write_mask
+}
+
+// Stages declaring the same push constant block share one range.
+fn reflected_push_constant_ranges(
+    stages: &[(ShaderStageFlags, &ShaderReflection)],
+) -> Vec<PushConstantRange> {
+    let mut ranges: Vec<PushConstantRange> = Vec::new();
+    for (stage, reflection) in stages {
+        let Ok(Some(block)) = reflection.push_constant_ranges() else {
+            continue;
+        };
+        match ranges
+            .iter_mut()
+            .find(|range| range.offset == block.offset && range.size == block.size)
+        {
+            Some(range) => range.stage_flags |= *stage,
+            None => ranges.push(
+                PushConstantRange::default()
+                    .offset(block.offset)
+                    .size(block.size)
+                    .stage_flags(*stage),
+            ),
+        }
+    }
+    ranges
+}
+
 pub struct GraphicsPipeline {
     device: Rc<DeviceContext>,
     pipeline_layout: PipelineLayout,
     pipeline: Pipeline,
+    // Shared through the device like the module of a ComputePipeline.
+    _shader_modules: Vec<Rc<ShaderModuleHandle>>,
 }
 
 impl GraphicsPipeline {
     pub fn new(device: Rc<DeviceContext>, state: &GraphicsPipelineState) -> Self {
+        Self::create(device, state, &[], PipelineLayout::null(), Vec::new())
+    }
+
+    // Both stages are compiled SPIR-V with a "main" entry point. The render pass replaces the one
+    // on the state, the vertex attributes are validated against the vertex shader inputs and the
+    // push constant ranges are reflected from both stages. descriptor_layouts are in set order.
+    pub fn new_from_shaders(
+        device: Rc<DeviceContext>,
+        vertex_spirv: &[u32],
+        fragment_spirv: &[u32],
+        state: &GraphicsPipelineState,
+        render_pass: &RenderPass,
+        descriptor_layouts: &[DescriptorSetLayout],
+    ) -> Self {
+        let vertex_reflection = ShaderReflection::from_spirv(vertex_spirv)
+            .unwrap_or_else(|error| panic!("Vertex shader reflection failed: {}", error));
+        let fragment_reflection = ShaderReflection::from_spirv(fragment_spirv)
+            .unwrap_or_else(|error| panic!("Fragment shader reflection failed: {}", error));
+        for (name, reflection) in [
+            ("Vertex", &vertex_reflection),
+            ("Fragment", &fragment_reflection),
+        ] {
+            assert!(
+                reflection.has_entry_point("main"),
+                "{} shader has no \"main\" entry point, available entry points: {:?}",
+                name,
+                reflection.entry_points()
+            );
+        }
+
+        let state = state
+            .clone()
+            .with_render_pass(render_pass, state.subpass)
+            .with_vertex_shader_reflection(&vertex_reflection);
+        assert!(
+            !state.viewports.is_empty(),
+            "Graphics pipelines need a viewport, set one with with_viewport"
+        );
+
+        let push_constant_ranges = reflected_push_constant_ranges(&[
+            (ShaderStageFlags::VERTEX, &vertex_reflection),
+            (ShaderStageFlags::FRAGMENT, &fragment_reflection),
+        ]);
+        let layout_info = PipelineLayoutCreateInfo::default()
+            .set_layouts(descriptor_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .handle()
+                .create_pipeline_layout(&layout_info, device.allocation_callbacks())
+                .expect("Pipeline layout creation failed")
+        };
+
+        let modules = vec![
+            device.get_or_create_shader_module(vertex_spirv),
+            device.get_or_create_shader_module(fragment_spirv),
+        ];
+        let stages = [
+            PipelineShaderStageCreateInfo::default()
+                .stage(ShaderStageFlags::VERTEX)
+                .module(modules[0].handle())
+                .name(c"main"),
+            PipelineShaderStageCreateInfo::default()
+                .stage(ShaderStageFlags::FRAGMENT)
+                .module(modules[1].handle())
+                .name(c"main"),
+        ];
+        Self::create(device, &state, &stages, pipeline_layout, modules)
+    }
+
+    fn create(
+        device: Rc<DeviceContext>,
+        state: &GraphicsPipelineState,
+        stages: &[PipelineShaderStageCreateInfo],
+        pipeline_layout: PipelineLayout,
+        shader_modules: Vec<Rc<ShaderModuleHandle>>,
+    ) -> Self {
         // let dynamic_state = state.dynamic_state.unwrap_or_default();
         // let rasterizer_state = state.rasterization_state.unwrap_or_default();
         // let blend_state = state.blend_state.unwrap_or_default();
 
-        let info = GraphicsPipelineCreateInfo::default();
+        let rasterizer = state.rasterization_state.clone().unwrap_or_default();
+        validate_line_width(&device, rasterizer.line_width);
+        let rasterization_state = PipelineRasterizationStateCreateInfo::default()
+            .rasterizer_discard_enable(rasterizer.rasterizer_discard_enable != 0)
+            .polygon_mode(rasterizer.polygon_mode)
+            .cull_mode(rasterizer.cull_mode)
+            .front_face(rasterizer.front_face)
+            .depth_bias_enable(rasterizer.depth_bias_enable != 0)
+            .depth_bias_constant_factor(rasterizer.depth_bias_constant_factor)
+            .depth_bias_slope_factor(rasterizer.depth_bias_slope_factor)
+            .line_width(rasterizer.line_width);
+
+        state.validate_primitive_restart();
+        let input_assembly_state = PipelineInputAssemblyStateCreateInfo::default()
+            .topology(state.topology.unwrap_or(PrimitiveTopology::TRIANGLE_LIST))
+            .primitive_restart_enable(state.primitive_restart);
+
+        let features = device.enabled_features();
+        assert!(
+            state.logic_op.is_none() || features.logic_op != 0,
+            "Blend logic ops require the logic_op device feature"
+        );
+        assert!(
+            features.independent_blend != 0
+                || state
+                    .blend_states
+                    .windows(2)
+                    .all(|pair| blend_states_equal(&pair[0], &pair[1])),
+            "Per attachment blend states differ but the independent_blend device feature is not enabled"
+        );
+
+        let blend_state = PipelineColorBlendStateCreateInfo::default()
+            .attachments(&state.blend_states)
+            .logic_op_enable(state.logic_op.is_some())
+            .logic_op(state.logic_op.unwrap_or(LogicOp::COPY));
+
+        for viewport in &state.viewports {
+            validate_viewport(&device, viewport);
+        }
+        let scissors: Vec<Rect2D> = if state.scissors.is_empty() {
+            state.viewports.iter().map(viewport_scissor).collect()
+        } else {
+            state.scissors.clone()
+        };
+        assert_eq!(
+            scissors.len(),
+            state.viewports.len(),
+            "Every viewport needs exactly one scissor"
+        );
+        let viewport_state = PipelineViewportStateCreateInfo::default()
+            .viewports(&state.viewports)
+            .scissors(&scissors);
+
+        if let Err(error) = validate_vertex_formats(device.gpu(), &state.vertex_attributes) {
+            panic!("{}", error);
+        }
+        if let Some(inputs) = &state.vertex_shader_inputs {
+            if let Err(error) = validate_vertex_layout(&state.vertex_attributes, inputs) {
+                panic!("{}", error);
+            }
+        }
+        let vertex_input_state = PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&state.vertex_bindings)
+            .vertex_attribute_descriptions(&state.vertex_attributes);
+
+        let multisample = state.multisample_state.clone().unwrap_or_default();
+        validate_multisample_state(&device, &multisample);
+        let mut multisample_state = PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(multisample.rasterization_samples)
+            .sample_shading_enable(multisample.sample_shading_enable)
+            .min_sample_shading(multisample.min_sample_shading)
+            .alpha_to_coverage_enable(multisample.alpha_to_coverage_enable);
+        if !multisample.sample_mask.is_empty() {
+            multisample_state = multisample_state.sample_mask(&multisample.sample_mask);
+        }
+
+        let depth_stencil_state = state.depth_stencil_state.as_ref().map(|depth| {
+            PipelineDepthStencilStateCreateInfo::default()
+                .depth_test_enable(depth.depth_test_enable != 0)
+                .depth_write_enable(depth.depth_write_enable != 0)
+                .depth_compare_op(CompareOp::from_raw(depth.depth_compare_op as i32))
+        });
+
+        let dynamic_state =
+            PipelineDynamicStateCreateInfo::default().dynamic_states(&state.dynamic_states);
+
+        let mut info = GraphicsPipelineCreateInfo::default()
+            .flags(state.flags)
+            .stages(stages)
+            .layout(pipeline_layout)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample_state)
+            .color_blend_state(&blend_state)
+            .render_pass(state.render_pass)
+            .subpass(state.subpass);
+        if !state.viewports.is_empty() {
+            info = info.viewport_state(&viewport_state);
+        }
+        if let Some(depth_stencil_state) = &depth_stencil_state {
+            info = info.depth_stencil_state(depth_stencil_state);
+        }
+        if !state.dynamic_states.is_empty() {
+            info = info.dynamic_state(&dynamic_state);
+        }
 
         let pipelines = unsafe {
             device
                 .handle()
-                .create_graphics_pipelines(PipelineCache::null(), &[info], None)
+                .create_graphics_pipelines(
+                    PipelineCache::null(),
+                    &[info],
+                    device.allocation_callbacks(),
+                )
                 .expect("Pipeline Creation Failed")
         };
         Self {
             device,
-            pipeline_layout: PipelineLayout::null(),
+            pipeline_layout,
             pipeline: pipelines[0],
+            _shader_modules: shader_modules,
         }
     }
 
@@ -195,6 +781,14 @@ impl GraphicsPipeline {
 
 impl Drop for GraphicsPipeline {
     fn drop(&mut self) {
-        unsafe { self.device.handle().destroy_pipeline(self.pipeline, None) }
+        unsafe {
+            self.device
+                .handle()
+                .destroy_pipeline(self.pipeline, self.device.allocation_callbacks());
+            // Destroying a null layout is a no-op.
+            self.device
+                .handle()
+                .destroy_pipeline_layout(self.pipeline_layout, self.device.allocation_callbacks());
+        }
     }
 }
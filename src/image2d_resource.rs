@@ -1,27 +1,89 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+use crate::command_buffer::CommandBuffer;
 use crate::device_context::DeviceContext;
+use crate::format_info::{
+    aspect_mask, block_size_bytes, is_compressed_format, is_depth_format, is_integer_format,
+    is_stencil_format,
+};
 use crate::image_resource::ImageResource;
 use crate::memory::memory_type_index;
+use crate::queue::CommandQueue;
 
 use ash::vk::{
-    DeviceMemory, Extent3D, Format, Image, ImageAspectFlags, ImageCreateInfo, ImageLayout,
-    ImageSubresourceRange, ImageType, ImageUsageFlags, ImageView, ImageViewCreateInfo,
-    ImageViewType, MemoryAllocateInfo, MemoryPropertyFlags, PhysicalDeviceMemoryProperties2,
-    SampleCountFlags, SharingMode,
+    DeviceMemory, Extent3D, Format, FormatFeatureFlags, Image, ImageAspectFlags, ImageCreateInfo,
+    ImageLayout, ImageSubresourceRange, ImageType, ImageUsageFlags, ImageView, ImageViewCreateInfo,
+    ImageViewType, MemoryAllocateInfo, MemoryPropertyFlags, SampleCountFlags, SharingMode,
 };
 
-use ash::Device;
-
 pub struct Image2DResource {
-    device: Device,
+    device: Rc<DeviceContext>,
     image: Image,
     memory: DeviceMemory,
+    memory_type: u32,
+    allocation_size: u64,
     pub layout: ImageLayout,
+    // Only differs from layout after a stencil-only transition on a combined depth/stencil image.
+    stencil_layout: ImageLayout,
     view: ImageView,
+    sampled_view: Option<ImageView>,
+    range_views: RefCell<HashMap<(u32, u32, u32, u32), ImageView>>,
     width: u32,
     height: u32,
+    mip_levels: u32,
+    array_layers: u32,
     format: Format,
+    usage: ImageUsageFlags,
+    samples: SampleCountFlags,
+}
+
+// Multisampled images must be single mip and the count has to be in the device limits for the usage,
+// storage usage also needs the shader_storage_image_multisample feature.
+fn validate_sample_count(
+    device: &DeviceContext,
+    format: Format,
+    usage: ImageUsageFlags,
+    samples: SampleCountFlags,
+) {
+    if samples == SampleCountFlags::TYPE_1 {
+        return;
+    }
+
+    let limits = device.gpu().limits();
+    if usage.contains(ImageUsageFlags::STORAGE) {
+        assert!(
+            device.enabled_features().shader_storage_image_multisample != 0,
+            "Multisampled storage images require the shader_storage_image_multisample device feature"
+        );
+        assert!(
+            limits.storage_image_sample_counts.contains(samples),
+            "{:?} is not supported for storage images, supported: {:?}",
+            samples,
+            limits.storage_image_sample_counts
+        );
+    }
+    if usage.contains(ImageUsageFlags::SAMPLED) {
+        let supported = if is_depth_format(format) {
+            limits.sampled_image_depth_sample_counts
+        } else if is_integer_format(format) {
+            limits.sampled_image_integer_sample_counts
+        } else {
+            limits.sampled_image_color_sample_counts
+        };
+        assert!(
+            supported.contains(samples),
+            "{:?} is not supported for sampled {:?} images, supported: {:?}",
+            samples,
+            format,
+            supported
+        );
+    }
+}
+
+pub fn full_mip_count(width: u32, height: u32) -> u32 {
+    width.max(height).max(1).ilog2() + 1
 }
 
 impl Image2DResource {
@@ -32,29 +94,103 @@ impl Image2DResource {
         format: Format,
         usage: ImageUsageFlags,
         property_flags: MemoryPropertyFlags,
+        mip_levels: u32,
+    ) -> Self {
+        Self::create(
+            context,
+            width,
+            height,
+            format,
+            usage,
+            property_flags,
+            mip_levels,
+            SampleCountFlags::TYPE_1,
+        )
+    }
+
+    // A full mip chain, floor(log2(max(width, height))) + 1 levels.
+    pub fn new_with_mips(
+        context: Rc<DeviceContext>,
+        width: u32,
+        height: u32,
+        format: Format,
+        usage: ImageUsageFlags,
+        property_flags: MemoryPropertyFlags,
+    ) -> Self {
+        let mip_levels = full_mip_count(width, height);
+        Self::new(
+            context,
+            width,
+            height,
+            format,
+            usage,
+            property_flags,
+            mip_levels,
+        )
+    }
+
+    pub fn new_multisampled(
+        context: Rc<DeviceContext>,
+        width: u32,
+        height: u32,
+        format: Format,
+        usage: ImageUsageFlags,
+        property_flags: MemoryPropertyFlags,
+        samples: SampleCountFlags,
     ) -> Self {
+        Self::create(
+            context,
+            width,
+            height,
+            format,
+            usage,
+            property_flags,
+            1,
+            samples,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        context: Rc<DeviceContext>,
+        width: u32,
+        height: u32,
+        format: Format,
+        usage: ImageUsageFlags,
+        property_flags: MemoryPropertyFlags,
+        mip_levels: u32,
+        samples: SampleCountFlags,
+    ) -> Self {
+        validate_sample_count(&context, format, usage, samples);
+        assert!(
+            (1..=full_mip_count(width, height)).contains(&mip_levels),
+            "A {}x{} image can have 1 to {} mip levels, {} requested",
+            width,
+            height,
+            full_mip_count(width, height),
+            mip_levels
+        );
         unsafe {
             let image_info = ImageCreateInfo::default()
                 .image_type(ImageType::TYPE_2D)
-                .samples(SampleCountFlags::TYPE_1)
+                .samples(samples)
                 .sharing_mode(SharingMode::EXCLUSIVE)
                 .format(format)
                 .extent(Extent3D::default().width(width).height(height).depth(1))
                 .array_layers(1)
-                .mip_levels(1)
+                .mip_levels(mip_levels)
                 .usage(usage);
 
             let device = context.handle();
 
             let image = device
-                .create_image(&image_info, None)
+                .create_image(&image_info, context.allocation_callbacks())
                 .expect("Image creation failed");
             let memory_requirements = device.get_image_memory_requirements(image);
-            let mut properties = PhysicalDeviceMemoryProperties2::default();
-            context.gpu().memory_properties(&mut properties);
+            let memory_properties = context.gpu().memory_properties2();
             let type_index = memory_type_index(
                 memory_requirements.memory_type_bits,
-                &properties.memory_properties,
+                &memory_properties,
                 property_flags,
             );
             if let Some(type_index) = type_index {
@@ -62,8 +198,9 @@ impl Image2DResource {
                     .memory_type_index(type_index)
                     .allocation_size(memory_requirements.size);
                 let memory = device
-                    .allocate_memory(&allocation_info, None)
+                    .allocate_memory(&allocation_info, context.allocation_callbacks())
                     .expect("Memory allocation failed");
+                context.notify_allocated(memory_requirements.size, type_index, "Image2DResource");
 
                 device
                     .bind_image_memory(image, memory, 0)
@@ -71,8 +208,8 @@ impl Image2DResource {
 
                 let subresource_range = ImageSubresourceRange::default()
                     .base_array_layer(0)
-                    .aspect_mask(ImageAspectFlags::COLOR)
-                    .level_count(1)
+                    .aspect_mask(aspect_mask(format))
+                    .level_count(mip_levels)
                     .layer_count(1);
                 let view_info = ImageViewCreateInfo::default()
                     .format(format)
@@ -81,18 +218,43 @@ impl Image2DResource {
                     .subresource_range(subresource_range);
                 let view = context
                     .handle()
-                    .create_image_view(&view_info, None)
+                    .create_image_view(&view_info, context.allocation_callbacks())
                     .expect("Image view creation failed");
 
+                // Combined depth/stencil views can't be sampled, so keep a depth-only view around.
+                let sampled_view = if is_depth_format(format)
+                    && is_stencil_format(format)
+                    && usage.contains(ImageUsageFlags::SAMPLED)
+                {
+                    let view_info = view_info
+                        .subresource_range(subresource_range.aspect_mask(ImageAspectFlags::DEPTH));
+                    Some(
+                        device
+                            .create_image_view(&view_info, context.allocation_callbacks())
+                            .expect("Image view creation failed"),
+                    )
+                } else {
+                    None
+                };
+
                 Self {
-                    device: device.clone(),
+                    device: context.clone(),
                     image,
                     memory,
+                    memory_type: type_index,
+                    allocation_size: memory_requirements.size,
                     layout: ImageLayout::UNDEFINED,
+                    stencil_layout: ImageLayout::UNDEFINED,
                     width,
                     height,
+                    mip_levels,
+                    array_layers: 1,
                     format,
+                    usage,
+                    samples,
                     view,
+                    sampled_view,
+                    range_views: RefCell::new(HashMap::new()),
                 }
             } else {
                 panic!()
@@ -113,8 +275,98 @@ impl Image2DResource {
             format,
             ImageUsageFlags::STORAGE | ImageUsageFlags::TRANSFER_SRC,
             MemoryPropertyFlags::DEVICE_LOCAL,
+            1,
         )
     }
+
+    pub fn new_depth(context: Rc<DeviceContext>, width: u32, height: u32, format: Format) -> Self {
+        Self::new(
+            context,
+            width,
+            height,
+            format,
+            ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | ImageUsageFlags::SAMPLED,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            1,
+        )
+    }
+
+    pub fn sampled_view(&self) -> ImageView {
+        self.sampled_view.unwrap_or(self.view)
+    }
+
+    pub fn samples(&self) -> SampleCountFlags {
+        self.samples
+    }
+
+    pub fn is_multisampled(&self) -> bool {
+        self.samples != SampleCountFlags::TYPE_1
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        is_compressed_format(self.format)
+    }
+
+    pub fn block_size_bytes(format: Format) -> u32 {
+        block_size_bytes(format).expect("Not a block compressed format")
+    }
+
+    pub fn full_view(&self) -> ImageView {
+        self.view
+    }
+
+    pub fn view_range(
+        &self,
+        base_mip: u32,
+        mip_count: u32,
+        base_layer: u32,
+        layer_count: u32,
+    ) -> ImageView {
+        assert!(
+            base_mip + mip_count <= self.mip_levels
+                && base_layer + layer_count <= self.array_layers,
+            "View range mips {}..{} layers {}..{} is outside the image ({} mips, {} layers)",
+            base_mip,
+            base_mip + mip_count,
+            base_layer,
+            base_layer + layer_count,
+            self.mip_levels,
+            self.array_layers
+        );
+
+        *self
+            .range_views
+            .borrow_mut()
+            .entry((base_mip, mip_count, base_layer, layer_count))
+            .or_insert_with(|| {
+                let subresource_range = ImageSubresourceRange::default()
+                    .aspect_mask(aspect_mask(self.format))
+                    .base_mip_level(base_mip)
+                    .level_count(mip_count)
+                    .base_array_layer(base_layer)
+                    .layer_count(layer_count);
+                let view_type = if layer_count > 1 {
+                    ImageViewType::TYPE_2D_ARRAY
+                } else {
+                    ImageViewType::TYPE_2D
+                };
+                let view_info = ImageViewCreateInfo::default()
+                    .format(self.format)
+                    .image(self.image)
+                    .view_type(view_type)
+                    .subresource_range(subresource_range);
+                unsafe {
+                    self.device
+                        .handle()
+                        .create_image_view(&view_info, self.device.allocation_callbacks())
+                        .expect("Image view creation failed")
+                }
+            })
+    }
 }
 
 impl ImageResource for Image2DResource {
@@ -138,17 +390,126 @@ impl ImageResource for Image2DResource {
     }
 
     fn set_layout(&mut self, layout: ImageLayout) {
-        self.layout = layout
+        self.layout = layout;
+        self.stencil_layout = layout
+    }
+
+    fn aspect_layout(&self, aspect: ImageAspectFlags) -> ImageLayout {
+        if aspect == ImageAspectFlags::STENCIL && is_depth_format(self.format) {
+            self.stencil_layout
+        } else {
+            self.layout
+        }
+    }
+
+    fn set_aspect_layout(&mut self, aspect: ImageAspectFlags, layout: ImageLayout) {
+        if aspect == ImageAspectFlags::STENCIL && is_depth_format(self.format) {
+            self.stencil_layout = layout
+        } else if aspect == ImageAspectFlags::DEPTH && is_stencil_format(self.format) {
+            self.layout = layout
+        } else {
+            self.set_layout(layout)
+        }
     }
 
     fn view(&self) -> ImageView {
         self.view
     }
+
+    fn usage(&self) -> ImageUsageFlags {
+        self.usage
+    }
+
+    fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    fn sampled_view(&self) -> ImageView {
+        Image2DResource::sampled_view(self)
+    }
 }
 
 impl Drop for Image2DResource {
     fn drop(&mut self) {
-        unsafe { self.device.free_memory(self.memory, None) }
-        unsafe { self.device.destroy_image(self.image, None) }
+        for view in self.range_views.borrow().values() {
+            unsafe {
+                self.device
+                    .handle()
+                    .destroy_image_view(*view, self.device.allocation_callbacks())
+            }
+        }
+        if let Some(view) = self.sampled_view {
+            unsafe {
+                self.device
+                    .handle()
+                    .destroy_image_view(view, self.device.allocation_callbacks())
+            }
+        }
+        let device = self.device.handle();
+        unsafe { device.destroy_image_view(self.view, self.device.allocation_callbacks()) }
+        unsafe { device.free_memory(self.memory, self.device.allocation_callbacks()) }
+        self.device
+            .notify_freed(self.allocation_size, self.memory_type, "Image2DResource");
+        unsafe { device.destroy_image(self.image, self.device.allocation_callbacks()) }
     }
 }
+
+pub fn convert_image_format(
+    queue: Rc<CommandQueue>,
+    src: &mut Image2DResource,
+    dst_format: Format,
+) -> Image2DResource {
+    let (width, height) = (src.width(), src.height());
+    convert_image_format_with_extent(queue, src, dst_format, width, height)
+}
+
+// Blits into a new TRANSFER_DST | SAMPLED image, left in SHADER_READ_ONLY_OPTIMAL. Blocks until done.
+pub fn convert_image_format_with_extent(
+    queue: Rc<CommandQueue>,
+    src: &mut Image2DResource,
+    dst_format: Format,
+    width: u32,
+    height: u32,
+) -> Image2DResource {
+    let device = queue.device();
+    let src_features = device
+        .gpu()
+        .format_properties(src.format())
+        .optimal_tiling_features;
+    assert!(
+        src_features.contains(
+            FormatFeatureFlags::BLIT_SRC | FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+        ),
+        "Format {:?} does not support linear blits from it",
+        src.format()
+    );
+    let dst_features = device
+        .gpu()
+        .format_properties(dst_format)
+        .optimal_tiling_features;
+    assert!(
+        dst_features.contains(FormatFeatureFlags::BLIT_DST),
+        "Format {:?} does not support blits into it",
+        dst_format
+    );
+
+    let mut dst = Image2DResource::new(
+        device,
+        width,
+        height,
+        dst_format,
+        ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::SAMPLED,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        1,
+    );
+
+    let mut command_buffer = CommandBuffer::new(queue);
+    command_buffer.begin();
+    command_buffer.image_resource_transition(src, ImageLayout::TRANSFER_SRC_OPTIMAL);
+    command_buffer.image_resource_transition(&mut dst, ImageLayout::TRANSFER_DST_OPTIMAL);
+    command_buffer.blit(src, &mut dst);
+    command_buffer.image_resource_transition(&mut dst, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    command_buffer.submit().wait();
+
+    dst
+}
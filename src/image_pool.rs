@@ -0,0 +1,67 @@
+use std::rc::Rc;
+
+use ash::vk::{Format, ImageUsageFlags, MemoryPropertyFlags};
+
+use crate::{
+    device_context::DeviceContext, image2d_resource::Image2DResource, image_resource::ImageResource,
+};
+
+// Every image currently owns its own allocation. The plan is to back the pool
+// with a single DeviceMemory and bind each image at an offset (vkBindImageMemory2).
+pub struct ImagePool<I: ImageResource> {
+    images: Vec<I>,
+}
+
+impl ImagePool<Image2DResource> {
+    pub fn new(
+        context: Rc<DeviceContext>,
+        count: usize,
+        width: u32,
+        height: u32,
+        format: Format,
+        usage: ImageUsageFlags,
+        property_flags: MemoryPropertyFlags,
+    ) -> Self {
+        let images = (0..count)
+            .map(|_| {
+                Image2DResource::new(
+                    context.clone(),
+                    width,
+                    height,
+                    format,
+                    usage,
+                    property_flags,
+                    1,
+                )
+            })
+            .collect();
+
+        Self { images }
+    }
+}
+
+impl<I: ImageResource> ImagePool<I> {
+    pub fn from_images(images: Vec<I>) -> Self {
+        Self { images }
+    }
+
+    pub fn get(&self, index: usize) -> &I {
+        &self.images[index]
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut I {
+        &mut self.images[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &I> {
+        self.images.iter()
+    }
+}
@@ -1,4 +1,4 @@
-use ash::vk::{Format, Image, ImageLayout, ImageView};
+use ash::vk::{Format, Image, ImageAspectFlags, ImageLayout, ImageUsageFlags, ImageView};
 
 pub trait ImageResource {
     fn width(&self) -> u32;
@@ -9,4 +9,28 @@ pub trait ImageResource {
     fn layout(&self) -> ImageLayout;
     fn handle(&self) -> Image;
     fn view(&self) -> ImageView;
+    fn usage(&self) -> ImageUsageFlags;
+
+    fn mip_levels(&self) -> u32 {
+        1
+    }
+
+    // The view descriptors sample through, depth/stencil images only sample their depth aspect.
+    fn sampled_view(&self) -> ImageView {
+        self.view()
+    }
+
+    // Images that don't track depth and stencil layouts separately use one layout for every aspect.
+    fn aspect_layout(&self, _aspect: ImageAspectFlags) -> ImageLayout {
+        self.layout()
+    }
+
+    fn set_aspect_layout(&mut self, _aspect: ImageAspectFlags, layout: ImageLayout) {
+        self.set_layout(layout)
+    }
+
+    // Used to name the image in validation messages.
+    fn debug_name(&self) -> String {
+        format!("{:?}", self.handle())
+    }
 }
@@ -0,0 +1,128 @@
+use std::rc::Rc;
+
+use ash::vk::ImageLayout;
+
+use crate::buffer_resource::BufferResource;
+use crate::command_buffer::CommandBuffer;
+use crate::format_info::texel_size_bytes;
+use crate::image_resource::ImageResource;
+use crate::queue::CommandQueue;
+
+// Upper bound for the staging memory used by a single transfer, larger images are split into
+// bands of rows that each fit.
+pub const DEFAULT_STAGING_BUDGET: usize = 64 * 1024 * 1024;
+
+pub(crate) fn row_size(image: &impl ImageResource, width: u32) -> usize {
+    let texel_size = texel_size_bytes(image.format()).unwrap_or_else(|| {
+        panic!(
+            "Transferring rows of {:?} images is not supported",
+            image.format()
+        )
+    });
+    width as usize * texel_size as usize
+}
+
+// Number of rows per band, at least one even if a single row exceeds the budget.
+pub(crate) fn band_rows(row_size: usize, height: u32, staging_budget: usize) -> u32 {
+    ((staging_budget / row_size.max(1)) as u32).clamp(1, height.max(1))
+}
+
+// Uploads tightly packed pixel data band by band, reusing one staging buffer sized to the budget.
+pub struct ImageUploader {
+    queue: Rc<CommandQueue>,
+    staging_budget: usize,
+    staging: Option<BufferResource>,
+}
+
+impl ImageUploader {
+    pub fn new(queue: Rc<CommandQueue>) -> Self {
+        Self {
+            queue,
+            staging_budget: DEFAULT_STAGING_BUDGET,
+            staging: None,
+        }
+    }
+
+    pub fn with_staging_budget(mut self, bytes: usize) -> Self {
+        self.staging_budget = bytes;
+        self.staging = None;
+        self
+    }
+
+    pub fn staging_budget(&self) -> usize {
+        self.staging_budget
+    }
+
+    // Blocks until the last band has been copied, the image ends up in final_layout.
+    pub fn upload(
+        &mut self,
+        image: &mut impl ImageResource,
+        data: &[u8],
+        final_layout: ImageLayout,
+    ) {
+        let (width, height) = (image.width(), image.height());
+        assert!(
+            width > 0 && height > 0,
+            "Can't upload to the {}x{} image {}",
+            width,
+            height,
+            image.debug_name()
+        );
+        let row_size = row_size(image, width);
+        assert_eq!(
+            data.len(),
+            row_size * height as usize,
+            "Pixel data doesn't match the {}x{} image {}",
+            width,
+            height,
+            image.debug_name()
+        );
+
+        let rows = band_rows(row_size, height, self.staging_budget);
+        let band_size = row_size * rows as usize;
+        if self
+            .staging
+            .as_ref()
+            .is_none_or(|buffer| (buffer.content_size() as usize) < band_size)
+        {
+            self.staging = Some(BufferResource::new_staging(self.queue.device(), band_size));
+        }
+        let staging = self.staging.as_mut().unwrap();
+
+        let mut y = 0;
+        while y < height {
+            let band_height = rows.min(height - y);
+            let start = y as usize * row_size;
+            staging.upload(&data[start..start + band_height as usize * row_size]);
+
+            let mut command_buffer = CommandBuffer::new(self.queue.clone());
+            command_buffer.begin();
+            if image.layout() != ImageLayout::TRANSFER_DST_OPTIMAL {
+                command_buffer.image_resource_transition(image, ImageLayout::TRANSFER_DST_OPTIMAL);
+            }
+            command_buffer.copy_buffer_to_image_region(
+                staging,
+                image,
+                0,
+                y as i32,
+                width,
+                band_height,
+            );
+            if y + band_height == height && final_layout != ImageLayout::TRANSFER_DST_OPTIMAL {
+                command_buffer.image_resource_transition(image, final_layout);
+            }
+            // The staging buffer is refilled for the next band, so wait before moving on.
+            command_buffer.submit().wait();
+            y += band_height;
+        }
+    }
+}
+
+pub fn upload_image(
+    queue: Rc<CommandQueue>,
+    image: &mut impl ImageResource,
+    data: &[u8],
+    final_layout: ImageLayout,
+) {
+    ImageUploader::new(queue).upload(image, data, final_layout)
+}
@@ -0,0 +1,42 @@
+// Index newtypes so set, binding and queue family indices can't be passed in each other's place.
+// From<u32> keeps call sites with plain literals terse, the types only have to be spelled out
+// where an index is stored or passed along.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SetIndex(pub u32);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BindingIndex(pub u32);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct QueueFamilyIndex(pub u32);
+
+impl SetIndex {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl QueueFamilyIndex {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl From<u32> for SetIndex {
+    fn from(index: u32) -> Self {
+        Self(index)
+    }
+}
+
+impl From<u32> for BindingIndex {
+    fn from(index: u32) -> Self {
+        Self(index)
+    }
+}
+
+impl From<u32> for QueueFamilyIndex {
+    fn from(index: u32) -> Self {
+        Self(index)
+    }
+}
@@ -0,0 +1,296 @@
+use std::{collections::HashMap, rc::Rc};
+
+use ash::vk::{
+    AccessFlags, BufferUsageFlags, DescriptorSetLayoutBinding, DescriptorType, MemoryPropertyFlags,
+    PipelineStageFlags, ShaderStageFlags,
+};
+
+use crate::{
+    buffer_resource::BufferResource,
+    command_buffer::{CommandBuffer, Recorder},
+    descriptor_allocator::DescriptorAllocator,
+    descriptor_set_layout::DescriptorSetLayoutHandle,
+    descriptor_update::DescriptorUpdateBatch,
+    device_context::DeviceContext,
+    pipeline_descriptor::ComputePipeline,
+    queue::CommandQueue,
+    wait_handle::WaitHandle,
+};
+
+const SCAN_GROUP_SIZE: u32 = 256;
+
+// Exclusive scan of the mask within each workgroup, the workgroup totals go to block_sums.
+const SCAN_BLOCKS_SRC: &str = r"
+#version 450
+layout(local_size_x = 256) in;
+layout(set = 0, binding = 0) readonly buffer Mask { uint mask[]; };
+layout(set = 0, binding = 1) writeonly buffer Offsets { uint offsets[]; };
+layout(set = 0, binding = 2) writeonly buffer BlockSums { uint block_sums[]; };
+layout(push_constant) uniform Constants { uint count; };
+
+shared uint scratch[256];
+
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    uint l = gl_LocalInvocationID.x;
+    uint value = (i < count && mask[i] != 0) ? 1 : 0;
+    scratch[l] = value;
+    barrier();
+    for (uint stride = 1; stride < 256; stride <<= 1) {
+        uint add = l >= stride ? scratch[l - stride] : 0;
+        barrier();
+        scratch[l] += add;
+        barrier();
+    }
+    if (i < count) {
+        offsets[i] = scratch[l] - value;
+    }
+    if (l == 255) {
+        block_sums[gl_WorkGroupID.x] = scratch[l];
+    }
+}
+";
+
+// Turns the workgroup totals into workgroup offsets and writes the total count.
+const SCAN_BLOCK_SUMS_SRC: &str = r"
+#version 450
+layout(local_size_x = 1) in;
+layout(set = 0, binding = 0) buffer BlockSums { uint block_sums[]; };
+layout(set = 0, binding = 1) writeonly buffer Count { uint total; };
+layout(push_constant) uniform Constants { uint block_count; };
+
+void main() {
+    uint sum = 0;
+    for (uint block = 0; block < block_count; ++block) {
+        uint value = block_sums[block];
+        block_sums[block] = sum;
+        sum += value;
+    }
+    total = sum;
+}
+";
+
+const SCATTER_SRC: &str = r"
+#version 450
+layout(local_size_x = 256) in;
+layout(set = 0, binding = 0) readonly buffer Source { uint src[]; };
+layout(set = 0, binding = 1) readonly buffer Mask { uint mask[]; };
+layout(set = 0, binding = 2) readonly buffer Offsets { uint offsets[]; };
+layout(set = 0, binding = 3) readonly buffer BlockSums { uint block_sums[]; };
+layout(set = 0, binding = 4) writeonly buffer Destination { uint dst[]; };
+layout(push_constant) uniform Constants { uint count; uint words; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    if (i >= count || mask[i] == 0) {
+        return;
+    }
+    uint offset = offsets[i] + block_sums[gl_WorkGroupID.x];
+    for (uint word = 0; word < words; ++word) {
+        dst[offset * words + word] = src[i * words + word];
+    }
+}
+";
+
+fn storage_bindings(count: u32) -> HashMap<u32, Vec<DescriptorSetLayoutBinding<'static>>> {
+    let bindings = (0..count)
+        .map(|binding| {
+            DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(ShaderStageFlags::COMPUTE)
+        })
+        .collect();
+    HashMap::from([(0, bindings)])
+}
+
+// A pipeline reused by every call, each call binds descriptor sets of its own so calls in flight
+// don't overwrite each other's buffers.
+struct Kernel {
+    pipeline: ComputePipeline,
+    layout: Rc<DescriptorSetLayoutHandle>,
+}
+
+impl Kernel {
+    fn new(device: &Rc<DeviceContext>, src: &str, binding_count: u32) -> Self {
+        let bindings = storage_bindings(binding_count);
+        let layout = device.get_or_create_descriptor_set_layout(&bindings[&0]);
+        let pipeline =
+            ComputePipeline::new_from_source_string(device.clone(), 1, src, "main", Some(bindings))
+                .expect("Kernel compilation failed");
+        Self { pipeline, layout }
+    }
+
+    // Binds the pipeline with a set holding buffers at bindings 0 and up.
+    fn bind(
+        &self,
+        command_buffer: &mut Recorder,
+        allocator: &mut DescriptorAllocator,
+        buffers: &[&BufferResource],
+    ) {
+        let set = allocator
+            .allocate(&self.layout)
+            .expect("Kernel descriptor set allocation failed");
+        let mut batch = DescriptorUpdateBatch::new();
+        for (binding, buffer) in buffers.iter().enumerate() {
+            batch.write_buffer(
+                set,
+                binding as u32,
+                DescriptorType::STORAGE_BUFFER,
+                buffer.buffer,
+                buffer.content_size(),
+            );
+        }
+        batch.flush(&command_buffer.queue().device());
+
+        command_buffer.bind_compute_pipeline(&self.pipeline);
+        command_buffer.bind_compute_descriptor_set(&self.pipeline, 0, set);
+        command_buffer.track_bound_buffers(
+            buffers
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| {
+                    (
+                        buffer.buffer,
+                        DescriptorType::STORAGE_BUFFER,
+                        self.pipeline.binding_access(0, binding as u32),
+                    )
+                })
+                .collect(),
+        );
+    }
+}
+
+// Every kernel pipeline of a device, built on first use. See DeviceContext::get_or_create_kernels.
+pub(crate) struct Kernels {
+    scan_blocks: Kernel,
+    scan_block_sums: Kernel,
+    scatter: Kernel,
+}
+
+impl Kernels {
+    pub(crate) fn new(device: &Rc<DeviceContext>) -> Self {
+        Self {
+            scan_blocks: Kernel::new(device, SCAN_BLOCKS_SRC, 3),
+            scan_block_sums: Kernel::new(device, SCAN_BLOCK_SUMS_SRC, 2),
+            scatter: Kernel::new(device, SCATTER_SRC, 5),
+        }
+    }
+}
+
+fn compute_barrier(command_buffer: &mut Recorder, buffer: &BufferResource) {
+    command_buffer.buffer_resource_barrier(
+        buffer,
+        PipelineStageFlags::COMPUTE_SHADER,
+        PipelineStageFlags::COMPUTE_SHADER,
+        AccessFlags::SHADER_WRITE,
+        AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE,
+    );
+}
+
+/// Packs the elements of `src` whose `mask` entry (one u32 per element) is non-zero into the front
+/// of `dst`, preserving their order. Returns the submission and a host visible buffer holding the
+/// number of packed elements as a single u32. `element_size` must be a multiple of 4 bytes.
+pub fn compact(
+    queue: Rc<CommandQueue>,
+    src: &BufferResource,
+    mask: &BufferResource,
+    element_size: u32,
+    dst: &BufferResource,
+) -> (WaitHandle, BufferResource) {
+    let mut command_buffer = CommandBuffer::new(queue);
+    command_buffer.begin();
+    let total = record_compact(&mut command_buffer, src, mask, element_size, dst);
+    (command_buffer.submit(), total)
+}
+
+/// Records [`compact`] into a command buffer that is being recorded, the count buffer is ready
+/// once it completes.
+pub fn record_compact(
+    command_buffer: &mut Recorder,
+    src: &BufferResource,
+    mask: &BufferResource,
+    element_size: u32,
+    dst: &BufferResource,
+) -> BufferResource {
+    assert!(
+        element_size > 0 && element_size.is_multiple_of(4),
+        "Compacted elements must be a multiple of 4 bytes, got {}",
+        element_size
+    );
+    let count = (mask.content_size() / 4) as u32;
+    assert!(
+        src.content_size() >= count as u64 * element_size as u64,
+        "Source buffer holds fewer elements than the mask"
+    );
+    assert!(
+        dst.content_size() >= count as u64 * element_size as u64,
+        "Destination buffer is too small to hold every element"
+    );
+
+    let device = command_buffer.queue().device();
+    let block_count = count.div_ceil(SCAN_GROUP_SIZE).max(1);
+    let offsets = BufferResource::new(
+        device.clone(),
+        count.max(1) as usize * 4,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        BufferUsageFlags::STORAGE_BUFFER,
+    );
+    let block_sums = BufferResource::new(
+        device.clone(),
+        block_count as usize * 4,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        BufferUsageFlags::STORAGE_BUFFER,
+    );
+    let total = BufferResource::new(
+        device.clone(),
+        4,
+        MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+        BufferUsageFlags::STORAGE_BUFFER,
+    );
+
+    let kernels = command_buffer.queue().kernels();
+    // One set per kernel, the pool is freed with the command buffer.
+    let mut allocator =
+        DescriptorAllocator::new(device.clone(), kernels.scatter.layout.pool_sizes(), 3);
+
+    let scan_blocks = &kernels.scan_blocks;
+    scan_blocks.bind(
+        command_buffer,
+        &mut allocator,
+        &[mask, &offsets, &block_sums],
+    );
+    command_buffer.push_compute_constants(&scan_blocks.pipeline, 0, &count);
+    command_buffer.dispatch_compute(block_count, 1, 1);
+    compute_barrier(command_buffer, &offsets);
+    compute_barrier(command_buffer, &block_sums);
+
+    let scan_block_sums = &kernels.scan_block_sums;
+    scan_block_sums.bind(command_buffer, &mut allocator, &[&block_sums, &total]);
+    command_buffer.push_compute_constants(&scan_block_sums.pipeline, 0, &block_count);
+    command_buffer.dispatch_compute(1, 1, 1);
+    compute_barrier(command_buffer, &block_sums);
+
+    let scatter = &kernels.scatter;
+    scatter.bind(
+        command_buffer,
+        &mut allocator,
+        &[src, mask, &offsets, &block_sums, dst],
+    );
+    command_buffer.push_compute_constants(&scatter.pipeline, 0, &[count, element_size / 4]);
+    command_buffer.dispatch_compute(block_count, 1, 1);
+    command_buffer.buffer_resource_barrier(
+        &total,
+        PipelineStageFlags::COMPUTE_SHADER,
+        PipelineStageFlags::HOST,
+        AccessFlags::SHADER_WRITE,
+        AccessFlags::HOST_READ,
+    );
+
+    command_buffer.retain(offsets);
+    command_buffer.retain(block_sums);
+    command_buffer.retain(allocator);
+    command_buffer.retain(kernels);
+    total
+}
@@ -1,20 +1,47 @@
+pub mod acceleration_structure;
+pub mod allocator;
 pub mod buffer_resource;
+pub mod clear_value;
 pub mod command_buffer;
+pub mod command_buffer_set;
+pub mod compute_graph;
+pub mod descriptor_allocator;
+pub mod descriptor_set_layout;
+pub mod descriptor_update;
 pub mod device_context;
+pub mod format_info;
+pub mod framebuffer;
+pub mod gbuffer;
 pub mod gpu;
 pub mod graphics_pipeline;
 pub mod image2d_resource;
+pub mod image_pool;
 pub mod image_resource;
+pub mod image_upload;
+pub mod indices;
+pub mod kernels;
 pub mod memory;
+pub mod mesh;
+pub mod ping_pong;
 pub mod pipeline_descriptor;
 pub mod queue;
+pub mod readback;
 pub mod renderpass;
+pub mod sampler_resource;
 pub mod shader_compiler;
+pub mod shader_module_cache;
+pub mod shadow_map;
+pub mod shared_binding;
+pub mod submission_batch;
 pub mod swapchain;
 pub mod swapchain_image;
 pub mod swapchain_util;
+pub mod validation_capture;
+pub mod vertex_layout;
 pub mod vulkan;
 pub mod wait_handle;
+#[cfg(feature = "winit")]
+pub mod window_renderer;
 
 pub use ash::ext::{buffer_device_address, debug_utils};
 pub use ash::vk::{
@@ -6,7 +6,7 @@ pub fn memory_type_index(
     property_flags: MemoryPropertyFlags,
 ) -> Option<u32> {
     for i in 0..properties.memory_type_count {
-        if (filter & (1 << i) > 1)
+        if (filter & (1 << i) != 0)
             && (properties.memory_types[i as usize].property_flags & property_flags)
                 == property_flags
         {
@@ -15,3 +15,155 @@ pub fn memory_type_index(
     }
     None
 }
+
+// Tries every set of flags in order and returns the first type that matches, e.g.
+// DEVICE_LOCAL | HOST_VISIBLE first with HOST_VISIBLE | HOST_COHERENT as the fallback.
+pub fn preferred_memory_type_index(
+    filter: u32,
+    properties: &PhysicalDeviceMemoryProperties,
+    preferences: &[MemoryPropertyFlags],
+) -> Option<u32> {
+    preferences
+        .iter()
+        .find_map(|property_flags| memory_type_index(filter, properties, *property_flags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::MemoryType;
+
+    const DEVICE_LOCAL: MemoryPropertyFlags = MemoryPropertyFlags::DEVICE_LOCAL;
+    const HOST_VISIBLE: MemoryPropertyFlags = MemoryPropertyFlags::HOST_VISIBLE;
+    const HOST_COHERENT: MemoryPropertyFlags = MemoryPropertyFlags::HOST_COHERENT;
+    const HOST_CACHED: MemoryPropertyFlags = MemoryPropertyFlags::HOST_CACHED;
+
+    fn memory_properties(types: &[MemoryPropertyFlags]) -> PhysicalDeviceMemoryProperties {
+        let mut properties = PhysicalDeviceMemoryProperties {
+            memory_type_count: types.len() as u32,
+            ..Default::default()
+        };
+        for (i, property_flags) in types.iter().enumerate() {
+            properties.memory_types[i] = MemoryType {
+                property_flags: *property_flags,
+                heap_index: 0,
+            };
+        }
+        properties
+    }
+
+    // A typical discrete GPU: device local, host visible, host cached and a small BAR heap.
+    fn discrete() -> PhysicalDeviceMemoryProperties {
+        memory_properties(&[
+            DEVICE_LOCAL,
+            HOST_VISIBLE | HOST_COHERENT,
+            HOST_VISIBLE | HOST_COHERENT | HOST_CACHED,
+            DEVICE_LOCAL | HOST_VISIBLE | HOST_COHERENT,
+        ])
+    }
+
+    #[test]
+    fn matches_type_bit_zero() {
+        let properties = discrete();
+        assert_eq!(memory_type_index(0b1, &properties, DEVICE_LOCAL), Some(0));
+        assert_eq!(
+            memory_type_index(u32::MAX, &properties, DEVICE_LOCAL),
+            Some(0)
+        );
+        assert_eq!(
+            memory_type_index(0b1, &properties, MemoryPropertyFlags::empty()),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn picks_the_first_of_multiple_candidates() {
+        let properties = discrete();
+        assert_eq!(
+            memory_type_index(0b1111, &properties, HOST_VISIBLE),
+            Some(1)
+        );
+        assert_eq!(
+            memory_type_index(0b1111, &properties, HOST_VISIBLE | HOST_CACHED),
+            Some(2)
+        );
+        assert_eq!(
+            memory_type_index(0b1110, &properties, DEVICE_LOCAL),
+            Some(3)
+        );
+        // The filter excludes the earlier candidates.
+        assert_eq!(
+            memory_type_index(0b1000, &properties, HOST_VISIBLE),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn no_match() {
+        let properties = discrete();
+        assert_eq!(
+            memory_type_index(0b1111, &properties, MemoryPropertyFlags::LAZILY_ALLOCATED),
+            None
+        );
+        assert_eq!(memory_type_index(0b0001, &properties, HOST_VISIBLE), None);
+        assert_eq!(
+            memory_type_index(0, &properties, MemoryPropertyFlags::empty()),
+            None
+        );
+        // Filter bits past memory_type_count are ignored.
+        assert_eq!(memory_type_index(0b10000, &properties, DEVICE_LOCAL), None);
+        assert_eq!(
+            memory_type_index(
+                u32::MAX,
+                &memory_properties(&[]),
+                MemoryPropertyFlags::empty()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn preferred_matches_type_bit_zero() {
+        let properties = discrete();
+        assert_eq!(
+            preferred_memory_type_index(0b1, &properties, &[HOST_VISIBLE, DEVICE_LOCAL]),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn preferred_tries_preferences_in_order() {
+        let properties = discrete();
+        assert_eq!(
+            preferred_memory_type_index(
+                0b1111,
+                &properties,
+                &[DEVICE_LOCAL | HOST_VISIBLE, HOST_VISIBLE | HOST_COHERENT]
+            ),
+            Some(3)
+        );
+        // Without the BAR heap in the filter the fallback is used.
+        assert_eq!(
+            preferred_memory_type_index(
+                0b0111,
+                &properties,
+                &[DEVICE_LOCAL | HOST_VISIBLE, HOST_VISIBLE | HOST_COHERENT]
+            ),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn preferred_no_match() {
+        let properties = discrete();
+        assert_eq!(preferred_memory_type_index(0b1111, &properties, &[]), None);
+        assert_eq!(
+            preferred_memory_type_index(
+                0b0001,
+                &properties,
+                &[HOST_VISIBLE, HOST_CACHED, MemoryPropertyFlags::PROTECTED]
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    BufferUsageFlags, Format, VertexInputAttributeDescription, VertexInputBindingDescription,
+    VertexInputRate,
+};
+
+use crate::{
+    buffer_resource::{BufferData, BufferResource},
+    device_context::DeviceContext,
+    queue::CommandQueue,
+};
+
+pub struct Mesh {
+    vertex_buffer: BufferResource,
+    index_buffer: Option<BufferResource>,
+    vertex_count: u32,
+    index_count: u32,
+    vertex_stride: u32,
+    attributes: Vec<VertexInputAttributeDescription>,
+}
+
+impl Mesh {
+    pub fn new<V: BufferData>(
+        device: Rc<DeviceContext>,
+        queue: Rc<CommandQueue>,
+        vertices: &[V],
+        indices: Option<&[u32]>,
+    ) -> Self {
+        let vertex_buffer = BufferResource::new_device_local_with_data(
+            device.clone(),
+            queue.clone(),
+            BufferUsageFlags::VERTEX_BUFFER,
+            vertices,
+        );
+        let index_buffer = indices.map(|indices| {
+            BufferResource::new_device_local_with_data(
+                device,
+                queue,
+                BufferUsageFlags::INDEX_BUFFER,
+                indices,
+            )
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.map_or(0, |indices| indices.len() as u32),
+            vertex_stride: std::mem::size_of::<V>() as u32,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn with_attributes(mut self, attributes: &[VertexInputAttributeDescription]) -> Self {
+        self.attributes = attributes.to_vec();
+        self
+    }
+
+    pub fn vertex_buffer(&self) -> &BufferResource {
+        &self.vertex_buffer
+    }
+
+    pub fn index_buffer(&self) -> Option<&BufferResource> {
+        self.index_buffer.as_ref()
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.index_buffer.is_some()
+    }
+
+    pub fn binding_description(&self) -> VertexInputBindingDescription {
+        VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(self.vertex_stride)
+            .input_rate(VertexInputRate::VERTEX)
+    }
+
+    pub fn attribute_descriptions(&self) -> &[VertexInputAttributeDescription] {
+        &self.attributes
+    }
+}
+
+#[derive(Default)]
+pub struct MeshBuilder<'a> {
+    positions: &'a [[f32; 3]],
+    normals: Option<&'a [[f32; 3]]>,
+    uvs: Option<&'a [[f32; 2]]>,
+    indices: Option<&'a [u32]>,
+}
+
+impl<'a> MeshBuilder<'a> {
+    pub fn new(positions: &'a [[f32; 3]]) -> Self {
+        Self {
+            positions,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_normals(mut self, normals: &'a [[f32; 3]]) -> Self {
+        assert_eq!(normals.len(), self.positions.len(), "Normal count mismatch");
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: &'a [[f32; 2]]) -> Self {
+        assert_eq!(uvs.len(), self.positions.len(), "UV count mismatch");
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn with_indices(mut self, indices: &'a [u32]) -> Self {
+        self.indices = Some(indices);
+        self
+    }
+
+    pub fn attribute_descriptions(&self) -> Vec<VertexInputAttributeDescription> {
+        let mut attributes = vec![VertexInputAttributeDescription::default()
+            .location(0)
+            .format(Format::R32G32B32_SFLOAT)
+            .offset(0)];
+        let mut offset = 12;
+        if self.normals.is_some() {
+            attributes.push(
+                VertexInputAttributeDescription::default()
+                    .location(attributes.len() as u32)
+                    .format(Format::R32G32B32_SFLOAT)
+                    .offset(offset),
+            );
+            offset += 12;
+        }
+        if self.uvs.is_some() {
+            attributes.push(
+                VertexInputAttributeDescription::default()
+                    .location(attributes.len() as u32)
+                    .format(Format::R32G32_SFLOAT)
+                    .offset(offset),
+            );
+        }
+        attributes
+    }
+
+    pub fn interleave(&self) -> Vec<f32> {
+        let mut vertices = Vec::new();
+        for (index, position) in self.positions.iter().enumerate() {
+            vertices.extend_from_slice(position);
+            if let Some(normals) = self.normals {
+                vertices.extend_from_slice(&normals[index]);
+            }
+            if let Some(uvs) = self.uvs {
+                vertices.extend_from_slice(&uvs[index]);
+            }
+        }
+        vertices
+    }
+
+    pub fn stride(&self) -> u32 {
+        12 + self.normals.map_or(0, |_| 12) + self.uvs.map_or(0, |_| 8)
+    }
+
+    pub fn build(&self, device: Rc<DeviceContext>, queue: Rc<CommandQueue>) -> Mesh {
+        let mut mesh = Mesh::new(device, queue, &self.interleave(), self.indices)
+            .with_attributes(&self.attribute_descriptions());
+        mesh.vertex_count = self.positions.len() as u32;
+        mesh.vertex_stride = self.stride();
+        mesh
+    }
+}
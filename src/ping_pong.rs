@@ -0,0 +1,188 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    AccessFlags, DescriptorPool, DescriptorPoolCreateInfo, DescriptorPoolSize, DescriptorSet,
+    DescriptorSetAllocateInfo, DescriptorType, Format, ImageLayout, ImageUsageFlags,
+    MemoryPropertyFlags, PipelineStageFlags, Sampler,
+};
+
+use crate::command_buffer::Recorder;
+use crate::descriptor_update::DescriptorUpdateBatch;
+use crate::device_context::DeviceContext;
+use crate::image2d_resource::Image2DResource;
+use crate::image_resource::ImageResource;
+use crate::pipeline_descriptor::ComputePipeline;
+
+// Two storage images in GENERAL layout that swap between being read and written every pass.
+pub struct PingPong {
+    device: Rc<DeviceContext>,
+    images: [Image2DResource; 2],
+    source: usize,
+}
+
+// One descriptor set per orientation so flipping never rewrites descriptors.
+pub struct PingPongDescriptors {
+    device: Rc<DeviceContext>,
+    pool: DescriptorPool,
+    set: u32,
+    descriptor_sets: Vec<DescriptorSet>,
+}
+
+impl PingPong {
+    pub fn new(device: Rc<DeviceContext>, width: u32, height: u32, format: Format) -> Self {
+        let image = || {
+            Image2DResource::new(
+                device.clone(),
+                width,
+                height,
+                format,
+                ImageUsageFlags::STORAGE
+                    | ImageUsageFlags::TRANSFER_SRC
+                    | ImageUsageFlags::TRANSFER_DST,
+                MemoryPropertyFlags::DEVICE_LOCAL,
+                1,
+            )
+        };
+        let images = [image(), image()];
+        Self {
+            device,
+            images,
+            source: 0,
+        }
+    }
+
+    // Has to be recorded once before the first pass.
+    pub fn prepare(&mut self, command_buffer: &mut Recorder) {
+        for image in &mut self.images {
+            if image.layout() != ImageLayout::GENERAL {
+                command_buffer.image_resource_transition(image, ImageLayout::GENERAL);
+            }
+        }
+    }
+
+    pub fn source(&self) -> &Image2DResource {
+        &self.images[self.source]
+    }
+
+    pub fn source_mut(&mut self) -> &mut Image2DResource {
+        &mut self.images[self.source]
+    }
+
+    pub fn target(&self) -> &Image2DResource {
+        &self.images[1 - self.source]
+    }
+
+    pub fn target_mut(&mut self) -> &mut Image2DResource {
+        &mut self.images[1 - self.source]
+    }
+
+    // Builds the descriptor sets for a pipeline that reads `source_binding` and writes `target_binding` in `set`.
+    pub fn descriptors(
+        &self,
+        pipeline: &ComputePipeline,
+        set: u32,
+        source_binding: u32,
+        target_binding: u32,
+    ) -> PingPongDescriptors {
+        let pool_sizes = [DescriptorPoolSize::default()
+            .ty(DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(4)];
+        let pool_info = DescriptorPoolCreateInfo::default()
+            .pool_sizes(&pool_sizes)
+            .max_sets(2);
+        let pool = unsafe {
+            self.device
+                .handle()
+                .create_descriptor_pool(&pool_info, self.device.allocation_callbacks())
+                .expect("Descriptor pool creation failed")
+        };
+        let layouts = [pipeline.descriptor_set_layout(set); 2];
+        let allocation_info = DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        let descriptor_sets = unsafe {
+            self.device
+                .handle()
+                .allocate_descriptor_sets(&allocation_info)
+                .expect("Descriptor set allocation failed")
+        };
+
+        let mut batch = DescriptorUpdateBatch::new();
+        for (orientation, descriptor_set) in descriptor_sets.iter().enumerate() {
+            batch
+                .write_image(
+                    *descriptor_set,
+                    source_binding,
+                    DescriptorType::STORAGE_IMAGE,
+                    self.images[orientation].view(),
+                    Sampler::null(),
+                    ImageLayout::GENERAL,
+                )
+                .write_image(
+                    *descriptor_set,
+                    target_binding,
+                    DescriptorType::STORAGE_IMAGE,
+                    self.images[1 - orientation].view(),
+                    Sampler::null(),
+                    ImageLayout::GENERAL,
+                );
+        }
+        batch.flush(&self.device);
+
+        PingPongDescriptors {
+            device: self.device.clone(),
+            pool,
+            set,
+            descriptor_sets,
+        }
+    }
+
+    // Binds the set matching the current orientation, after bind_compute_pipeline.
+    pub fn bind(
+        &self,
+        command_buffer: &mut Recorder,
+        pipeline: &ComputePipeline,
+        descriptors: &PingPongDescriptors,
+    ) {
+        command_buffer.bind_compute_descriptor_set(
+            pipeline,
+            descriptors.set,
+            descriptors.descriptor_sets[self.source],
+        );
+    }
+
+    // Makes the pass that was just written readable by the next one and swaps the roles.
+    pub fn flip(&mut self, command_buffer: &mut Recorder) {
+        debug_assert!(
+            self.images
+                .iter()
+                .all(|image| image.layout() == ImageLayout::GENERAL),
+            "PingPong images are not in GENERAL layout, record PingPong::prepare first"
+        );
+        command_buffer.image_resource_barrier(
+            self.target(),
+            PipelineStageFlags::COMPUTE_SHADER,
+            PipelineStageFlags::COMPUTE_SHADER,
+            AccessFlags::SHADER_WRITE,
+            AccessFlags::SHADER_READ,
+        );
+        command_buffer.image_resource_barrier(
+            self.source(),
+            PipelineStageFlags::COMPUTE_SHADER,
+            PipelineStageFlags::COMPUTE_SHADER,
+            AccessFlags::SHADER_READ,
+            AccessFlags::SHADER_WRITE,
+        );
+        self.source = 1 - self.source;
+    }
+}
+
+impl Drop for PingPongDescriptors {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .destroy_descriptor_pool(self.pool, self.device.allocation_callbacks())
+        }
+    }
+}
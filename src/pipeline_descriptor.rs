@@ -1,29 +1,104 @@
 use std::{collections::HashMap, ffi::CString, path::Path, rc::Rc};
 
 use ash::vk::{
-    ComputePipelineCreateInfo, DescriptorBufferInfo, DescriptorImageInfo, DescriptorPoolCreateInfo,
-    DescriptorPoolSize, DescriptorSet, DescriptorSetAllocateInfo, DescriptorSetLayout,
-    DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType, Pipeline,
-    PipelineCache, PipelineLayout, PipelineLayoutCreateInfo, PipelineShaderStageCreateInfo,
-    PushConstantRange, ShaderModuleCreateInfo, ShaderStageFlags, WriteDescriptorSet,
+    AccessFlags, Buffer, BufferUsageFlags, ComputePipelineCreateInfo, DescriptorPoolSize,
+    DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType, ImageLayout,
+    Pipeline, PipelineCache, PipelineCreateFlags, PipelineCreationFeedback,
+    PipelineCreationFeedbackCreateInfo, PipelineCreationFeedbackFlags, PipelineLayout,
+    PipelineLayoutCreateInfo, PipelineShaderStageCreateInfo, PushConstantRange, Sampler,
+    ShaderStageFlags,
 };
 use rspirv_reflect::BindingCount;
 use shaderc::ShaderKind;
 
 use crate::{
+    acceleration_structure::AccelerationStructure,
     buffer_resource::BufferResource,
+    descriptor_allocator::DescriptorAllocator,
+    descriptor_set_layout::DescriptorSetLayoutHandle,
+    descriptor_update::DescriptorUpdateBatch,
     device_context::DeviceContext,
-    image2d_resource::Image2DResource,
     image_resource::ImageResource,
+    indices::{BindingIndex, SetIndex},
+    sampler_resource::SamplerResource,
     shader_compiler::{ShaderCompiler, ShaderReflection},
+    shader_module_cache::{ShaderModuleCache, ShaderModuleHandle},
+    shared_binding::SharedBinding,
 };
 
 pub struct ComputePipeline {
     device: Rc<DeviceContext>,
     pipeline_layout: PipelineLayout,
     pipeline: Pipeline,
+    descriptor_set_layouts: Vec<DescriptorSetLayout>,
+    // Set index and layout of the sets the pipeline allocates itself, the other layouts belong to
+    // their SharedBinding. Shared through the device with pipelines declaring the same bindings.
+    owned_set_layouts: Vec<(usize, Rc<DescriptorSetLayoutHandle>)>,
+    // Layout of the set numbers the shader skips, e.g. set 1 when it only uses 0 and 2.
+    _empty_set_layout: Option<Rc<DescriptorSetLayoutHandle>>,
+    descriptor_allocator: Option<DescriptorAllocator>,
+    // Null for the skipped set numbers.
     descriptor_sets: Vec<DescriptorSet>,
+    push_constant_ranges: Vec<PushConstantRange>,
     workgroup_size: (u32, u32, u32),
+    creation_feedback: Option<PipelineCreationFeedback>,
+    // Keeps the shared set layouts alive for as long as the pipeline layout uses them.
+    _shared_sets: Vec<Rc<SharedBinding>>,
+    _shader_module: Option<Rc<ShaderModuleHandle>>,
+    buffer_bindings: HashMap<(usize, u32), (Buffer, DescriptorType)>,
+    // Reflected shader access per (set, binding), bindings missing from it count as read / write.
+    binding_access: HashMap<(usize, u32), AccessFlags>,
+    // Samplers written to the pipeline's own sets, alive for as long as a set references them.
+    samplers: HashMap<(usize, u32), Rc<SamplerResource>>,
+}
+
+// Creation feedback is core in Vulkan 1.3, older devices need VK_EXT_pipeline_creation_feedback.
+pub(crate) fn creation_feedback_supported(device: &DeviceContext) -> bool {
+    device.gpu().api_version() >= ash::vk::API_VERSION_1_3
+        || device.has_extension(ash::ext::pipeline_creation_feedback::NAME.to_str().unwrap())
+}
+
+// A buffer created with several usages, e.g. STORAGE_BUFFER | UNIFORM_BUFFER, may be bound as any of them.
+pub(crate) fn validate_buffer_usage(buffer: &BufferResource, ty: DescriptorType) {
+    let usage = match ty {
+        DescriptorType::UNIFORM_BUFFER => BufferUsageFlags::UNIFORM_BUFFER,
+        DescriptorType::STORAGE_BUFFER => BufferUsageFlags::STORAGE_BUFFER,
+        _ => return,
+    };
+    debug_assert!(
+        buffer.usage().contains(usage),
+        "Binding a buffer as {:?} requires {:?} usage, the buffer has {:?}",
+        ty,
+        usage,
+        buffer.usage()
+    );
+}
+
+pub(crate) fn validate_push_constant_range(ranges: &[PushConstantRange], offset: u32, size: u32) {
+    assert!(
+        ranges
+            .iter()
+            .any(|range| offset >= range.offset && offset + size <= range.offset + range.size),
+        "Push constant update of {} bytes at offset {} is outside the pipeline's push constant ranges {:?}",
+        size,
+        offset,
+        ranges
+            .iter()
+            .map(|range| (range.offset, range.offset + range.size))
+            .collect::<Vec<_>>()
+    );
+}
+
+// Works on any descriptor set, so graphics pipelines can bind a TLAS for ray queries as well.
+pub fn write_acceleration_structure(
+    device: &DeviceContext,
+    set: DescriptorSet,
+    binding: u32,
+    acceleration_structure: &AccelerationStructure,
+) {
+    DescriptorUpdateBatch::new()
+        .write_acceleration_structure(set, binding, acceleration_structure.handle())
+        .flush(device);
 }
 
 impl ComputePipeline {
@@ -39,49 +114,308 @@ impl ComputePipeline {
         &self.descriptor_sets
     }
 
+    // None when the driver didn't report feedback. The VALID and APPLICATION_PIPELINE_CACHE_HIT
+    // flags and the duration in nanoseconds tell whether the pipeline cache was effective.
+    pub fn creation_feedback(&self) -> Option<PipelineCreationFeedback> {
+        self.creation_feedback.filter(|feedback| {
+            feedback
+                .flags
+                .contains(PipelineCreationFeedbackFlags::VALID)
+        })
+    }
+
+    // An extra set for one of the pipeline's own set layouts, e.g. for a material variant. It stays
+    // valid as long as the pipeline.
+    pub fn allocate_descriptor_set(
+        &mut self,
+        set: impl Into<SetIndex>,
+    ) -> Result<DescriptorSet, ash::vk::Result> {
+        let set = set.into().index();
+        let layout = self.owned_layout(set);
+        self.descriptor_allocator
+            .as_mut()
+            .expect("The pipeline has no descriptor sets of its own")
+            .allocate(&layout)
+    }
+
+    // Valid until reset_frame_descriptor_sets is called for the same frame index.
+    pub fn allocate_frame_descriptor_set(
+        &mut self,
+        frame_index: usize,
+        set: impl Into<SetIndex>,
+    ) -> Result<DescriptorSet, ash::vk::Result> {
+        let set = set.into().index();
+        let layout = self.owned_layout(set);
+        self.descriptor_allocator
+            .as_mut()
+            .expect("The pipeline has no descriptor sets of its own")
+            .allocate_for_frame(frame_index, &layout)
+    }
+
+    pub fn reset_frame_descriptor_sets(&mut self, frame_index: usize) {
+        if let Some(allocator) = &mut self.descriptor_allocator {
+            allocator.reset_frame(frame_index)
+        }
+    }
+
+    fn owned_layout(&self, set: usize) -> Rc<DescriptorSetLayoutHandle> {
+        self.owned_set_layouts
+            .iter()
+            .find(|(index, _)| *index == set)
+            .map(|(_, layout)| layout.clone())
+            .unwrap_or_else(|| panic!("Set {} is shared, allocate it from its SharedBinding", set))
+    }
+
+    pub fn descriptor_set_layout(&self, set: impl Into<SetIndex>) -> DescriptorSetLayout {
+        let set = set.into().index();
+        self.descriptor_set_layouts[set]
+    }
+
+    pub(crate) fn descriptor_set_layouts(&self) -> &[DescriptorSetLayout] {
+        &self.descriptor_set_layouts
+    }
+
     pub fn workgroup_size(&self) -> (u32, u32, u32) {
         self.workgroup_size
     }
 
-    pub fn set_storage_buffer(&mut self, set: usize, binding: usize, buffer: &BufferResource) {
-        let buffer_info = [DescriptorBufferInfo::default()
-            .buffer(buffer.buffer)
-            .range(buffer.content_size())];
-        let write = WriteDescriptorSet::default()
-            .buffer_info(&buffer_info)
-            .descriptor_type(DescriptorType::STORAGE_BUFFER)
-            .dst_set(self.descriptor_sets[set])
-            .dst_binding(binding as _);
-        unsafe { self.device.handle().update_descriptor_sets(&[write], &[]) }
-    }
-
-    pub fn set_storage_image(&mut self, set: usize, binding: usize, image: &Image2DResource) {
-        let image_info = [DescriptorImageInfo::default()
-            .image_view(image.view())
-            .image_layout(image.layout())];
-        let write = WriteDescriptorSet::default()
-            .image_info(&image_info)
-            .descriptor_type(DescriptorType::STORAGE_IMAGE)
-            .dst_set(self.descriptor_sets[set])
-            .dst_binding(binding as _);
-        unsafe { self.device.handle().update_descriptor_sets(&[write], &[]) }
-    }
-
-    pub fn set_uniform_buffer(&mut self, set: usize, binding: usize, buffer: &BufferResource) {
-        let buffer_info = [DescriptorBufferInfo::default()
-            .buffer(buffer.buffer)
-            .range(buffer.size())];
-        let write = WriteDescriptorSet::default()
-            .buffer_info(&buffer_info)
-            .descriptor_type(DescriptorType::UNIFORM_BUFFER)
-            .dst_set(self.descriptor_sets[set])
-            .dst_binding(binding as _);
-        unsafe { self.device.handle().update_descriptor_sets(&[write], &[]) }
-    }
-
-    fn create_descriptor_set_bindings(
+    pub fn push_constant_ranges(&self) -> &[PushConstantRange] {
+        &self.push_constant_ranges
+    }
+
+    // Buffers written to the pipeline's own sets, the descriptor type they were written as and
+    // how the shader accesses them.
+    pub(crate) fn bound_buffers(
+        &self,
+    ) -> impl Iterator<Item = (Buffer, DescriptorType, AccessFlags)> + '_ {
+        self.buffer_bindings
+            .iter()
+            .map(|(&(set, binding), &(buffer, ty))| (buffer, ty, self.binding_access(set, binding)))
+    }
+
+    // Read and write for bindings the shader doesn't declare.
+    pub(crate) fn binding_access(&self, set: usize, binding: u32) -> AccessFlags {
+        self.binding_access
+            .get(&(set, binding))
+            .copied()
+            .unwrap_or(AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE)
+    }
+
+    pub fn set_storage_buffer(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        buffer: &BufferResource,
+    ) {
+        let (set, binding) = (set.into().index(), binding.into().0);
+        validate_buffer_usage(buffer, DescriptorType::STORAGE_BUFFER);
+        self.buffer_bindings.insert(
+            (set, binding),
+            (buffer.buffer, DescriptorType::STORAGE_BUFFER),
+        );
+        DescriptorUpdateBatch::new()
+            .write_buffer(
+                self.descriptor_sets[set],
+                binding,
+                DescriptorType::STORAGE_BUFFER,
+                buffer.buffer,
+                buffer.content_size(),
+            )
+            .flush(&self.device);
+    }
+
+    /// Writes the image with the layout it is tracked in right now. The layout is captured when the
+    /// descriptor is written, not when the command buffer executes, so use
+    /// `set_storage_image_with_layout` when the image is transitioned after this call.
+    pub fn set_storage_image(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+    ) {
+        Self::warn_undefined_layout(image);
+        self.set_storage_image_with_layout(set, binding, image, image.layout())
+    }
+
+    /// Writes the image with the layout it will be in when the dispatch executes. Multisampled images
+    /// bind to `image2DMS` declarations.
+    pub fn set_storage_image_with_layout(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        layout: ImageLayout,
+    ) {
+        let (set, binding) = (set.into().index(), binding.into().0);
+        DescriptorUpdateBatch::new()
+            .write_image(
+                self.descriptor_sets[set],
+                binding,
+                DescriptorType::STORAGE_IMAGE,
+                image.view(),
+                Sampler::null(),
+                layout,
+            )
+            .flush(&self.device);
+    }
+
+    /// Writes a `SAMPLED_IMAGE` descriptor, a `texture2D` declaration. Pair it with `set_sampler`
+    /// for `texture(sampler2D(image, sampler), uv)`. Same layout capture rules as
+    /// `set_storage_image`.
+    pub fn set_sampled_image(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+    ) {
+        Self::warn_undefined_layout(image);
+        self.set_sampled_image_with_layout(set, binding, image, image.layout())
+    }
+
+    /// Writes a sampled image without a sampler, e.g. a `texture2DMS` read per sample with `texelFetch`.
+    pub fn set_sampled_image_with_layout(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        layout: ImageLayout,
+    ) {
+        let (set, binding) = (set.into().index(), binding.into().0);
+        DescriptorUpdateBatch::new()
+            .write_image(
+                self.descriptor_sets[set],
+                binding,
+                DescriptorType::SAMPLED_IMAGE,
+                image.sampled_view(),
+                Sampler::null(),
+                layout,
+            )
+            .flush(&self.device);
+    }
+
+    #[deprecated(note = "use set_sampled_image, which writes the same SAMPLED_IMAGE descriptor")]
+    pub fn set_separate_image(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+    ) {
+        self.set_sampled_image(set, binding, image)
+    }
+
+    #[deprecated(
+        note = "use set_sampled_image_with_layout, which writes the same SAMPLED_IMAGE descriptor"
+    )]
+    pub fn set_separate_image_with_layout(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        layout: ImageLayout,
+    ) {
+        self.set_sampled_image_with_layout(set, binding, image, layout)
+    }
+
+    /// Writes a `COMBINED_IMAGE_SAMPLER` descriptor, a `sampler2D` declaration. The pipeline keeps
+    /// the sampler alive until the binding is written again. Same layout capture rules as
+    /// `set_storage_image`.
+    pub fn set_combined_image_sampler(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        sampler: Rc<SamplerResource>,
+    ) {
+        Self::warn_undefined_layout(image);
+        self.set_combined_image_sampler_with_layout(set, binding, image, sampler, image.layout())
+    }
+
+    pub fn set_combined_image_sampler_with_layout(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        sampler: Rc<SamplerResource>,
+        layout: ImageLayout,
+    ) {
+        let (set, binding) = (set.into().index(), binding.into().0);
+        DescriptorUpdateBatch::new()
+            .write_image(
+                self.descriptor_sets[set],
+                binding,
+                DescriptorType::COMBINED_IMAGE_SAMPLER,
+                image.sampled_view(),
+                sampler.handle(),
+                layout,
+            )
+            .flush(&self.device);
+        self.samplers.insert((set, binding), sampler);
+    }
+
+    /// Writes a standalone `sampler` declaration, kept alive like in `set_combined_image_sampler`.
+    pub fn set_sampler(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        sampler: Rc<SamplerResource>,
+    ) {
+        let (set, binding) = (set.into().index(), binding.into().0);
+        DescriptorUpdateBatch::new()
+            .write_sampler(self.descriptor_sets[set], binding, sampler.handle())
+            .flush(&self.device);
+        self.samplers.insert((set, binding), sampler);
+    }
+
+    fn warn_undefined_layout(image: &impl ImageResource) {
+        if cfg!(debug_assertions) && image.layout() == ImageLayout::UNDEFINED {
+            println!(
+                "Writing descriptor for an image in UNDEFINED layout, transition the image first or pass the layout it will be used in"
+            );
+        }
+    }
+
+    pub fn set_uniform_buffer(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        buffer: &BufferResource,
+    ) {
+        let (set, binding) = (set.into().index(), binding.into().0);
+        validate_buffer_usage(buffer, DescriptorType::UNIFORM_BUFFER);
+        self.buffer_bindings.insert(
+            (set, binding),
+            (buffer.buffer, DescriptorType::UNIFORM_BUFFER),
+        );
+        DescriptorUpdateBatch::new()
+            .write_buffer(
+                self.descriptor_sets[set],
+                binding,
+                DescriptorType::UNIFORM_BUFFER,
+                buffer.buffer,
+                buffer.size(),
+            )
+            .flush(&self.device);
+    }
+
+    pub fn set_acceleration_structure(
+        &mut self,
+        set: impl Into<SetIndex>,
+        binding: impl Into<BindingIndex>,
+        acceleration_structure: &AccelerationStructure,
+    ) {
+        let (set, binding) = (set.into().index(), binding.into().0);
+        write_acceleration_structure(
+            &self.device,
+            self.descriptor_sets[set],
+            binding,
+            acceleration_structure,
+        )
+    }
+
+    pub(crate) fn create_descriptor_set_bindings(
         reflection: &ShaderReflection,
-    ) -> HashMap<u32, Vec<DescriptorSetLayoutBinding>> {
+        stage: ShaderStageFlags,
+    ) -> HashMap<u32, Vec<DescriptorSetLayoutBinding<'static>>> {
         let mut sets = HashMap::<u32, Vec<DescriptorSetLayoutBinding>>::new();
         if let Some(descriptor_sets) = reflection.descriptor_sets() {
             #[cfg(debug_assertions)]
@@ -93,7 +427,7 @@ impl ComputePipeline {
                     // let mut v = Vec::new();
                     let mut b = DescriptorSetLayoutBinding::default()
                         .binding(index)
-                        .stage_flags(ShaderStageFlags::COMPUTE);
+                        .stage_flags(stage);
                     match descriptor.binding_count {
                         BindingCount::One => {
                             b = b.descriptor_count(1);
@@ -126,7 +460,7 @@ impl ComputePipeline {
                             b = b.descriptor_type(DescriptorType::STORAGE_TEXEL_BUFFER);
                         }
                         rspirv_reflect::DescriptorType::UNIFORM_BUFFER => {
-                            b = b.descriptor_type(DescriptorType::STORAGE_BUFFER);
+                            b = b.descriptor_type(DescriptorType::UNIFORM_BUFFER);
                         }
                         rspirv_reflect::DescriptorType::STORAGE_BUFFER => {
                             b = b.descriptor_type(DescriptorType::STORAGE_BUFFER);
@@ -185,133 +519,400 @@ impl ComputePipeline {
         src: &str,
         entry_point: &str,
         explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+    ) -> Option<Self> {
+        Self::new_with_module_cache(
+            device,
+            max_frames_in_flight,
+            src,
+            entry_point,
+            explicit_bindings,
+            None,
+        )
+    }
+
+    pub fn new_with_module_cache(
+        device: Rc<DeviceContext>,
+        max_frames_in_flight: u32,
+        src: &str,
+        entry_point: &str,
+        explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+        cache: Option<&mut ShaderModuleCache>,
+    ) -> Option<Self> {
+        Self::new_with_flags(
+            device,
+            max_frames_in_flight,
+            src,
+            entry_point,
+            explicit_bindings,
+            cache,
+            PipelineCreateFlags::empty(),
+        )
+    }
+
+    // For shaders compiled offline, returns None when the SPIR-V can't be reflected.
+    pub fn new_from_spirv(
+        device: Rc<DeviceContext>,
+        max_frames_in_flight: u32,
+        code: &[u32],
+        entry_point: &str,
+        explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+    ) -> Option<Self> {
+        let reflection = match ShaderReflection::from_spirv(code) {
+            Ok(reflection) => reflection,
+            Err(error) => {
+                println!("Compute shader reflection failed: {}", error);
+                return None;
+            }
+        };
+        Self::create_from_spirv(
+            device,
+            max_frames_in_flight,
+            code,
+            reflection,
+            entry_point,
+            explicit_bindings,
+            &[],
+            None,
+            PipelineCreateFlags::empty(),
+        )
+    }
+
+    // Reads the file like load_spirv, but returns None when it is missing or not whole words.
+    pub fn new_from_spirv_file(
+        path: &Path,
+        device: Rc<DeviceContext>,
+        max_frames_in_flight: u32,
+        entry_point: &str,
+        explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+    ) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        if bytes.len() % 4 != 0 {
+            return None;
+        }
+        let code: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        Self::new_from_spirv(
+            device,
+            max_frames_in_flight,
+            &code,
+            entry_point,
+            explicit_bindings,
+        )
+    }
+
+    // Returns None when the shader fails to compile, or when FAIL_ON_PIPELINE_COMPILE_REQUIRED is
+    // set and the pipeline isn't in the cache.
+    pub fn new_with_flags(
+        device: Rc<DeviceContext>,
+        max_frames_in_flight: u32,
+        src: &str,
+        entry_point: &str,
+        explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+        cache: Option<&mut ShaderModuleCache>,
+        flags: PipelineCreateFlags,
+    ) -> Option<Self> {
+        Self::create(
+            device,
+            max_frames_in_flight,
+            src,
+            entry_point,
+            explicit_bindings,
+            &[],
+            cache,
+            flags,
+        )
+    }
+
+    // Uses the layout and set of each shared binding for its set index instead of creating them,
+    // bind_compute_pipeline binds the shared sets along with the pipeline's own sets.
+    pub fn new_with_shared_sets(
+        device: Rc<DeviceContext>,
+        max_frames_in_flight: u32,
+        src: &str,
+        entry_point: &str,
+        explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+        shared_sets: &[(u32, Rc<SharedBinding>)],
+    ) -> Option<Self> {
+        Self::create(
+            device,
+            max_frames_in_flight,
+            src,
+            entry_point,
+            explicit_bindings,
+            shared_sets,
+            None,
+            PipelineCreateFlags::empty(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        device: Rc<DeviceContext>,
+        max_frames_in_flight: u32,
+        src: &str,
+        entry_point: &str,
+        explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+        shared_sets: &[(u32, Rc<SharedBinding>)],
+        cache: Option<&mut ShaderModuleCache>,
+        flags: PipelineCreateFlags,
     ) -> Option<Self> {
         let result = ShaderCompiler::compile_string(src, ShaderKind::Compute, "", entry_point);
-        let this = if !result.failed() {
-            let reflection = result.reflect();
-            let mut descriptor_set_bindings = HashMap::new();
-            if let Some(explicit_bindings) = explicit_bindings {
-                for (index, bindings) in explicit_bindings {
-                    if let std::collections::hash_map::Entry::Vacant(e) =
-                        descriptor_set_bindings.entry(index)
-                    {
-                        e.insert(bindings);
-                    } else {
-                        descriptor_set_bindings
-                            .get_mut(&index)
-                            .unwrap()
-                            .extend(bindings.iter())
-                    }
+        if result.failed() {
+            println!("{}", result.error_string());
+            return None;
+        }
+        Self::create_from_spirv(
+            device,
+            max_frames_in_flight,
+            result.spirv(),
+            result.reflect(),
+            entry_point,
+            explicit_bindings,
+            shared_sets,
+            cache,
+            flags,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_from_spirv(
+        device: Rc<DeviceContext>,
+        max_frames_in_flight: u32,
+        code: &[u32],
+        reflection: ShaderReflection,
+        entry_point: &str,
+        explicit_bindings: Option<HashMap<u32, Vec<DescriptorSetLayoutBinding>>>,
+        shared_sets: &[(u32, Rc<SharedBinding>)],
+        cache: Option<&mut ShaderModuleCache>,
+        flags: PipelineCreateFlags,
+    ) -> Option<Self> {
+        if !reflection.has_entry_point(entry_point) {
+            println!(
+                "Entry point \"{}\" not found in compute shader, available entry points: {:?}",
+                entry_point,
+                reflection.entry_points()
+            );
+            return None;
+        }
+        let mut descriptor_set_bindings = HashMap::new();
+        if let Some(explicit_bindings) = explicit_bindings {
+            for (index, bindings) in explicit_bindings {
+                if let std::collections::hash_map::Entry::Vacant(e) =
+                    descriptor_set_bindings.entry(index)
+                {
+                    e.insert(bindings);
+                } else {
+                    descriptor_set_bindings
+                        .get_mut(&index)
+                        .unwrap()
+                        .extend(bindings.iter())
                 }
             }
+        }
+        for (index, _) in shared_sets {
+            descriptor_set_bindings.remove(index);
+        }
 
-            let mut constant_ranges = Vec::new();
-            if let Ok(push_blocks) = reflection.push_constant_ranges() {
-                push_blocks.into_iter().for_each(|block| {
-                    #[cfg(debug_assertions)]
-                    {
-                        println!("Push constant range: {:?}", block);
-                    }
-                    constant_ranges.push(
-                        PushConstantRange::default()
-                            .size(block.size)
-                            .offset(block.offset)
-                            .stage_flags(ShaderStageFlags::COMPUTE),
-                    );
-                });
+        let mut constant_ranges = Vec::new();
+        if let Ok(push_blocks) = reflection.push_constant_ranges() {
+            push_blocks.into_iter().for_each(|block| {
+                #[cfg(debug_assertions)]
+                {
+                    println!("Push constant range: {:?}", block);
+                }
+                constant_ranges.push(
+                    PushConstantRange::default()
+                        .size(block.size)
+                        .offset(block.offset)
+                        .stage_flags(ShaderStageFlags::COMPUTE),
+                );
+            });
+        }
+
+        // Set numbers can be sparse, the gaps get an empty layout and no set.
+        let set_count = descriptor_set_bindings
+            .keys()
+            .chain(shared_sets.iter().map(|(index, _)| index))
+            .max()
+            .map_or(0, |index| *index as usize + 1);
+        let empty_set_layout = (set_count > descriptor_set_bindings.len() + shared_sets.len())
+            .then(|| device.get_or_create_descriptor_set_layout(&[]));
+        let mut layouts = vec![
+            empty_set_layout
+                .as_ref()
+                .map_or(DescriptorSetLayout::null(), |layout| layout.handle());
+            set_count
+        ];
+        for (index, shared) in shared_sets {
+            layouts[*index as usize] = shared.layout();
+        }
+        let mut pool_sizes = Vec::new();
+        let mut owned_set_layouts = Vec::new();
+        for (index, set) in &descriptor_set_bindings {
+            let layout = device.get_or_create_descriptor_set_layout(set);
+            layouts[*index as usize] = layout.handle();
+            owned_set_layouts.push((*index as usize, layout));
+
+            for binding in set {
+                let size = DescriptorPoolSize::default()
+                    .ty(binding.descriptor_type)
+                    .descriptor_count(binding.descriptor_count);
+                pool_sizes.push(size);
             }
+        }
 
-            let mut layouts = vec![DescriptorSetLayout::default(); descriptor_set_bindings.len()];
-            let mut pool_sizes = Vec::new();
-            for (index, set) in &descriptor_set_bindings {
-                let mut builder = DescriptorSetLayoutCreateInfo::default();
-                builder = builder.bindings(set);
-                let layout = unsafe {
-                    device
-                        .handle()
-                        .create_descriptor_set_layout(&builder, None)
-                        .expect("Creating descriptorset layout failed: {}")
-                };
+        let pipeline_info_builder = PipelineLayoutCreateInfo::default()
+            .set_layouts(&layouts)
+            .push_constant_ranges(&constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .handle()
+                .create_pipeline_layout(&pipeline_info_builder, device.allocation_callbacks())
+                .expect("Pipeline layout creation failed")
+        };
+
+        // Without an explicit cache the module is shared through the device, and kept alive
+        // by the pipeline so pipelines created from the same source reuse it.
+        let (shader_module, shared_module) = if let Some(cache) = cache {
+            (cache.get_or_create(code), None)
+        } else {
+            let module = device.get_or_create_shader_module(code);
+            (module.handle(), Some(module))
+        };
+
+        let s = CString::new(entry_point).expect("String creation failed");
+        let shader_stage_info = PipelineShaderStageCreateInfo::default()
+            .module(shader_module)
+            .stage(ShaderStageFlags::COMPUTE)
+            .name(&s);
+
+        let mut compute_pipeline_info = ComputePipelineCreateInfo::default()
+            .flags(flags)
+            .layout(pipeline_layout)
+            .stage(shader_stage_info);
 
-                layouts[*index as usize] = layout;
+        let mut creation_feedback = PipelineCreationFeedback::default();
+        let mut stage_feedback = [PipelineCreationFeedback::default()];
+        let feedback_supported = creation_feedback_supported(&device);
+        let mut feedback_info = PipelineCreationFeedbackCreateInfo::default()
+            .pipeline_creation_feedback(&mut creation_feedback)
+            .pipeline_stage_creation_feedbacks(&mut stage_feedback);
+        if feedback_supported {
+            compute_pipeline_info = compute_pipeline_info.push_next(&mut feedback_info);
+        }
+
+        let pipeline = unsafe {
+            device.handle().create_compute_pipelines(
+                PipelineCache::null(),
+                &[compute_pipeline_info],
+                device.allocation_callbacks(),
+            )
+        };
+        // Only the sets the pipeline owns are allocated, the shared ones are filled in after.
+        owned_set_layouts.sort_unstable_by_key(|(index, _)| *index);
 
-                for binding in set {
-                    let size = DescriptorPoolSize::default()
-                        .ty(binding.descriptor_type)
-                        .descriptor_count(binding.descriptor_count);
-                    pool_sizes.push(size);
+        let pipeline = match pipeline {
+            Ok(pipelines) => pipelines[0],
+            Err((_, ash::vk::Result::PIPELINE_COMPILE_REQUIRED)) => {
+                #[cfg(debug_assertions)]
+                {
+                    println!("Compute pipeline requires compilation, not created");
+                }
+                unsafe {
+                    device
+                        .handle()
+                        .destroy_pipeline_layout(pipeline_layout, device.allocation_callbacks());
                 }
+                return None;
             }
+            Err((_, error)) => panic!("Pipeline creation failed: {}", error),
+        };
 
-            let pipeline_info_builder = PipelineLayoutCreateInfo::default()
-                .set_layouts(&layouts)
-                .push_constant_ranges(&constant_ranges);
-            let pipeline_layout = unsafe {
-                device
-                    .handle()
-                    .create_pipeline_layout(&pipeline_info_builder, None)
-                    .expect("Pipeline layout creation failed")
-            };
-
-            let shader_info = ShaderModuleCreateInfo::default().code(result.spirv());
-            let shader_module = unsafe {
-                device
-                    .handle()
-                    .create_shader_module(&shader_info, None)
-                    .expect("Shader module creation failed")
-            };
-
-            let s = CString::new(entry_point).expect("String creation failed");
-            let shader_stage_info = PipelineShaderStageCreateInfo::default()
-                .module(shader_module)
-                .stage(ShaderStageFlags::COMPUTE)
-                .name(&s);
-
-            let compute_pipeline_info = ComputePipelineCreateInfo::default()
-                .layout(pipeline_layout)
-                .stage(shader_stage_info);
-
-            let pipeline = unsafe {
-                device
-                    .handle()
-                    .create_compute_pipelines(PipelineCache::null(), &[compute_pipeline_info], None)
-                    .expect("Pipeline creation failed")[0]
-            };
-
-            let pool_info = DescriptorPoolCreateInfo::default()
-                .pool_sizes(&pool_sizes)
-                .max_sets(max_frames_in_flight * descriptor_set_bindings.len() as u32);
-
-            let pool = unsafe {
-                device
-                    .handle()
-                    .create_descriptor_pool(&pool_info, None)
-                    .expect("Descriptor pool creation failed")
-            };
-            let allocation_info = DescriptorSetAllocateInfo::default()
-                .descriptor_pool(pool)
-                .set_layouts(&layouts);
-            let descriptor_sets = unsafe {
-                device
-                    .handle()
-                    .allocate_descriptor_sets(&allocation_info)
+        // Every pool fits any of the owned sets, the allocator adds pools for extra sets.
+        let mut descriptor_allocator = (!owned_set_layouts.is_empty()).then(|| {
+            DescriptorAllocator::new(
+                device.clone(),
+                &pool_sizes,
+                max_frames_in_flight * owned_set_layouts.len() as u32,
+            )
+        });
+        let mut owned_sets = owned_set_layouts
+            .iter()
+            .map(|(_, layout)| {
+                descriptor_allocator
+                    .as_mut()
+                    .unwrap()
+                    .allocate(layout)
                     .expect("Descriptor set allocation failed")
-            };
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+        let descriptor_sets: Vec<DescriptorSet> = (0..set_count as u32)
+            .map(|index| {
+                match shared_sets
+                    .iter()
+                    .find(|(shared_index, _)| *shared_index == index)
+                {
+                    Some((_, shared)) => shared.descriptor_set(),
+                    None if descriptor_set_bindings.contains_key(&index) => {
+                        owned_sets.next().unwrap()
+                    }
+                    None => DescriptorSet::null(),
+                }
+            })
+            .collect();
 
-            let workgroup_size = reflection.compute_work_group_size().unwrap_or((1, 1, 1));
+        let workgroup_size = reflection.compute_work_group_size().unwrap_or((1, 1, 1));
+        let binding_access = reflection
+            .binding_access()
+            .into_iter()
+            .map(|((set, binding), access)| ((set as usize, binding), access))
+            .collect();
 
-            Some(Self {
-                device,
-                pipeline_layout,
-                pipeline,
-                descriptor_sets,
-                workgroup_size,
-            })
-        } else {
-            println!("{}", result.error_string());
-            None
-        };
+        Some(Self {
+            device,
+            pipeline_layout,
+            pipeline,
+            descriptor_set_layouts: layouts,
+            owned_set_layouts,
+            _empty_set_layout: empty_set_layout,
+            descriptor_allocator,
+            descriptor_sets,
+            push_constant_ranges: constant_ranges,
+            workgroup_size,
+            creation_feedback: feedback_supported.then_some(creation_feedback),
+            _shared_sets: shared_sets
+                .iter()
+                .map(|(_, shared)| shared.clone())
+                .collect(),
+            _shader_module: shared_module,
+            buffer_bindings: HashMap::new(),
+            binding_access,
+            samplers: HashMap::new(),
+        })
+    }
+}
 
-        this
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        // In reverse creation order: pipeline, its layout, then the set layouts the pipeline
+        // layout was created from. Set layouts are destroyed with the last pipeline using them,
+        // shared ones are left to their SharedBinding.
+        unsafe {
+            self.device
+                .handle()
+                .destroy_pipeline(self.pipeline, self.device.allocation_callbacks());
+            self.device
+                .handle()
+                .destroy_pipeline_layout(self.pipeline_layout, self.device.allocation_callbacks());
+        }
+        self.owned_set_layouts.clear();
+        // Destroying the pools frees the sets allocated from them.
+        self.descriptor_allocator = None;
+        // The module is destroyed with the last pipeline holding it, or stays in the cache that
+        // created it.
+        self._shader_module = None;
     }
 }
@@ -1,7 +1,36 @@
+use std::any::Any;
+use std::cell::{Cell, OnceCell, RefCell};
 use std::rc::Rc;
+use std::time::Instant;
 
 use crate::device_context::DeviceContext;
-use ash::vk::{CommandPool, CommandPoolCreateFlags, CommandPoolCreateInfo, Queue, QueueFlags};
+use crate::indices::QueueFamilyIndex;
+use crate::kernels::Kernels;
+use ash::vk::{
+    CommandBuffer, CommandPool, CommandPoolCreateFlags, CommandPoolCreateInfo, Fence, Queue,
+    QueueFlags, QueueGlobalPriorityKHR,
+};
+
+/// What a `WaitHandle` does when it is dropped before its submission completed.
+/// The default is `Block`, which stalls until the GPU is done and logs the stall in debug builds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WaitHandleDropPolicy {
+    #[default]
+    Block,
+    /// Hand the command buffer and fence to the queue, freed by `CommandQueue::collect_garbage`.
+    Defer,
+    /// Panic in debug builds to catch ignored submissions, block in release builds.
+    PanicInDebug,
+}
+
+type DeferredSubmission = (CommandBuffer, Fence, u64, Vec<Box<dyn Any>>);
+pub(crate) type Garbage = RefCell<Vec<DeferredSubmission>>;
+
+#[derive(Default)]
+struct SubmissionTracker {
+    next_id: Cell<u64>,
+    pending: RefCell<Vec<(u64, Instant)>>,
+}
 
 #[derive(Clone)]
 pub struct CommandQueue {
@@ -9,29 +38,83 @@ pub struct CommandQueue {
     handle: Queue,
     queue_family_index: u32,
     command_pool: CommandPool,
+    resettable: bool,
+    // Shared between clones so deferred submissions are only freed once, and a policy set on one
+    // clone applies to all of them.
+    drop_policy: Rc<Cell<WaitHandleDropPolicy>>,
+    garbage: Rc<Garbage>,
+    submissions: Rc<SubmissionTracker>,
+    // Holds the device's kernel pipelines once a kernel ran on this queue, so later calls reuse them.
+    kernels: Rc<OnceCell<Rc<Kernels>>>,
 }
 
 impl CommandQueue {
     pub fn new(device: Rc<DeviceContext>, flags: QueueFlags) -> Self {
         let queue_family_index = device.queue_family_index(flags).unwrap();
+        Self::new_with_family_index(device, queue_family_index)
+    }
+
+    pub fn new_transfer_dedicated(device: Rc<DeviceContext>) -> Option<Self> {
+        let queue_family_index = device.gpu().dedicated_transfer_family_index()?;
+        Some(Self::new_with_family_index(device, queue_family_index))
+    }
+
+    // Command buffers from this queue can be reset and recorded again, see CommandBuffer::reset.
+    pub fn new_resettable(device: Rc<DeviceContext>, flags: QueueFlags) -> Self {
+        let queue_family_index = device.queue_family_index(flags).unwrap();
+        Self::create(
+            device,
+            queue_family_index,
+            CommandPoolCreateFlags::TRANSIENT | CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+        )
+    }
+
+    pub fn new_with_family_index(
+        device: Rc<DeviceContext>,
+        queue_family_index: impl Into<QueueFamilyIndex>,
+    ) -> Self {
+        Self::create(
+            device,
+            queue_family_index.into().0,
+            CommandPoolCreateFlags::TRANSIENT,
+        )
+    }
 
+    fn create(
+        device: Rc<DeviceContext>,
+        queue_family_index: u32,
+        pool_flags: CommandPoolCreateFlags,
+    ) -> Self {
         let pool_info = CommandPoolCreateInfo::default()
-            .flags(CommandPoolCreateFlags::TRANSIENT)
+            .flags(pool_flags)
             .queue_family_index(queue_family_index);
         let command_pool = unsafe {
             device
                 .handle()
-                .create_command_pool(&pool_info, None)
+                .create_command_pool(&pool_info, device.allocation_callbacks())
                 .expect("Command Pool Creation failed")
         };
+        let garbage = Rc::new(RefCell::new(Vec::new()));
+        device.register_garbage(command_pool, &garbage);
         Self {
             device: device.clone(),
             handle: device.queue(queue_family_index),
             queue_family_index,
             command_pool,
+            resettable: pool_flags.contains(CommandPoolCreateFlags::RESET_COMMAND_BUFFER),
+            drop_policy: Rc::new(Cell::new(WaitHandleDropPolicy::default())),
+            garbage,
+            submissions: Rc::new(SubmissionTracker::default()),
+            kernels: Rc::new(OnceCell::new()),
         }
     }
 
+    pub(crate) fn kernels(&self) -> Rc<Kernels> {
+        self.kernels
+            .get_or_init(|| self.device.get_or_create_kernels())
+            .clone()
+    }
+
     pub fn device(&self) -> Rc<DeviceContext> {
         self.device.clone()
     }
@@ -44,7 +127,111 @@ impl CommandQueue {
         self.handle
     }
 
+    pub fn priority(&self) -> f32 {
+        self.device.queue_priority(self.queue_family_index).priority
+    }
+
+    // None when no global priority was requested or it was denied at device creation.
+    pub fn global_priority(&self) -> Option<QueueGlobalPriorityKHR> {
+        self.device
+            .queue_priority(self.queue_family_index)
+            .global_priority
+    }
+
     pub(crate) fn pool(&self) -> CommandPool {
         self.command_pool
     }
+
+    pub fn is_resettable(&self) -> bool {
+        self.resettable
+    }
+
+    pub fn drop_policy(&self) -> WaitHandleDropPolicy {
+        self.drop_policy.get()
+    }
+
+    pub fn set_drop_policy(&self, policy: WaitHandleDropPolicy) {
+        self.drop_policy.set(policy)
+    }
+
+    pub(crate) fn defer_free(
+        &self,
+        command_buffer: CommandBuffer,
+        fence: Fence,
+        submission: u64,
+        retained: Vec<Box<dyn Any>>,
+    ) {
+        self.garbage
+            .borrow_mut()
+            .push((command_buffer, fence, submission, retained))
+    }
+
+    pub(crate) fn begin_submission(&self) -> u64 {
+        let id = self.submissions.next_id.get();
+        self.submissions.next_id.set(id + 1);
+        self.submissions
+            .pending
+            .borrow_mut()
+            .push((id, Instant::now()));
+        id
+    }
+
+    pub(crate) fn end_submission(&self, id: u64) {
+        self.submissions
+            .pending
+            .borrow_mut()
+            .retain(|(pending, _)| *pending != id)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.submissions.pending.borrow().len()
+    }
+
+    pub fn oldest_pending_ms(&self) -> Option<u64> {
+        self.submissions
+            .pending
+            .borrow()
+            .iter()
+            .map(|(_, submitted)| submitted.elapsed().as_millis() as u64)
+            .max()
+    }
+
+    pub fn collect_garbage(&self) {
+        let device = self.device.handle();
+        self.garbage
+            .borrow_mut()
+            .retain(|(command_buffer, fence, submission, _)| unsafe {
+                if device.get_fence_status(*fence).unwrap_or(false) {
+                    self.end_submission(*submission);
+                    device.free_command_buffers(self.command_pool, &[*command_buffer]);
+                    device.destroy_fence(*fence, self.device.allocation_callbacks());
+                    false
+                } else {
+                    true
+                }
+            })
+    }
+}
+
+impl Drop for CommandQueue {
+    fn drop(&mut self) {
+        // Clones share the pool and the deferred submissions, the last one destroys them.
+        if Rc::strong_count(&self.garbage) > 1 {
+            return;
+        }
+        let device = self.device.handle();
+        unsafe {
+            for (command_buffer, fence, _, _) in self.garbage.borrow_mut().drain(..) {
+                if let Err(error) =
+                    self.device
+                        .observe(device.wait_for_fences(&[fence], true, u64::MAX))
+                {
+                    println!("Waiting for deferred submission failed: {:?}", error);
+                }
+                device.free_command_buffers(self.command_pool, &[command_buffer]);
+                device.destroy_fence(fence, self.device.allocation_callbacks());
+            }
+            device.destroy_command_pool(self.command_pool, self.device.allocation_callbacks());
+        }
+    }
 }
@@ -0,0 +1,214 @@
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use ash::vk::{BufferUsageFlags, ImageLayout, MemoryPropertyFlags, PipelineStageFlags};
+
+use crate::buffer_resource::{BufferData, BufferResource};
+use crate::command_buffer::{CommandBuffer, Recorder};
+use crate::image_resource::ImageResource;
+use crate::image_upload::{band_rows, row_size, DEFAULT_STAGING_BUDGET};
+use crate::queue::CommandQueue;
+use crate::wait_handle::WaitHandle;
+
+fn region_size(image: &impl ImageResource, width: u32, height: u32) -> usize {
+    row_size(image, width) * height as usize
+}
+
+fn readback_buffer(queue: &CommandQueue, size: usize) -> BufferResource {
+    BufferResource::new(
+        queue.device(),
+        size,
+        MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+        BufferUsageFlags::TRANSFER_DST,
+    )
+}
+
+// Copies the region and puts the image back in the layout it was in.
+fn record_region_copy(
+    command_buffer: &mut Recorder,
+    image: &mut impl ImageResource,
+    buffer: &mut BufferResource,
+    region: (i32, i32, u32, u32),
+) {
+    let (x, y, width, height) = region;
+    let layout = image.layout();
+    assert!(
+        layout != ImageLayout::UNDEFINED,
+        "Image {} has undefined contents and can't be read back",
+        image.debug_name()
+    );
+    command_buffer.image_resource_transition(image, ImageLayout::TRANSFER_SRC_OPTIMAL);
+    command_buffer.copy_image_region_to_buffer(image, buffer, x, y, width, height);
+    command_buffer.image_resource_transition(image, layout);
+    command_buffer.buffer_resource_barrier(
+        buffer,
+        PipelineStageFlags::TRANSFER,
+        PipelineStageFlags::HOST,
+        ash::vk::AccessFlags::TRANSFER_WRITE,
+        ash::vk::AccessFlags::HOST_READ,
+    );
+}
+
+// Reads small regions, e.g. the pixel under the cursor for picking, reusing one readback buffer.
+// Regions larger than the staging budget are read in bands of rows through the same buffer.
+pub struct RegionReader {
+    queue: Rc<CommandQueue>,
+    staging_budget: usize,
+    buffer: Option<BufferResource>,
+}
+
+impl RegionReader {
+    pub fn new(queue: Rc<CommandQueue>) -> Self {
+        Self {
+            queue,
+            staging_budget: DEFAULT_STAGING_BUDGET,
+            buffer: None,
+        }
+    }
+
+    pub fn with_staging_budget(mut self, bytes: usize) -> Self {
+        self.staging_budget = bytes;
+        self.buffer = None;
+        self
+    }
+
+    pub fn staging_budget(&self) -> usize {
+        self.staging_budget
+    }
+
+    // Blocks until the copy has finished.
+    pub fn read(
+        &mut self,
+        image: &mut impl ImageResource,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        let row_size = row_size(image, width);
+        let rows = band_rows(row_size, height, self.staging_budget);
+        let band_size = row_size * rows as usize;
+        if self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| (buffer.content_size() as usize) < band_size)
+        {
+            self.buffer = Some(readback_buffer(&self.queue, band_size));
+        }
+        let buffer = self.buffer.as_mut().unwrap();
+
+        let mut data = Vec::with_capacity(row_size * height as usize);
+        let mut band_y = 0;
+        while band_y < height {
+            let band_height = rows.min(height - band_y);
+            let mut command_buffer = CommandBuffer::new(self.queue.clone());
+            command_buffer.begin();
+            record_region_copy(
+                &mut command_buffer,
+                image,
+                buffer,
+                (x, y + band_y as i32, width, band_height),
+            );
+            command_buffer.submit().wait();
+
+            let band = buffer.copy_data::<u8>();
+            data.extend_from_slice(&band[..row_size * band_height as usize]);
+            band_y += band_height;
+        }
+        data
+    }
+}
+
+pub fn read_image_region(
+    queue: Rc<CommandQueue>,
+    image: &mut impl ImageResource,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    RegionReader::new(queue).read(image, x, y, width, height)
+}
+
+// Starts the copy without waiting for it, poll the returned handle to get the data.
+pub fn read_image_region_async(
+    queue: Rc<CommandQueue>,
+    image: &mut impl ImageResource,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> PendingRead<u8> {
+    let mut buffer = readback_buffer(&queue, region_size(image, width, height));
+    let mut command_buffer = CommandBuffer::new(queue);
+    command_buffer.begin();
+    record_region_copy(
+        &mut command_buffer,
+        image,
+        &mut buffer,
+        (x, y, width, height),
+    );
+    PendingRead::new(command_buffer.submit(), buffer)
+}
+
+pub fn read_image_async(
+    queue: Rc<CommandQueue>,
+    image: &mut impl ImageResource,
+) -> PendingRead<u8> {
+    let (width, height) = (image.width(), image.height());
+    read_image_region_async(queue, image, 0, 0, width, height)
+}
+
+// Keeps the readback buffer and the submitted command buffer alive until the data is taken.
+pub struct PendingRead<T> {
+    wait_handle: WaitHandle,
+    buffer: BufferResource,
+    element: PhantomData<T>,
+}
+
+impl<T: BufferData + Copy> PendingRead<T> {
+    pub(crate) fn new(wait_handle: WaitHandle, buffer: BufferResource) -> Self {
+        Self {
+            wait_handle,
+            buffer,
+            element: PhantomData,
+        }
+    }
+
+    pub fn wait_handle(&self) -> &WaitHandle {
+        &self.wait_handle
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.wait_handle.has_completed()
+    }
+
+    // Doesn't block, None while the copy is still in flight.
+    pub fn poll(&self) -> Option<Vec<T>> {
+        self.is_ready().then(|| self.buffer.copy_data())
+    }
+
+    pub fn block(self) -> Vec<T> {
+        self.wait_handle.wait();
+        self.buffer.copy_data()
+    }
+}
+
+// Checks the fence on every poll and wakes itself right away, no executor specific waking.
+#[cfg(feature = "async")]
+impl<T: BufferData + Copy> std::future::Future for PendingRead<T> {
+    type Output = Vec<T>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        context: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        match PendingRead::poll(&*self) {
+            Some(data) => std::task::Poll::Ready(data),
+            None => {
+                context.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
@@ -1,11 +1,183 @@
 use std::rc::Rc;
 
 use ash::vk::{
-    AttachmentDescription, AttachmentReference, Format, ImageLayout, SubpassDependency,
-    SubpassDescription,
+    AccessFlags, AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+    Format, ImageLayout, PipelineBindPoint, PipelineStageFlags, RenderPassCreateInfo,
+    SampleCountFlags, SubpassDependency, SubpassDescription, SUBPASS_EXTERNAL,
 };
 
-use crate::{device_context::DeviceContext, swapchain::Swapchain};
+use crate::{
+    device_context::DeviceContext, format_info::is_depth_format, swapchain::Swapchain,
+    swapchain_util::color_initial_layout,
+};
+
+#[derive(Default, Clone)]
+pub struct RenderPassBuilder {
+    color_attachments: Vec<AttachmentDescription>,
+    depth_attachment: Option<AttachmentDescription>,
+    depth_layout: Option<ImageLayout>,
+}
+
+impl RenderPassBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_color_attachment(
+        mut self,
+        format: Format,
+        load_op: AttachmentLoadOp,
+        store_op: AttachmentStoreOp,
+        final_layout: ImageLayout,
+    ) -> Self {
+        self.color_attachments.push(
+            AttachmentDescription::default()
+                .format(format)
+                .samples(SampleCountFlags::TYPE_1)
+                .load_op(load_op)
+                .store_op(store_op)
+                .stencil_load_op(AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(AttachmentStoreOp::DONT_CARE)
+                .final_layout(final_layout),
+        );
+        self
+    }
+
+    pub fn with_depth_attachment(
+        mut self,
+        format: Format,
+        load_op: AttachmentLoadOp,
+        store_op: AttachmentStoreOp,
+        final_layout: ImageLayout,
+    ) -> Self {
+        assert!(
+            is_depth_format(format),
+            "{:?} is not a depth format",
+            format
+        );
+        self.depth_layout = None;
+        self.depth_attachment = Some(
+            AttachmentDescription::default()
+                .format(format)
+                .samples(SampleCountFlags::TYPE_1)
+                .load_op(load_op)
+                .store_op(store_op)
+                .stencil_load_op(load_op)
+                .stencil_store_op(store_op)
+                .final_layout(final_layout),
+        );
+        self
+    }
+
+    // Reuses depth from an earlier pass (e.g. a depth prepass) without writing it. The image has to be
+    // in `layout` already, DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL keeps stencil writable.
+    pub fn with_read_only_depth_attachment(mut self, format: Format, layout: ImageLayout) -> Self {
+        assert!(
+            is_depth_format(format),
+            "{:?} is not a depth format",
+            format
+        );
+        assert!(
+            matches!(
+                layout,
+                ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+                    | ImageLayout::DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL
+            ),
+            "{:?} is not a read-only depth layout",
+            layout
+        );
+        self.depth_attachment = Some(
+            AttachmentDescription::default()
+                .format(format)
+                .samples(SampleCountFlags::TYPE_1)
+                .load_op(AttachmentLoadOp::LOAD)
+                .store_op(AttachmentStoreOp::STORE)
+                .stencil_load_op(AttachmentLoadOp::LOAD)
+                .stencil_store_op(AttachmentStoreOp::STORE)
+                .initial_layout(layout)
+                .final_layout(layout),
+        );
+        self.depth_layout = Some(layout);
+        self
+    }
+
+    pub fn build(self, device: Rc<DeviceContext>) -> RenderPass {
+        let mut attachment_descriptions = self.color_attachments.clone();
+        let attachment_refs: Vec<AttachmentReference> = (0..self.color_attachments.len())
+            .map(|index| AttachmentReference {
+                attachment: index as u32,
+                layout: ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .collect();
+
+        let depth_ref = self.depth_attachment.map(|depth| {
+            attachment_descriptions.push(depth);
+            AttachmentReference {
+                attachment: self.color_attachments.len() as u32,
+                layout: self
+                    .depth_layout
+                    .unwrap_or(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+            }
+        });
+
+        let subpass_dependencies = vec![
+            SubpassDependency {
+                src_subpass: SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: AccessFlags::COLOR_ATTACHMENT_READ
+                    | AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: SUBPASS_EXTERNAL,
+                src_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: PipelineStageFlags::FRAGMENT_SHADER
+                    | PipelineStageFlags::COMPUTE_SHADER
+                    | PipelineStageFlags::TRANSFER,
+                src_access_mask: AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access_mask: AccessFlags::SHADER_READ | AccessFlags::TRANSFER_READ,
+                ..Default::default()
+            },
+        ];
+
+        let mut subpass = SubpassDescription::default()
+            .color_attachments(&attachment_refs)
+            .pipeline_bind_point(PipelineBindPoint::GRAPHICS);
+        if let Some(depth_ref) = depth_ref.as_ref() {
+            subpass = subpass.depth_stencil_attachment(depth_ref);
+        }
+        let subpasses = [subpass];
+
+        let renderpass_create_info = RenderPassCreateInfo::default()
+            .attachments(&attachment_descriptions)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+
+        let handle = unsafe {
+            device
+                .handle()
+                .create_render_pass(&renderpass_create_info, device.allocation_callbacks())
+                .expect("Renderpass creation failed")
+        };
+
+        RenderPass {
+            device,
+            attachment_refs,
+            attachment_descriptions,
+            subpass_dependencies,
+            handle,
+        }
+    }
+}
 
 pub struct RenderPass {
     device: Rc<DeviceContext>,
@@ -15,12 +187,15 @@ pub struct RenderPass {
     handle: ash::vk::RenderPass,
 }
 impl RenderPass {
+    // Uses the swapchain's color load op, so it stays compatible with the swapchain's framebuffers.
     pub fn from_swapchain(device: Rc<DeviceContext>, swapchain: &Swapchain) -> Self {
+        let load_op = swapchain.resolved_config().color_load_op;
         let attachment_descriptions = vec![ash::vk::AttachmentDescription {
             format: *swapchain.format(),
             samples: ash::vk::SampleCountFlags::TYPE_1,
-            load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+            load_op,
             store_op: ash::vk::AttachmentStoreOp::STORE,
+            initial_layout: color_initial_layout(load_op),
             final_layout: ash::vk::ImageLayout::PRESENT_SRC_KHR,
             ..Default::default()
         }];
@@ -53,7 +228,7 @@ impl RenderPass {
         let handle = unsafe {
             device
                 .handle()
-                .create_render_pass(&renderpass_create_info, None)
+                .create_render_pass(&renderpass_create_info, device.allocation_callbacks())
                 .expect("Renderpass creation failed for swapchain")
         };
 
@@ -117,7 +292,7 @@ impl RenderPass {
         let handle = unsafe {
             device
                 .handle()
-                .create_render_pass(&renderpass_create_info, None)
+                .create_render_pass(&renderpass_create_info, device.allocation_callbacks())
                 .expect("Renderpass creation failed for swapchain")
         };
 
@@ -133,10 +308,35 @@ impl RenderPass {
     pub fn handle(&self) -> &ash::vk::RenderPass {
         &self.handle
     }
+
+    pub fn attachment_count(&self) -> usize {
+        self.attachment_descriptions.len()
+    }
+
+    pub fn color_attachment_count(&self) -> usize {
+        self.attachment_refs.len()
+    }
+
+    pub fn attachment_formats(&self) -> Vec<Format> {
+        self.attachment_descriptions
+            .iter()
+            .map(|description| description.format)
+            .collect()
+    }
+
+    pub fn has_depth_attachment(&self) -> bool {
+        self.attachment_descriptions
+            .iter()
+            .any(|description| is_depth_format(description.format))
+    }
 }
 
 impl Drop for RenderPass {
     fn drop(&mut self) {
-        unsafe { self.device.handle().destroy_render_pass(self.handle, None) }
+        unsafe {
+            self.device
+                .handle()
+                .destroy_render_pass(self.handle, self.device.allocation_callbacks())
+        }
     }
 }
@@ -0,0 +1,225 @@
+use std::{
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use ash::vk::{
+    BorderColor, ClearColorValue, CompareOp, Filter, Sampler, SamplerAddressMode,
+    SamplerCreateInfo, SamplerCustomBorderColorCreateInfoEXT, SamplerMipmapMode,
+};
+
+use crate::device_context::DeviceContext;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDescriptor {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap_mode: SamplerMipmapMode,
+    pub address_mode_u: SamplerAddressMode,
+    pub address_mode_v: SamplerAddressMode,
+    pub address_mode_w: SamplerAddressMode,
+    pub max_anisotropy: Option<f32>,
+    pub compare_op: Option<CompareOp>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub border_color: BorderColor,
+    pub custom_border_color: Option<[f32; 4]>,
+}
+
+impl Default for SamplerDescriptor {
+    fn default() -> Self {
+        Self {
+            mag_filter: Filter::LINEAR,
+            min_filter: Filter::LINEAR,
+            mipmap_mode: SamplerMipmapMode::LINEAR,
+            address_mode_u: SamplerAddressMode::REPEAT,
+            address_mode_v: SamplerAddressMode::REPEAT,
+            address_mode_w: SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+            compare_op: None,
+            min_lod: 0.0,
+            max_lod: ash::vk::LOD_CLAMP_NONE,
+            border_color: BorderColor::FLOAT_TRANSPARENT_BLACK,
+            custom_border_color: None,
+        }
+    }
+}
+
+impl PartialEq for SamplerDescriptor {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDescriptor {}
+
+impl Hash for SamplerDescriptor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl SamplerDescriptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_filter(mut self, mag_filter: Filter, min_filter: Filter) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self
+    }
+
+    pub fn with_mipmap_mode(mut self, mode: SamplerMipmapMode) -> Self {
+        self.mipmap_mode = mode;
+        self
+    }
+
+    pub fn with_address_mode(mut self, mode: SamplerAddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = Some(max_anisotropy);
+        self
+    }
+
+    pub fn with_compare_op(mut self, op: CompareOp) -> Self {
+        self.compare_op = Some(op);
+        self
+    }
+
+    pub fn with_lod_range(mut self, min_lod: f32, max_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+        self
+    }
+
+    pub fn with_border_color(mut self, color: BorderColor) -> Self {
+        self.border_color = color;
+        self
+    }
+
+    // Requires VK_EXT_custom_border_color with the custom_border_colors feature enabled.
+    pub fn with_custom_border_color(mut self, color: [f32; 4]) -> Self {
+        self.border_color = BorderColor::FLOAT_CUSTOM_EXT;
+        self.custom_border_color = Some(color);
+        self
+    }
+
+    fn uses_address_mode(&self, mode: SamplerAddressMode) -> bool {
+        self.address_mode_u == mode || self.address_mode_v == mode || self.address_mode_w == mode
+    }
+
+    fn key(&self) -> impl Eq + Hash {
+        (
+            (self.mag_filter, self.min_filter, self.mipmap_mode),
+            (
+                self.address_mode_u,
+                self.address_mode_v,
+                self.address_mode_w,
+            ),
+            self.max_anisotropy.map(f32::to_bits),
+            self.compare_op,
+            (self.min_lod.to_bits(), self.max_lod.to_bits()),
+            self.border_color,
+            self.custom_border_color
+                .map(|color| color.map(f32::to_bits)),
+        )
+    }
+}
+
+pub struct SamplerResource {
+    device: Rc<DeviceContext>,
+    handle: Sampler,
+    descriptor: SamplerDescriptor,
+}
+
+impl SamplerResource {
+    pub fn new(device: Rc<DeviceContext>, descriptor: &SamplerDescriptor) -> Self {
+        let max_anisotropy = descriptor.max_anisotropy.map(|requested| {
+            assert!(
+                device.enabled_features().sampler_anisotropy != 0,
+                "Anisotropic filtering requires the sampler_anisotropy feature, enable it with Gpu::device_context_builder"
+            );
+            let limit = device.gpu().limits().max_sampler_anisotropy;
+            #[cfg(debug_assertions)]
+            {
+                if requested > limit {
+                    println!(
+                        "Sampler anisotropy {} clamped to the device limit of {}",
+                        requested, limit
+                    );
+                }
+            }
+            requested.min(limit)
+        });
+
+        assert!(
+            !descriptor.uses_address_mode(SamplerAddressMode::MIRROR_CLAMP_TO_EDGE)
+                || device.enabled_vulkan12_features().sampler_mirror_clamp_to_edge != 0
+                || device.has_extension("VK_KHR_sampler_mirror_clamp_to_edge"),
+            "MIRROR_CLAMP_TO_EDGE requires the sampler_mirror_clamp_to_edge feature, enable it with Gpu::device_context_builder"
+        );
+        assert!(
+            descriptor.custom_border_color.is_none() || device.custom_border_colors_enabled(),
+            "Custom border colors require VK_EXT_custom_border_color and the custom_border_colors feature, enable them with Gpu::device_context_builder"
+        );
+
+        let mut custom_border_color = SamplerCustomBorderColorCreateInfoEXT::default()
+            .custom_border_color(ClearColorValue {
+                float32: descriptor.custom_border_color.unwrap_or_default(),
+            });
+        let mut info = SamplerCreateInfo::default()
+            .mag_filter(descriptor.mag_filter)
+            .min_filter(descriptor.min_filter)
+            .mipmap_mode(descriptor.mipmap_mode)
+            .address_mode_u(descriptor.address_mode_u)
+            .address_mode_v(descriptor.address_mode_v)
+            .address_mode_w(descriptor.address_mode_w)
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+            .compare_enable(descriptor.compare_op.is_some())
+            .compare_op(descriptor.compare_op.unwrap_or(CompareOp::ALWAYS))
+            .min_lod(descriptor.min_lod)
+            .max_lod(descriptor.max_lod)
+            .border_color(descriptor.border_color);
+        if descriptor.custom_border_color.is_some() {
+            info = info.push_next(&mut custom_border_color);
+        }
+
+        let handle = unsafe {
+            device
+                .handle()
+                .create_sampler(&info, device.allocation_callbacks())
+                .expect("Sampler creation failed")
+        };
+
+        Self {
+            device,
+            handle,
+            descriptor: *descriptor,
+        }
+    }
+
+    pub fn handle(&self) -> Sampler {
+        self.handle
+    }
+
+    pub fn descriptor(&self) -> &SamplerDescriptor {
+        &self.descriptor
+    }
+}
+
+impl Drop for SamplerResource {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .destroy_sampler(self.handle, self.device.allocation_callbacks())
+        }
+    }
+}
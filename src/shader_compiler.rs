@@ -1,8 +1,13 @@
+use ash::vk::{AccessFlags, DescriptorSetLayoutBinding, Format, ShaderStageFlags};
 use byteorder::ReadBytesExt;
+use rspirv_reflect::rspirv::dr::{Instruction, Operand};
+use rspirv_reflect::rspirv::spirv::{Decoration, ExecutionModel, Op, StorageClass};
 use rspirv_reflect::{DescriptorInfo, Reflection};
+use std::collections::HashMap;
 use std::path::Path;
 use std::{collections::BTreeMap, fs::File};
 
+use crate::pipeline_descriptor::ComputePipeline;
 use shaderc::{CompilationArtifact, CompileOptions, Compiler, OptimizationLevel, ShaderKind};
 
 pub fn load_spirv(path: &str) -> Vec<u32> {
@@ -23,6 +28,14 @@ pub struct ShaderReflection {
 }
 
 impl ShaderReflection {
+    // For SPIR-V compiled offline, e.g. loaded with load_spirv.
+    pub fn from_spirv(code: &[u32]) -> Result<Self, rspirv_reflect::ReflectError> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(code.as_ptr() as *const u8, std::mem::size_of_val(code))
+        };
+        Reflection::new_from_spirv(bytes).map(|reflection| Self { reflection })
+    }
+
     pub fn descriptor_sets(&self) -> Option<BTreeMap<u32, BTreeMap<u32, DescriptorInfo>>> {
         match self.reflection.get_descriptor_sets() {
             Ok(sets) => Some(sets),
@@ -39,6 +52,233 @@ impl ShaderReflection {
     pub fn compute_work_group_size(&self) -> Option<(u32, u32, u32)> {
         self.reflection.get_compute_group_size()
     }
+
+    // Names of the OpEntryPoint instructions in the module.
+    pub fn entry_points(&self) -> Vec<String> {
+        self.reflection
+            .0
+            .entry_points
+            .iter()
+            .filter_map(|instruction| match instruction.operands.get(2) {
+                Some(Operand::LiteralString(name)) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn has_entry_point(&self, entry_point: &str) -> bool {
+        self.entry_points().iter().any(|name| name == entry_point)
+    }
+
+    // How the shader accesses each (set, binding), from the NonReadable / NonWritable decorations
+    // GLSL emits for writeonly / readonly. Blocks count when every member is decorated.
+    pub fn binding_access(&self) -> HashMap<(u32, u32), AccessFlags> {
+        let module = &self.reflection.0;
+        let find = |id: u32| {
+            module
+                .types_global_values
+                .iter()
+                .find(|instruction| instruction.result_id == Some(id))
+        };
+        let decoration_value = |id: u32, decoration: Decoration| {
+            module
+                .annotations
+                .iter()
+                .find_map(|annotation| match annotation.operands.as_slice() {
+                    [Operand::IdRef(target), Operand::Decoration(d), Operand::LiteralInt32(value)]
+                        if *target == id && *d == decoration =>
+                    {
+                        Some(*value)
+                    }
+                    _ => None,
+                })
+        };
+        let decorated = |id: u32, decoration: Decoration| {
+            module.annotations.iter().any(|annotation| {
+                matches!(annotation.operands.as_slice(),
+                    [Operand::IdRef(target), Operand::Decoration(d)] if *target == id && *d == decoration)
+            })
+        };
+        let member_decorated = |id: u32, member: u32, decoration: Decoration| {
+            module.annotations.iter().any(|annotation| {
+                matches!(annotation.operands.as_slice(),
+                    [Operand::IdRef(target), Operand::LiteralInt32(m), Operand::Decoration(d)]
+                        if *target == id && *m == member && *d == decoration)
+            })
+        };
+
+        let mut access = HashMap::new();
+        let variables = module
+            .types_global_values
+            .iter()
+            .filter(|instruction| instruction.class.opcode == Op::Variable);
+        for variable in variables {
+            let Some(id) = variable.result_id else {
+                continue;
+            };
+            let (Some(set), Some(binding)) = (
+                decoration_value(id, Decoration::DescriptorSet),
+                decoration_value(id, Decoration::Binding),
+            ) else {
+                continue;
+            };
+            // Through the pointer and any array of blocks to the block itself.
+            let mut ty = variable.result_type.and_then(find).and_then(|pointer| {
+                match pointer.operands.get(1) {
+                    Some(Operand::IdRef(pointee)) => find(*pointee),
+                    _ => None,
+                }
+            });
+            while let Some(array) =
+                ty.filter(|ty| matches!(ty.class.opcode, Op::TypeArray | Op::TypeRuntimeArray))
+            {
+                ty = match array.operands.first() {
+                    Some(Operand::IdRef(element)) => find(*element),
+                    _ => None,
+                };
+            }
+            let has = |decoration: Decoration| {
+                decorated(id, decoration)
+                    || ty.is_some_and(|ty| {
+                        ty.class.opcode == Op::TypeStruct
+                            && !ty.operands.is_empty()
+                            && ty.result_id.is_some_and(|block| {
+                                (0..ty.operands.len() as u32)
+                                    .all(|member| member_decorated(block, member, decoration))
+                            })
+                    })
+            };
+            // Uniform blocks can't be written, storage blocks in the Uniform class are BufferBlock.
+            let uniform_block = matches!(
+                variable.operands.first(),
+                Some(Operand::StorageClass(StorageClass::Uniform))
+            ) && ty
+                .and_then(|ty| ty.result_id)
+                .is_some_and(|block| decorated(block, Decoration::Block));
+            let mut flags = AccessFlags::empty();
+            if !has(Decoration::NonReadable) {
+                flags |= AccessFlags::SHADER_READ;
+            }
+            if !uniform_block && !has(Decoration::NonWritable) {
+                flags |= AccessFlags::SHADER_WRITE;
+            }
+            access.insert((set, binding), flags);
+        }
+        access
+    }
+
+    // Layout bindings per set with the given stage flags, e.g. for a SharedBinding used by a
+    // graphics pipeline, which doesn't create its own sets.
+    pub fn descriptor_set_bindings(
+        &self,
+        stage: ShaderStageFlags,
+    ) -> HashMap<u32, Vec<DescriptorSetLayoutBinding<'static>>> {
+        ComputePipeline::create_descriptor_set_bindings(self, stage)
+    }
+
+    // Location and format of every scalar / vector input of the vertex entry point. Built-ins,
+    // matrices and arrays are skipped.
+    pub fn vertex_inputs(&self) -> BTreeMap<u32, Format> {
+        let module = &self.reflection.0;
+        let find = |id: u32| {
+            module
+                .types_global_values
+                .iter()
+                .find(|instruction| instruction.result_id == Some(id))
+        };
+        let location = |id: u32| {
+            module.annotations.iter().find_map(|annotation| {
+                match annotation.operands.as_slice() {
+                    [Operand::IdRef(target), Operand::Decoration(Decoration::Location), Operand::LiteralInt32(location)]
+                        if *target == id =>
+                    {
+                        Some(*location)
+                    }
+                    _ => None,
+                }
+            })
+        };
+
+        let mut inputs = BTreeMap::new();
+        let interface = module.entry_points.iter().filter(|entry_point| {
+            matches!(
+                entry_point.operands.first(),
+                Some(Operand::ExecutionModel(ExecutionModel::Vertex))
+            )
+        });
+        for entry_point in interface {
+            for operand in entry_point.operands.iter().skip(3) {
+                let Operand::IdRef(id) = operand else {
+                    continue;
+                };
+                let Some(variable) = find(*id) else {
+                    continue;
+                };
+                if variable.class.opcode != Op::Variable
+                    || !matches!(
+                        variable.operands.first(),
+                        Some(Operand::StorageClass(StorageClass::Input))
+                    )
+                {
+                    continue;
+                }
+                let pointee = variable
+                    .result_type
+                    .and_then(find)
+                    .and_then(|pointer| match pointer.operands.get(1) {
+                        Some(Operand::IdRef(pointee)) => find(*pointee),
+                        _ => None,
+                    });
+                if let (Some(location), Some(format)) = (
+                    location(*id),
+                    pointee.and_then(|ty| input_format(ty, &find)),
+                ) {
+                    inputs.insert(location, format);
+                }
+            }
+        }
+        inputs
+    }
+}
+
+fn input_format<'a>(
+    ty: &'a Instruction,
+    find: &dyn Fn(u32) -> Option<&'a Instruction>,
+) -> Option<Format> {
+    let (component, count) = match (ty.class.opcode, ty.operands.as_slice()) {
+        (Op::TypeVector, [Operand::IdRef(component), Operand::LiteralInt32(count)]) => {
+            (find(*component)?, *count)
+        }
+        _ => (ty, 1),
+    };
+    let formats = match (component.class.opcode, component.operands.as_slice()) {
+        (Op::TypeFloat, [Operand::LiteralInt32(32)]) => [
+            Format::R32_SFLOAT,
+            Format::R32G32_SFLOAT,
+            Format::R32G32B32_SFLOAT,
+            Format::R32G32B32A32_SFLOAT,
+        ],
+        (Op::TypeFloat, [Operand::LiteralInt32(64)]) => [
+            Format::R64_SFLOAT,
+            Format::R64G64_SFLOAT,
+            Format::R64G64B64_SFLOAT,
+            Format::R64G64B64A64_SFLOAT,
+        ],
+        (Op::TypeInt, [Operand::LiteralInt32(32), Operand::LiteralInt32(1)]) => [
+            Format::R32_SINT,
+            Format::R32G32_SINT,
+            Format::R32G32B32_SINT,
+            Format::R32G32B32A32_SINT,
+        ],
+        (Op::TypeInt, [Operand::LiteralInt32(32), Operand::LiteralInt32(0)]) => [
+            Format::R32_UINT,
+            Format::R32G32_UINT,
+            Format::R32G32B32_UINT,
+            Format::R32G32B32A32_UINT,
+        ],
+        _ => return None,
+    };
+    formats.get(count as usize - 1).copied()
 }
 
 pub struct CompilationResult {
@@ -111,9 +351,15 @@ impl ShaderCompiler {
         let compiler = Compiler::new();
         if let Some(compiler) = compiler {
             let mut options = CompileOptions::new().unwrap();
+            // SPIR-V 1.6 needs a Vulkan 1.3 environment, which also enables extensions like GL_EXT_ray_query.
+            options.set_target_env(
+                shaderc::TargetEnv::Vulkan,
+                shaderc::EnvVersion::Vulkan1_3 as u32,
+            );
             options.set_target_spirv(shaderc::SpirvVersion::V1_6);
             options.set_optimization_level(OptimizationLevel::Performance);
-            let result = compiler.compile_into_spirv(src, kind, origin, entry_point, None);
+            let result =
+                compiler.compile_into_spirv(src, kind, origin, entry_point, Some(&options));
             CompilationResult { result }
         } else {
             panic!("No Compiler can be created")
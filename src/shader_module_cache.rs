@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    rc::Rc,
+};
+
+use ash::vk::{ShaderModule, ShaderModuleCreateInfo};
+
+use crate::device_context::DeviceContext;
+
+// A module shared through DeviceContext::get_or_create_shader_module, destroyed with the last Rc.
+pub struct ShaderModuleHandle {
+    device: Rc<DeviceContext>,
+    module: ShaderModule,
+}
+
+impl ShaderModuleHandle {
+    pub(crate) fn new(device: Rc<DeviceContext>, spirv: &[u32]) -> Self {
+        let info = ShaderModuleCreateInfo::default().code(spirv);
+        let module = unsafe {
+            device
+                .handle()
+                .create_shader_module(&info, device.allocation_callbacks())
+                .expect("Shader module creation failed")
+        };
+        Self { device, module }
+    }
+
+    pub fn handle(&self) -> ShaderModule {
+        self.module
+    }
+}
+
+impl Drop for ShaderModuleHandle {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .destroy_shader_module(self.module, self.device.allocation_callbacks())
+        }
+    }
+}
+
+pub struct ShaderModuleCache {
+    device: Rc<DeviceContext>,
+    modules: HashMap<u64, ShaderModule>,
+}
+
+impl ShaderModuleCache {
+    pub fn new(device: Rc<DeviceContext>) -> Self {
+        Self {
+            device,
+            modules: HashMap::new(),
+        }
+    }
+
+    pub fn hash(spirv: &[u32]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        spirv.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn get_or_create(&mut self, spirv: &[u32]) -> ShaderModule {
+        let device = &self.device;
+        *self.modules.entry(Self::hash(spirv)).or_insert_with(|| {
+            let info = ShaderModuleCreateInfo::default().code(spirv);
+            unsafe {
+                device
+                    .handle()
+                    .create_shader_module(&info, device.allocation_callbacks())
+                    .expect("Shader module creation failed")
+            }
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+impl Drop for ShaderModuleCache {
+    fn drop(&mut self) {
+        for module in self.modules.values() {
+            unsafe {
+                self.device
+                    .handle()
+                    .destroy_shader_module(*module, self.device.allocation_callbacks())
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    AttachmentLoadOp, AttachmentStoreOp, BorderColor, CompareOp, Filter, Format, ImageLayout,
+    SamplerAddressMode,
+};
+
+use crate::{
+    device_context::DeviceContext,
+    framebuffer::Framebuffer,
+    image2d_resource::Image2DResource,
+    renderpass::{RenderPass, RenderPassBuilder},
+    sampler_resource::{SamplerDescriptor, SamplerResource},
+};
+
+pub struct ShadowMap {
+    image: Image2DResource,
+    render_pass: RenderPass,
+    framebuffer: Framebuffer,
+    sampler: Rc<SamplerResource>,
+}
+
+impl ShadowMap {
+    pub fn new(device: Rc<DeviceContext>, size: u32) -> Self {
+        Self::new_with_format(device, size, Format::D32_SFLOAT)
+    }
+
+    pub fn new_with_format(device: Rc<DeviceContext>, size: u32, format: Format) -> Self {
+        let image = Image2DResource::new_depth(device.clone(), size, size, format);
+        let render_pass = RenderPassBuilder::new()
+            .with_depth_attachment(
+                format,
+                AttachmentLoadOp::CLEAR,
+                AttachmentStoreOp::STORE,
+                ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            )
+            .build(device.clone());
+        let framebuffer = Framebuffer::new(device.clone(), &render_pass, &[&image], size, size);
+        let sampler = device.get_sampler(
+            &SamplerDescriptor::new()
+                .with_filter(Filter::LINEAR, Filter::LINEAR)
+                .with_address_mode(SamplerAddressMode::CLAMP_TO_BORDER)
+                .with_border_color(BorderColor::FLOAT_OPAQUE_WHITE)
+                .with_compare_op(CompareOp::LESS_OR_EQUAL),
+        );
+
+        Self {
+            image,
+            render_pass,
+            framebuffer,
+            sampler,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.framebuffer.width()
+    }
+
+    pub fn image(&self) -> &Image2DResource {
+        &self.image
+    }
+
+    pub fn image_mut(&mut self) -> &mut Image2DResource {
+        &mut self.image
+    }
+
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn sampler(&self) -> &Rc<SamplerResource> {
+        &self.sampler
+    }
+}
@@ -0,0 +1,218 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use ash::vk::{
+    DescriptorPool, DescriptorPoolCreateInfo, DescriptorPoolSize, DescriptorSet,
+    DescriptorSetAllocateInfo, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+    ImageLayout, ImageView, Sampler,
+};
+
+use crate::{
+    acceleration_structure::AccelerationStructure,
+    buffer_resource::BufferResource,
+    descriptor_set_layout::DescriptorSetLayoutHandle,
+    descriptor_update::DescriptorUpdateBatch,
+    device_context::DeviceContext,
+    image_resource::ImageResource,
+    indices::BindingIndex,
+    pipeline_descriptor::{validate_buffer_usage, write_acceleration_structure},
+    sampler_resource::SamplerResource,
+};
+
+// A descriptor set owned outside of any pipeline, e.g. frame constants shared by many compute
+// pipelines. Pass it to ComputePipeline::new_with_shared_sets for the set index it occupies, its
+// resources then only have to be written once.
+pub struct SharedBinding {
+    device: Rc<DeviceContext>,
+    bindings: Vec<DescriptorSetLayoutBinding<'static>>,
+    // Shared through the device, pipelines declaring the same bindings for their own sets use
+    // the same layout object.
+    layout: Rc<DescriptorSetLayoutHandle>,
+    pool: DescriptorPool,
+    set: DescriptorSet,
+    // Alive for as long as the set references them, replaced when the binding is written again.
+    samplers: RefCell<HashMap<u32, Rc<SamplerResource>>>,
+}
+
+impl SharedBinding {
+    pub fn new(
+        device: Rc<DeviceContext>,
+        bindings: &[DescriptorSetLayoutBinding<'static>],
+    ) -> Self {
+        let layout = device.get_or_create_descriptor_set_layout(bindings);
+
+        let pool_sizes: Vec<DescriptorPoolSize> = bindings
+            .iter()
+            .map(|binding| {
+                DescriptorPoolSize::default()
+                    .ty(binding.descriptor_type)
+                    .descriptor_count(binding.descriptor_count)
+            })
+            .collect();
+        let pool_info = DescriptorPoolCreateInfo::default()
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let pool = unsafe {
+            device
+                .handle()
+                .create_descriptor_pool(&pool_info, device.allocation_callbacks())
+                .expect("Descriptor pool creation failed")
+        };
+
+        let layouts = [layout.handle()];
+        let allocation_info = DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        let set = unsafe {
+            device
+                .handle()
+                .allocate_descriptor_sets(&allocation_info)
+                .expect("Descriptor set allocation failed")[0]
+        };
+
+        Self {
+            device,
+            bindings: bindings.to_vec(),
+            layout,
+            pool,
+            set,
+            samplers: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn layout(&self) -> DescriptorSetLayout {
+        self.layout.handle()
+    }
+
+    pub fn descriptor_set(&self) -> DescriptorSet {
+        self.set
+    }
+
+    pub fn bindings(&self) -> &[DescriptorSetLayoutBinding<'static>] {
+        &self.bindings
+    }
+
+    fn validate_binding(&self, binding: u32, ty: DescriptorType) {
+        let declared = self
+            .bindings
+            .iter()
+            .find(|declared| declared.binding == binding)
+            .unwrap_or_else(|| panic!("Binding {} is not part of this shared binding", binding));
+        assert_eq!(
+            declared.descriptor_type, ty,
+            "Binding {} was declared as {:?}",
+            binding, declared.descriptor_type
+        );
+    }
+
+    pub fn set_uniform_buffer(&self, binding: impl Into<BindingIndex>, buffer: &BufferResource) {
+        let binding = binding.into().0;
+        self.write_buffer(binding, DescriptorType::UNIFORM_BUFFER, buffer)
+    }
+
+    pub fn set_storage_buffer(&self, binding: impl Into<BindingIndex>, buffer: &BufferResource) {
+        let binding = binding.into().0;
+        self.write_buffer(binding, DescriptorType::STORAGE_BUFFER, buffer)
+    }
+
+    fn write_buffer(&self, binding: u32, ty: DescriptorType, buffer: &BufferResource) {
+        self.validate_binding(binding, ty);
+        validate_buffer_usage(buffer, ty);
+        DescriptorUpdateBatch::new()
+            .write_buffer(self.set, binding, ty, buffer.buffer, buffer.content_size())
+            .flush(&self.device);
+    }
+
+    pub fn set_storage_image_with_layout(
+        &self,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        layout: ImageLayout,
+    ) {
+        self.write_image(
+            binding.into().0,
+            DescriptorType::STORAGE_IMAGE,
+            image.view(),
+            Sampler::null(),
+            layout,
+        )
+    }
+
+    pub fn set_sampled_image_with_layout(
+        &self,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        layout: ImageLayout,
+    ) {
+        self.write_image(
+            binding.into().0,
+            DescriptorType::SAMPLED_IMAGE,
+            image.sampled_view(),
+            Sampler::null(),
+            layout,
+        )
+    }
+
+    #[deprecated(
+        note = "use set_sampled_image_with_layout, which writes the same SAMPLED_IMAGE descriptor"
+    )]
+    pub fn set_separate_image_with_layout(
+        &self,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        layout: ImageLayout,
+    ) {
+        self.set_sampled_image_with_layout(binding, image, layout)
+    }
+
+    pub fn set_combined_image_sampler_with_layout(
+        &self,
+        binding: impl Into<BindingIndex>,
+        image: &impl ImageResource,
+        sampler: Rc<SamplerResource>,
+        layout: ImageLayout,
+    ) {
+        let binding = binding.into().0;
+        self.write_image(
+            binding,
+            DescriptorType::COMBINED_IMAGE_SAMPLER,
+            image.sampled_view(),
+            sampler.handle(),
+            layout,
+        );
+        self.samplers.borrow_mut().insert(binding, sampler);
+    }
+
+    fn write_image(
+        &self,
+        binding: u32,
+        ty: DescriptorType,
+        view: ImageView,
+        sampler: Sampler,
+        layout: ImageLayout,
+    ) {
+        self.validate_binding(binding, ty);
+        DescriptorUpdateBatch::new()
+            .write_image(self.set, binding, ty, view, sampler, layout)
+            .flush(&self.device);
+    }
+
+    pub fn set_acceleration_structure(
+        &self,
+        binding: impl Into<BindingIndex>,
+        acceleration_structure: &AccelerationStructure,
+    ) {
+        let binding = binding.into().0;
+        self.validate_binding(binding, DescriptorType::ACCELERATION_STRUCTURE_KHR);
+        write_acceleration_structure(&self.device, self.set, binding, acceleration_structure)
+    }
+}
+
+impl Drop for SharedBinding {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle()
+                .destroy_descriptor_pool(self.pool, self.device.allocation_callbacks());
+        }
+    }
+}
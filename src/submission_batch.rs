@@ -0,0 +1,215 @@
+use std::rc::Rc;
+
+use ash::vk::{
+    Fence, FenceCreateInfo, PipelineStageFlags, Semaphore, SemaphoreCreateInfo, SubmitInfo,
+};
+
+use crate::command_buffer::CommandBuffer;
+use crate::device_context::DeviceContext;
+use crate::queue::CommandQueue;
+
+// Collects the command buffers of a frame and submits them with a single vkQueueSubmit and one
+// fence. Buffers without dependencies on each other share a SubmitInfo, a new SubmitInfo is only
+// started when a buffer depends on one in the current group, chained to it with a semaphore.
+pub struct SubmissionBatch {
+    queue: Rc<CommandQueue>,
+    command_buffers: Vec<CommandBuffer>,
+    // Index of the first buffer of every SubmitInfo.
+    group_starts: Vec<usize>,
+}
+
+impl SubmissionBatch {
+    pub fn new(queue: Rc<CommandQueue>) -> Self {
+        Self {
+            queue,
+            command_buffers: Vec::new(),
+            group_starts: Vec::new(),
+        }
+    }
+
+    // Returns the index to declare dependencies on.
+    pub fn add(&mut self, command_buffer: CommandBuffer) -> usize {
+        self.add_after(command_buffer, &[])
+    }
+
+    // The command buffer runs after all buffers in dependencies have completed.
+    #[track_caller]
+    pub fn add_after(&mut self, command_buffer: CommandBuffer, dependencies: &[usize]) -> usize {
+        let index = self.command_buffers.len();
+        assert!(
+            dependencies.iter().all(|dependency| *dependency < index),
+            "Dependencies {:?} must have been added to the batch before {}",
+            dependencies,
+            index
+        );
+        let group_start = self.group_starts.last().copied();
+        if group_start
+            .is_none_or(|start| dependencies.iter().any(|dependency| *dependency >= start))
+        {
+            self.group_starts.push(index);
+        }
+        self.command_buffers.push(command_buffer);
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.command_buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.command_buffers.is_empty()
+    }
+
+    // Number of SubmitInfos flush will emit, all of them in one vkQueueSubmit call.
+    pub fn submit_info_count(&self) -> usize {
+        self.group_starts.len()
+    }
+
+    // Ends the buffers that are still recording and submits everything.
+    #[track_caller]
+    pub fn flush(mut self) -> BatchWaitHandle {
+        let device = self.queue.device();
+        assert!(
+            !device.is_shut_down(),
+            "submit called after DeviceContext::shutdown"
+        );
+        for command_buffer in &mut self.command_buffers {
+            command_buffer.prepare_submit();
+        }
+
+        let handles: Vec<ash::vk::CommandBuffer> = self
+            .command_buffers
+            .iter()
+            .map(|command_buffer| command_buffer.handle())
+            .collect();
+        let semaphores: Vec<Semaphore> = (1..self.group_starts.len())
+            .map(|_| unsafe {
+                device
+                    .handle()
+                    .create_semaphore(
+                        &SemaphoreCreateInfo::default(),
+                        device.allocation_callbacks(),
+                    )
+                    .expect("Semaphore creation failed")
+            })
+            .collect();
+
+        let wait_stages = [PipelineStageFlags::ALL_COMMANDS];
+        let submit_infos: Vec<SubmitInfo> = self
+            .group_starts
+            .iter()
+            .enumerate()
+            .map(|(group, start)| {
+                let end = self
+                    .group_starts
+                    .get(group + 1)
+                    .copied()
+                    .unwrap_or(handles.len());
+                let mut info = SubmitInfo::default().command_buffers(&handles[*start..end]);
+                if group > 0 {
+                    info = info
+                        .wait_semaphores(&semaphores[group - 1..group])
+                        .wait_dst_stage_mask(&wait_stages);
+                }
+                if group < semaphores.len() {
+                    info = info.signal_semaphores(&semaphores[group..group + 1]);
+                }
+                info
+            })
+            .collect();
+
+        let fence = unsafe {
+            device
+                .handle()
+                .create_fence(&FenceCreateInfo::default(), device.allocation_callbacks())
+                .expect("Fence creation failed")
+        };
+        unsafe {
+            device
+                .observe(
+                    device
+                        .handle()
+                        .queue_submit(self.queue.handle(), &submit_infos, fence),
+                )
+                .expect("Queue submit failed");
+        }
+
+        let submission = self.queue.begin_submission();
+        BatchWaitHandle {
+            device,
+            queue: self.queue,
+            command_buffers: self.command_buffers,
+            semaphores,
+            fence,
+            submission: Some(submission),
+        }
+    }
+}
+
+// Frees the command buffers and semaphores of a flushed batch, blocking on drop when still pending.
+pub struct BatchWaitHandle {
+    device: Rc<DeviceContext>,
+    queue: Rc<CommandQueue>,
+    command_buffers: Vec<CommandBuffer>,
+    semaphores: Vec<Semaphore>,
+    fence: Fence,
+    submission: Option<u64>,
+}
+
+impl BatchWaitHandle {
+    pub fn fence(&self) -> Fence {
+        self.fence
+    }
+
+    fn wait_for_fence(&mut self, timeout: u64) -> Result<(), ash::vk::Result> {
+        let result = unsafe {
+            self.device.observe(
+                self.device
+                    .handle()
+                    .wait_for_fences(&[self.fence], true, timeout),
+            )
+        };
+        if result.is_ok() {
+            if let Some(submission) = self.submission.take() {
+                self.queue.end_submission(submission)
+            }
+        }
+        result
+    }
+
+    pub fn has_completed(&mut self) -> bool {
+        self.wait_for_fence(0).is_ok()
+    }
+
+    pub fn wait(&mut self) {
+        self.wait_for_fence(u64::MAX).expect("Wait failed")
+    }
+}
+
+impl Drop for BatchWaitHandle {
+    fn drop(&mut self) {
+        if let Err(error) = self.wait_for_fence(u64::MAX) {
+            println!("Waiting for submission batch failed: {:?}", error);
+        }
+        let handles: Vec<ash::vk::CommandBuffer> = self
+            .command_buffers
+            .iter()
+            .map(|command_buffer| command_buffer.handle())
+            .collect();
+        unsafe {
+            if !handles.is_empty() {
+                self.device
+                    .handle()
+                    .free_command_buffers(self.queue.pool(), &handles);
+            }
+            for semaphore in &self.semaphores {
+                self.device
+                    .handle()
+                    .destroy_semaphore(*semaphore, self.device.allocation_callbacks());
+            }
+            self.device
+                .handle()
+                .destroy_fence(self.fence, self.device.allocation_callbacks());
+        }
+    }
+}
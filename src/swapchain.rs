@@ -1,14 +1,88 @@
 use crate::device_context::DeviceContext;
+use crate::image2d_resource::Image2DResource;
+use crate::image_resource::ImageResource;
 use crate::queue::CommandQueue;
 use crate::swapchain_image::SwapchainImage;
-use crate::swapchain_util::create_swapchain;
-use ash::khr::{surface, swapchain};
-use ash::vk::{SurfaceKHR, SwapchainKHR};
+use crate::swapchain_util::{
+    create_swapchain, ResolvedSwapchainConfig, SurfaceInfo, SwapchainConfig, SwapchainConfigError,
+};
+use ash::khr::{present_id, present_wait, swapchain};
+use ash::vk::{Extent2D, SurfaceKHR, SwapchainKHR};
+use std::cell::Cell;
 use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentResult<T = ()> {
+    Ok(T),
+    // The swapchain still works but no longer matches the surface exactly; recreate it soon.
+    Suboptimal(T),
+    // The swapchain can no longer be used and has to be recreated.
+    OutOfDate,
+    Err(ash::vk::Result),
+}
+
+impl<T> PresentResult<T> {
+    fn from_vk(result: Result<(T, bool), ash::vk::Result>) -> Self {
+        match result {
+            Ok((value, false)) => Self::Ok(value),
+            Ok((value, true)) => Self::Suboptimal(value),
+            Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => Self::OutOfDate,
+            Err(code) => Self::Err(code),
+        }
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> PresentResult<U> {
+        match self {
+            Self::Ok(value) => PresentResult::Ok(f(value)),
+            Self::Suboptimal(value) => PresentResult::Suboptimal(f(value)),
+            Self::OutOfDate => PresentResult::OutOfDate,
+            Self::Err(code) => PresentResult::Err(code),
+        }
+    }
+
+    pub fn value(self) -> Option<T> {
+        match self {
+            Self::Ok(value) | Self::Suboptimal(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn needs_recreate(&self) -> bool {
+        matches!(self, Self::Suboptimal(_) | Self::OutOfDate)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SwapchainError {
+    // None of the device's queue families can present to the surface.
+    NoPresentQueue { families: Vec<u32> },
+    // The config asks for something the surface doesn't support.
+    InvalidConfig(SwapchainConfigError),
+    // The window is minimized or has no area, skip rendering until it is restored.
+    ZeroExtent,
+}
+
+impl std::fmt::Display for SwapchainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoPresentQueue { families } => write!(
+                f,
+                "None of the queue families {:?} can present to this surface",
+                families
+            ),
+            Self::InvalidConfig(error) => write!(f, "Invalid swapchain config: {}", error),
+            Self::ZeroExtent => write!(f, "The surface has a zero extent"),
+        }
+    }
+}
+
 pub struct Swapchain {
     device: Rc<DeviceContext>,
     queue: Rc<CommandQueue>,
     swapchain_loader: swapchain::Device,
+    present_wait_loader: Option<present_wait::Device>,
+    present_ids_enabled: bool,
+    last_present_id: Cell<u64>,
     surface: ash::vk::SurfaceKHR,
     handle: SwapchainKHR,
     images: Vec<SwapchainImage>,
@@ -18,6 +92,10 @@ pub struct Swapchain {
     framebuffers: Vec<ash::vk::Framebuffer>,
     current_index: u32,
     format: ash::vk::Format,
+    depth_image: Option<Image2DResource>,
+    config: SwapchainConfig,
+    resolved: ResolvedSwapchainConfig,
+    generation: u64,
 
     logical_width: u32,
     logical_height: u32,
@@ -27,7 +105,39 @@ pub struct Swapchain {
 }
 
 impl Swapchain {
+    // The single entry point, the config is checked against the surface before anything is created.
     pub fn new(
+        device: Rc<DeviceContext>,
+        surface: ash::vk::SurfaceKHR,
+        queue: Rc<CommandQueue>,
+        config: SwapchainConfig,
+    ) -> Result<Self, SwapchainError> {
+        Self::create_validated(device, surface, None, queue, config)
+    }
+
+    // Picks a present capable queue itself and returns it alongside the swapchain.
+    pub fn new_auto(
+        device: Rc<DeviceContext>,
+        surface: ash::vk::SurfaceKHR,
+        width: u32,
+        height: u32,
+    ) -> Result<(Self, Rc<CommandQueue>), SwapchainError> {
+        let Some(queue) = device.present_queue(surface) else {
+            return Err(SwapchainError::NoPresentQueue {
+                families: (0..device.gpu().queue_family_count()).collect(),
+            });
+        };
+        let swapchain = Self::new(
+            device,
+            surface,
+            queue.clone(),
+            SwapchainConfig::new(width, height),
+        )?;
+        Ok((swapchain, queue))
+    }
+
+    #[deprecated(note = "use Swapchain::new with a SwapchainConfig")]
+    pub fn new_with_extent(
         device: Rc<DeviceContext>,
         surface: ash::vk::SurfaceKHR,
         old_swapchain: Option<&Swapchain>,
@@ -35,54 +145,193 @@ impl Swapchain {
         width: u32,
         height: u32,
     ) -> Self {
+        let config = SwapchainConfig::new(width, height);
+        Self::create_validated(device, surface, old_swapchain, queue, config)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    #[deprecated(note = "use Swapchain::new with SwapchainConfig::with_image_count")]
+    pub fn new_with_image_count(
+        device: Rc<DeviceContext>,
+        surface: ash::vk::SurfaceKHR,
+        old_swapchain: Option<&Swapchain>,
+        queue: Rc<CommandQueue>,
+        width: u32,
+        height: u32,
+        preferred_count: u32,
+    ) -> Self {
+        assert!(
+            preferred_count >= 2,
+            "Single buffered presentation is not supported"
+        );
+        let info = SurfaceInfo::query(device.gpu(), device.gpu().vulkan(), surface);
+        let config = SwapchainConfig::new(width, height)
+            .with_image_count(info.clamp_image_count(preferred_count));
+        Self::create_validated(device, surface, old_swapchain, queue, config)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    #[deprecated(note = "use Swapchain::new with SwapchainConfig::with_depth_format")]
+    pub fn new_with_depth(
+        device: Rc<DeviceContext>,
+        surface: ash::vk::SurfaceKHR,
+        old_swapchain: Option<&Swapchain>,
+        queue: Rc<CommandQueue>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let depth_format = device
+            .gpu()
+            .supported_depth_format(false)
+            .expect("No supported depth format found");
+        let config = SwapchainConfig::new(width, height).with_depth_format(depth_format);
+        Self::create_validated(device, surface, old_swapchain, queue, config)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    #[deprecated(note = "use Swapchain::new with SwapchainConfig::with_clear_color")]
+    pub fn new_with_clear_color(
+        device: Rc<DeviceContext>,
+        surface: ash::vk::SurfaceKHR,
+        old_swapchain: Option<&Swapchain>,
+        queue: Rc<CommandQueue>,
+        width: u32,
+        height: u32,
+        clear: [f32; 4],
+    ) -> Self {
+        let config = SwapchainConfig::new(width, height).with_clear_color(clear);
+        Self::create_validated(device, surface, old_swapchain, queue, config)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn create_validated(
+        device: Rc<DeviceContext>,
+        surface: ash::vk::SurfaceKHR,
+        old_swapchain: Option<&Swapchain>,
+        queue: Rc<CommandQueue>,
+        config: SwapchainConfig,
+    ) -> Result<Self, SwapchainError> {
+        let info = SurfaceInfo::query(device.gpu(), device.gpu().vulkan(), surface);
+        if info
+            .resolve_nonzero_extent(config.extent.width, config.extent.height)
+            .is_none()
+        {
+            return Err(SwapchainError::ZeroExtent);
+        }
+        let resolved = config
+            .validate(&info)
+            .map_err(SwapchainError::InvalidConfig)?;
+        Ok(Self::create(
+            device,
+            surface,
+            old_swapchain,
+            queue,
+            config,
+            resolved,
+        ))
+    }
+
+    fn create(
+        device: Rc<DeviceContext>,
+        surface: ash::vk::SurfaceKHR,
+        old_swapchain: Option<&Swapchain>,
+        queue: Rc<CommandQueue>,
+        config: SwapchainConfig,
+        resolved: ResolvedSwapchainConfig,
+    ) -> Self {
+        let (width, height) = (config.extent.width, config.extent.height);
+        debug_assert!(
+            device
+                .gpu()
+                .present_family_indices(surface)
+                .contains(&queue.family_type_index()),
+            "Queue family {} can't present to this surface, use Swapchain::new_auto or DeviceContext::present_queue",
+            queue.family_type_index()
+        );
         let vulkan = device.gpu().vulkan();
-        let surface_loader = surface::Instance::new(vulkan.library(), vulkan.vk_instance());
         let swapchain_loader = swapchain::Device::new(vulkan.vk_instance(), device.handle());
+        let present_wait_loader = device
+            .has_extension(present_wait::NAME.to_str().unwrap())
+            .then(|| present_wait::Device::new(vulkan.vk_instance(), device.handle()));
         let old_swapchain_handle = if let Some(old_sc) = old_swapchain {
             old_sc.handle()
         } else {
             SwapchainKHR::null()
         };
-        let (swapchain, images, image_views, format, physical_width, physical_height) =
-            create_swapchain(
-                vulkan.vk_instance(),
-                device.gpu().vk_physical_device(),
-                device.handle(),
-                &surface_loader,
-                surface,
-                &swapchain_loader,
-                old_swapchain_handle,
-                queue.clone(),
-                width,
-                height,
-            );
-
-        let attachments = [ash::vk::AttachmentDescription {
+        let (swapchain, images, image_views) = create_swapchain(
+            &device,
+            surface,
+            &swapchain_loader,
+            old_swapchain_handle,
+            &resolved,
+        );
+        let format = resolved.format;
+        let (physical_width, physical_height) = (resolved.extent.width, resolved.extent.height);
+
+        let depth_image = resolved.depth_format.map(|depth_format| {
+            Image2DResource::new(
+                device.clone(),
+                physical_width,
+                physical_height,
+                depth_format,
+                ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                    | ash::vk::ImageUsageFlags::SAMPLED,
+                ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                1,
+            )
+        });
+
+        let mut attachments = vec![ash::vk::AttachmentDescription {
             format: format.format,
             samples: ash::vk::SampleCountFlags::TYPE_1,
-            load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+            load_op: resolved.color_load_op,
             store_op: ash::vk::AttachmentStoreOp::STORE,
+            initial_layout: resolved.color_initial_layout(),
             final_layout: ash::vk::ImageLayout::PRESENT_SRC_KHR,
             ..Default::default()
         }];
+        if let Some(depth_format) = resolved.depth_format {
+            attachments.push(ash::vk::AttachmentDescription {
+                format: depth_format,
+                samples: ash::vk::SampleCountFlags::TYPE_1,
+                load_op: ash::vk::AttachmentLoadOp::CLEAR,
+                store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                stencil_load_op: ash::vk::AttachmentLoadOp::CLEAR,
+                stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                final_layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ..Default::default()
+            });
+        }
 
         let attachment_refs = [ash::vk::AttachmentReference {
             attachment: 0,
             layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         }];
 
+        let depth_attachment_ref = ash::vk::AttachmentReference {
+            attachment: 1,
+            layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
         let dependencies = [ash::vk::SubpassDependency {
             src_subpass: ash::vk::SUBPASS_EXTERNAL,
-            src_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | ash::vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
             dst_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_READ
-                | ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            dst_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                | ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
             ..Default::default()
         }];
 
-        let subpasses = [ash::vk::SubpassDescription::default()
+        let mut subpass = ash::vk::SubpassDescription::default()
             .color_attachments(&attachment_refs)
-            .pipeline_bind_point(ash::vk::PipelineBindPoint::GRAPHICS)];
+            .pipeline_bind_point(ash::vk::PipelineBindPoint::GRAPHICS);
+        if depth_image.is_some() {
+            subpass = subpass.depth_stencil_attachment(&depth_attachment_ref);
+        }
+        let subpasses = [subpass];
 
         let renderpass_create_info = ash::vk::RenderPassCreateInfo::default()
             .attachments(&attachments)
@@ -92,14 +341,17 @@ impl Swapchain {
         let renderpass = unsafe {
             device
                 .handle()
-                .create_render_pass(&renderpass_create_info, None)
+                .create_render_pass(&renderpass_create_info, device.allocation_callbacks())
                 .expect("Renderpass creation failed for swapchain")
         };
 
         let framebuffers: Vec<ash::vk::Framebuffer> = image_views
             .iter()
             .map(|&image_view| {
-                let attachments = [image_view];
+                let mut attachments = vec![image_view];
+                if let Some(depth_image) = depth_image.as_ref() {
+                    attachments.push(depth_image.view());
+                }
                 let create_info = ash::vk::FramebufferCreateInfo::default()
                     .render_pass(renderpass)
                     .attachments(&attachments)
@@ -109,7 +361,7 @@ impl Swapchain {
                 unsafe {
                     device
                         .handle()
-                        .create_framebuffer(&create_info, None)
+                        .create_framebuffer(&create_info, device.allocation_callbacks())
                         .expect("Framebuffer creation failed for swapchain images")
                 }
             })
@@ -122,11 +374,19 @@ impl Swapchain {
             present_semaphores.push(unsafe {
                 device
                     .handle()
-                    .create_semaphore(&semaphore_create_info, None)
+                    .create_semaphore(&semaphore_create_info, device.allocation_callbacks())
                     .unwrap()
             });
         }
 
+        // Fresh images are UNDEFINED. With LOAD the render pass expects PRESENT_SRC_KHR, the
+        // transition is recorded by begin_swapchain_render_pass once the image has been acquired.
+        let tracked_layout = if resolved.color_load_op == ash::vk::AttachmentLoadOp::LOAD {
+            ash::vk::ImageLayout::UNDEFINED
+        } else {
+            ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        };
+
         let swapchain_images = images
             .iter()
             .enumerate()
@@ -134,10 +394,11 @@ impl Swapchain {
                 SwapchainImage::new(
                     *image,
                     image_views[index],
-                    ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    tracked_layout,
                     format.format,
                     width,
                     height,
+                    resolved.image_usage,
                 )
             })
             .collect();
@@ -148,6 +409,9 @@ impl Swapchain {
             surface,
             handle: swapchain,
             swapchain_loader,
+            present_wait_loader,
+            present_ids_enabled: device.has_extension(present_id::NAME.to_str().unwrap()),
+            last_present_id: Cell::new(0),
             images: swapchain_images,
             _image_views: image_views,
             present_semaphores,
@@ -155,6 +419,10 @@ impl Swapchain {
             framebuffers,
             current_index: 0,
             format: format.format,
+            depth_image,
+            config,
+            resolved,
+            generation: old_swapchain.map_or(0, |old| old.generation + 1),
             logical_width: width,
             logical_height: height,
             physical_width,
@@ -162,40 +430,127 @@ impl Swapchain {
         }
     }
 
+    // Keeps the current swapchain and returns ZeroExtent while the window is minimized.
+    pub fn recreate(&mut self, width: u32, height: u32) -> Result<(), SwapchainError> {
+        let config = self.config.with_extent(Extent2D { width, height });
+        let info = SurfaceInfo::query(self.device.gpu(), self.device.gpu().vulkan(), self.surface);
+        if info.resolve_nonzero_extent(width, height).is_none() {
+            return Err(SwapchainError::ZeroExtent);
+        }
+        if let Err(error) = self.device.try_wait() {
+            println!(
+                "Waiting for the device before recreating the swapchain failed: {:?}",
+                error
+            );
+        }
+        *self = Self::create_validated(
+            self.device.clone(),
+            self.surface,
+            Some(self),
+            self.queue.clone(),
+            config,
+        )?;
+        Ok(())
+    }
+
+    // Like recreate, but ignores a zero extent.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        match self.recreate(width, height) {
+            Ok(()) | Err(SwapchainError::ZeroExtent) => {}
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn requested_image_count(&self) -> u32 {
+        self.resolved.image_count
+    }
+
+    pub fn queue(&self) -> Rc<CommandQueue> {
+        self.queue.clone()
+    }
+
+    // Increases every time the swapchain is recreated from an old one.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn config(&self) -> &SwapchainConfig {
+        &self.config
+    }
+
+    pub fn resolved_config(&self) -> &ResolvedSwapchainConfig {
+        &self.resolved
+    }
+
+    pub fn clear_color(&self) -> Option<[f32; 4]> {
+        self.config.clear_color
+    }
+
+    pub fn clear_values(&self) -> Vec<ash::vk::ClearValue> {
+        let mut clear_values = vec![ash::vk::ClearValue {
+            color: ash::vk::ClearColorValue {
+                float32: self.config.clear_color.unwrap_or_default(),
+            },
+        }];
+        if self.depth_image.is_some() {
+            clear_values.push(ash::vk::ClearValue {
+                depth_stencil: ash::vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            });
+        }
+        clear_values
+    }
+
+    pub fn framebuffer(&self, index: u32) -> ash::vk::Framebuffer {
+        self.framebuffers[index as usize]
+    }
+
+    pub fn depth_image(&self) -> Option<&Image2DResource> {
+        self.depth_image.as_ref()
+    }
+
     pub fn surface(&self) -> &SurfaceKHR {
         &self.surface
     }
 
+    // Destroys the swapchain but hands back the surface, which belongs to the window rather than
+    // the device. Rebuilding after DeviceContext::is_lost or when moving to another gpu:
+    //  1. drop everything created from the old device: pipelines, images, queues, command buffers
+    //  2. let surface = swapchain.into_surface(); the swapchain must go before the device does
+    //  3. create a new DeviceContext on a gpu that can present to the surface
+    //  4. Swapchain::new_auto(new_device, surface, width, height)
+    // The surface itself is destroyed by its owner once the window closes.
+    pub fn into_surface(self) -> SurfaceKHR {
+        self.surface
+    }
+
     pub fn handle(&self) -> SwapchainKHR {
         self.handle
     }
 
     pub fn next_frame_buffer(
         &mut self,
-    ) -> Result<(bool, u32, ash::vk::Framebuffer, ash::vk::Semaphore), ash::vk::Result> {
+    ) -> PresentResult<(u32, ash::vk::Framebuffer, ash::vk::Semaphore)> {
+        // The returned semaphore is the one the acquire signals, not necessarily the image's index.
+        let semaphore = self.present_semaphores[self.current_index as usize];
         unsafe {
-            let result = self.swapchain_loader.acquire_next_image(
-                self.handle,
-                std::u64::MAX,
-                self.present_semaphores[self.current_index as usize],
-                ash::vk::Fence::null(),
-            );
-
-            match result {
-                Ok((index, sub_optimal)) => {
-                    let result = (
-                        sub_optimal,
-                        index,
-                        self.framebuffers[index as usize],
-                        self.present_semaphores[index as usize],
-                    );
-                    self.current_index += 1;
-                    self.current_index %= self.image_count() as u32;
-                    Ok(result)
-                }
-
-                Err(code) => Err(code),
-            }
+            let result = self
+                .device
+                .observe(self.swapchain_loader.acquire_next_image(
+                    self.handle,
+                    std::u64::MAX,
+                    semaphore,
+                    ash::vk::Fence::null(),
+                ));
+
+            PresentResult::from_vk(result).map(|index| {
+                let result = (index, self.framebuffers[index as usize], semaphore);
+                self.current_index += 1;
+                self.current_index %= self.image_count() as u32;
+                result
+            })
         }
     }
 
@@ -243,21 +598,52 @@ impl Swapchain {
         &self.format
     }
 
-    pub fn swap(&self, semaphore: &ash::vk::Semaphore, index: u32) -> bool {
+    pub fn swap(
+        &self,
+        queue: &CommandQueue,
+        semaphore: &ash::vk::Semaphore,
+        index: u32,
+    ) -> PresentResult {
         let s = &[*semaphore];
         let sc = &[self.handle];
         let i = &[index];
-        let present_info = ash::vk::PresentInfoKHR::default()
+        let id = &[self.last_present_id.get() + 1];
+        self.last_present_id.set(id[0]);
+        let mut present_id = ash::vk::PresentIdKHR::default().present_ids(id);
+        let mut present_info = ash::vk::PresentInfoKHR::default()
             .wait_semaphores(s)
             .swapchains(sc)
             .image_indices(i);
+        if self.present_ids_enabled {
+            present_info = present_info.push_next(&mut present_id);
+        }
 
         unsafe {
-            let r = self
-                .swapchain_loader
-                .queue_present(self.queue.handle(), &present_info);
+            let r = self.device.observe(
+                self.swapchain_loader
+                    .queue_present(queue.handle(), &present_info),
+            );
 
-            r.is_err()
+            PresentResult::from_vk(r.map(|suboptimal| ((), suboptimal)))
+        }
+    }
+
+    // Id of the most recent swap, ids start at 1 for every new swapchain.
+    pub fn last_present_id(&self) -> u64 {
+        self.last_present_id.get()
+    }
+
+    // Needs VK_KHR_present_wait and VK_KHR_present_id enabled on the device, see Gpu::present_wait_supported.
+    pub fn wait_for_present(
+        &self,
+        present_id: u64,
+        timeout_ns: u64,
+    ) -> Result<(), ash::vk::Result> {
+        match &self.present_wait_loader {
+            Some(loader) if self.present_ids_enabled => unsafe {
+                loader.wait_for_present(self.handle, present_id, timeout_ns)
+            },
+            _ => Err(ash::vk::Result::ERROR_EXTENSION_NOT_PRESENT),
         }
     }
 }
@@ -266,20 +652,29 @@ impl Drop for Swapchain {
     fn drop(&mut self) {
         unsafe {
             for view in &self._image_views {
-                self.device.handle().destroy_image_view(*view, None);
+                self.device
+                    .handle()
+                    .destroy_image_view(*view, self.device.allocation_callbacks());
             }
 
             for semaphore in &self.present_semaphores {
-                self.device.handle().destroy_semaphore(*semaphore, None);
+                self.device
+                    .handle()
+                    .destroy_semaphore(*semaphore, self.device.allocation_callbacks());
             }
 
             for framebuffer in &self.framebuffers {
-                self.device.handle().destroy_framebuffer(*framebuffer, None);
+                self.device
+                    .handle()
+                    .destroy_framebuffer(*framebuffer, self.device.allocation_callbacks());
             }
 
             self.device
                 .handle()
-                .destroy_render_pass(self.renderpass, None);
+                .destroy_render_pass(self.renderpass, self.device.allocation_callbacks());
+
+            self.swapchain_loader
+                .destroy_swapchain(self.handle, self.device.allocation_callbacks());
         }
     }
 }
@@ -1,4 +1,6 @@
-use ash::vk::{Format, Image, ImageLayout, ImageView};
+use std::cell::Cell;
+
+use ash::vk::{Format, Image, ImageLayout, ImageUsageFlags, ImageView};
 
 use crate::image_resource::ImageResource;
 
@@ -9,6 +11,9 @@ pub struct SwapchainImage {
     width: u32,
     height: u32,
     view: ImageView,
+    usage: ImageUsageFlags,
+    // Set once the image was acquired and used, before that its contents are UNDEFINED.
+    initialized: Cell<bool>,
 }
 
 impl SwapchainImage {
@@ -19,6 +24,7 @@ impl SwapchainImage {
         format: Format,
         width: u32,
         height: u32,
+        usage: ImageUsageFlags,
     ) -> Self {
         Self {
             handle,
@@ -27,8 +33,16 @@ impl SwapchainImage {
             width,
             height,
             view,
+            usage,
+            initialized: Cell::new(false),
         }
     }
+
+    // True the first time only. Presentable images can't be used before they're acquired, so
+    // their first transition is recorded in the frame that uses them.
+    pub(crate) fn take_first_use(&self) -> bool {
+        !self.initialized.replace(true)
+    }
 }
 
 impl ImageResource for SwapchainImage {
@@ -63,4 +77,8 @@ impl ImageResource for SwapchainImage {
     fn view(&self) -> ImageView {
         self.view
     }
+
+    fn usage(&self) -> ImageUsageFlags {
+        self.usage
+    }
 }
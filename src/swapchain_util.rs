@@ -1,90 +1,404 @@
-use crate::queue::CommandQueue;
+use crate::device_context::DeviceContext;
+use crate::gpu::Gpu;
+use crate::vulkan::Vulkan;
 use ash::khr::{surface, swapchain};
-use std::rc::Rc;
+use ash::vk::{
+    AttachmentLoadOp, ColorSpaceKHR, CompositeAlphaFlagsKHR, Extent2D, ImageLayout,
+    ImageUsageFlags, PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceFormatKHR, SurfaceKHR,
+    SurfaceTransformFlagsKHR,
+};
+
+// Everything a surface reports about itself for a given gpu, queried once up front.
+#[derive(Clone, Debug)]
+pub struct SurfaceInfo {
+    pub formats: Vec<SurfaceFormatKHR>,
+    pub present_modes: Vec<PresentModeKHR>,
+    pub capabilities: SurfaceCapabilitiesKHR,
+}
+
+impl SurfaceInfo {
+    pub fn query(gpu: &Gpu, vulkan: &Vulkan, surface: SurfaceKHR) -> Self {
+        let surface_loader = surface::Instance::new(vulkan.library(), vulkan.vk_instance());
+        let physical_device = *gpu.vk_physical_device();
+        unsafe {
+            Self {
+                formats: surface_loader
+                    .get_physical_device_surface_formats(physical_device, surface)
+                    .expect("No surface formats found for surface / device combination"),
+                present_modes: surface_loader
+                    .get_physical_device_surface_present_modes(physical_device, surface)
+                    .expect("No present modes found"),
+                capabilities: surface_loader
+                    .get_physical_device_surface_capabilities(physical_device, surface)
+                    .expect("No surface capabilities found for surface / device combination"),
+            }
+        }
+    }
+
+    pub fn min_image_count(&self) -> u32 {
+        self.capabilities.min_image_count
+    }
+
+    // None when the surface doesn't limit the number of images.
+    pub fn max_image_count(&self) -> Option<u32> {
+        match self.capabilities.max_image_count {
+            0 => None,
+            count => Some(count),
+        }
+    }
+
+    // None when the extent is decided by the swapchain instead of the surface.
+    pub fn current_extent(&self) -> Option<Extent2D> {
+        match self.capabilities.current_extent.width {
+            u32::MAX => None,
+            _ => Some(self.capabilities.current_extent),
+        }
+    }
+
+    pub fn min_extent(&self) -> Extent2D {
+        self.capabilities.min_image_extent
+    }
+
+    pub fn max_extent(&self) -> Extent2D {
+        self.capabilities.max_image_extent
+    }
+
+    pub fn supported_transforms(&self) -> SurfaceTransformFlagsKHR {
+        self.capabilities.supported_transforms
+    }
+
+    pub fn current_transform(&self) -> SurfaceTransformFlagsKHR {
+        self.capabilities.current_transform
+    }
+
+    pub fn supported_composite_alpha(&self) -> CompositeAlphaFlagsKHR {
+        self.capabilities.supported_composite_alpha
+    }
+
+    pub fn supports_present_mode(&self, mode: PresentModeKHR) -> bool {
+        self.present_modes.contains(&mode)
+    }
+
+    pub fn clamp_image_count(&self, count: u32) -> u32 {
+        let count = count.max(self.min_image_count());
+        match self.max_image_count() {
+            Some(max) => count.min(max),
+            None => count,
+        }
+    }
+
+    // None when either the requested or the surface's extent is zero, e.g. for a minimized window.
+    // Presenting is impossible then, wait until the window is restored.
+    pub fn resolve_nonzero_extent(&self, width: u32, height: u32) -> Option<Extent2D> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(self.resolve_extent(width, height))
+            .filter(|extent| extent.width > 0 && extent.height > 0)
+    }
+
+    pub fn resolve_extent(&self, width: u32, height: u32) -> Extent2D {
+        self.current_extent().unwrap_or(Extent2D {
+            width: width.clamp(self.min_extent().width, self.max_extent().width),
+            height: height.clamp(self.min_extent().height, self.max_extent().height),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapchainConfigError {
+    NoSurfaceFormats,
+    UnsupportedFormat(SurfaceFormatKHR),
+    UnsupportedPresentMode(PresentModeKHR),
+    ImageCountOutOfRange {
+        requested: u32,
+        min: u32,
+        max: Option<u32>,
+    },
+    UnsupportedImageUsage(ImageUsageFlags),
+    UnsupportedTransform(SurfaceTransformFlagsKHR),
+    UnsupportedCompositeAlpha(CompositeAlphaFlagsKHR),
+}
+
+impl std::fmt::Display for SwapchainConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSurfaceFormats => write!(f, "The surface reports no formats"),
+            Self::UnsupportedFormat(format) => write!(
+                f,
+                "Surface format {:?} with color space {:?} is not supported by the surface",
+                format.format, format.color_space
+            ),
+            Self::UnsupportedPresentMode(mode) => {
+                write!(f, "Present mode {:?} is not supported by the surface", mode)
+            }
+            Self::ImageCountOutOfRange {
+                requested,
+                min,
+                max,
+            } => match max {
+                Some(max) => write!(
+                    f,
+                    "Requested {} swapchain images, the surface supports {} to {}",
+                    requested, min, max
+                ),
+                None => write!(
+                    f,
+                    "Requested {} swapchain images, the surface needs at least {}",
+                    requested, min
+                ),
+            },
+            Self::UnsupportedImageUsage(usage) => write!(
+                f,
+                "Swapchain image usage {:?} is not supported by the surface",
+                usage
+            ),
+            Self::UnsupportedTransform(transform) => write!(
+                f,
+                "Pre transform {:?} is not supported by the surface",
+                transform
+            ),
+            Self::UnsupportedCompositeAlpha(alpha) => write!(
+                f,
+                "Composite alpha {:?} is not supported by the surface",
+                alpha
+            ),
+        }
+    }
+}
+
+// What the application asks for, resolved against the surface by validate before any Vulkan
+// object is created. Defaults:
+// - format: an SRGB format with nonlinear SRGB color space, else the first one reported
+// - present mode: FIFO, the only one every surface supports
+// - images: one more than the surface minimum
+// - usage: COLOR_ATTACHMENT, plus TRANSFER_DST for blits when the surface supports it
+// - opaque composite alpha and identity transform
+// - color load op: CLEAR, to the clear color or black when there is none
+#[derive(Clone, Copy, Debug)]
+pub struct SwapchainConfig {
+    // None picks the default described above.
+    pub format: Option<SurfaceFormatKHR>,
+    pub present_mode: PresentModeKHR,
+    // None picks one more than the surface minimum.
+    pub image_count: Option<u32>,
+    // Clamped to the surface limits, ignored when the surface dictates the extent.
+    pub extent: Extent2D,
+    pub pre_transform: SurfaceTransformFlagsKHR,
+    pub composite_alpha: CompositeAlphaFlagsKHR,
+    // None picks the default described above.
+    pub image_usage: Option<ImageUsageFlags>,
+    pub depth_format: Option<ash::vk::Format>,
+    pub clear_color: Option<[f32; 4]>,
+    // LOAD keeps the previous contents of the image, DONT_CARE leaves them undefined.
+    pub color_load_op: AttachmentLoadOp,
+}
+
+// The config with every choice made, as used to create the swapchain.
+#[derive(Clone, Copy, Debug)]
+pub struct ResolvedSwapchainConfig {
+    pub format: SurfaceFormatKHR,
+    pub present_mode: PresentModeKHR,
+    pub image_count: u32,
+    pub extent: Extent2D,
+    pub pre_transform: SurfaceTransformFlagsKHR,
+    pub composite_alpha: CompositeAlphaFlagsKHR,
+    pub image_usage: ImageUsageFlags,
+    pub depth_format: Option<ash::vk::Format>,
+    pub clear_color: Option<[f32; 4]>,
+    pub color_load_op: AttachmentLoadOp,
+}
+
+impl ResolvedSwapchainConfig {
+    // LOAD reads what was presented last, so the image has to arrive in PRESENT_SRC_KHR. The
+    // other ops discard the contents and can start from UNDEFINED.
+    pub fn color_initial_layout(&self) -> ImageLayout {
+        color_initial_layout(self.color_load_op)
+    }
+}
+
+pub(crate) fn color_initial_layout(load_op: AttachmentLoadOp) -> ImageLayout {
+    if load_op == AttachmentLoadOp::LOAD {
+        ImageLayout::PRESENT_SRC_KHR
+    } else {
+        ImageLayout::UNDEFINED
+    }
+}
+
+fn default_surface_format(info: &SurfaceInfo) -> Option<SurfaceFormatKHR> {
+    info.formats
+        .iter()
+        .find(|format| {
+            matches!(
+                format.format,
+                ash::vk::Format::B8G8R8A8_SRGB | ash::vk::Format::R8G8B8A8_SRGB
+            ) && format.color_space == ColorSpaceKHR::SRGB_NONLINEAR
+        })
+        .or(info.formats.first())
+        .copied()
+}
+
+impl SwapchainConfig {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            format: None,
+            present_mode: PresentModeKHR::FIFO,
+            image_count: None,
+            extent: Extent2D { width, height },
+            pre_transform: SurfaceTransformFlagsKHR::IDENTITY,
+            composite_alpha: CompositeAlphaFlagsKHR::OPAQUE,
+            image_usage: None,
+            depth_format: None,
+            clear_color: None,
+            color_load_op: AttachmentLoadOp::CLEAR,
+        }
+    }
+
+    pub fn with_format(mut self, format: SurfaceFormatKHR) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn with_present_mode(mut self, present_mode: PresentModeKHR) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    pub fn with_image_count(mut self, image_count: u32) -> Self {
+        self.image_count = Some(image_count);
+        self
+    }
+
+    pub fn with_extent(mut self, extent: Extent2D) -> Self {
+        self.extent = extent;
+        self
+    }
+
+    pub fn with_pre_transform(mut self, pre_transform: SurfaceTransformFlagsKHR) -> Self {
+        self.pre_transform = pre_transform;
+        self
+    }
+
+    pub fn with_composite_alpha(mut self, composite_alpha: CompositeAlphaFlagsKHR) -> Self {
+        self.composite_alpha = composite_alpha;
+        self
+    }
+
+    pub fn with_image_usage(mut self, image_usage: ImageUsageFlags) -> Self {
+        self.image_usage = Some(image_usage);
+        self
+    }
+
+    pub fn with_depth_format(mut self, depth_format: ash::vk::Format) -> Self {
+        self.depth_format = Some(depth_format);
+        self
+    }
+
+    pub fn with_clear_color(mut self, clear: [f32; 4]) -> Self {
+        self.clear_color = Some(clear);
+        self
+    }
+
+    pub fn with_color_load_op(mut self, load_op: AttachmentLoadOp) -> Self {
+        self.color_load_op = load_op;
+        self
+    }
+
+    pub fn validate(
+        &self,
+        info: &SurfaceInfo,
+    ) -> Result<ResolvedSwapchainConfig, SwapchainConfigError> {
+        let format = match self.format {
+            Some(format) if info.formats.contains(&format) => format,
+            Some(format) => return Err(SwapchainConfigError::UnsupportedFormat(format)),
+            None => default_surface_format(info).ok_or(SwapchainConfigError::NoSurfaceFormats)?,
+        };
+        if !info.supports_present_mode(self.present_mode) {
+            return Err(SwapchainConfigError::UnsupportedPresentMode(
+                self.present_mode,
+            ));
+        }
+        let image_count = match self.image_count {
+            Some(count) if count == info.clamp_image_count(count) => count,
+            Some(count) => {
+                return Err(SwapchainConfigError::ImageCountOutOfRange {
+                    requested: count,
+                    min: info.min_image_count(),
+                    max: info.max_image_count(),
+                })
+            }
+            None => info.clamp_image_count(info.min_image_count() + 1),
+        };
+        let supported_usage = info.capabilities.supported_usage_flags;
+        let image_usage = match self.image_usage {
+            Some(usage) if supported_usage.contains(usage) => usage,
+            Some(usage) => return Err(SwapchainConfigError::UnsupportedImageUsage(usage)),
+            None => {
+                ImageUsageFlags::COLOR_ATTACHMENT
+                    | (supported_usage & ImageUsageFlags::TRANSFER_DST)
+            }
+        };
+        if !supported_usage.contains(image_usage) {
+            return Err(SwapchainConfigError::UnsupportedImageUsage(image_usage));
+        }
+        if !info.supported_transforms().contains(self.pre_transform) {
+            return Err(SwapchainConfigError::UnsupportedTransform(
+                self.pre_transform,
+            ));
+        }
+        if !info
+            .supported_composite_alpha()
+            .contains(self.composite_alpha)
+        {
+            return Err(SwapchainConfigError::UnsupportedCompositeAlpha(
+                self.composite_alpha,
+            ));
+        }
+
+        Ok(ResolvedSwapchainConfig {
+            format,
+            present_mode: self.present_mode,
+            image_count,
+            extent: info.resolve_extent(self.extent.width, self.extent.height),
+            pre_transform: self.pre_transform,
+            composite_alpha: self.composite_alpha,
+            image_usage,
+            depth_format: self.depth_format,
+            clear_color: self.clear_color,
+            color_load_op: self.color_load_op,
+        })
+    }
+}
 
 pub(crate) fn create_swapchain(
-    instance: &ash::Instance,
-    gpu: &ash::vk::PhysicalDevice,
-    ctx: &ash::Device,
-    surface_loader: &surface::Instance,
-    surface: ash::vk::SurfaceKHR,
+    device: &DeviceContext,
+    surface: SurfaceKHR,
     swapchain_loader: &swapchain::Device,
     old_swapchain: ash::vk::SwapchainKHR,
-    queue: Rc<CommandQueue>,
-    width: u32,
-    height: u32,
+    config: &ResolvedSwapchainConfig,
 ) -> (
     ash::vk::SwapchainKHR,
     Vec<ash::vk::Image>,
     Vec<ash::vk::ImageView>,
-    ash::vk::SurfaceFormatKHR,
-    u32,
-    u32,
 ) {
-    let _ = unsafe {
-        surface_loader
-            .get_physical_device_surface_support(*gpu, queue.family_type_index(), surface)
-            .expect("Query physical device queue surface support failed")
-    };
-
-    let formats = unsafe {
-        surface_loader
-            .get_physical_device_surface_formats(*gpu, surface)
-            .expect("No surface formats found for surface / device combination")
-    };
-
-    // Choose first format for now.
-    let format = formats[0];
-    let capabilities = unsafe {
-        surface_loader
-            .get_physical_device_surface_capabilities(*gpu, surface)
-            .expect("No surface capabilities found for surface / device combination")
-    };
-    let mut desired_image_count = capabilities.min_image_count + 1;
-    if capabilities.max_image_count > 0 && desired_image_count > capabilities.max_image_count {
-        desired_image_count = capabilities.max_image_count;
-    }
-    let surface_resolution = match capabilities.current_extent.width {
-        std::u32::MAX => ash::vk::Extent2D { width, height },
-        _ => capabilities.current_extent,
-    };
-    let pre_transform = if capabilities
-        .supported_transforms
-        .contains(ash::vk::SurfaceTransformFlagsKHR::IDENTITY)
-    {
-        ash::vk::SurfaceTransformFlagsKHR::IDENTITY
-    } else {
-        capabilities.current_transform
-    };
-    let present_modes = unsafe {
-        surface_loader
-            .get_physical_device_surface_present_modes(*gpu, surface)
-            .expect("No present modes found")
-    };
-    let present_mode = present_modes
-        .iter()
-        .cloned()
-        .find(|&mode| mode == ash::vk::PresentModeKHR::MAILBOX)
-        .unwrap_or(ash::vk::PresentModeKHR::FIFO);
-
     let swapchain_create_info = ash::vk::SwapchainCreateInfoKHR::default()
         .surface(surface)
-        .min_image_count(desired_image_count)
-        .image_color_space(format.color_space)
-        .image_format(format.format)
-        .image_extent(surface_resolution)
-        .image_usage(ash::vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .min_image_count(config.image_count)
+        .image_color_space(config.format.color_space)
+        .image_format(config.format.format)
+        .image_extent(config.extent)
+        .image_usage(config.image_usage)
         .image_sharing_mode(ash::vk::SharingMode::EXCLUSIVE)
-        .pre_transform(pre_transform)
-        .composite_alpha(ash::vk::CompositeAlphaFlagsKHR::OPAQUE)
-        .present_mode(present_mode)
+        .pre_transform(config.pre_transform)
+        .composite_alpha(config.composite_alpha)
+        .present_mode(config.present_mode)
         .clipped(true)
         .image_array_layers(1)
         .old_swapchain(old_swapchain);
 
     let swapchain = unsafe {
         swapchain_loader
-            .create_swapchain(&swapchain_create_info, None)
+            .create_swapchain(&swapchain_create_info, device.allocation_callbacks())
             .expect("Swapchain creation failed")
     };
 
@@ -99,7 +413,7 @@ pub(crate) fn create_swapchain(
         .map(|&image| {
             let create_view_info = ash::vk::ImageViewCreateInfo::default()
                 .view_type(ash::vk::ImageViewType::TYPE_2D)
-                .format(format.format)
+                .format(config.format.format)
                 .components(ash::vk::ComponentMapping {
                     r: ash::vk::ComponentSwizzle::R,
                     g: ash::vk::ComponentSwizzle::G,
@@ -115,18 +429,256 @@ pub(crate) fn create_swapchain(
                 })
                 .image(image);
             unsafe {
-                ctx.create_image_view(&create_view_info, None)
+                device
+                    .handle()
+                    .create_image_view(&create_view_info, device.allocation_callbacks())
                     .expect("Image view creation for swapchain images failed")
             }
         })
         .collect();
 
-    (
-        swapchain,
-        images,
-        image_views,
-        format,
-        surface_resolution.width,
-        surface_resolution.height,
-    )
+    (swapchain, images, image_views)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Format;
+
+    fn surface_format(format: Format, color_space: ColorSpaceKHR) -> SurfaceFormatKHR {
+        SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    // A desktop surface: UNORM listed before SRGB, FIFO and MAILBOX, 2 to 8 images and an extent
+    // that follows the window.
+    fn surface_info() -> SurfaceInfo {
+        SurfaceInfo {
+            formats: vec![
+                surface_format(Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+                surface_format(Format::B8G8R8A8_SRGB, ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            present_modes: vec![PresentModeKHR::FIFO, PresentModeKHR::MAILBOX],
+            capabilities: SurfaceCapabilitiesKHR {
+                min_image_count: 2,
+                max_image_count: 8,
+                current_extent: Extent2D {
+                    width: u32::MAX,
+                    height: u32::MAX,
+                },
+                min_image_extent: Extent2D {
+                    width: 1,
+                    height: 1,
+                },
+                max_image_extent: Extent2D {
+                    width: 4096,
+                    height: 4096,
+                },
+                max_image_array_layers: 1,
+                supported_transforms: SurfaceTransformFlagsKHR::IDENTITY,
+                current_transform: SurfaceTransformFlagsKHR::IDENTITY,
+                supported_composite_alpha: CompositeAlphaFlagsKHR::OPAQUE,
+                supported_usage_flags: ImageUsageFlags::COLOR_ATTACHMENT
+                    | ImageUsageFlags::TRANSFER_DST
+                    | ImageUsageFlags::TRANSFER_SRC,
+            },
+        }
+    }
+
+    #[test]
+    fn defaults_prefer_srgb_fifo_and_one_more_than_the_minimum() {
+        let resolved = SwapchainConfig::new(800, 600)
+            .validate(&surface_info())
+            .unwrap();
+        assert_eq!(resolved.format.format, Format::B8G8R8A8_SRGB);
+        assert_eq!(resolved.present_mode, PresentModeKHR::FIFO);
+        assert_eq!(resolved.image_count, 3);
+        assert_eq!(
+            resolved.extent,
+            Extent2D {
+                width: 800,
+                height: 600
+            }
+        );
+        assert_eq!(resolved.color_initial_layout(), ImageLayout::UNDEFINED);
+    }
+
+    #[test]
+    fn default_format_falls_back_to_the_first_one() {
+        let mut info = surface_info();
+        info.formats = vec![
+            surface_format(
+                Format::A2B10G10R10_UNORM_PACK32,
+                ColorSpaceKHR::HDR10_ST2084_EXT,
+            ),
+            surface_format(
+                Format::R8G8B8A8_SRGB,
+                ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT,
+            ),
+        ];
+        let resolved = SwapchainConfig::new(800, 600).validate(&info).unwrap();
+        assert_eq!(resolved.format, info.formats[0]);
+
+        info.formats.clear();
+        assert_eq!(
+            SwapchainConfig::new(800, 600).validate(&info).unwrap_err(),
+            SwapchainConfigError::NoSurfaceFormats
+        );
+    }
+
+    #[test]
+    fn explicit_format_and_present_mode() {
+        let info = surface_info();
+        let unorm = surface_format(Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR);
+        let resolved = SwapchainConfig::new(800, 600)
+            .with_format(unorm)
+            .with_present_mode(PresentModeKHR::MAILBOX)
+            .validate(&info)
+            .unwrap();
+        assert_eq!(resolved.format, unorm);
+        assert_eq!(resolved.present_mode, PresentModeKHR::MAILBOX);
+
+        let hdr = surface_format(
+            Format::R16G16B16A16_SFLOAT,
+            ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        );
+        assert_eq!(
+            SwapchainConfig::new(800, 600)
+                .with_format(hdr)
+                .validate(&info)
+                .unwrap_err(),
+            SwapchainConfigError::UnsupportedFormat(hdr)
+        );
+        assert_eq!(
+            SwapchainConfig::new(800, 600)
+                .with_present_mode(PresentModeKHR::IMMEDIATE)
+                .validate(&info)
+                .unwrap_err(),
+            SwapchainConfigError::UnsupportedPresentMode(PresentModeKHR::IMMEDIATE)
+        );
+    }
+
+    #[test]
+    fn image_count_limits() {
+        let mut info = surface_info();
+        let count = |count| {
+            SwapchainConfig::new(800, 600)
+                .with_image_count(count)
+                .validate(&info)
+        };
+        assert_eq!(count(2).unwrap().image_count, 2);
+        assert_eq!(count(8).unwrap().image_count, 8);
+        assert_eq!(
+            count(1).unwrap_err(),
+            SwapchainConfigError::ImageCountOutOfRange {
+                requested: 1,
+                min: 2,
+                max: Some(8)
+            }
+        );
+        assert!(count(9).is_err());
+
+        // No maximum, and the default is clamped to a maximum equal to the minimum.
+        info.capabilities.max_image_count = 0;
+        assert_eq!(info.max_image_count(), None);
+        assert_eq!(info.clamp_image_count(100), 100);
+        info.capabilities.max_image_count = 2;
+        let resolved = SwapchainConfig::new(800, 600).validate(&info).unwrap();
+        assert_eq!(resolved.image_count, 2);
+    }
+
+    #[test]
+    fn extent_is_clamped_unless_the_surface_dictates_it() {
+        let mut info = surface_info();
+        assert_eq!(info.current_extent(), None);
+        assert_eq!(
+            info.resolve_extent(10_000, 0),
+            Extent2D {
+                width: 4096,
+                height: 1
+            }
+        );
+        assert_eq!(info.resolve_nonzero_extent(0, 600), None);
+
+        info.capabilities.current_extent = Extent2D {
+            width: 1280,
+            height: 720,
+        };
+        let resolved = SwapchainConfig::new(800, 600).validate(&info).unwrap();
+        assert_eq!(resolved.extent, info.capabilities.current_extent);
+
+        // A minimized window reports a zero extent.
+        info.capabilities.current_extent = Extent2D {
+            width: 0,
+            height: 0,
+        };
+        assert_eq!(info.resolve_nonzero_extent(800, 600), None);
+    }
+
+    #[test]
+    fn usage_transform_and_composite_alpha_must_be_supported() {
+        let info = surface_info();
+        assert_eq!(
+            SwapchainConfig::new(800, 600)
+                .with_image_usage(ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::STORAGE)
+                .validate(&info)
+                .unwrap_err(),
+            SwapchainConfigError::UnsupportedImageUsage(
+                ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::STORAGE
+            )
+        );
+        assert_eq!(
+            SwapchainConfig::new(800, 600)
+                .with_pre_transform(SurfaceTransformFlagsKHR::ROTATE_90)
+                .validate(&info)
+                .unwrap_err(),
+            SwapchainConfigError::UnsupportedTransform(SurfaceTransformFlagsKHR::ROTATE_90)
+        );
+        assert_eq!(
+            SwapchainConfig::new(800, 600)
+                .with_composite_alpha(CompositeAlphaFlagsKHR::PRE_MULTIPLIED)
+                .validate(&info)
+                .unwrap_err(),
+            SwapchainConfigError::UnsupportedCompositeAlpha(CompositeAlphaFlagsKHR::PRE_MULTIPLIED)
+        );
+    }
+
+    #[test]
+    fn transfer_dst_is_only_added_when_supported() {
+        let mut info = surface_info();
+        let resolved = SwapchainConfig::new(800, 600).validate(&info).unwrap();
+        assert_eq!(
+            resolved.image_usage,
+            ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_DST
+        );
+
+        info.capabilities.supported_usage_flags = ImageUsageFlags::COLOR_ATTACHMENT;
+        let resolved = SwapchainConfig::new(800, 600).validate(&info).unwrap();
+        assert_eq!(resolved.image_usage, ImageUsageFlags::COLOR_ATTACHMENT);
+
+        // Asking for it explicitly still fails.
+        assert_eq!(
+            SwapchainConfig::new(800, 600)
+                .with_image_usage(ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_DST)
+                .validate(&info)
+                .unwrap_err(),
+            SwapchainConfigError::UnsupportedImageUsage(
+                ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_DST
+            )
+        );
+    }
+
+    #[test]
+    fn load_keeps_the_presented_image() {
+        let resolved = SwapchainConfig::new(800, 600)
+            .with_color_load_op(AttachmentLoadOp::LOAD)
+            .validate(&surface_info())
+            .unwrap();
+        assert_eq!(
+            resolved.color_initial_layout(),
+            ImageLayout::PRESENT_SRC_KHR
+        );
+    }
 }
@@ -0,0 +1,197 @@
+use std::sync::{Arc, Mutex};
+
+use ash::vk::DebugUtilsMessageSeverityFlagsEXT;
+
+// Reported for every child object still alive when its device or instance is destroyed.
+const LEAK_IDS: [&str; 3] = [
+    "VUID-vkDestroyDevice-device-05137",
+    "VUID-vkDestroyInstance-instance-00629",
+    "UNASSIGNED-ObjectTracker-ObjectLeak",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub severity: DebugUtilsMessageSeverityFlagsEXT,
+    pub id_name: String,
+    pub id_number: i32,
+    pub text: String,
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} [{} ({})] : {}",
+            self.severity, self.id_name, self.id_number, self.text
+        )
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct CapturedMessages {
+    messages: Mutex<Vec<Message>>,
+    ignored_ids: Mutex<Vec<String>>,
+}
+
+impl CapturedMessages {
+    pub(crate) fn record(&self, message: Message) {
+        let ignored = self.ignored_ids.lock().unwrap().contains(&message.id_name);
+        if !ignored {
+            self.messages.lock().unwrap().push(message)
+        }
+    }
+}
+
+// Records validation messages so tests can fail on them, install it with
+// Vulkan::set_validation_capture. Needs the validation layer and VK_EXT_debug_utils enabled.
+// Clones share the same buffer, messages may arrive from any thread the driver calls back on.
+#[derive(Clone, Default)]
+pub struct ValidationCapture {
+    captured: Arc<CapturedMessages>,
+}
+
+impl ValidationCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Known benign messages by id name, e.g. "VUID-VkSwapchainCreateInfoKHR-imageExtent-01274".
+    pub fn with_ignored_ids(self, ids: &[&str]) -> Self {
+        for id in ids {
+            self.ignore(id)
+        }
+        self
+    }
+
+    pub fn ignore(&self, id: &str) {
+        self.captured
+            .ignored_ids
+            .lock()
+            .unwrap()
+            .push(id.to_string())
+    }
+
+    pub fn messages(&self) -> Vec<Message> {
+        self.captured.messages.lock().unwrap().clone()
+    }
+
+    pub fn errors(&self) -> Vec<Message> {
+        self.messages()
+            .into_iter()
+            .filter(|message| {
+                message
+                    .severity
+                    .contains(DebugUtilsMessageSeverityFlagsEXT::ERROR)
+            })
+            .collect()
+    }
+
+    // Objects the validation layer found alive on vkDestroyDevice/vkDestroyInstance, only
+    // complete once the device is gone.
+    pub fn leaks(&self) -> Vec<Message> {
+        self.messages()
+            .into_iter()
+            .filter(|message| LEAK_IDS.contains(&message.id_name.as_str()))
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.captured.messages.lock().unwrap().clear()
+    }
+
+    #[track_caller]
+    pub fn assert_no_errors(&self) {
+        let errors = self.errors();
+        assert!(
+            errors.is_empty(),
+            "{} validation error(s):\n{}",
+            errors.len(),
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_no_leaks(&self) {
+        let leaks = self.leaks();
+        assert!(
+            leaks.is_empty(),
+            "{} leaked object(s):\n{}",
+            leaks.len(),
+            leaks
+                .iter()
+                .map(|leak| leak.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    pub(crate) fn captured(&self) -> &Arc<CapturedMessages> {
+        &self.captured
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(severity: DebugUtilsMessageSeverityFlagsEXT, id_name: &str) -> Message {
+        Message {
+            severity,
+            id_name: id_name.to_string(),
+            id_number: 0,
+            text: String::new(),
+        }
+    }
+
+    #[test]
+    fn leaks_only_contains_destroy_messages() {
+        let capture = ValidationCapture::new();
+        capture.captured().record(message(
+            DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            "VUID-vkDestroyDevice-device-05137",
+        ));
+        capture.captured().record(message(
+            DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            "VUID-vkCmdDispatch-None-08114",
+        ));
+        capture.captured().record(message(
+            DebugUtilsMessageSeverityFlagsEXT::WARNING,
+            "UNASSIGNED-BestPractices-vkAllocateMemory-small-allocation",
+        ));
+
+        assert_eq!(capture.messages().len(), 3);
+        assert_eq!(capture.errors().len(), 2);
+        assert_eq!(capture.leaks().len(), 1);
+        assert_eq!(
+            capture.leaks()[0].id_name,
+            "VUID-vkDestroyDevice-device-05137"
+        );
+    }
+
+    #[test]
+    fn ignored_leaks_are_not_recorded() {
+        let capture =
+            ValidationCapture::new().with_ignored_ids(&["VUID-vkDestroyDevice-device-05137"]);
+        capture.captured().record(message(
+            DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            "VUID-vkDestroyDevice-device-05137",
+        ));
+        capture.assert_no_leaks();
+        capture.assert_no_errors();
+    }
+
+    #[test]
+    #[should_panic(expected = "1 leaked object(s)")]
+    fn assert_no_leaks_fails_on_a_leak() {
+        let capture = ValidationCapture::new();
+        capture.captured().record(message(
+            DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            "VUID-vkDestroyInstance-instance-00629",
+        ));
+        capture.assert_no_leaks();
+    }
+}
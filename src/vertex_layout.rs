@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+
+use ash::vk::{Format, FormatFeatureFlags, VertexInputAttributeDescription};
+
+use crate::format_info::{attribute_size_bytes, is_integer_format, is_signed_integer_format};
+use crate::gpu::Gpu;
+
+// Attribute formats and byte offsets of a vertex struct, in location order.
+pub trait VertexLayout: Sized {
+    fn layout() -> Vec<(Format, u32)>;
+
+    fn stride() -> u32 {
+        std::mem::size_of::<Self>() as u32
+    }
+
+    fn attribute_descriptions(
+        binding: u32,
+        first_location: u32,
+    ) -> Vec<VertexInputAttributeDescription> {
+        Self::layout()
+            .into_iter()
+            .enumerate()
+            .map(|(index, (format, offset))| {
+                if let Some(size) = attribute_size_bytes(format) {
+                    debug_assert!(
+                        offset + size <= Self::stride(),
+                        "Vertex attribute {} ({:?}) at offset {} doesn't fit in the {} byte stride",
+                        index,
+                        format,
+                        offset,
+                        Self::stride()
+                    );
+                }
+                VertexInputAttributeDescription::default()
+                    .binding(binding)
+                    .location(first_location + index as u32)
+                    .format(format)
+                    .offset(offset)
+            })
+            .collect()
+    }
+}
+
+// Implements VertexLayout with offset_of!, fields are assigned locations in the order listed:
+//
+// impl_vertex_layout!(Vertex { position: Format::R32G32B32_SFLOAT, uv: Format::R32G32_SFLOAT });
+#[macro_export]
+macro_rules! impl_vertex_layout {
+    ($vertex:ty { $($field:ident: $format:expr),* $(,)? }) => {
+        impl $crate::vertex_layout::VertexLayout for $vertex {
+            fn layout() -> Vec<($crate::Format, u32)> {
+                vec![$(($format, std::mem::offset_of!($vertex, $field) as u32)),*]
+            }
+        }
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexLayoutError {
+    // The vertex shader reads a location no attribute provides.
+    MissingLocation {
+        location: u32,
+        shader: Format,
+    },
+    // Float, signed and unsigned integer attributes can't be read as one another.
+    FormatMismatch {
+        location: u32,
+        shader: Format,
+        layout: Format,
+    },
+    // The device can't read this format from a vertex buffer.
+    UnsupportedFormat {
+        location: u32,
+        format: Format,
+    },
+}
+
+impl std::fmt::Display for VertexLayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingLocation { location, shader } => write!(
+                f,
+                "Vertex shader input at location {} ({:?}) has no matching vertex attribute",
+                location, shader
+            ),
+            Self::FormatMismatch {
+                location,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "Vertex attribute at location {} is {:?} but the vertex shader reads {:?}",
+                location, layout, shader
+            ),
+            Self::UnsupportedFormat { location, format } => write!(
+                f,
+                "Vertex attribute at location {} is {:?}, which the device doesn't support as a vertex buffer format",
+                location, format
+            ),
+        }
+    }
+}
+
+fn numeric_class(format: Format) -> u32 {
+    if !is_integer_format(format) {
+        0
+    } else if is_signed_integer_format(format) {
+        1
+    } else {
+        2
+    }
+}
+
+// Compares the attributes against the reflected vertex shader inputs, see ShaderReflection::vertex_inputs.
+// Attributes the shader doesn't read are allowed, as are differing component counts.
+pub fn validate_vertex_layout(
+    attributes: &[VertexInputAttributeDescription],
+    shader_inputs: &BTreeMap<u32, Format>,
+) -> Result<(), VertexLayoutError> {
+    for (&location, &shader) in shader_inputs {
+        let attribute = attributes
+            .iter()
+            .find(|attribute| attribute.location == location)
+            .ok_or(VertexLayoutError::MissingLocation { location, shader })?;
+        if numeric_class(attribute.format) != numeric_class(shader) {
+            return Err(VertexLayoutError::FormatMismatch {
+                location,
+                shader,
+                layout: attribute.format,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Packed and half precision formats aren't vertex buffer formats on every device, checks the
+// buffer features of every attribute format.
+pub fn validate_vertex_formats(
+    gpu: &Gpu,
+    attributes: &[VertexInputAttributeDescription],
+) -> Result<(), VertexLayoutError> {
+    for attribute in attributes {
+        let features = gpu.format_properties(attribute.format).buffer_features;
+        if !features.contains(FormatFeatureFlags::VERTEX_BUFFER) {
+            return Err(VertexLayoutError::UnsupportedFormat {
+                location: attribute.location,
+                format: attribute.format,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct PackedVertex {
+        position: [f32; 3],
+        normal: u32,
+        uv: [u16; 2],
+        joints: [u8; 4],
+    }
+
+    impl_vertex_layout!(PackedVertex {
+        position: Format::R32G32B32_SFLOAT,
+        normal: Format::A2B10G10R10_SNORM_PACK32,
+        uv: Format::R16G16_SFLOAT,
+        joints: Format::R8G8B8A8_UINT,
+    });
+
+    #[test]
+    fn packed_attributes_fit_the_stride() {
+        let attributes = PackedVertex::attribute_descriptions(0, 0);
+        let table = [
+            (0, Format::R32G32B32_SFLOAT, 0),
+            (1, Format::A2B10G10R10_SNORM_PACK32, 12),
+            (2, Format::R16G16_SFLOAT, 16),
+            (3, Format::R8G8B8A8_UINT, 20),
+        ];
+        assert_eq!(attributes.len(), table.len());
+        for (attribute, (location, format, offset)) in attributes.iter().zip(table) {
+            assert_eq!(attribute.location, location);
+            assert_eq!(attribute.format, format);
+            assert_eq!(attribute.offset, offset);
+            assert!(
+                attribute.offset + attribute_size_bytes(format).unwrap() <= PackedVertex::stride()
+            );
+        }
+        assert_eq!(PackedVertex::stride(), 24);
+    }
+
+    #[test]
+    fn shader_inputs_against_attribute_formats() {
+        let attributes = PackedVertex::attribute_descriptions(0, 0);
+        // Shader input format per location, and whether the layout can feed it.
+        let table = [
+            (0, Format::R32G32B32_SFLOAT, Ok(())),
+            // Normalized and float formats are all read as floats, regardless of component count.
+            (1, Format::R32G32B32A32_SFLOAT, Ok(())),
+            (2, Format::R32G32_SFLOAT, Ok(())),
+            (3, Format::R32G32B32A32_UINT, Ok(())),
+            (
+                3,
+                Format::R32G32B32A32_SINT,
+                Err(VertexLayoutError::FormatMismatch {
+                    location: 3,
+                    shader: Format::R32G32B32A32_SINT,
+                    layout: Format::R8G8B8A8_UINT,
+                }),
+            ),
+            (
+                1,
+                Format::R32_UINT,
+                Err(VertexLayoutError::FormatMismatch {
+                    location: 1,
+                    shader: Format::R32_UINT,
+                    layout: Format::A2B10G10R10_SNORM_PACK32,
+                }),
+            ),
+            (
+                4,
+                Format::R32_SFLOAT,
+                Err(VertexLayoutError::MissingLocation {
+                    location: 4,
+                    shader: Format::R32_SFLOAT,
+                }),
+            ),
+        ];
+        for (location, shader, expected) in table {
+            let inputs = BTreeMap::from([(location, shader)]);
+            assert_eq!(
+                validate_vertex_layout(&attributes, &inputs),
+                expected,
+                "location {} read as {:?}",
+                location,
+                shader
+            );
+        }
+    }
+}
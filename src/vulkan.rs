@@ -1,27 +1,27 @@
 use ash::vk::{
-    make_api_version, ApplicationInfo, Bool32, DebugUtilsMessageSeverityFlagsEXT,
-    DebugUtilsMessageTypeFlagsEXT, DebugUtilsMessengerCallbackDataEXT,
-    DebugUtilsMessengerCreateInfoEXT, DebugUtilsMessengerEXT, InstanceCreateFlags,
-    InstanceCreateInfo, QueueFlags, FALSE,
+    make_api_version, AllocationCallbacks, ApplicationInfo, Bool32,
+    DebugUtilsMessageSeverityFlagsEXT, DebugUtilsMessageTypeFlagsEXT,
+    DebugUtilsMessengerCallbackDataEXT, DebugUtilsMessengerCreateInfoEXT, DebugUtilsMessengerEXT,
+    InstanceCreateFlags, InstanceCreateInfo, PhysicalDeviceGroupProperties, QueueFamilyProperties,
+    QueueFlags, FALSE,
 };
 pub use ash::{Entry, Instance};
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::ffi::{CStr, CString};
+use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
 
 use ash::ext::{debug_utils, metal_surface};
 use ash::khr::{get_physical_device_properties2, portability_enumeration, win32_surface};
 
-use crate::gpu::Gpu;
-
-unsafe extern "system" fn vulkan_debug_callback(
-    message_severity: DebugUtilsMessageSeverityFlagsEXT,
-    message_type: DebugUtilsMessageTypeFlagsEXT,
-    p_callback_data: *const DebugUtilsMessengerCallbackDataEXT,
-    _user_data: *mut std::os::raw::c_void,
-) -> Bool32 {
-    let callback_data = *p_callback_data;
-    let message_id_number = callback_data.message_id_number;
+use crate::allocator::{HostAllocator, HostCallbacks};
+use crate::gpu::{Gpu, PhysicalDeviceGroup};
+use crate::validation_capture::{CapturedMessages, Message, ValidationCapture};
 
+unsafe fn message_strings<'a>(
+    callback_data: &DebugUtilsMessengerCallbackDataEXT<'a>,
+) -> (Cow<'a, str>, Cow<'a, str>) {
     let message_id_name = if callback_data.p_message_id_name.is_null() {
         Cow::from("")
     } else {
@@ -34,6 +34,19 @@ unsafe extern "system" fn vulkan_debug_callback(
         CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
+    (message_id_name, message)
+}
+
+unsafe extern "system" fn vulkan_debug_callback(
+    message_severity: DebugUtilsMessageSeverityFlagsEXT,
+    message_type: DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const DebugUtilsMessengerCallbackDataEXT,
+    _user_data: *mut std::os::raw::c_void,
+) -> Bool32 {
+    let callback_data = *p_callback_data;
+    let message_id_number = callback_data.message_id_number;
+    let (message_id_name, message) = message_strings(&callback_data);
+
     println!(
         "{:?}:\n{:?} [{} ({})] : {}\n",
         message_severity,
@@ -46,6 +59,26 @@ unsafe extern "system" fn vulkan_debug_callback(
     FALSE
 }
 
+// user_data points at the CapturedMessages kept alive by Vulkan::validation_capture.
+unsafe extern "system" fn validation_capture_callback(
+    message_severity: DebugUtilsMessageSeverityFlagsEXT,
+    _message_type: DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const DebugUtilsMessengerCallbackDataEXT,
+    user_data: *mut std::os::raw::c_void,
+) -> Bool32 {
+    let callback_data = *p_callback_data;
+    let (message_id_name, message) = message_strings(&callback_data);
+    let captured = &*(user_data as *const CapturedMessages);
+    captured.record(Message {
+        severity: message_severity,
+        id_name: message_id_name.into_owned(),
+        id_number: callback_data.message_id_number,
+        text: message.into_owned(),
+    });
+
+    FALSE
+}
+
 pub fn surface_extension_name() -> &'static CStr {
     if cfg!(unix) {
         metal_surface::NAME
@@ -54,15 +87,173 @@ pub fn surface_extension_name() -> &'static CStr {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VulkanError {
+    // No Vulkan loader / driver could be found on this machine.
+    LoaderUnavailable(String),
+    Vk(ash::vk::Result),
+}
+
+impl std::fmt::Display for VulkanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LoaderUnavailable(reason) => write!(f, "No Vulkan driver found: {}", reason),
+            Self::Vk(result) => write!(f, "Vulkan call failed: {}", result),
+        }
+    }
+}
+
+impl From<ash::vk::Result> for VulkanError {
+    fn from(result: ash::vk::Result) -> Self {
+        Self::Vk(result)
+    }
+}
+
+// The loader is only loaded once per process, every instance and enumeration shares it.
+fn entry() -> Result<Entry, VulkanError> {
+    static ENTRY: OnceLock<Result<Entry, String>> = OnceLock::new();
+    ENTRY
+        .get_or_init(|| unsafe { Entry::load() }.map_err(|error| error.to_string()))
+        .clone()
+        .map_err(VulkanError::LoaderUnavailable)
+}
+
+fn supports_queue_flags(families: &[QueueFamilyProperties], flags: QueueFlags) -> bool {
+    families
+        .iter()
+        .any(|family| family.queue_flags.contains(flags))
+}
+
+// Which messages the printing debug messenger subscribes to.
+#[derive(Clone, Copy, Debug)]
+pub struct DebugMessengerConfig {
+    pub severity: DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: DebugUtilsMessageTypeFlagsEXT,
+}
+
+impl Default for DebugMessengerConfig {
+    fn default() -> Self {
+        Self {
+            severity: DebugUtilsMessageSeverityFlagsEXT::ERROR
+                | DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | DebugUtilsMessageSeverityFlagsEXT::INFO,
+            message_type: DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+        }
+    }
+}
+
+// Optional instance settings. The messenger is only created when VK_EXT_debug_utils is enabled,
+// but enabling the extension alone (e.g. for object names) doesn't create one in release builds.
+pub struct InstanceOptions {
+    allocator: Option<Box<dyn HostAllocator>>,
+    debug_messenger: Option<DebugMessengerConfig>,
+}
+
+impl Default for InstanceOptions {
+    fn default() -> Self {
+        Self {
+            allocator: None,
+            debug_messenger: cfg!(debug_assertions).then(DebugMessengerConfig::default),
+        }
+    }
+}
+
+impl InstanceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Every object created through the instance and its devices uses the allocator for host memory.
+    pub fn with_allocator(mut self, allocator: Box<dyn HostAllocator>) -> Self {
+        self.allocator = Some(allocator);
+        self
+    }
+
+    pub fn with_debug_messenger(mut self, config: DebugMessengerConfig) -> Self {
+        self.debug_messenger = Some(config);
+        self
+    }
+
+    pub fn without_debug_messenger(mut self) -> Self {
+        self.debug_messenger = None;
+        self
+    }
+}
+
+// Shared by all clones of Vulkan, destroys the messengers and the instance once the last clone
+// (including the ones held by every Gpu and DeviceContext) is gone.
+struct InstanceOwner {
+    instance: Instance,
+    debug_utils_loader: Option<debug_utils::Instance>,
+    debug_callback: Cell<Option<DebugUtilsMessengerEXT>>,
+    validation_capture: RefCell<Option<(ValidationCapture, DebugUtilsMessengerEXT)>>,
+    host_callbacks: Option<Rc<HostCallbacks>>,
+}
+
+impl Drop for InstanceOwner {
+    fn drop(&mut self) {
+        let allocation_callbacks = self.host_callbacks.as_ref().map(|host| host.callbacks());
+        unsafe {
+            if let Some(loader) = &self.debug_utils_loader {
+                if let Some(callback) = self.debug_callback.take() {
+                    loader.destroy_debug_utils_messenger(callback, allocation_callbacks)
+                }
+                if let Some((_, messenger)) = self.validation_capture.take() {
+                    loader.destroy_debug_utils_messenger(messenger, allocation_callbacks)
+                }
+            }
+            self.instance.destroy_instance(allocation_callbacks)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Vulkan {
-    _debug_callback: Option<DebugUtilsMessengerEXT>,
     library: Entry,
-    instance: Instance,
+    owner: Rc<InstanceOwner>,
 }
 
 impl Vulkan {
     pub fn new(name: &str, layers: &[&str], extensions: &[&str]) -> Self {
+        match Self::try_new(name, layers, extensions) {
+            Ok(vulkan) => vulkan,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn is_available() -> bool {
+        entry().is_ok()
+    }
+
+    pub fn try_new(name: &str, layers: &[&str], extensions: &[&str]) -> Result<Self, VulkanError> {
+        Self::try_new_with_options(name, layers, extensions, InstanceOptions::default())
+    }
+
+    // Every object created through this instance and its devices uses the allocator for host memory.
+    pub fn try_new_with_allocator(
+        name: &str,
+        layers: &[&str],
+        extensions: &[&str],
+        allocator: Box<dyn HostAllocator>,
+    ) -> Result<Self, VulkanError> {
+        Self::try_new_with_options(
+            name,
+            layers,
+            extensions,
+            InstanceOptions::default().with_allocator(allocator),
+        )
+    }
+
+    pub fn try_new_with_options(
+        name: &str,
+        layers: &[&str],
+        extensions: &[&str],
+        options: InstanceOptions,
+    ) -> Result<Self, VulkanError> {
+        let library = entry()?;
+        let host_callbacks = options
+            .allocator
+            .map(|allocator| Rc::new(HostCallbacks::new(allocator)));
         let layers_names: Vec<String> = layers.iter().map(|s| s.to_string() + "\0").collect();
         let layers_names_raw: Vec<*const i8> =
             layers_names.iter().map(|s| s.as_ptr() as _).collect();
@@ -94,7 +285,7 @@ impl Vulkan {
         #[cfg(debug_assertions)]
         {
             // print all instance extensions
-            let available_extensions = Self::available_instance_extensions();
+            let available_extensions = Self::available_instance_extensions()?;
             println!("Available Instance extensions:");
             for ext in available_extensions {
                 println!("{}", ext);
@@ -108,79 +299,179 @@ impl Vulkan {
             .flags(flags);
 
         unsafe {
-            let library = Entry::load().unwrap();
-            let instance: Instance = library
-                .create_instance(&create_info, None)
-                .expect("Instance creation error");
-
-            let debug_info = DebugUtilsMessengerCreateInfoEXT::default()
-                .message_severity(
-                    DebugUtilsMessageSeverityFlagsEXT::ERROR
-                        | DebugUtilsMessageSeverityFlagsEXT::WARNING
-                        | DebugUtilsMessageSeverityFlagsEXT::INFO,
-                )
-                .message_type(DebugUtilsMessageTypeFlagsEXT::VALIDATION)
-                .pfn_user_callback(Some(vulkan_debug_callback));
+            let instance: Instance = library.create_instance(
+                &create_info,
+                host_callbacks.as_ref().map(|host| host.callbacks()),
+            )?;
 
             if layers.contains(&"VK_LAYER_KHRONOS_validation") {
                 println!("Validation layer enabled");
             }
 
-            let debug_callback = if extension_names_raw.contains(&debug_utils::NAME.as_ptr()) {
+            let debug_utils_loader = if extensions
+                .iter()
+                .any(|extension| extension.as_bytes() == debug_utils::NAME.to_bytes())
+            {
                 println!("Debug utils enabled");
-                let debug_utils_loader = debug_utils::Instance::new(&library, &instance);
-                match debug_utils_loader.create_debug_utils_messenger(&debug_info, None) {
-                    Ok(succes) => Some(succes),
-                    Err(error) => {
-                        println!("{}", error);
-                        None
-                    }
-                }
+                Some(debug_utils::Instance::new(&library, &instance))
             } else {
                 None
             };
+            let debug_callback = debug_utils_loader
+                .as_ref()
+                .zip(options.debug_messenger)
+                .and_then(|(loader, config)| {
+                    Self::create_debug_messenger(
+                        loader,
+                        host_callbacks.as_ref().map(|host| host.callbacks()),
+                        config,
+                    )
+                });
 
-            Self {
-                _debug_callback: debug_callback,
+            Ok(Self {
                 library,
-                instance,
+                owner: Rc::new(InstanceOwner {
+                    instance,
+                    debug_utils_loader,
+                    debug_callback: Cell::new(debug_callback),
+                    validation_capture: RefCell::new(None),
+                    host_callbacks,
+                }),
+            })
+        }
+    }
+
+    fn create_debug_messenger(
+        loader: &debug_utils::Instance,
+        allocation_callbacks: Option<&AllocationCallbacks>,
+        config: DebugMessengerConfig,
+    ) -> Option<DebugUtilsMessengerEXT> {
+        let debug_info = DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(config.severity)
+            .message_type(config.message_type)
+            .pfn_user_callback(Some(vulkan_debug_callback));
+
+        match unsafe { loader.create_debug_utils_messenger(&debug_info, allocation_callbacks) } {
+            Ok(succes) => Some(succes),
+            Err(error) => {
+                println!("{}", error);
+                None
+            }
+        }
+    }
+
+    pub fn set_debug_filter(&mut self, filter: DebugUtilsMessageSeverityFlagsEXT) {
+        self.set_debug_messenger(Some(DebugMessengerConfig {
+            severity: filter,
+            ..Default::default()
+        }))
+    }
+
+    // Replaces the printing messenger, None removes it. Does nothing without VK_EXT_debug_utils.
+    pub fn set_debug_messenger(&mut self, config: Option<DebugMessengerConfig>) {
+        if let Some(loader) = &self.owner.debug_utils_loader {
+            if let Some(callback) = self.owner.debug_callback.take() {
+                unsafe {
+                    loader.destroy_debug_utils_messenger(callback, self.allocation_callbacks())
+                }
+            }
+            self.owner.debug_callback.set(config.and_then(|config| {
+                Self::create_debug_messenger(loader, self.allocation_callbacks(), config)
+            }));
+        }
+    }
+
+    pub fn has_debug_messenger(&self) -> bool {
+        self.owner.debug_callback.get().is_some()
+    }
+
+    // Records warnings and errors into the capture next to the regular printing, replacing any
+    // capture installed before. Returns false when VK_EXT_debug_utils isn't enabled.
+    pub fn set_validation_capture(&mut self, capture: &ValidationCapture) -> bool {
+        let Some(loader) = &self.owner.debug_utils_loader else {
+            return false;
+        };
+        if let Some((_, messenger)) = self.owner.validation_capture.take() {
+            unsafe { loader.destroy_debug_utils_messenger(messenger, self.allocation_callbacks()) }
+        }
+        let debug_info = DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(
+                DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | DebugUtilsMessageSeverityFlagsEXT::WARNING,
+            )
+            .message_type(DebugUtilsMessageTypeFlagsEXT::VALIDATION)
+            .pfn_user_callback(Some(validation_capture_callback))
+            .user_data(Arc::as_ptr(capture.captured()) as *mut std::os::raw::c_void);
+        match unsafe {
+            loader.create_debug_utils_messenger(&debug_info, self.allocation_callbacks())
+        } {
+            Ok(messenger) => {
+                *self.owner.validation_capture.borrow_mut() = Some((capture.clone(), messenger));
+                true
+            }
+            Err(error) => {
+                println!("{}", error);
+                false
             }
         }
     }
 
+    pub fn validation_capture(&self) -> Option<ValidationCapture> {
+        self.owner
+            .validation_capture
+            .borrow()
+            .as_ref()
+            .map(|(capture, _)| capture.clone())
+    }
+
+    pub fn suppress_info_messages(&mut self) {
+        self.set_debug_filter(
+            DebugUtilsMessageSeverityFlagsEXT::ERROR | DebugUtilsMessageSeverityFlagsEXT::WARNING,
+        )
+    }
+
+    // The single place the crate gets its allocator argument from, None without an allocator.
+    pub fn allocation_callbacks(&self) -> Option<&AllocationCallbacks<'static>> {
+        self.owner
+            .host_callbacks
+            .as_ref()
+            .map(|host| host.callbacks())
+    }
+
     pub fn library(&self) -> &Entry {
         &self.library
     }
     pub fn vk_instance(&self) -> &Instance {
-        &self.instance
+        &self.owner.instance
     }
 
     pub fn devices_with_queue_support(&self, flags: QueueFlags) -> Vec<Gpu> {
+        self.devices_supporting(flags, &[])
+    }
+
+    pub fn devices_supporting(&self, flags: QueueFlags, extensions: &[&str]) -> Vec<Gpu> {
         unsafe {
-            self.instance
+            self.vk_instance()
                 .enumerate_physical_devices()
                 .expect("Physical device error")
                 .iter()
-                .filter_map(|pdevice| {
-                    self.instance
-                        .get_physical_device_queue_family_properties(*pdevice)
-                        .iter()
-                        .filter_map(|info| {
-                            if info.queue_flags.contains(flags) {
-                                Some(Gpu::new(self, pdevice))
-                            } else {
-                                None
-                            }
-                        })
-                        .next()
+                .filter(|pdevice| {
+                    supports_queue_flags(
+                        &self
+                            .vk_instance()
+                            .get_physical_device_queue_family_properties(**pdevice),
+                        flags,
+                    )
                 })
+                .map(|pdevice| Gpu::new(self, pdevice))
+                .filter(|gpu| gpu.has_all_extensions(extensions))
                 .collect::<Vec<Gpu>>()
         }
     }
 
     pub fn physical_devices(&self) -> Vec<Gpu> {
         unsafe {
-            self.instance
+            self.vk_instance()
                 .enumerate_physical_devices()
                 .expect("Physical device enumeration failed")
                 .iter()
@@ -189,33 +480,124 @@ impl Vulkan {
         }
     }
 
-    pub fn available_instance_layers() -> Vec<String> {
-        let library = unsafe { Entry::load().unwrap() };
+    pub fn device_by_uuid(&self, uuid: [u8; 16]) -> Option<Gpu> {
+        self.physical_devices()
+            .into_iter()
+            .find(|gpu| gpu.uuid() == uuid)
+    }
+
+    pub fn physical_device_groups(&self) -> Vec<PhysicalDeviceGroup> {
+        unsafe {
+            let count = self
+                .vk_instance()
+                .enumerate_physical_device_groups_len()
+                .expect("Physical device group enumeration failed");
+            let mut groups = vec![PhysicalDeviceGroupProperties::default(); count];
+            self.vk_instance()
+                .enumerate_physical_device_groups(&mut groups)
+                .expect("Physical device group enumeration failed");
+            groups
+                .iter()
+                .map(|group| PhysicalDeviceGroup {
+                    devices: group.physical_devices[..group.physical_device_count as usize]
+                        .iter()
+                        .map(|device| Gpu::new(self, device))
+                        .collect(),
+                    subset_allocation: group.subset_allocation != 0,
+                })
+                .collect()
+        }
+    }
+
+    pub fn available_instance_layers() -> Result<Vec<String>, VulkanError> {
+        let library = entry()?;
         unsafe {
-            library
-                .enumerate_instance_layer_properties()
-                .unwrap()
+            Ok(library
+                .enumerate_instance_layer_properties()?
                 .iter()
                 .map(|layer| {
                     let name = CStr::from_ptr(layer.layer_name.as_ptr());
                     name.to_str().unwrap().to_string()
                 })
-                .collect()
+                .collect())
         }
     }
 
-    pub fn available_instance_extensions() -> Vec<String> {
-        let library = unsafe { Entry::load().unwrap() };
+    pub fn available_instance_extensions() -> Result<Vec<String>, VulkanError> {
+        let library = entry()?;
         unsafe {
-            library
-                .enumerate_instance_extension_properties(None)
-                .unwrap()
+            Ok(library
+                .enumerate_instance_extension_properties(None)?
                 .iter()
                 .map(|ext| {
                     let name = CStr::from_ptr(ext.extension_name.as_ptr());
                     name.to_str().unwrap().to_string()
                 })
-                .collect()
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family(queue_flags: QueueFlags) -> QueueFamilyProperties {
+        QueueFamilyProperties {
+            queue_flags,
+            queue_count: 1,
+            ..Default::default()
         }
     }
+
+    // A typical discrete GPU: one universal family, an async compute and a transfer-only family.
+    fn families() -> Vec<QueueFamilyProperties> {
+        vec![
+            family(QueueFlags::GRAPHICS | QueueFlags::COMPUTE | QueueFlags::TRANSFER),
+            family(QueueFlags::COMPUTE | QueueFlags::TRANSFER),
+            family(QueueFlags::TRANSFER | QueueFlags::SPARSE_BINDING),
+        ]
+    }
+
+    #[test]
+    fn single_flags_match_any_family() {
+        let families = families();
+        assert!(supports_queue_flags(&families, QueueFlags::GRAPHICS));
+        assert!(supports_queue_flags(&families, QueueFlags::COMPUTE));
+        assert!(supports_queue_flags(&families, QueueFlags::SPARSE_BINDING));
+        assert!(!supports_queue_flags(&families, QueueFlags::PROTECTED));
+        assert!(!supports_queue_flags(
+            &families,
+            QueueFlags::VIDEO_DECODE_KHR
+        ));
+    }
+
+    #[test]
+    fn combined_flags_need_one_family_with_all_of_them() {
+        let families = families();
+        assert!(supports_queue_flags(
+            &families,
+            QueueFlags::GRAPHICS | QueueFlags::COMPUTE
+        ));
+        assert!(supports_queue_flags(
+            &families,
+            QueueFlags::TRANSFER | QueueFlags::SPARSE_BINDING
+        ));
+        // Each flag is supported, but never by the same family.
+        assert!(!supports_queue_flags(
+            &families,
+            QueueFlags::GRAPHICS | QueueFlags::SPARSE_BINDING
+        ));
+    }
+
+    #[test]
+    fn empty_lists_and_flags() {
+        assert!(!supports_queue_flags(&[], QueueFlags::COMPUTE));
+        assert!(!supports_queue_flags(&[], QueueFlags::empty()));
+        assert!(supports_queue_flags(&families(), QueueFlags::empty()));
+        assert!(supports_queue_flags(
+            &[family(QueueFlags::empty())],
+            QueueFlags::empty()
+        ));
+    }
 }
@@ -1,66 +1,201 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::Location;
+use std::rc::Rc;
+
 use ash::vk::Fence;
 
 use crate::command_buffer::CommandBuffer;
+use crate::queue::{CommandQueue, WaitHandleDropPolicy};
 
 pub struct WaitHandle {
-    command_buffer: CommandBuffer,
+    queue: Rc<CommandQueue>,
+    // None for reusable submissions, e.g. ComputeGraph's. Their owner keeps the command buffer and
+    // the fence, and waits for them before submitting again or dropping them.
+    command_buffer: Option<CommandBuffer>,
     fence: Fence,
+    // Keeps the fence of a reusable submission alive.
+    _fence_owner: Option<Rc<dyn Any>>,
+    drop_policy: WaitHandleDropPolicy,
+    location: &'static Location<'static>,
+    submission: Cell<Option<u64>>,
 }
 
 impl WaitHandle {
-    pub(crate) fn new(command_buffer: CommandBuffer, fence: Fence) -> Self {
+    pub(crate) fn new(
+        command_buffer: CommandBuffer,
+        fence: Fence,
+        location: &'static Location<'static>,
+    ) -> Self {
+        let queue = command_buffer.queue();
+        let submission = queue.begin_submission();
+        Self {
+            drop_policy: queue.drop_policy(),
+            queue,
+            command_buffer: Some(command_buffer),
+            fence,
+            _fence_owner: None,
+            location,
+            submission: Cell::new(Some(submission)),
+        }
+    }
+
+    // Borrows the fence of a reusable submission, see ComputeGraph::submit. Dropping the handle
+    // never blocks, the submission is retired by whoever waits on the fence first.
+    pub(crate) fn new_reusable(
+        queue: Rc<CommandQueue>,
+        fence: Fence,
+        fence_owner: Rc<dyn Any>,
+        submission: u64,
+        location: &'static Location<'static>,
+    ) -> Self {
         Self {
-            command_buffer,
+            drop_policy: queue.drop_policy(),
+            queue,
+            command_buffer: None,
             fence,
+            _fence_owner: Some(fence_owner),
+            location,
+            submission: Cell::new(Some(submission)),
+        }
+    }
+
+    pub fn set_drop_policy(&mut self, policy: WaitHandleDropPolicy) {
+        self.drop_policy = policy
+    }
+
+    pub fn block_on_drop(&mut self, block: bool) {
+        self.drop_policy = if block {
+            WaitHandleDropPolicy::Block
+        } else {
+            WaitHandleDropPolicy::Defer
+        }
+    }
+
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    fn retire(&self) {
+        if let Some(submission) = self.submission.take() {
+            self.queue.end_submission(submission)
         }
     }
 
     pub fn has_completed(&self) -> bool {
-        unsafe {
-            match self
-                .command_buffer
-                .device()
-                .handle()
-                .wait_for_fences(&[self.fence], true, 0)
-            {
+        let device = self.queue.device();
+        let completed = unsafe {
+            match device.observe(device.handle().wait_for_fences(&[self.fence], true, 0)) {
                 Err(_) => false,
                 Ok(()) => true,
             }
+        };
+        if completed {
+            self.retire();
         }
+        completed
     }
 
     pub fn wait(&self) {
+        let device = self.queue.device();
         unsafe {
-            self.command_buffer
-                .device()
-                .handle()
-                .wait_for_fences(&[self.fence], true, std::u64::MAX)
+            device
+                .observe(
+                    device
+                        .handle()
+                        .wait_for_fences(&[self.fence], true, u64::MAX),
+                )
                 .expect("Wait failed");
         }
+        self.retire();
+    }
+
+    // Used when dropping, a lost device is logged since panicking here aborts while unwinding.
+    fn wait_or_log(&self) {
+        let device = self.queue.device();
+        let result = unsafe {
+            device.observe(
+                device
+                    .handle()
+                    .wait_for_fences(&[self.fence], true, u64::MAX),
+            )
+        };
+        match result {
+            Ok(()) => self.retire(),
+            Err(error) => println!(
+                "Waiting for submission at {} failed: {:?}",
+                self.location, error
+            ),
+        }
     }
 
     pub fn wait_for(&self, timeout: u64) -> bool {
-        unsafe {
-            match self.command_buffer.device().handle().wait_for_fences(
-                &[self.fence],
-                true,
-                timeout,
+        let device = self.queue.device();
+        let completed = unsafe {
+            match device.observe(
+                device
+                    .handle()
+                    .wait_for_fences(&[self.fence], true, timeout),
             ) {
                 Err(_) => false,
                 Ok(()) => true,
             }
+        };
+        if completed {
+            self.retire();
         }
+        completed
     }
 }
 
 impl Drop for WaitHandle {
     fn drop(&mut self) {
+        let Some(mut command_buffer) = self.command_buffer.take() else {
+            return;
+        };
+        if !self.has_completed() {
+            match self.drop_policy {
+                WaitHandleDropPolicy::Defer => {
+                    let retained = command_buffer.take_retained();
+                    if let Some(submission) = self.submission.take() {
+                        self.queue.defer_free(
+                            command_buffer.handle(),
+                            self.fence,
+                            submission,
+                            retained,
+                        );
+                    }
+                    return;
+                }
+                WaitHandleDropPolicy::PanicInDebug
+                    if cfg!(debug_assertions) && !std::thread::panicking() =>
+                {
+                    panic!(
+                        "WaitHandle for submission at {} dropped before completion",
+                        self.location
+                    )
+                }
+                _ => {
+                    #[cfg(debug_assertions)]
+                    {
+                        println!(
+                            "WaitHandle for submission at {} dropped before completion, blocking",
+                            self.location
+                        );
+                    }
+                    self.wait_or_log();
+                }
+            }
+        }
+
         unsafe {
-            self.wait();
-            self.command_buffer.device().handle().free_command_buffers(
-                self.command_buffer.queue().pool(),
-                &[self.command_buffer.handle()],
-            )
+            let device = self.queue.device();
+            device
+                .handle()
+                .free_command_buffers(self.queue.pool(), &[command_buffer.handle()]);
+            device
+                .handle()
+                .destroy_fence(self.fence, device.allocation_callbacks());
         }
     }
 }
@@ -0,0 +1,382 @@
+use std::ffi::CStr;
+use std::rc::Rc;
+
+use ash::khr::surface;
+use ash::vk::{
+    Extent2D, Fence, FenceCreateFlags, FenceCreateInfo, Framebuffer, PipelineStageFlags, Semaphore,
+    SemaphoreCreateInfo, SurfaceKHR,
+};
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+
+use crate::command_buffer::CommandBuffer;
+use crate::device_context::DeviceContext;
+use crate::gpu::Gpu;
+use crate::queue::CommandQueue;
+use crate::swapchain::{PresentResult, Swapchain, SwapchainError};
+use crate::swapchain_util::SwapchainConfig;
+use crate::vulkan::{Vulkan, VulkanError};
+
+const FRAMES_IN_FLIGHT: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WindowError {
+    // The window or display handle isn't available, e.g. before the app was resumed on Android.
+    Handle(String),
+    Vulkan(VulkanError),
+    // None of the gpus has a graphics queue that can present to the window.
+    NoPresentableGpu,
+    Swapchain(SwapchainError),
+}
+
+impl std::fmt::Display for WindowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Handle(reason) => write!(f, "Window handle unavailable: {}", reason),
+            Self::Vulkan(error) => write!(f, "{}", error),
+            Self::NoPresentableGpu => write!(f, "No gpu can present to this window"),
+            Self::Swapchain(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<VulkanError> for WindowError {
+    fn from(error: VulkanError) -> Self {
+        Self::Vulkan(error)
+    }
+}
+
+impl From<ash::vk::Result> for WindowError {
+    fn from(result: ash::vk::Result) -> Self {
+        Self::Vulkan(VulkanError::Vk(result))
+    }
+}
+
+impl From<SwapchainError> for WindowError {
+    fn from(error: SwapchainError) -> Self {
+        Self::Swapchain(error)
+    }
+}
+
+impl From<raw_window_handle::HandleError> for WindowError {
+    fn from(error: raw_window_handle::HandleError) -> Self {
+        Self::Handle(error.to_string())
+    }
+}
+
+struct FrameSync {
+    fence: Fence,
+    render_finished: Semaphore,
+    // Freed once the fence shows the previous use of this slot has completed.
+    command_buffer: Option<CommandBuffer>,
+}
+
+// A frame in flight, record into the command buffer and hand it back to end_frame.
+pub struct Frame {
+    pub image_index: u32,
+    pub framebuffer: Framebuffer,
+    pub command_buffer: CommandBuffer,
+    image_available: Semaphore,
+}
+
+// Owns everything between a winit window and a presented image. The window has to outlive it.
+// Minimized windows can't be presented to, begin_frame returns None until the window is restored.
+pub struct WindowRenderer {
+    frames: Vec<FrameSync>,
+    current_frame: usize,
+    // Fence of the frame that last rendered to each swapchain image, null when the image is unused.
+    // An image can be acquired again while that frame is still running when there are more images
+    // than frames in flight or the presentation engine hands them out of order.
+    images_in_flight: Vec<Fence>,
+    // The swapchain can't be recreated while the surface has a zero extent.
+    minimized: bool,
+    swapchain: Option<Swapchain>,
+    surface: SurfaceKHR,
+    config: SwapchainConfig,
+    queue: Rc<CommandQueue>,
+    device: Rc<DeviceContext>,
+    gpu: Gpu,
+    vulkan: Vulkan,
+}
+
+impl WindowRenderer {
+    // The extent in config is replaced by the window's inner size.
+    pub fn new(
+        window: &winit::window::Window,
+        config: SwapchainConfig,
+    ) -> Result<Self, WindowError> {
+        let display_handle = window.display_handle()?.as_raw();
+        let window_handle = window.window_handle()?.as_raw();
+
+        let extensions: Vec<&str> = ash_window::enumerate_required_extensions(display_handle)?
+            .iter()
+            .map(|name| unsafe { CStr::from_ptr(*name) }.to_str().unwrap())
+            .collect();
+        let vulkan = Vulkan::try_new("vk_utils window", &[], &extensions)?;
+        let surface = unsafe {
+            ash_window::create_surface(
+                vulkan.library(),
+                vulkan.vk_instance(),
+                display_handle,
+                window_handle,
+                vulkan.allocation_callbacks(),
+            )?
+        };
+
+        let Some(gpu) = vulkan
+            .physical_devices()
+            .into_iter()
+            .find(|gpu| gpu.supports_graphics() && !gpu.present_family_indices(surface).is_empty())
+        else {
+            unsafe {
+                surface::Instance::new(vulkan.library(), vulkan.vk_instance())
+                    .destroy_surface(surface, vulkan.allocation_callbacks())
+            }
+            return Err(WindowError::NoPresentableGpu);
+        };
+        let device = Rc::new(gpu.device_context(&[ash::khr::swapchain::NAME.to_str().unwrap()]));
+        let queue = device
+            .present_queue(surface)
+            .ok_or(WindowError::NoPresentableGpu)?;
+
+        let size = window.inner_size();
+        let config = config.with_extent(Extent2D {
+            width: size.width,
+            height: size.height,
+        });
+        let swapchain = match Swapchain::new(device.clone(), surface, queue.clone(), config) {
+            Ok(swapchain) => Some(swapchain),
+            Err(SwapchainError::ZeroExtent) => None,
+            Err(error) => return Err(error.into()),
+        };
+
+        let frames = (0..FRAMES_IN_FLIGHT)
+            .map(|_| unsafe {
+                FrameSync {
+                    fence: device
+                        .handle()
+                        .create_fence(
+                            &FenceCreateInfo::default().flags(FenceCreateFlags::SIGNALED),
+                            device.allocation_callbacks(),
+                        )
+                        .expect("Fence creation failed"),
+                    render_finished: device
+                        .handle()
+                        .create_semaphore(
+                            &SemaphoreCreateInfo::default(),
+                            device.allocation_callbacks(),
+                        )
+                        .expect("Semaphore creation failed"),
+                    command_buffer: None,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            frames,
+            current_frame: 0,
+            images_in_flight: Vec::new(),
+            minimized: swapchain.is_none(),
+            swapchain,
+            surface,
+            config,
+            queue,
+            device,
+            gpu,
+            vulkan,
+        })
+    }
+
+    // Call on WindowEvent::Resized, which winit also sends after ScaleFactorChanged.
+    // While the surface has a zero extent frames are skipped, the swapchain is kept.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.config = self.config.with_extent(Extent2D { width, height });
+        let result = if let Some(swapchain) = &mut self.swapchain {
+            swapchain.recreate(width, height)
+        } else {
+            Swapchain::new(
+                self.device.clone(),
+                self.surface,
+                self.queue.clone(),
+                self.config,
+            )
+            .map(|swapchain| self.swapchain = Some(swapchain))
+        };
+        // The new images haven't been rendered to yet.
+        self.images_in_flight.clear();
+        self.minimized = match result {
+            Ok(()) => false,
+            Err(SwapchainError::ZeroExtent) => true,
+            Err(error) => panic!("{}", error),
+        };
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    // The fence of the frame still owning the swapchain image, if any.
+    pub fn image_in_flight_fence(&self, image_index: u32) -> Option<Fence> {
+        self.images_in_flight
+            .get(image_index as usize)
+            .copied()
+            .filter(|fence| *fence != Fence::null())
+    }
+
+    fn recreate(&mut self) {
+        let Extent2D { width, height } = self.config.extent;
+        self.resize(width, height)
+    }
+
+    // None while minimized or when the swapchain had to be recreated, skip the frame then.
+    pub fn begin_frame(&mut self) -> Option<Frame> {
+        if self.minimized {
+            return None;
+        }
+        let swapchain = self.swapchain.as_mut()?;
+        let sync = &mut self.frames[self.current_frame];
+        unsafe {
+            self.device
+                .observe(
+                    self.device
+                        .handle()
+                        .wait_for_fences(&[sync.fence], true, u64::MAX),
+                )
+                .expect("Wait failed");
+            if let Some(command_buffer) = sync.command_buffer.take() {
+                self.device
+                    .handle()
+                    .free_command_buffers(self.queue.pool(), &[command_buffer.handle()]);
+            }
+        }
+
+        let (image_index, framebuffer, image_available) = match swapchain.next_frame_buffer() {
+            PresentResult::Ok(acquired) | PresentResult::Suboptimal(acquired) => acquired,
+            PresentResult::OutOfDate => {
+                self.recreate();
+                return None;
+            }
+            PresentResult::Err(error) => {
+                panic!("Acquiring the next swapchain image failed: {}", error)
+            }
+        };
+
+        self.images_in_flight
+            .resize(swapchain.image_count(), Fence::null());
+        let fence = self.frames[self.current_frame].fence;
+        let image_fence = self.images_in_flight[image_index as usize];
+        if image_fence != Fence::null() && image_fence != fence {
+            unsafe {
+                self.device
+                    .observe(
+                        self.device
+                            .handle()
+                            .wait_for_fences(&[image_fence], true, u64::MAX),
+                    )
+                    .expect("Wait failed");
+            }
+        }
+        self.images_in_flight[image_index as usize] = fence;
+
+        let mut command_buffer = CommandBuffer::new(self.queue.clone());
+        command_buffer.begin();
+        Some(Frame {
+            image_index,
+            framebuffer,
+            command_buffer,
+            image_available,
+        })
+    }
+
+    pub fn end_frame(&mut self, frame: Frame) {
+        self.end_frame_with_wait_stage(frame, PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+    }
+
+    // The acquired image may still be read by the presentation engine until wait_stage, use
+    // TRANSFER when the frame writes it with a copy or blit instead of a render pass.
+    pub fn end_frame_with_wait_stage(&mut self, frame: Frame, wait_stage: PipelineStageFlags) {
+        let Some(swapchain) = self.swapchain.as_ref() else {
+            return;
+        };
+        let sync = &mut self.frames[self.current_frame];
+        let mut command_buffer = frame.command_buffer;
+        command_buffer.end();
+        unsafe {
+            self.device
+                .handle()
+                .reset_fences(&[sync.fence])
+                .expect("Fence reset failed");
+        }
+        command_buffer.submit_reusable(
+            &[frame.image_available],
+            &[wait_stage],
+            &[sync.render_finished],
+            sync.fence,
+        );
+        let render_finished = sync.render_finished;
+        sync.command_buffer = Some(command_buffer);
+        self.current_frame = (self.current_frame + 1) % FRAMES_IN_FLIGHT;
+
+        let result = swapchain.swap(&self.queue, &render_finished, frame.image_index);
+        match result {
+            PresentResult::Ok(()) => {}
+            PresentResult::Suboptimal(()) | PresentResult::OutOfDate => self.recreate(),
+            PresentResult::Err(error) => panic!("Presenting failed: {}", error),
+        }
+    }
+
+    pub fn swapchain(&self) -> Option<&Swapchain> {
+        self.swapchain.as_ref()
+    }
+
+    // E.g. for blit_to_swapchain, which tracks the layout of the image it writes.
+    pub fn swapchain_mut(&mut self) -> Option<&mut Swapchain> {
+        self.swapchain.as_mut()
+    }
+
+    pub fn surface(&self) -> SurfaceKHR {
+        self.surface
+    }
+
+    pub fn queue(&self) -> Rc<CommandQueue> {
+        self.queue.clone()
+    }
+
+    pub fn device(&self) -> Rc<DeviceContext> {
+        self.device.clone()
+    }
+
+    pub fn gpu(&self) -> &Gpu {
+        &self.gpu
+    }
+
+    pub fn vulkan(&self) -> &Vulkan {
+        &self.vulkan
+    }
+}
+
+impl Drop for WindowRenderer {
+    fn drop(&mut self) {
+        if let Err(error) = self.device.try_wait() {
+            println!("Waiting for the device before teardown failed: {:?}", error);
+        }
+        unsafe {
+            for sync in self.frames.drain(..) {
+                if let Some(command_buffer) = sync.command_buffer {
+                    self.device
+                        .handle()
+                        .free_command_buffers(self.queue.pool(), &[command_buffer.handle()]);
+                }
+                self.device
+                    .handle()
+                    .destroy_semaphore(sync.render_finished, self.device.allocation_callbacks());
+                self.device
+                    .handle()
+                    .destroy_fence(sync.fence, self.device.allocation_callbacks());
+            }
+            // The swapchain has to go before the surface it was created for.
+            self.swapchain = None;
+            surface::Instance::new(self.vulkan.library(), self.vulkan.vk_instance())
+                .destroy_surface(self.surface, self.vulkan.allocation_callbacks());
+        }
+    }
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use ash::vk::{AccessFlags, BufferUsageFlags, DescriptorType, MemoryPropertyFlags};
+use common::TestContext;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::{BufferHazard, CommandBuffer};
+use vk_utils::pipeline_descriptor::ComputePipeline;
+
+const WRITE_SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) buffer Data { uint data[]; };
+void main() {
+    data[gl_GlobalInvocationID.x] = gl_GlobalInvocationID.x;
+}
+";
+
+const UNIFORM_READ_SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) uniform Params { uint value; };
+layout(set = 0, binding = 1) writeonly buffer Result { uint result[]; };
+void main() {
+    result[gl_GlobalInvocationID.x] = value;
+}
+";
+
+const STORAGE_READ_SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) readonly buffer Data { uint data[]; };
+layout(set = 0, binding = 1) writeonly buffer Result { uint result[]; };
+void main() {
+    result[gl_GlobalInvocationID.x] = data[gl_GlobalInvocationID.x];
+}
+";
+
+#[test]
+fn uniform_reads_after_storage_writes_need_a_barrier() {
+    if !cfg!(debug_assertions) {
+        println!("Hazards are only tracked in debug builds, skipping");
+        return;
+    }
+    let Some(context) = TestContext::compute("Buffer hazards") else {
+        return;
+    };
+
+    let shared = BufferResource::new(
+        context.device.clone(),
+        64,
+        MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+        BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::UNIFORM_BUFFER,
+    );
+    let result = BufferResource::new_host_visible_storage(context.device.clone(), 64);
+    let mut writer =
+        ComputePipeline::new_from_source_string(context.device.clone(), 1, WRITE_SRC, "main", None)
+            .unwrap();
+    writer.set_storage_buffer(0, 0, &shared);
+    let mut reader = ComputePipeline::new_from_source_string(
+        context.device.clone(),
+        1,
+        UNIFORM_READ_SRC,
+        "main",
+        None,
+    )
+    .unwrap();
+    reader.set_uniform_buffer(0, 0, &shared);
+    reader.set_storage_buffer(0, 1, &result);
+
+    for with_barrier in [false, true] {
+        let mut command_buffer = CommandBuffer::new(context.queue.clone());
+        command_buffer.begin();
+        command_buffer.bind_compute_pipeline(&writer);
+        command_buffer.dispatch_compute(16, 1, 1);
+        if with_barrier {
+            command_buffer.compute_write_to_uniform_read_barrier(&shared);
+        }
+        command_buffer.bind_compute_pipeline(&reader);
+        command_buffer.dispatch_compute(16, 1, 1);
+
+        let expected = if with_barrier {
+            vec![]
+        } else {
+            vec![BufferHazard {
+                buffer: shared.buffer,
+                descriptor_type: DescriptorType::UNIFORM_BUFFER,
+                access: AccessFlags::SHADER_READ,
+            }]
+        };
+        assert_eq!(command_buffer.buffer_hazards(), expected.as_slice());
+        command_buffer.submit().wait();
+    }
+
+    context.capture.assert_no_errors();
+}
+
+#[test]
+fn readonly_storage_buffers_are_not_writes() {
+    if !cfg!(debug_assertions) {
+        println!("Hazards are only tracked in debug builds, skipping");
+        return;
+    }
+    let Some(context) = TestContext::compute("Read only hazards") else {
+        return;
+    };
+
+    let data = BufferResource::new_host_visible_with_data(context.device.clone(), &[1u32; 16]);
+    let results = [
+        BufferResource::new_host_visible_storage(context.device.clone(), 64),
+        BufferResource::new_host_visible_storage(context.device.clone(), 64),
+    ];
+    let readers: Vec<ComputePipeline> = results
+        .iter()
+        .map(|result| {
+            let mut reader = ComputePipeline::new_from_source_string(
+                context.device.clone(),
+                1,
+                STORAGE_READ_SRC,
+                "main",
+                None,
+            )
+            .unwrap();
+            reader.set_storage_buffer(0, 0, &data);
+            reader.set_storage_buffer(0, 1, result);
+            reader
+        })
+        .collect();
+
+    // Both dispatches only read data and write their own result, nothing to synchronize.
+    let mut command_buffer = CommandBuffer::new(context.queue.clone());
+    command_buffer.begin();
+    for reader in &readers {
+        command_buffer.bind_compute_pipeline(reader);
+        command_buffer.dispatch_compute(16, 1, 1);
+    }
+    assert!(command_buffer.buffer_hazards().is_empty());
+    // A second dispatch writing the same result buffer is a write after write.
+    command_buffer.bind_compute_pipeline(&readers[0]);
+    command_buffer.dispatch_compute(16, 1, 1);
+    assert_eq!(
+        command_buffer.buffer_hazards(),
+        &[BufferHazard {
+            buffer: results[0].buffer,
+            descriptor_type: DescriptorType::STORAGE_BUFFER,
+            access: AccessFlags::SHADER_WRITE,
+        }]
+    );
+    command_buffer.submit().wait();
+
+    context.capture.assert_no_errors();
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::TestContext;
+use vk_utils::buffer_resource::BufferResource;
+
+#[test]
+fn read_guards_share_one_mapping() {
+    let Some(context) = TestContext::compute("Buffer mapping") else {
+        return;
+    };
+
+    let data: Vec<u32> = (0..64).collect();
+    let mut buffer = BufferResource::new_host_visible_with_data(context.device.clone(), &data);
+    {
+        // Mapping memory that is already mapped is invalid, the second guard reuses the first's.
+        let first = buffer.map_read::<u32>();
+        let second = buffer.map_read::<u32>();
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        assert_eq!(&*first, data.as_slice());
+        drop(first);
+        assert_eq!(&*second, data.as_slice());
+        assert_eq!(buffer.download::<u32>(), data);
+    }
+
+    // Both guards are gone, the memory was unmapped and can be mapped again for writing.
+    buffer.map_write::<u32>().fill(7);
+    assert!(buffer.map_read::<u32>().iter().all(|&x| x == 7));
+
+    drop(buffer);
+    let capture = context.teardown();
+    capture.assert_no_leaks();
+    capture.assert_no_errors();
+}
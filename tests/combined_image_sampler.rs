@@ -0,0 +1,91 @@
+mod common;
+
+use ash::vk::{Filter, Format, ImageLayout, ImageUsageFlags, MemoryPropertyFlags};
+use common::TestContext;
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::image2d_resource::Image2DResource;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::sampler_resource::SamplerDescriptor;
+
+const SRC: &str = r"
+#version 450
+layout(local_size_x = 2, local_size_y = 2) in;
+layout(set = 0, binding = 0) uniform sampler2D tex;
+layout(set = 0, binding = 1) buffer Result { vec4 texels[]; };
+void main() {
+    uvec2 p = gl_GlobalInvocationID.xy;
+    texels[p.y * 2 + p.x] = textureLod(tex, (vec2(p) + 0.5) / 2.0, 0.0);
+}
+";
+
+#[test]
+fn the_pipeline_keeps_its_sampler_alive() {
+    let Some(context) = TestContext::compute("Combined image sampler") else {
+        return;
+    };
+    let device = context.device.clone();
+
+    let pixels: [[u8; 4]; 4] = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [255; 4],
+    ];
+    let staging = BufferResource::new_staging(device.clone(), 16).with_data(&pixels);
+    let mut image = Image2DResource::new(
+        device.clone(),
+        2,
+        2,
+        Format::R8G8B8A8_UNORM,
+        ImageUsageFlags::SAMPLED | ImageUsageFlags::TRANSFER_DST,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        1,
+    );
+    let result = BufferResource::new_host_visible_storage(device.clone(), 4 * 16);
+
+    let descriptor = SamplerDescriptor {
+        mag_filter: Filter::NEAREST,
+        min_filter: Filter::NEAREST,
+        ..Default::default()
+    };
+    let mut pipeline =
+        ComputePipeline::new_from_source_string(device.clone(), 1, SRC, "main", None)
+            .expect("Compute pipeline creation failed");
+    // The only strong reference ends up in the pipeline.
+    let sampler = device.get_sampler(&descriptor);
+    let handle = sampler.handle();
+    pipeline.set_combined_image_sampler_with_layout(
+        0,
+        0,
+        &image,
+        sampler,
+        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+    pipeline.set_storage_buffer(0, 1, &result);
+
+    let cached = device.get_sampler(&descriptor);
+    assert_eq!(cached.handle(), handle);
+    assert_eq!(Rc::strong_count(&cached), 2);
+    drop(cached);
+
+    CommandBuffer::record(context.queue.clone(), |recorder| {
+        recorder.image_resource_transition(&mut image, ImageLayout::TRANSFER_DST_OPTIMAL);
+        recorder.copy_buffer_to_image(&staging, &mut image);
+        recorder.image_resource_transition(&mut image, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        recorder.bind_compute_pipeline(&pipeline);
+        recorder.dispatch_compute(1, 1, 1);
+    })
+    .wait();
+
+    let texels = result.copy_data::<[f32; 4]>();
+    for (texel, pixel) in texels.iter().zip(&pixels) {
+        assert_eq!(*texel, pixel.map(|channel| channel as f32 / 255.0));
+    }
+
+    // Dropping the pipeline releases the sampler, the next request creates a new one.
+    drop(pipeline);
+    assert_eq!(Rc::strong_count(&device.get_sampler(&descriptor)), 1);
+    context.capture.assert_no_errors();
+}
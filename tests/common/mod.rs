@@ -0,0 +1,98 @@
+#![allow(dead_code)]
+
+use ash::ext::debug_utils;
+use ash::vk::QueueFlags;
+use shaderc::ShaderKind;
+use std::rc::Rc;
+use vk_utils::device_context::DeviceContext;
+use vk_utils::queue::CommandQueue;
+use vk_utils::shader_compiler::ShaderCompiler;
+use vk_utils::validation_capture::ValidationCapture;
+use vk_utils::vulkan::Vulkan;
+
+// A validated instance with the capture installed. None (and the test passes) on machines without a
+// Vulkan driver or the validation layer, e.g. CI runners without a GPU.
+pub fn instance(name: &str) -> Option<(Vulkan, ValidationCapture)> {
+    if !Vulkan::is_available() {
+        println!("No Vulkan driver, skipping");
+        return None;
+    }
+    let mut vulkan = match Vulkan::try_new(
+        name,
+        &["VK_LAYER_KHRONOS_validation"],
+        &[debug_utils::NAME.to_str().unwrap()],
+    ) {
+        Ok(vulkan) => vulkan,
+        Err(error) => {
+            println!("{}, skipping", error);
+            return None;
+        }
+    };
+    let capture = ValidationCapture::new();
+    if !vulkan.set_validation_capture(&capture) {
+        println!("VK_EXT_debug_utils is not available, skipping");
+        return None;
+    }
+    Some((vulkan, capture))
+}
+
+// Fields drop in declaration order, the queue and device go before the instance.
+pub struct TestContext {
+    pub queue: Rc<CommandQueue>,
+    pub device: Rc<DeviceContext>,
+    pub capture: ValidationCapture,
+    pub vulkan: Vulkan,
+}
+
+impl TestContext {
+    pub fn new(name: &str, flags: QueueFlags) -> Option<Self> {
+        let (vulkan, capture) = instance(name)?;
+        let Some(gpu) = vulkan.devices_with_queue_support(flags).into_iter().next() else {
+            println!("No device with {:?} queues, skipping", flags);
+            return None;
+        };
+        let device = Rc::new(gpu.device_context(&[]));
+        let queue = Rc::new(CommandQueue::new(device.clone(), flags));
+        Some(Self {
+            queue,
+            device,
+            capture,
+            vulkan,
+        })
+    }
+
+    pub fn compute(name: &str) -> Option<Self> {
+        Self::new(name, QueueFlags::COMPUTE)
+    }
+
+    pub fn graphics(name: &str) -> Option<Self> {
+        Self::new(name, QueueFlags::GRAPHICS)
+    }
+
+    // Destroys the device, after which the layer has reported every object still alive. Fails if
+    // anything outside the context still holds on to the device.
+    #[track_caller]
+    pub fn teardown(self) -> ValidationCapture {
+        let Self {
+            queue,
+            device,
+            capture,
+            vulkan,
+        } = self;
+        drop(queue);
+        assert_eq!(
+            Rc::strong_count(&device),
+            1,
+            "Resources created from the device are still alive"
+        );
+        drop(device);
+        drop(vulkan);
+        capture
+    }
+}
+
+pub fn compile(src: &str, kind: ShaderKind) -> Vec<u32> {
+    let result = ShaderCompiler::compile_string(src, kind, "test", "main");
+    assert!(!result.failed(), "{}", result.error_string());
+    result.spirv().to_vec()
+}
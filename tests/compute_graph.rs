@@ -0,0 +1,99 @@
+mod common;
+
+use ash::vk::QueueFlags;
+use common::TestContext;
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::compute_graph::ComputeGraph;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::queue::CommandQueue;
+
+const COUNT: u32 = 256;
+
+const FILL_SRC: &str = r"
+#version 450
+layout(local_size_x = 64) in;
+layout(set = 0, binding = 0) buffer Data { uint x[]; };
+void main() {
+    x[gl_GlobalInvocationID.x] = gl_GlobalInvocationID.x;
+}
+";
+
+const MULTIPLY_SRC: &str = r"
+#version 450
+layout(local_size_x = 64) in;
+layout(set = 0, binding = 0) buffer Data { uint x[]; };
+void main() {
+    x[gl_GlobalInvocationID.x] *= 3;
+}
+";
+
+// Adds one and counts the frames, so every frame is known to have run all three passes.
+const ADD_SRC: &str = r"
+#version 450
+layout(local_size_x = 64) in;
+layout(set = 0, binding = 0) buffer Data { uint x[]; };
+layout(set = 0, binding = 1) buffer Frames { uint frames; };
+void main() {
+    x[gl_GlobalInvocationID.x] += 1;
+    if (gl_GlobalInvocationID.x == 0) {
+        frames += 1;
+    }
+}
+";
+
+#[test]
+fn three_passes_for_three_frames_reuse_one_command_buffer() {
+    let Some(context) = TestContext::compute("Compute graph") else {
+        return;
+    };
+    let device = context.device.clone();
+    let queue = Rc::new(CommandQueue::new_resettable(
+        device.clone(),
+        QueueFlags::COMPUTE,
+    ));
+
+    let data =
+        BufferResource::new_host_visible_with_data(device.clone(), &[u32::MAX; COUNT as usize]);
+    let frames = BufferResource::new_host_visible_with_data(device.clone(), &[0u32]);
+    let pipeline = |src: &str| {
+        ComputePipeline::new_from_source_string(device.clone(), 1, src, "main", None)
+            .expect("Compute pipeline creation failed")
+    };
+    let mut fill = pipeline(FILL_SRC);
+    fill.set_storage_buffer(0, 0, &data);
+    let mut multiply = pipeline(MULTIPLY_SRC);
+    multiply.set_storage_buffer(0, 0, &data);
+    let mut add = pipeline(ADD_SRC);
+    add.set_storage_buffer(0, 0, &data);
+    add.set_storage_buffer(0, 1, &frames);
+
+    let mut graph = ComputeGraph::new(queue.clone());
+    for pipeline in [fill, multiply, add] {
+        graph.add_pass(Rc::new(pipeline), COUNT / 64, 1, 1);
+    }
+    assert_eq!(graph.len(), 3);
+
+    let command_buffer = graph.command_buffer();
+    for frame in 1..=3 {
+        let handle = graph.submit(&[], &[]);
+        assert_eq!(queue.in_flight(), 1);
+        handle.wait();
+        assert_eq!(queue.in_flight(), 0);
+
+        assert_eq!(frames.copy_data::<u32>()[0], frame);
+        let expected: Vec<u32> = (0..COUNT).map(|i| i * 3 + 1).collect();
+        assert_eq!(data.copy_data::<u32>(), expected, "frame {}", frame);
+        assert_eq!(graph.command_buffer(), command_buffer);
+    }
+    // Recorded once, the later frames resubmit the same recording.
+    assert_eq!(graph.record_count(), 1);
+
+    // Dropping a handle before completion doesn't block, the graph retires the submission.
+    drop(graph.submit(&[], &[]));
+    graph.wait();
+    assert_eq!(queue.in_flight(), 0);
+    assert_eq!(frames.copy_data::<u32>()[0], 4);
+
+    context.capture.assert_no_errors();
+}
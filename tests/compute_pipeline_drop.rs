@@ -0,0 +1,70 @@
+mod common;
+
+use ash::vk::{DescriptorSetLayoutBinding, DescriptorType, ShaderStageFlags};
+use common::TestContext;
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::shared_binding::SharedBinding;
+
+const SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) buffer Globals { uint counter[]; };
+layout(set = 1, binding = 0) buffer Data { uint x[]; };
+void main() {
+    x[gl_GlobalInvocationID.x] += counter[0];
+}
+";
+
+#[test]
+fn dropping_pipelines_in_a_loop_leaks_nothing() {
+    let Some(context) = TestContext::compute("Compute pipeline drop") else {
+        return;
+    };
+
+    let globals = BufferResource::new_host_visible_with_data(context.device.clone(), &[1u32]);
+    let data = BufferResource::new_host_visible_with_data(context.device.clone(), &[0u32; 64]);
+    let shared = Rc::new(SharedBinding::new(
+        context.device.clone(),
+        &[DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(ShaderStageFlags::COMPUTE)],
+    ));
+    shared.set_storage_buffer(0, &globals);
+
+    const ITERATIONS: u32 = 100;
+    for _ in 0..ITERATIONS {
+        // Owned and shared sets, a cached module and descriptor pools, all released on drop.
+        let mut pipeline = ComputePipeline::new_with_shared_sets(
+            context.device.clone(),
+            2,
+            SRC,
+            "main",
+            None,
+            &[(0, shared.clone())],
+        )
+        .expect("Compute pipeline creation failed");
+        pipeline.set_storage_buffer(1, 0, &data);
+
+        CommandBuffer::record(context.queue.clone(), |recorder| {
+            recorder.bind_compute_pipeline(&pipeline);
+            recorder.dispatch_compute(64, 1, 1);
+        })
+        .wait();
+    }
+    assert!(data
+        .copy_data::<u32>()
+        .iter()
+        .all(|&value| value == ITERATIONS));
+    assert_eq!(Rc::strong_count(&shared), 1);
+
+    drop(shared);
+    drop(globals);
+    drop(data);
+    let capture = context.teardown();
+    capture.assert_no_leaks();
+    capture.assert_no_errors();
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use ash::vk::{Filter, Format, ImageLayout, ImageUsageFlags, MemoryPropertyFlags};
+use common::TestContext;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::image2d_resource::{convert_image_format, Image2DResource};
+use vk_utils::image_resource::ImageResource;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::sampler_resource::SamplerDescriptor;
+
+const SIZE: u32 = 4;
+
+// The converted image is only SAMPLED, so it's read back through a shader.
+const READ_SRC: &str = r"
+#version 450
+layout(local_size_x = 4, local_size_y = 4) in;
+layout(set = 0, binding = 0) uniform sampler2D tex;
+layout(set = 0, binding = 1) buffer Result { vec4 texels[]; };
+void main() {
+    uvec2 p = gl_GlobalInvocationID.xy;
+    texels[p.y * 4 + p.x] = textureLod(tex, (vec2(p) + 0.5) / 4.0, 0.0);
+}
+";
+
+// Normal numbers and zero only, the mantissa is truncated.
+fn to_f16(value: f32) -> u16 {
+    if value == 0.0 {
+        return 0;
+    }
+    let bits = value.to_bits();
+    let sign = (bits >> 16) & 0x8000;
+    let exponent = ((bits >> 23) & 0xff) - 127 + 15;
+    let mantissa = (bits >> 13) & 0x3ff;
+    (sign | (exponent << 10) | mantissa) as u16
+}
+
+#[test]
+fn float_to_unorm_is_within_tolerance() {
+    let Some(context) = TestContext::compute("Convert image format") else {
+        return;
+    };
+    let device = context.device.clone();
+
+    // Runs from -0.25 to 1.25 so both ends clamp.
+    let values: Vec<f32> = (0..SIZE * SIZE * 4)
+        .map(|i| i as f32 / 63.0 * 1.5 - 0.25)
+        .collect();
+    let halfs: Vec<u16> = values.iter().map(|&value| to_f16(value)).collect();
+    let staging = BufferResource::new_staging(device.clone(), halfs.len() * 2).with_data(&halfs);
+    let mut src = Image2DResource::new(
+        device.clone(),
+        SIZE,
+        SIZE,
+        Format::R16G16B16A16_SFLOAT,
+        ImageUsageFlags::SAMPLED | ImageUsageFlags::TRANSFER_SRC | ImageUsageFlags::TRANSFER_DST,
+        MemoryPropertyFlags::DEVICE_LOCAL,
+        1,
+    );
+    CommandBuffer::record(context.queue.clone(), |recorder| {
+        recorder.image_resource_transition(&mut src, ImageLayout::TRANSFER_DST_OPTIMAL);
+        recorder.copy_buffer_to_image(&staging, &mut src);
+    })
+    .wait();
+
+    let converted = convert_image_format(context.queue.clone(), &mut src, Format::R8G8B8A8_UNORM);
+    assert_eq!(converted.format(), Format::R8G8B8A8_UNORM);
+    assert_eq!((converted.width(), converted.height()), (SIZE, SIZE));
+
+    let result = BufferResource::new_host_visible_storage(device.clone(), values.len() * 4);
+    let mut read =
+        ComputePipeline::new_from_source_string(device.clone(), 1, READ_SRC, "main", None)
+            .expect("Compute pipeline creation failed");
+    read.set_combined_image_sampler_with_layout(
+        0,
+        0,
+        &converted,
+        device.get_sampler(&SamplerDescriptor::new().with_filter(Filter::NEAREST, Filter::NEAREST)),
+        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+    read.set_storage_buffer(0, 1, &result);
+    CommandBuffer::record(context.queue.clone(), |recorder| {
+        recorder.bind_compute_pipeline(&read);
+        recorder.dispatch_compute(1, 1, 1);
+    })
+    .wait();
+
+    // One unorm step, plus the error of truncating to half precision.
+    let tolerance = 1.0 / 255.0 + 1.0 / 1024.0;
+    let texels = result.copy_data::<f32>();
+    for (i, (&texel, &value)) in texels.iter().zip(&values).enumerate() {
+        let expected = value.clamp(0.0, 1.0);
+        assert!(
+            (texel - expected).abs() <= tolerance,
+            "channel {}: {} converted to {}, expected {}",
+            i,
+            value,
+            texel,
+            expected
+        );
+    }
+    context.capture.assert_no_errors();
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use ash::vk::{DescriptorPoolSize, DescriptorSetLayoutBinding, DescriptorType, ShaderStageFlags};
+use common::TestContext;
+use vk_utils::descriptor_allocator::DescriptorAllocator;
+
+fn binding(binding: u32, ty: DescriptorType, count: u32) -> DescriptorSetLayoutBinding<'static> {
+    DescriptorSetLayoutBinding::default()
+        .binding(binding)
+        .descriptor_type(ty)
+        .descriptor_count(count)
+        .stage_flags(ShaderStageFlags::COMPUTE)
+}
+
+#[test]
+fn pools_grow_to_fit_layouts_larger_than_the_initial_sizes() {
+    let Some(context) = TestContext::compute("Descriptor allocator") else {
+        return;
+    };
+
+    // The allocator is told about a single storage buffer, the layout needs far more than that
+    // and a descriptor type the initial sizes don't mention.
+    let layout = context.device.get_or_create_descriptor_set_layout(&[
+        binding(0, DescriptorType::STORAGE_BUFFER, 4),
+        binding(1, DescriptorType::UNIFORM_BUFFER, 2),
+    ]);
+    let small = context
+        .device
+        .get_or_create_descriptor_set_layout(&[binding(0, DescriptorType::STORAGE_BUFFER, 1)]);
+    let mut allocator = DescriptorAllocator::new(
+        context.device.clone(),
+        &[DescriptorPoolSize::default()
+            .ty(DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)],
+        4,
+    );
+
+    const SETS: usize = 5000;
+    let mut sets = Vec::with_capacity(SETS);
+    for i in 0..SETS {
+        let layout = if i % 3 == 0 { &small } else { &layout };
+        sets.push(
+            allocator
+                .allocate(layout)
+                .expect("Descriptor set allocation failed"),
+        );
+    }
+    for frame in 0..2 {
+        for _ in 0..SETS {
+            allocator
+                .allocate_for_frame(frame, &layout)
+                .expect("Frame descriptor set allocation failed");
+        }
+        allocator.reset_frame(frame);
+    }
+    sets.sort_unstable();
+    sets.dedup();
+    assert_eq!(sets.len(), SETS);
+    // Doubling from 4 sets per pool, thousands of sets only take a handful of pools.
+    assert!(
+        allocator.pool_count() < 32,
+        "{} pools",
+        allocator.pool_count()
+    );
+
+    drop(allocator);
+    drop(layout);
+    drop(small);
+    let capture = context.teardown();
+    capture.assert_no_leaks();
+    capture.assert_no_errors();
+}
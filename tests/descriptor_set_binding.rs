@@ -0,0 +1,182 @@
+mod common;
+
+use ash::vk::{
+    AccessFlags, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+    PipelineStageFlags, ShaderStageFlags,
+};
+use common::TestContext;
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::shared_binding::SharedBinding;
+
+const ADD_SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) readonly buffer Globals { uint add; uint multiply; };
+layout(set = 1, binding = 0) buffer Data { uint x[]; };
+void main() {
+    x[gl_GlobalInvocationID.x] += add;
+}
+";
+
+const MULTIPLY_SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) readonly buffer Globals { uint add; uint multiply; };
+layout(set = 1, binding = 0) buffer Data { uint x[]; };
+void main() {
+    x[gl_GlobalInvocationID.x] *= multiply;
+}
+";
+
+// Sets 1 and 2 are never used.
+const SPARSE_SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) buffer Data { uint x[]; };
+layout(set = 3, binding = 0) readonly buffer Globals { uint add; uint multiply; };
+void main() {
+    x[gl_GlobalInvocationID.x] = x[gl_GlobalInvocationID.x] * multiply + add;
+}
+";
+
+fn globals_binding() -> DescriptorSetLayoutBinding<'static> {
+    DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(ShaderStageFlags::COMPUTE)
+}
+
+#[test]
+fn alternating_pipelines_keep_the_shared_globals_bound() {
+    let Some(context) = TestContext::compute("Descriptor set binding") else {
+        return;
+    };
+
+    let globals = BufferResource::new_host_visible_with_data(context.device.clone(), &[3u32, 2]);
+    let added = BufferResource::new_host_visible_with_data(context.device.clone(), &[0u32; 64]);
+    let multiplied =
+        BufferResource::new_host_visible_with_data(context.device.clone(), &[1u32; 64]);
+    let shared = Rc::new(SharedBinding::new(
+        context.device.clone(),
+        &[globals_binding()],
+    ));
+    shared.set_storage_buffer(0, &globals);
+
+    let mut add = ComputePipeline::new_with_shared_sets(
+        context.device.clone(),
+        1,
+        ADD_SRC,
+        "main",
+        None,
+        &[(0, shared.clone())],
+    )
+    .expect("Compute pipeline creation failed");
+    add.set_storage_buffer(1, 0, &added);
+    let mut multiply = ComputePipeline::new_with_shared_sets(
+        context.device.clone(),
+        1,
+        MULTIPLY_SRC,
+        "main",
+        None,
+        &[(0, shared.clone())],
+    )
+    .expect("Compute pipeline creation failed");
+    multiply.set_storage_buffer(1, 0, &multiplied);
+
+    const ROUNDS: usize = 10;
+    let mut command_buffer = CommandBuffer::new(context.queue.clone());
+    command_buffer.begin();
+    for _ in 0..ROUNDS {
+        for (pipeline, data) in [(&add, &added), (&multiply, &multiplied)] {
+            command_buffer.bind_compute_pipeline(pipeline);
+            command_buffer.dispatch_compute(64, 1, 1);
+            command_buffer.buffer_resource_barrier(
+                data,
+                PipelineStageFlags::COMPUTE_SHADER,
+                PipelineStageFlags::COMPUTE_SHADER,
+                AccessFlags::SHADER_WRITE,
+                AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE,
+            );
+        }
+    }
+    // Both sets for the first bind, after that only set 1 changes on every switch. Without the
+    // shared layout every switch would bind both.
+    let switches = 2 * ROUNDS;
+    assert_eq!(
+        command_buffer.descriptor_set_bind_count(),
+        2 + (switches - 1)
+    );
+    assert!(command_buffer.descriptor_set_bind_count() < 2 * switches);
+
+    // Binding the same pipeline again binds nothing.
+    command_buffer.bind_compute_pipeline(&multiply);
+    assert_eq!(
+        command_buffer.descriptor_set_bind_count(),
+        2 + (switches - 1)
+    );
+    command_buffer.submit().wait();
+
+    assert!(added
+        .copy_data::<u32>()
+        .iter()
+        .all(|&value| value == 3 * ROUNDS as u32));
+    assert!(multiplied
+        .copy_data::<u32>()
+        .iter()
+        .all(|&value| value == 2u32.pow(ROUNDS as u32)));
+    context.capture.assert_no_errors();
+}
+
+#[test]
+fn sparse_set_numbers_leave_gaps_unallocated() {
+    let Some(context) = TestContext::compute("Sparse descriptor sets") else {
+        return;
+    };
+
+    let globals = BufferResource::new_host_visible_with_data(context.device.clone(), &[3u32, 2]);
+    let data = BufferResource::new_host_visible_with_data(context.device.clone(), &[5u32; 64]);
+    let shared = Rc::new(SharedBinding::new(
+        context.device.clone(),
+        &[globals_binding()],
+    ));
+    shared.set_storage_buffer(0, &globals);
+
+    let mut pipeline = ComputePipeline::new_with_shared_sets(
+        context.device.clone(),
+        1,
+        SPARSE_SRC,
+        "main",
+        None,
+        &[(3, shared.clone())],
+    )
+    .expect("Compute pipeline creation failed");
+    pipeline.set_storage_buffer(0, 0, &data);
+
+    // The layout spans every set number up to the highest, the gaps have an empty layout but no set.
+    assert_eq!(pipeline.descriptor_sets().len(), 4);
+    for gap in [1u32, 2] {
+        assert_eq!(
+            pipeline.descriptor_sets()[gap as usize],
+            DescriptorSet::null()
+        );
+        assert_ne!(
+            pipeline.descriptor_set_layout(gap),
+            DescriptorSetLayout::null()
+        );
+    }
+    assert_eq!(pipeline.descriptor_sets()[3], shared.descriptor_set());
+
+    let mut command_buffer = CommandBuffer::new(context.queue.clone());
+    command_buffer.begin();
+    command_buffer.bind_compute_pipeline(&pipeline);
+    assert_eq!(command_buffer.descriptor_set_bind_count(), 2);
+    command_buffer.dispatch_compute(64, 1, 1);
+    command_buffer.submit().wait();
+
+    assert!(data
+        .copy_data::<u32>()
+        .iter()
+        .all(|&value| value == 5 * 2 + 3));
+    context.capture.assert_no_errors();
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::TestContext;
+use std::rc::Rc;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::queue::WaitHandleDropPolicy;
+
+#[test]
+fn clones_share_the_drop_policy() {
+    let Some(context) = TestContext::compute("Drop policy") else {
+        return;
+    };
+    let clone = Rc::new((*context.queue).clone());
+    assert_eq!(clone.drop_policy(), WaitHandleDropPolicy::Block);
+
+    clone.set_drop_policy(WaitHandleDropPolicy::Defer);
+    assert_eq!(context.queue.drop_policy(), WaitHandleDropPolicy::Defer);
+
+    // Submitted through the original, deferred and collected through the clone.
+    drop(CommandBuffer::record(context.queue.clone(), |_| {}));
+    context.device.wait();
+    clone.collect_garbage();
+    assert_eq!(context.queue.in_flight(), 0);
+
+    context
+        .queue
+        .set_drop_policy(WaitHandleDropPolicy::PanicInDebug);
+    assert_eq!(clone.drop_policy(), WaitHandleDropPolicy::PanicInDebug);
+    context.capture.assert_no_errors();
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{compile, TestContext};
+use shaderc::ShaderKind;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::shader_compiler::ShaderReflection;
+
+const SRC: &str = r"
+#version 450
+layout(set = 0, binding = 0) buffer Data { uint x[]; };
+void main() {
+    x[gl_GlobalInvocationID.x] = 1;
+}
+";
+
+#[test]
+fn a_wrong_entry_point_is_rejected() {
+    let Some(context) = TestContext::compute("Entry point") else {
+        return;
+    };
+
+    let spirv = compile(SRC, ShaderKind::Compute);
+    let reflection = ShaderReflection::from_spirv(&spirv).unwrap();
+    assert_eq!(reflection.entry_points(), ["main"]);
+    assert!(!reflection.has_entry_point("Main"));
+
+    // Rejected before anything is created, instead of a validation error at pipeline creation.
+    for entry_point in ["Main", "compute", ""] {
+        assert!(
+            ComputePipeline::new_from_spirv(context.device.clone(), 1, &spirv, entry_point, None)
+                .is_none(),
+            "{:?}",
+            entry_point
+        );
+    }
+    assert!(
+        ComputePipeline::new_from_spirv(context.device.clone(), 1, &spirv, "main", None).is_some()
+    );
+
+    let capture = context.teardown();
+    capture.assert_no_leaks();
+    capture.assert_no_errors();
+}
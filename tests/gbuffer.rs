@@ -0,0 +1,122 @@
+mod common;
+
+use ash::vk::{CullModeFlags, Format, ImageLayout, PipelineBindPoint};
+use common::{compile, TestContext};
+use shaderc::ShaderKind;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::clear_value::Clear;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::gbuffer::GBuffer;
+use vk_utils::graphics_pipeline::{GraphicsPipeline, GraphicsPipelineState};
+use vk_utils::image_resource::ImageResource;
+
+const SIZE: u32 = 16;
+
+// One triangle covering the whole target.
+const FULLSCREEN_VERT: &str = r"
+#version 450
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(uv * 2.0 - 1.0, 0.5, 1.0);
+}
+";
+
+const GBUFFER_FRAG: &str = r"
+#version 450
+layout(location = 0) out vec4 albedo;
+layout(location = 1) out uint id;
+layout(location = 2) out vec4 position;
+void main() {
+    albedo = vec4(1.0, 0.5, 0.0, 1.0);
+    uvec2 pixel = uvec2(gl_FragCoord.xy);
+    id = pixel.y * 16 + pixel.x + 1;
+    position = vec4(gl_FragCoord.xy, gl_FragCoord.z, 1.0);
+}
+";
+
+#[test]
+fn every_target_of_the_gbuffer_is_written() {
+    let Some(context) = TestContext::graphics("GBuffer") else {
+        return;
+    };
+    let device = context.device.clone();
+
+    let formats = [
+        Format::R8G8B8A8_UNORM,
+        Format::R32_UINT,
+        Format::R32G32B32A32_SFLOAT,
+    ];
+    let mut gbuffer = GBuffer::new(device.clone(), SIZE, SIZE, &formats, Format::D32_SFLOAT);
+    assert_eq!(gbuffer.render_pass().color_attachment_count(), 3);
+    assert!(gbuffer.render_pass().has_depth_attachment());
+
+    let pipeline = GraphicsPipeline::new_from_shaders(
+        device.clone(),
+        &compile(FULLSCREEN_VERT, ShaderKind::Vertex),
+        &compile(GBUFFER_FRAG, ShaderKind::Fragment),
+        &GraphicsPipelineState::new()
+            .with_viewport(SIZE, SIZE)
+            .with_cull_mode(CullModeFlags::NONE)
+            .with_depth_testing()
+            .with_depth_writing(),
+        gbuffer.render_pass(),
+        &[],
+    );
+
+    let texel_sizes = [4usize, 4, 16];
+    let mut readbacks: Vec<BufferResource> = texel_sizes
+        .iter()
+        .map(|size| {
+            BufferResource::new_host_visible_storage(device.clone(), (SIZE * SIZE) as usize * size)
+        })
+        .collect();
+
+    CommandBuffer::record(context.queue.clone(), |recorder| {
+        {
+            let mut pass = recorder
+                .begin_render_pass(
+                    gbuffer.render_pass(),
+                    gbuffer.framebuffer().handle(),
+                    SIZE,
+                    SIZE,
+                    &[
+                        Clear::Color(0.0, 0.0, 0.0, 0.0),
+                        Clear::ColorU32([0; 4]),
+                        Clear::Color(0.0, 0.0, 0.0, 0.0),
+                        Clear::DepthStencil(1.0, 0),
+                    ],
+                )
+                .expect("Clear values don't match the attachments");
+            pass.bind_pipeline(PipelineBindPoint::GRAPHICS, pipeline.handle());
+            pass.draw_vertices(3, 0, 1, 0);
+        }
+        for (index, readback) in readbacks.iter_mut().enumerate() {
+            // The render pass left the target in its final layout.
+            let target = gbuffer.target_mut(index);
+            target.set_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            recorder.image_resource_transition(target, ImageLayout::TRANSFER_SRC_OPTIMAL);
+            recorder.copy_image_to_buffer(target, readback);
+        }
+    })
+    .wait();
+
+    let albedo = readbacks[0].copy_data::<[u8; 4]>();
+    assert!(albedo
+        .iter()
+        .all(|&texel| texel == [255, 128, 0, 255] || texel == [255, 127, 0, 255]));
+
+    let ids = readbacks[1].copy_data::<u32>();
+    let expected: Vec<u32> = (1..=SIZE * SIZE).collect();
+    assert_eq!(ids, expected);
+
+    let positions = readbacks[2].copy_data::<[f32; 4]>();
+    for (i, position) in positions.iter().enumerate() {
+        let (x, y) = (
+            (i as u32 % SIZE) as f32 + 0.5,
+            (i as u32 / SIZE) as f32 + 0.5,
+        );
+        assert_eq!(*position, [x, y, 0.5, 1.0], "pixel {}", i);
+    }
+
+    context.capture.assert_no_errors();
+}
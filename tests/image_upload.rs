@@ -0,0 +1,53 @@
+mod common;
+
+use ash::vk::{Format, ImageLayout, ImageUsageFlags, MemoryPropertyFlags};
+use common::TestContext;
+use vk_utils::image2d_resource::Image2DResource;
+use vk_utils::image_upload::ImageUploader;
+use vk_utils::readback::read_image_async;
+
+const WIDTH: u32 = 37;
+const HEIGHT: u32 = 23;
+
+#[test]
+fn banded_uploads_match_a_single_shot_upload() {
+    let Some(context) = TestContext::compute("Image upload") else {
+        return;
+    };
+
+    let new_image = || {
+        Image2DResource::new(
+            context.device.clone(),
+            WIDTH,
+            HEIGHT,
+            Format::R8G8B8A8_UNORM,
+            ImageUsageFlags::TRANSFER_SRC | ImageUsageFlags::TRANSFER_DST,
+            MemoryPropertyFlags::DEVICE_LOCAL,
+            1,
+        )
+    };
+    // Every byte differs from its neighbours, misplaced rows or bands can't go unnoticed.
+    let row_size = WIDTH as usize * 4;
+    let pixels: Vec<u8> = (0..row_size * HEIGHT as usize)
+        .map(|i| (i * 7 + i / row_size) as u8)
+        .collect();
+
+    let mut single = new_image();
+    ImageUploader::new(context.queue.clone()).upload(
+        &mut single,
+        &pixels,
+        ImageLayout::TRANSFER_SRC_OPTIMAL,
+    );
+    // Five rows and a bit per band, 23 rows end in a partial band of three.
+    let mut banded = new_image();
+    let mut uploader =
+        ImageUploader::new(context.queue.clone()).with_staging_budget(row_size * 5 + 3);
+    uploader.upload(&mut banded, &pixels, ImageLayout::TRANSFER_SRC_OPTIMAL);
+
+    let single = read_image_async(context.queue.clone(), &mut single).block();
+    let banded = read_image_async(context.queue.clone(), &mut banded).block();
+    assert_eq!(single, pixels);
+    assert_eq!(banded, single);
+
+    context.capture.assert_no_errors();
+}
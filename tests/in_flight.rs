@@ -0,0 +1,54 @@
+mod common;
+
+use common::TestContext;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::queue::WaitHandleDropPolicy;
+
+#[test]
+fn waited_submissions_leave_nothing_in_flight() {
+    let Some(context) = TestContext::compute("In flight") else {
+        return;
+    };
+    let queue = context.queue.clone();
+    assert_eq!(queue.in_flight(), 0);
+    assert_eq!(queue.oldest_pending_ms(), None);
+
+    // Submissions stay pending until their handle observes completion.
+    let handles: Vec<_> = (0..5)
+        .map(|_| CommandBuffer::record(queue.clone(), |_| {}))
+        .collect();
+    assert_eq!(queue.in_flight(), 5);
+    assert!(queue.oldest_pending_ms().is_some());
+
+    handles[0].wait();
+    assert_eq!(queue.in_flight(), 4);
+    // Retiring twice doesn't count twice.
+    assert!(handles[0].has_completed());
+    assert_eq!(queue.in_flight(), 4);
+
+    drop(handles);
+    assert_eq!(queue.in_flight(), 0);
+    assert_eq!(queue.oldest_pending_ms(), None);
+    context.capture.assert_no_errors();
+}
+
+#[test]
+fn deferred_submissions_are_retired_by_collect_garbage() {
+    let Some(context) = TestContext::compute("In flight deferred") else {
+        return;
+    };
+    let queue = context.queue.clone();
+    queue.set_drop_policy(WaitHandleDropPolicy::Defer);
+
+    for _ in 0..5 {
+        drop(CommandBuffer::record(queue.clone(), |_| {}));
+    }
+    // Any that already completed were retired on drop, the rest wait for the queue.
+    assert!(queue.in_flight() <= 5);
+
+    context.device.wait();
+    queue.collect_garbage();
+    assert_eq!(queue.in_flight(), 0);
+    assert_eq!(queue.oldest_pending_ms(), None);
+    context.capture.assert_no_errors();
+}
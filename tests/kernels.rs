@@ -0,0 +1,168 @@
+mod common;
+
+use common::TestContext;
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::kernels::{compact, record_compact};
+
+// Deterministic xorshift, the masks only need to be irregular, not high quality.
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+fn compact_reference(src: &[u32], mask: &[u32], words: usize) -> Vec<u32> {
+    mask.iter()
+        .enumerate()
+        .filter(|(_, &selected)| selected != 0)
+        .flat_map(|(i, _)| src[i * words..(i + 1) * words].iter().copied())
+        .collect()
+}
+
+#[test]
+fn compact_matches_the_cpu_reference_for_random_masks() {
+    let Some(context) = TestContext::compute("Compact") else {
+        return;
+    };
+
+    let mut random = Random(0x9e37_79b9);
+    // Single elements, partial and exact workgroups and enough blocks to cross a few thousand.
+    for &(count, words) in &[
+        (1usize, 1usize),
+        (255, 1),
+        (256, 1),
+        (257, 3),
+        (1000, 1),
+        (4096, 2),
+        (70_000, 1),
+    ] {
+        // Densities from empty to full, every mask entry is an arbitrary non-zero value when set.
+        for density in [0u32, 1, 50, 99, 100] {
+            let mask: Vec<u32> = (0..count)
+                .map(|_| {
+                    if random.next() % 100 < density {
+                        random.next() | 1
+                    } else {
+                        0
+                    }
+                })
+                .collect();
+            let src: Vec<u32> = (0..(count * words) as u32).collect();
+            let expected = compact_reference(&src, &mask, words);
+
+            let src_buffer =
+                BufferResource::new_host_visible_with_data(context.device.clone(), &src);
+            let mask_buffer =
+                BufferResource::new_host_visible_with_data(context.device.clone(), &mask);
+            let dst_buffer = BufferResource::new_host_visible_with_data(
+                context.device.clone(),
+                &vec![u32::MAX; count * words],
+            );
+
+            let (handle, total) = compact(
+                context.queue.clone(),
+                &src_buffer,
+                &mask_buffer,
+                words as u32 * 4,
+                &dst_buffer,
+            );
+            handle.wait();
+
+            let total = total.copy_data::<u32>()[0] as usize;
+            assert_eq!(
+                total * words,
+                expected.len(),
+                "count {} density {}",
+                count,
+                density
+            );
+            let dst = dst_buffer.copy_data::<u32>();
+            assert_eq!(
+                &dst[..expected.len()],
+                &expected[..],
+                "count {} density {}",
+                count,
+                density
+            );
+            // Nothing past the packed elements is touched.
+            assert!(dst[expected.len()..].iter().all(|&value| value == u32::MAX));
+        }
+    }
+
+    context.capture.assert_no_errors();
+}
+
+#[test]
+fn compact_records_no_buffer_hazards() {
+    let Some(context) = TestContext::compute("Compact hazards") else {
+        return;
+    };
+
+    let count = 1000;
+    let mask: Vec<u32> = (0..count).map(|i| i % 3).collect();
+    let src: Vec<u32> = (0..count).collect();
+    let src_buffer = BufferResource::new_host_visible_with_data(context.device.clone(), &src);
+    let mask_buffer = BufferResource::new_host_visible_with_data(context.device.clone(), &mask);
+    let dst_buffer =
+        BufferResource::new_host_visible_storage(context.device.clone(), count as usize * 4);
+
+    // The mask is read by both the scan and the scatter, reads alone need no barrier.
+    let mut command_buffer = CommandBuffer::new(context.queue.clone());
+    command_buffer.begin();
+    let total = record_compact(
+        &mut command_buffer,
+        &src_buffer,
+        &mask_buffer,
+        4,
+        &dst_buffer,
+    );
+    assert_eq!(command_buffer.buffer_hazards(), &[]);
+    command_buffer.submit().wait();
+
+    let expected = compact_reference(&src, &mask, 1);
+    assert_eq!(total.copy_data::<u32>()[0] as usize, expected.len());
+    assert_eq!(
+        &dst_buffer.copy_data::<u32>()[..expected.len()],
+        &expected[..]
+    );
+
+    context.capture.assert_no_errors();
+}
+
+#[test]
+fn kernel_pipelines_are_built_once_and_released_with_the_queue() {
+    let Some(context) = TestContext::compute("Kernel reuse") else {
+        return;
+    };
+
+    let mask = BufferResource::new_host_visible_with_data(context.device.clone(), &[1u32, 0, 1]);
+    let src = BufferResource::new_host_visible_with_data(context.device.clone(), &[7u32, 8, 9]);
+    let dst = BufferResource::new_host_visible_storage(context.device.clone(), 12);
+    let run = || {
+        let (handle, total) = compact(context.queue.clone(), &src, &mask, 4, &dst);
+        handle.wait();
+        assert_eq!(total.copy_data::<u32>(), [2]);
+    };
+
+    // The pipelines outlive the first call, the second one adds nothing holding the device.
+    let before = Rc::strong_count(&context.device);
+    run();
+    let cached = Rc::strong_count(&context.device);
+    assert!(cached > before);
+    run();
+    assert_eq!(Rc::strong_count(&context.device), cached);
+    assert_eq!(&dst.copy_data::<u32>()[..2], [7, 9]);
+
+    drop((mask, src, dst));
+    // Dropping the queue releases the pipelines, teardown checks nothing else holds the device.
+    let capture = context.teardown();
+    capture.assert_no_leaks();
+    capture.assert_no_errors();
+}
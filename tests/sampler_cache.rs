@@ -0,0 +1,29 @@
+mod common;
+
+use common::TestContext;
+use std::rc::Rc;
+use vk_utils::sampler_resource::SamplerDescriptor;
+
+#[test]
+fn identical_requests_share_one_sampler() {
+    let Some(context) = TestContext::compute("Sampler cache") else {
+        return;
+    };
+
+    let descriptor = SamplerDescriptor::default();
+    let samplers: Vec<_> = (0..1000)
+        .map(|_| context.device.get_sampler(&descriptor))
+        .collect();
+    assert!(samplers
+        .iter()
+        .all(|sampler| Rc::ptr_eq(sampler, &samplers[0])));
+    assert_eq!(Rc::strong_count(&samplers[0]), 1000);
+
+    // The cache doesn't keep it alive, the next request after the last drop creates a new one.
+    drop(samplers);
+    let sampler = context.device.get_sampler(&descriptor);
+    assert_eq!(Rc::strong_count(&sampler), 1);
+    drop(sampler);
+
+    context.capture.assert_no_errors();
+}
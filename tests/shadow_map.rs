@@ -0,0 +1,131 @@
+mod common;
+
+use ash::vk::{CullModeFlags, Filter, ImageLayout, PipelineBindPoint};
+use common::{compile, TestContext};
+use shaderc::ShaderKind;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::clear_value::Clear;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::graphics_pipeline::{GraphicsPipeline, GraphicsPipelineState};
+use vk_utils::image_resource::ImageResource;
+use vk_utils::pipeline_descriptor::ComputePipeline;
+use vk_utils::sampler_resource::SamplerDescriptor;
+use vk_utils::shadow_map::ShadowMap;
+
+const SIZE: u32 = 16;
+
+// A quad over the left half of the map at depth 0.25.
+const LEFT_HALF_VERT: &str = r"
+#version 450
+const vec2 corners[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(0.0, -1.0), vec2(-1.0, 1.0),
+    vec2(0.0, -1.0), vec2(0.0, 1.0), vec2(-1.0, 1.0)
+);
+void main() {
+    gl_Position = vec4(corners[gl_VertexIndex], 0.25, 1.0);
+}
+";
+
+const EMPTY_FRAG: &str = r"
+#version 450
+void main() {}
+";
+
+// Compute has no implicit derivatives, so both lookups use an explicit lod.
+const SAMPLE_SRC: &str = r"
+#version 450
+layout(local_size_x = 16, local_size_y = 16) in;
+layout(set = 0, binding = 0) uniform sampler2DShadow shadow;
+layout(set = 0, binding = 1) uniform sampler2D depth;
+layout(set = 0, binding = 2) buffer Result { vec2 texels[]; };
+void main() {
+    uvec2 p = gl_GlobalInvocationID.xy;
+    vec2 uv = (vec2(p) + 0.5) / 16.0;
+    texels[p.y * 16 + p.x] = vec2(
+        textureLod(shadow, vec3(uv, 0.5), 0.0),
+        textureLod(depth, uv, 0.0).r
+    );
+}
+";
+
+#[test]
+fn rendered_depth_can_be_sampled_and_compared() {
+    let Some(context) = TestContext::graphics("Shadow map") else {
+        return;
+    };
+    let device = context.device.clone();
+
+    let mut shadow_map = ShadowMap::new(device.clone(), SIZE);
+    let pipeline = GraphicsPipeline::new_from_shaders(
+        device.clone(),
+        &compile(LEFT_HALF_VERT, ShaderKind::Vertex),
+        &compile(EMPTY_FRAG, ShaderKind::Fragment),
+        &GraphicsPipelineState::new()
+            .with_viewport(SIZE, SIZE)
+            .with_cull_mode(CullModeFlags::NONE)
+            .with_depth_testing()
+            .with_depth_writing(),
+        shadow_map.render_pass(),
+        &[],
+    );
+
+    let result =
+        BufferResource::new_host_visible_storage(device.clone(), (SIZE * SIZE) as usize * 8);
+    let mut sample =
+        ComputePipeline::new_from_source_string(device.clone(), 1, SAMPLE_SRC, "main", None)
+            .expect("Compute pipeline creation failed");
+    let nearest =
+        device.get_sampler(&SamplerDescriptor::new().with_filter(Filter::NEAREST, Filter::NEAREST));
+    sample.set_combined_image_sampler_with_layout(
+        0,
+        0,
+        shadow_map.image(),
+        shadow_map.sampler().clone(),
+        ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+    );
+    sample.set_combined_image_sampler_with_layout(
+        0,
+        1,
+        shadow_map.image(),
+        nearest,
+        ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+    );
+    sample.set_storage_buffer(0, 2, &result);
+
+    CommandBuffer::record(context.queue.clone(), |recorder| {
+        {
+            let mut pass = recorder
+                .begin_render_pass(
+                    shadow_map.render_pass(),
+                    shadow_map.framebuffer().handle(),
+                    SIZE,
+                    SIZE,
+                    &[Clear::DepthStencil(1.0, 0)],
+                )
+                .expect("Clear values don't match the attachments");
+            pass.bind_pipeline(PipelineBindPoint::GRAPHICS, pipeline.handle());
+            pass.draw_vertices(6, 0, 1, 0);
+        }
+        // The render pass left the map in its final layout.
+        shadow_map
+            .image_mut()
+            .set_layout(ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+        recorder.bind_compute_pipeline(&sample);
+        recorder.dispatch_compute(1, 1, 1);
+    })
+    .wait();
+
+    let texels = result.copy_data::<[f32; 2]>();
+    for (i, &[lit, depth]) in texels.iter().enumerate() {
+        // LESS_OR_EQUAL: the reference 0.5 only passes behind the cleared half.
+        let (expected_lit, expected_depth) = if i as u32 % SIZE < SIZE / 2 {
+            (0.0, 0.25)
+        } else {
+            (1.0, 1.0)
+        };
+        assert_eq!(lit, expected_lit, "pixel {}", i);
+        assert_eq!(depth, expected_depth, "pixel {}", i);
+    }
+
+    context.capture.assert_no_errors();
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use ash::vk::BufferUsageFlags;
+use common::TestContext;
+use std::cell::Cell;
+use std::rc::Rc;
+use vk_utils::allocator::DeviceAllocationListener;
+use vk_utils::buffer_resource::BufferResource;
+
+// Bytes alive at once, and the largest of them.
+#[derive(Default)]
+struct PeakUsage {
+    live: Cell<u64>,
+    peak: Cell<u64>,
+    largest: Cell<u64>,
+}
+
+impl DeviceAllocationListener for PeakUsage {
+    fn allocated(&self, size: u64, _memory_type: u32, _tag: &str) {
+        self.live.set(self.live.get() + size);
+        self.peak.set(self.peak.get().max(self.live.get()));
+        self.largest.set(self.largest.get().max(size));
+    }
+
+    fn freed(&self, size: u64, _memory_type: u32, _tag: &str) {
+        self.live.set(self.live.get() - size);
+    }
+}
+
+#[test]
+fn async_uploads_stay_within_the_staging_budget() {
+    let Some(context) = TestContext::compute("Staging upload") else {
+        return;
+    };
+
+    const BUDGET: usize = 1024 * 1024;
+    // Eight times the budget, the ring of staging buffers wraps around several times.
+    let data: Vec<u32> = (0..2 * 1024 * 1024).collect();
+    let listener = Rc::new(PeakUsage::default());
+    context
+        .device
+        .set_allocation_listener(Some(listener.clone()));
+
+    let (buffer, handle) = BufferResource::new_device_local_with_data_async_within(
+        context.device.clone(),
+        context.queue.clone(),
+        BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::TRANSFER_SRC,
+        &data,
+        BUDGET,
+    );
+    handle.wait();
+    drop(handle);
+
+    // The destination is the largest allocation, everything else alive alongside it is staging.
+    // Allocations may be rounded up to the memory alignment, allow for it per slot.
+    let staging_peak = listener.peak.get() - listener.largest.get();
+    assert!(
+        staging_peak <= (BUDGET + 3 * 64 * 1024) as u64,
+        "{} bytes of staging memory for a {} byte budget",
+        staging_peak,
+        BUDGET
+    );
+    context.device.set_allocation_listener(None);
+    assert_eq!(
+        buffer.read_async::<u32>(context.queue.clone()).block(),
+        data
+    );
+
+    drop(buffer);
+    context.capture.assert_no_errors();
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use ash::ext::{debug_utils, headless_surface};
+use ash::khr::{surface, swapchain};
+use ash::vk::{
+    AttachmentLoadOp, Fence, Format, HeadlessSurfaceCreateInfoEXT, ImageLayout, ImageUsageFlags,
+    PipelineStageFlags, QueueFlags, SurfaceKHR,
+};
+use std::rc::Rc;
+use vk_utils::buffer_resource::BufferResource;
+use vk_utils::command_buffer::CommandBuffer;
+use vk_utils::device_context::DeviceContext;
+use vk_utils::image_resource::ImageResource;
+use vk_utils::swapchain::Swapchain;
+use vk_utils::swapchain_util::{SurfaceInfo, SwapchainConfig};
+use vk_utils::validation_capture::ValidationCapture;
+use vk_utils::vulkan::Vulkan;
+
+const SIZE: u32 = 16;
+
+// Fields drop in declaration order, the surface outlives the swapchains made from it.
+struct Headless {
+    device: Rc<DeviceContext>,
+    surface: SurfaceKHR,
+    capture: ValidationCapture,
+    vulkan: Vulkan,
+}
+
+impl Headless {
+    // VK_EXT_headless_surface gives a presentable surface without a window. None when the driver
+    // doesn't offer it or its images can't be read back.
+    fn new(name: &str) -> Option<Self> {
+        let extensions = [
+            debug_utils::NAME.to_str().unwrap(),
+            surface::NAME.to_str().unwrap(),
+            headless_surface::NAME.to_str().unwrap(),
+        ];
+        if !Vulkan::is_available()
+            || !extensions[1..].iter().all(|extension| {
+                Vulkan::available_instance_extensions()
+                    .is_ok_and(|available| available.iter().any(|name| name == extension))
+            })
+        {
+            println!("No headless surface support, skipping");
+            return None;
+        }
+        let mut vulkan = Vulkan::try_new(name, &["VK_LAYER_KHRONOS_validation"], &extensions)
+            .map_err(|error| println!("{}, skipping", error))
+            .ok()?;
+        let capture = ValidationCapture::new();
+        vulkan.set_validation_capture(&capture);
+
+        let surface = unsafe {
+            headless_surface::Instance::new(vulkan.library(), vulkan.vk_instance())
+                .create_headless_surface(&HeadlessSurfaceCreateInfoEXT::default(), None)
+                .expect("Headless surface creation failed")
+        };
+        let gpu = vulkan
+            .devices_supporting(QueueFlags::GRAPHICS, &[swapchain::NAME.to_str().unwrap()])
+            .into_iter()
+            .find(|gpu| !gpu.present_family_indices(surface).is_empty());
+        let supports_readback = gpu.as_ref().is_some_and(|gpu| {
+            SurfaceInfo::query(gpu, &vulkan, surface)
+                .capabilities
+                .supported_usage_flags
+                .contains(ImageUsageFlags::TRANSFER_SRC)
+        });
+        let Some(gpu) = gpu.filter(|_| supports_readback) else {
+            println!("No device can read back from the headless surface, skipping");
+            unsafe {
+                surface::Instance::new(vulkan.library(), vulkan.vk_instance())
+                    .destroy_surface(surface, None)
+            };
+            return None;
+        };
+        let device = Rc::new(gpu.device_context(&[swapchain::NAME.to_str().unwrap()]));
+        Some(Self {
+            device,
+            surface,
+            capture,
+            vulkan,
+        })
+    }
+
+    fn swapchain(&self, config: SwapchainConfig) -> Swapchain {
+        let queue = self
+            .device
+            .present_queue(self.surface)
+            .expect("No present queue");
+        Swapchain::new(
+            self.device.clone(),
+            self.surface,
+            queue,
+            config.with_image_usage(
+                ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC,
+            ),
+        )
+        .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Acquires an image, runs the swapchain render pass on it and reads the result back.
+    fn render_frame(&self, swapchain: &mut Swapchain) -> Vec<[u8; 4]> {
+        let (index, _, image_available) = swapchain
+            .next_frame_buffer()
+            .value()
+            .expect("Acquiring a swapchain image failed");
+        let mut readback = BufferResource::new_host_visible_storage(
+            self.device.clone(),
+            (SIZE * SIZE * 4) as usize,
+        );
+
+        let mut command_buffer = CommandBuffer::new(swapchain.queue());
+        command_buffer.begin();
+        command_buffer.begin_swapchain_render_pass(swapchain, index);
+        command_buffer.end_render_pass();
+        // The render pass leaves the image ready to present.
+        let image = swapchain.image_mut(index);
+        image.set_layout(ImageLayout::PRESENT_SRC_KHR);
+        command_buffer.image_resource_transition(image, ImageLayout::TRANSFER_SRC_OPTIMAL);
+        command_buffer.copy_image_to_buffer(image, &mut readback);
+        command_buffer.end();
+        command_buffer.submit_reusable(
+            &[image_available],
+            &[PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+            &[],
+            Fence::null(),
+        );
+        self.device.wait();
+        readback.copy_data::<[u8; 4]>()
+    }
+
+    fn destroy(self) -> ValidationCapture {
+        let Self {
+            device,
+            surface: surface_handle,
+            capture,
+            vulkan,
+        } = self;
+        drop(device);
+        unsafe {
+            surface::Instance::new(vulkan.library(), vulkan.vk_instance())
+                .destroy_surface(surface_handle, None)
+        };
+        drop(vulkan);
+        capture
+    }
+}
+
+#[test]
+fn the_clear_color_reaches_the_swapchain_image() {
+    let Some(headless) = Headless::new("Swapchain clear") else {
+        return;
+    };
+    let mut swapchain =
+        headless.swapchain(SwapchainConfig::new(SIZE, SIZE).with_clear_color([1.0, 0.0, 0.0, 1.0]));
+    let red = match *swapchain.format() {
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => [0, 0, 255, 255],
+        _ => [255, 0, 0, 255],
+    };
+    let pixels = headless.render_frame(&mut swapchain);
+    assert!(pixels.iter().all(|&pixel| pixel == red), "{:?}", pixels[0]);
+
+    drop(swapchain);
+    headless.destroy().assert_no_errors();
+}
+
+#[test]
+fn load_transitions_images_only_after_they_are_acquired() {
+    let Some(headless) = Headless::new("Swapchain load") else {
+        return;
+    };
+    // Using an image before it's acquired is a validation error, creation must not touch them.
+    let mut swapchain = headless
+        .swapchain(SwapchainConfig::new(SIZE, SIZE).with_color_load_op(AttachmentLoadOp::LOAD));
+    for _ in 0..swapchain.image_count() {
+        headless.render_frame(&mut swapchain);
+    }
+
+    drop(swapchain);
+    headless.destroy().assert_no_errors();
+}